@@ -0,0 +1,8 @@
+//! Feeds arbitrary bytes to every parser reading a map. Run with `cargo fuzz run map`, seeding
+//! the corpus with real maps for the fuzzer to mutate.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| bspinfo::fuzz::run(data));
@@ -0,0 +1,158 @@
+//! Lookup of game assets in the map's pakfile and in loose game content directories.
+
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+use crate::pak;
+
+/// Normalizes an asset path for comparison: forward slashes, lowercase, no leading slash and no
+/// empty or `.` components.
+pub fn normalize(path: &str) -> String {
+    path.replace('\\', "/")
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+        .to_ascii_lowercase()
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+fn strip_suffix_ignore_case<'a>(s: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = s.len().checked_sub(suffix.len())?;
+    let tail = s.get(split..)?;
+    tail.eq_ignore_ascii_case(suffix).then(|| &s[..split])
+}
+
+/// Finds a file under `dir` whose path matches the normalized `path` ignoring case, as content
+/// directories on case-sensitive filesystems often contain mixed-case names.
+fn find_ignore_case(dir: &Path, path: &str) -> Option<PathBuf> {
+    let exact = dir.join(path);
+    if exact.is_file() {
+        return Some(exact);
+    }
+
+    let (component, rest) = match path.split_once('/') {
+        Some((component, rest)) => (component, Some(rest)),
+        None => (path, None),
+    };
+
+    // Several entries can differ only in case, e.g. both `materials` and `Materials`
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(component)
+        })
+        .find_map(|entry| match rest {
+            Some(rest) => find_ignore_case(&entry.path(), rest),
+            None => Some(entry.path()).filter(|p| p.is_file()),
+        })
+}
+
+/// How asset references are compared against pakfile and filesystem entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PathMatching {
+    /// Ignore case and separators, like the engine's filesystem on Windows
+    #[default]
+    Normalized,
+    /// Require references to match exactly, like a case-sensitive filesystem
+    Strict,
+}
+
+impl PathMatching {
+    /// The form of `path` used as a lookup key
+    pub fn path(self, path: &str) -> String {
+        match self {
+            PathMatching::Normalized => normalize(path),
+            PathMatching::Strict => path.to_string(),
+        }
+    }
+
+    /// `prefix` + `name` + `extension`, for references that may or may not already include
+    /// the prefix and extension
+    fn asset(self, prefix: &str, name: &str, extension: &str) -> String {
+        let name = self.path(name);
+        let name = strip_prefix_ignore_case(&name, prefix).unwrap_or(&name);
+        let name = strip_suffix_ignore_case(name, extension).unwrap_or(name);
+
+        format!("{prefix}{name}{extension}")
+    }
+
+    /// Path of a material referenced by name, e.g. `brick/wall01` → `materials/brick/wall01.vmt`
+    pub fn material(self, name: &str) -> String {
+        self.asset("materials/", name, ".vmt")
+    }
+
+    /// Path of a texture referenced by name, e.g. `brick/wall01` → `materials/brick/wall01.vtf`
+    pub fn texture(self, name: &str) -> String {
+        self.asset("materials/", name, ".vtf")
+    }
+}
+
+pub struct AssetStore {
+    pak: Option<ZipArchive<Cursor<Vec<u8>>>>,
+    /// Normalized pakfile entry names, mapped to their index in the zip
+    pak_index: HashMap<String, usize>,
+    dirs: Vec<PathBuf>,
+    pub matching: PathMatching,
+}
+
+impl AssetStore {
+    pub fn new(pakfile: Option<Vec<u8>>, dirs: Vec<PathBuf>, matching: PathMatching) -> Self {
+        let mut pak = pakfile.and_then(|data| ZipArchive::new(Cursor::new(data)).ok());
+
+        let mut pak_index = HashMap::new();
+        if let Some(zip) = &mut pak {
+            for i in 0..zip.len() {
+                if let Ok(file) = zip.by_index_raw(i) {
+                    pak_index.insert(matching.path(file.name()), i);
+                }
+            }
+        }
+
+        Self {
+            pak,
+            pak_index,
+            dirs,
+            matching,
+        }
+    }
+
+    pub fn is_packed(&self, path: &str) -> bool {
+        self.pak_index.contains_key(&self.matching.path(path))
+    }
+
+    fn loose_path(&self, path: &str) -> Option<PathBuf> {
+        self.dirs.iter().find_map(|dir| match self.matching {
+            PathMatching::Normalized => find_ignore_case(dir, &normalize(path)),
+            PathMatching::Strict => Some(dir.join(path)).filter(|p| p.is_file()),
+        })
+    }
+
+    pub fn exists(&self, path: &str) -> bool {
+        self.is_packed(path) || self.loose_path(path).is_some()
+    }
+
+    /// Reads an asset, preferring the packed copy like the engine does.
+    pub fn read(&mut self, path: &str) -> Option<Vec<u8>> {
+        if let (Some(zip), Some(&index)) =
+            (&mut self.pak, self.pak_index.get(&self.matching.path(path)))
+        {
+            return pak::read_contents(zip, index).ok();
+        }
+
+        std::fs::read(self.loose_path(path)?).ok()
+    }
+}
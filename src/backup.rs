@@ -0,0 +1,122 @@
+//! Copies of original lumps stashed in the pakfile, so in-place edits can be undone.
+//!
+//! Each backup is a `bspinfo/backup/<LUMP>.bin` entry holding the lump's original directory
+//! entry followed by its raw, possibly compressed, contents.
+
+use std::io::{self, Read, Seek};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::bsp::{BspFile, LumpInfo, LumpType};
+use crate::pak;
+use crate::writer::LumpWriter;
+
+pub const PREFIX: &str = "bspinfo/backup/";
+
+fn entry_name(lump: LumpType) -> String {
+    format!("{PREFIX}{lump:?}.bin")
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn encode(lump: LumpType, info: &LumpInfo, data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    for value in [
+        lump as u32,
+        info.fileofs,
+        info.version,
+        info.uncompressed_size,
+    ] {
+        out.write_u32::<LittleEndian>(value).unwrap();
+    }
+    out.extend_from_slice(data);
+
+    out
+}
+
+fn decode(mut contents: &[u8]) -> Option<(LumpType, LumpInfo, Vec<u8>)> {
+    let lump = LumpType::try_from(contents.read_u32::<LittleEndian>().ok()?).ok()?;
+    let fileofs = contents.read_u32::<LittleEndian>().ok()?;
+    let version = contents.read_u32::<LittleEndian>().ok()?;
+    let uncompressed_size = contents.read_u32::<LittleEndian>().ok()?;
+
+    let info = LumpInfo {
+        fileofs,
+        filelen: contents.len() as u32,
+        version,
+        uncompressed_size,
+    };
+    Some((lump, info, contents.to_vec()))
+}
+
+/// Adds backups of `lumps` as they are in `bsp` to the pakfile written by `writer`. Lumps that
+/// already have a backup keep it, so restoring always returns to the state before the first
+/// edit.
+pub fn stash<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    writer: &mut LumpWriter,
+    lumps: &[LumpType],
+) -> io::Result<()> {
+    if lumps.contains(&LumpType::PAKFILE) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the pakfile can't be backed up into itself",
+        ));
+    }
+
+    let pakfile = bsp.get_lump(LumpType::PAKFILE);
+    let existing = match &pakfile {
+        Some(data) => pak::read_prefixed(data, PREFIX)?,
+        None => vec![],
+    };
+
+    let mut files = vec![];
+    for &lump in lumps {
+        let name = entry_name(lump);
+        if existing.iter().any(|(existing, _)| *existing == name) {
+            continue;
+        }
+
+        let info = *bsp.lump_info(lump);
+        let data = bsp.get_raw_lump(lump).unwrap_or_default();
+        files.push((name, encode(lump, &info, &data)));
+    }
+
+    if !files.is_empty() {
+        writer.append_lump(
+            LumpType::PAKFILE,
+            pak::rewrite(pakfile.as_deref(), |_| true, files)?,
+        );
+    }
+
+    Ok(())
+}
+
+/// Puts every backed up lump back into `writer` and removes the backups from the pakfile.
+/// Returns the restored lumps.
+pub fn restore<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    writer: &mut LumpWriter,
+) -> io::Result<Vec<LumpType>> {
+    let Some(pakfile) = bsp.get_lump(LumpType::PAKFILE) else {
+        return Ok(vec![]);
+    };
+
+    let mut restored = vec![];
+    for (name, contents) in pak::read_prefixed(&pakfile, PREFIX)? {
+        let (lump, info, data) =
+            decode(&contents).ok_or_else(|| invalid_data(format!("{name} is malformed")))?;
+
+        writer.replace_raw_lump(lump, info, data);
+        restored.push(lump);
+    }
+
+    if !restored.is_empty() {
+        let pakfile = pak::rewrite(Some(&pakfile), |name| !name.starts_with(PREFIX), vec![])?;
+        writer.append_lump(LumpType::PAKFILE, pakfile);
+    }
+
+    Ok(restored)
+}
@@ -0,0 +1,252 @@
+//! One report over a whole map pool: each map's version and revision, where its bytes go,
+//! how many entities it has and what validation finds.
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::json::Json;
+use crate::mmap::Mmap;
+use crate::policy::Policy;
+use crate::validate::{self, Finding, Severity};
+use crate::{crypt, csv, entities, parallel};
+
+/// Groups of lumps the size breakdown adds up, with everything else counted as geometry
+const CATEGORIES: &[(&str, &[LumpType])] = &[
+    (
+        "lighting",
+        &[
+            LumpType::LIGHTING,
+            LumpType::LIGHTING_HDR,
+            LumpType::LEAF_AMBIENT_INDEX,
+            LumpType::LEAF_AMBIENT_INDEX_HDR,
+            LumpType::LEAF_AMBIENT_LIGHTING,
+            LumpType::LEAF_AMBIENT_LIGHTING_HDR,
+            LumpType::WORLD_LIGHTS,
+            LumpType::WORLD_LIGHTS_HDR,
+            LumpType::DISPLACEMENT_LIGHTMAP_ALPHAS,
+            LumpType::DISPLACEMENT_LIGHTMAP_SAMPLE_POSITIONS,
+        ],
+    ),
+    ("visibility", &[LumpType::VISIBILITY]),
+    (
+        "physics",
+        &[
+            LumpType::PHYSICS_COLLIDE,
+            LumpType::PHYSICS_DISPLACEMENT,
+            LumpType::PHYSICS_COLLIDE_SURFACE,
+            LumpType::PHYSICS_LEVEL,
+        ],
+    ),
+    ("pakfile", &[LumpType::PAKFILE, LumpType::XZIP_PAKFILE]),
+    ("game_lump", &[LumpType::GAME_LUMP]),
+    ("entities", &[LumpType::ENTITIES]),
+];
+
+pub struct MapSummary {
+    pub version: u32,
+    pub revision: u32,
+    pub file_size: u64,
+    /// Bytes stored in each of [`CATEGORIES`], then in every other lump
+    pub sizes: Vec<u64>,
+    pub entities: Option<usize>,
+    pub findings: Vec<Finding>,
+}
+
+impl MapSummary {
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+}
+
+pub struct MapReport {
+    pub path: PathBuf,
+    pub summary: Result<MapSummary, String>,
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any run of characters and `?` one
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob_matches(&pattern[1..], name)
+                || (!name.is_empty() && glob_matches(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => glob_matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p.eq_ignore_ascii_case(n) => glob_matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Expands a directory to the maps in it, and a pattern with `*` or `?` in its file name to
+/// the files matching it. Anything else is taken as a map.
+pub fn expand(arg: &str) -> io::Result<Vec<PathBuf>> {
+    let path = Path::new(arg);
+    let (dir, pattern) = if path.is_dir() {
+        (path, "*.bsp".to_string())
+    } else {
+        match path.file_name().map(|name| name.to_string_lossy()) {
+            Some(name) if name.contains(['*', '?']) => {
+                (path.parent().unwrap_or(Path::new("")), name.into_owned())
+            }
+            _ => return Ok(vec![path.to_path_buf()]),
+        }
+    };
+
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut maps = vec![];
+    let read_dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    for entry in fs::read_dir(read_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let chars: Vec<char> = name.to_string_lossy().chars().collect();
+        if entry.path().is_file() && glob_matches(&pattern, &chars) {
+            maps.push(dir.join(name));
+        }
+    }
+    maps.sort();
+    Ok(maps)
+}
+
+fn summarize(path: &Path, policy: Policy) -> Result<MapSummary, String> {
+    let map = Mmap::open(path).map_err(|e| e.to_string())?;
+
+    // Encrypted maps need decrypting first, the rest are parsed straight from the mapping
+    let data = crypt::decrypt(&map).map_err(|e| e.to_string())?;
+    let mut reader = Cursor::new(&data[..]);
+    let mut bsp = BspFile::from_slice(&mut reader)
+        .map_err(|_| "not a BSP".to_string())?
+        .with_policy(policy);
+
+    let mut sizes = vec![0; CATEGORIES.len() + 1];
+    for i in 0..bsp.lump_count() {
+        let size = bsp.lump_info_by_index(i).filelen as u64;
+        let category = LumpType::try_from(i as u32).ok().and_then(|lump| {
+            CATEGORIES
+                .iter()
+                .position(|(_, lumps)| lumps.contains(&lump))
+        });
+        sizes[category.unwrap_or(CATEGORIES.len())] += size;
+    }
+
+    let entities = bsp
+        .get_lump(LumpType::ENTITIES)
+        .and_then(|data| entities::parse(&data).ok())
+        .map(|entities| entities.len());
+
+    Ok(MapSummary {
+        version: bsp.version(),
+        revision: bsp.map_revision(),
+        file_size: map.len() as u64,
+        sizes,
+        entities,
+        findings: validate::validate(&mut bsp).findings,
+    })
+}
+
+/// Summarizes every map, `threads` at a time
+pub fn run(paths: &[PathBuf], threads: usize, policy: Policy) -> Vec<MapReport> {
+    parallel::map(paths, threads, |path| MapReport {
+        path: path.clone(),
+        summary: summarize(path, policy),
+    })
+}
+
+fn size_columns() -> impl Iterator<Item = String> {
+    CATEGORIES
+        .iter()
+        .map(|(name, _)| format!("{name}_bytes"))
+        .chain(["geometry_bytes".to_string()])
+}
+
+pub fn write_csv<W: Write>(reports: &[MapReport], w: &mut W) -> io::Result<()> {
+    let mut header: Vec<String> = ["map", "version", "revision", "size"]
+        .map(String::from)
+        .to_vec();
+    header.extend(size_columns());
+    header.extend(["entities", "errors", "warnings", "problem"].map(String::from));
+    csv::write_record(w, &header)?;
+
+    for report in reports {
+        let mut record = vec![report.path.display().to_string()];
+        match &report.summary {
+            Ok(summary) => {
+                record.extend([
+                    summary.version.to_string(),
+                    summary.revision.to_string(),
+                    summary.file_size.to_string(),
+                ]);
+                record.extend(summary.sizes.iter().map(u64::to_string));
+                record.extend([
+                    summary.entities.map_or(String::new(), |n| n.to_string()),
+                    summary.count(Severity::Error).to_string(),
+                    summary.count(Severity::Warning).to_string(),
+                    String::new(),
+                ]);
+            }
+            Err(problem) => {
+                record.resize(header.len() - 1, String::new());
+                record.push(problem.clone());
+            }
+        }
+        csv::write_record(w, &record)?;
+    }
+
+    Ok(())
+}
+
+pub fn to_json(reports: &[MapReport]) -> Json {
+    Json::Array(
+        reports
+            .iter()
+            .map(|report| {
+                let mut fields = vec![("map", Json::from(report.path.display().to_string()))];
+                match &report.summary {
+                    Ok(summary) => {
+                        fields.extend([
+                            ("version", summary.version.into()),
+                            ("revision", summary.revision.into()),
+                            ("size", summary.file_size.into()),
+                            (
+                                "sizes",
+                                Json::object(
+                                    CATEGORIES
+                                        .iter()
+                                        .map(|(name, _)| *name)
+                                        .chain(["geometry"])
+                                        .zip(summary.sizes.iter().map(|&s| Json::from(s))),
+                                ),
+                            ),
+                            ("entities", summary.entities.into()),
+                            (
+                                "findings",
+                                Json::Array(
+                                    summary
+                                        .findings
+                                        .iter()
+                                        .map(|f| {
+                                            Json::object([
+                                                ("severity", f.severity.to_string().into()),
+                                                ("message", f.message.as_str().into()),
+                                            ])
+                                        })
+                                        .collect(),
+                                ),
+                            ),
+                        ]);
+                    }
+                    Err(problem) => fields.push(("problem", problem.as_str().into())),
+                }
+                Json::object(fields)
+            })
+            .collect(),
+    )
+}
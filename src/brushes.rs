@@ -0,0 +1,84 @@
+//! Brush counts by contents and the materials used on brush sides.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::lumps::{World, CONTENTS_NAMES};
+
+/// Materials listed in the report
+const TOP_MATERIALS: usize = 15;
+
+pub struct Stats {
+    pub brushes: usize,
+    pub sides: usize,
+    /// Sides the compiler added to brushes for collision
+    pub bevels: usize,
+    /// Brushes with each contents flag, in the order of `CONTENTS_NAMES`
+    pub contents: Vec<(&'static str, usize)>,
+    /// Brushes with no contents at all
+    pub empty: usize,
+    /// Sides using each material, most used first
+    pub materials: Vec<(String, usize)>,
+}
+
+impl Stats {
+    pub fn new(world: &World) -> Self {
+        let contents = CONTENTS_NAMES
+            .iter()
+            .map(|&(flag, name)| {
+                let count = world
+                    .brushes
+                    .iter()
+                    .filter(|b| b.contents & flag != 0)
+                    .count();
+                (name, count)
+            })
+            .collect();
+
+        let mut materials: HashMap<&str, usize> = HashMap::new();
+        for side in world.brush_sides.iter().filter(|s| s.bevel == 0) {
+            let name = world.texinfo_name(side.texinfo).unwrap_or("<none>");
+            *materials.entry(name).or_default() += 1;
+        }
+        let mut materials: Vec<(String, usize)> = materials
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        materials.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Self {
+            brushes: world.brushes.len(),
+            sides: world.brush_sides.len(),
+            bevels: world.brush_sides.iter().filter(|s| s.bevel != 0).count(),
+            contents,
+            empty: world.brushes.iter().filter(|b| b.contents == 0).count(),
+            materials,
+        }
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Brushes: {}", self.brushes)?;
+        writeln!(w, "Brush sides: {} ({} bevels)", self.sides, self.bevels)?;
+        if self.brushes == 0 {
+            return Ok(());
+        }
+
+        writeln!(w, "Contents:")?;
+        for (name, count) in self.contents.iter().filter(|(_, count)| *count != 0) {
+            writeln!(w, "  {name:<22} {count:8}")?;
+        }
+        if self.empty != 0 {
+            writeln!(w, "  {:<22} {:8}", "empty", self.empty)?;
+        }
+
+        writeln!(w, "Materials on brush sides:")?;
+        for (name, count) in self.materials.iter().take(TOP_MATERIALS) {
+            writeln!(w, "  {count:8} {name}")?;
+        }
+        if self.materials.len() > TOP_MATERIALS {
+            writeln!(w, "  ... and {} more", self.materials.len() - TOP_MATERIALS)?;
+        }
+
+        Ok(())
+    }
+}
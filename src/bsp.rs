@@ -0,0 +1,482 @@
+use binrw::BinRead;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::{
+    borrow::Cow,
+    io::{self, Cursor, Read, Seek},
+    path::PathBuf,
+};
+
+use crate::error::BspError;
+use crate::policy::{ParseContext, Policy};
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, num_enum::TryFromPrimitive, num_enum::IntoPrimitive,
+)]
+#[repr(u32)]
+pub enum LumpType {
+    ENTITIES = 0,
+    PLANES = 1,
+    TEXTURE_DATA = 2,
+    VERTICES = 3,
+    VISIBILITY = 4,
+    NODES = 5,
+    TEXTURE_INFO = 6,
+    FACES = 7,
+    LIGHTING = 8,
+    OCCLUSION = 9,
+    LEAVES = 10,
+    FACE_IDS = 11,
+    EDGES = 12,
+    SURFEDGES = 13,
+    MODELS = 14,
+    WORLD_LIGHTS = 15,
+    LEAF_FACES = 16,
+    LEAF_BRUSHES = 17,
+    BRUSHES = 18,
+    BRUSH_SIDES = 19,
+    AREAS = 20,
+    AREA_PORTALS = 21,
+    UNUSED_22 = 22,
+    UNUSED_23 = 23,
+    UNUSED_24 = 24,
+    UNUSED_25 = 25,
+    DISPLACEMENT_INFO = 26,
+    ORIGINAL_FACES = 27,
+    PHYSICS_DISPLACEMENT = 28,
+    PHYSICS_COLLIDE = 29,
+    VERTEX_NORMALS = 30,
+    VERTEX_NORMAL_INDICES = 31,
+    DISPLACEMENT_LIGHTMAP_ALPHAS = 32,
+    DISPLACEMENT_VERTICES = 33,
+    DISPLACEMENT_LIGHTMAP_SAMPLE_POSITIONS = 34,
+    GAME_LUMP = 35,
+    LEAF_WATER_DATA = 36,
+    PRIMITIVES = 37,
+    PRIMITIVE_VERTICES = 38,
+    PRIMITIVE_INDICES = 39,
+    PAKFILE = 40,
+    CLIP_PORTAL_VERTICES = 41,
+    CUBEMAPS = 42,
+    TEXTURE_DATA_STRING_DATA = 43,
+    TEXTURE_DATA_STRING_TABLE = 44,
+    OVERLAYS = 45,
+    LEAF_MIN_DIST_TO_WATER = 46,
+    FACE_MACRO_TEXTURE_INFO = 47,
+    DISPLACEMENT_TRIS = 48,
+    PHYSICS_COLLIDE_SURFACE = 49,
+    WATER_OVERLAYS = 50,
+    LEAF_AMBIENT_INDEX_HDR = 51,
+    LEAF_AMBIENT_INDEX = 52,
+    LIGHTING_HDR = 53,
+    WORLD_LIGHTS_HDR = 54,
+    LEAF_AMBIENT_LIGHTING_HDR = 55,
+    LEAF_AMBIENT_LIGHTING = 56,
+    XZIP_PAKFILE = 57,
+    FACES_HDR = 58,
+    MAP_FLAGS = 59,
+    OVERLAY_FADES = 60,
+    UNUSED_61 = 61,
+    PHYSICS_LEVEL = 62,
+    UNUSED_63 = 63,
+}
+
+pub const HEADER_LUMPS: usize = 64;
+
+impl LumpType {
+    /// Looks up a VBSP lump by the name it's printed with, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        (0..HEADER_LUMPS as u32)
+            .filter_map(|i| LumpType::try_from(i).ok())
+            .find(|lump| format!("{lump:?}").eq_ignore_ascii_case(name))
+    }
+}
+/// Lump count used by Respawn's rBSP format (Titanfall, Apex Legends)
+pub const RESPAWN_HEADER_LUMPS: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BspFormat {
+    Valve,
+    Respawn,
+}
+
+impl BspFormat {
+    pub fn ident(self) -> &'static str {
+        match self {
+            BspFormat::Valve => "VBSP",
+            BspFormat::Respawn => "rBSP",
+        }
+    }
+
+    /// Name of the lump at `index`. rBSP lumps are identified by their index.
+    pub fn lump_name(self, index: usize) -> String {
+        match (self, LumpType::try_from(index as u32)) {
+            (BspFormat::Valve, Ok(lump)) => format!("{lump:?}"),
+            _ => format!("LUMP_{index:04x}"),
+        }
+    }
+}
+
+// Only lives long enough to be converted into a BspHeader
+#[allow(clippy::large_enum_variant)]
+#[derive(BinRead)]
+#[br(little)]
+enum RawHeader {
+    #[br(magic = b"VBSP")]
+    Valve {
+        version: u32,
+        lumps: [LumpInfo; HEADER_LUMPS],
+        map_revision: u32,
+    },
+
+    #[br(magic = b"rBSP")]
+    Respawn {
+        version: u16,
+        _flags: u16,
+        map_revision: u32,
+        _last_lump: u32,
+        lumps: [LumpInfo; RESPAWN_HEADER_LUMPS],
+    },
+}
+
+#[derive(Debug)]
+pub struct BspHeader {
+    pub format: BspFormat,
+    pub version: u32,
+    pub lumps: Vec<LumpInfo>,
+    pub map_revision: u32,
+}
+
+impl From<RawHeader> for BspHeader {
+    fn from(header: RawHeader) -> Self {
+        match header {
+            RawHeader::Valve {
+                version,
+                lumps,
+                map_revision,
+            } => BspHeader {
+                format: BspFormat::Valve,
+                version,
+                lumps: lumps.to_vec(),
+                map_revision,
+            },
+            RawHeader::Respawn {
+                version,
+                map_revision,
+                lumps,
+                ..
+            } => BspHeader {
+                format: BspFormat::Respawn,
+                version: version.into(),
+                lumps: lumps.to_vec(),
+                map_revision,
+            },
+        }
+    }
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct LumpInfo {
+    pub fileofs: u32,
+    pub filelen: u32,
+    pub version: u32,
+    pub uncompressed_size: u32,
+}
+
+pub struct BspFile<'a, R> {
+    header: BspHeader,
+    reader: &'a mut R,
+    /// Contents of the reader when it reads from memory, which lumps are borrowed from
+    memory: Option<&'a [u8]>,
+    /// Path of the map, used to find lumps stored in external `.bsp_lump` files
+    external_lumps: Option<PathBuf>,
+    context: ParseContext,
+}
+
+impl<'a, R: Read + Seek> BspFile<'a, R> {
+    pub fn new(reader: &'a mut R) -> binrw::BinResult<BspFile<'a, R>> {
+        Ok(Self {
+            header: RawHeader::read(reader)?.into(),
+            reader,
+            memory: None,
+            external_lumps: None,
+            context: ParseContext::default(),
+        })
+    }
+
+    /// Sets how parsers reading this map treat problems with it
+    pub fn with_policy(mut self, policy: Policy) -> Self {
+        self.context = ParseContext::new(policy);
+        self
+    }
+
+    pub fn context(&self) -> &ParseContext {
+        &self.context
+    }
+
+    /// The reader the map is parsed from. Lumps are read by seeking to them, so it can be left
+    /// anywhere.
+    pub fn get_mut(&mut self) -> &mut R {
+        self.reader
+    }
+
+    /// Enables loading lumps from the `<map>.bsp.<index>.bsp_lump` files next to the map, as
+    /// used by rBSP maps.
+    pub fn with_external_lumps(mut self, map_path: impl Into<PathBuf>) -> Self {
+        self.external_lumps = Some(map_path.into());
+        self
+    }
+
+    pub fn format(&self) -> BspFormat {
+        self.header.format
+    }
+
+    pub fn version(&self) -> u32 {
+        self.header.version
+    }
+
+    pub fn map_revision(&self) -> u32 {
+        self.header.map_revision
+    }
+
+    pub fn file_size(&mut self) -> io::Result<u64> {
+        self.reader.seek(io::SeekFrom::End(0))
+    }
+
+    pub fn lump_count(&self) -> usize {
+        self.header.lumps.len()
+    }
+
+    pub fn lump_info(&self, lump: LumpType) -> &LumpInfo {
+        self.lump_info_by_index(lump as usize)
+    }
+
+    pub fn lump_info_by_index(&self, index: usize) -> &LumpInfo {
+        &self.header.lumps[index]
+    }
+
+    /// Path of the external file for a lump, if it exists
+    pub fn external_lump_path(&self, index: usize) -> Option<PathBuf> {
+        let map_path = self.external_lumps.as_ref()?;
+
+        let mut path = map_path.clone().into_os_string();
+        path.push(format!(".{index:04x}.bsp_lump"));
+
+        let path = PathBuf::from(path);
+        path.is_file().then_some(path)
+    }
+
+    /// Whether the map has data for the lump, in the file or an external lump file
+    pub fn has_lump(&self, index: usize) -> bool {
+        let stored = self
+            .header
+            .lumps
+            .get(index)
+            .is_some_and(|lump| lump.fileofs != 0 && lump.filelen != 0);
+        stored || self.external_lump_path(index).is_some()
+    }
+
+    /// Reads the lump exactly as it is stored in the file, without decompressing it. Maps
+    /// opened with [`from_slice`](BspFile::from_slice) lend the lump from their memory, others
+    /// read it into a buffer.
+    pub fn get_raw_lump(&mut self, lump: LumpType) -> Option<Cow<'a, [u8]>> {
+        self.get_raw_lump_by_index(lump as usize)
+    }
+
+    /// Like [`get_raw_lump`](Self::get_raw_lump), but saying why the lump couldn't be read
+    pub fn try_get_raw_lump(&mut self, lump: LumpType) -> Result<Cow<'a, [u8]>, BspError> {
+        self.read_raw_lump(lump as usize)?
+            .ok_or(BspError::MissingLump(lump))
+    }
+
+    pub fn get_raw_lump_by_index(&mut self, index: usize) -> Option<Cow<'a, [u8]>> {
+        self.read_raw_lump(index).ok().flatten()
+    }
+
+    /// Offset and length of the part of the file a lump is stored in, cut short at the end of
+    /// the file if the policy allows it. `None` if the map doesn't have the lump.
+    fn lump_range(&mut self, index: usize) -> Result<Option<(u64, u64)>, BspError> {
+        let Some(lump) = self.header.lumps.get(index) else {
+            return Ok(None);
+        };
+        if lump.fileofs == 0 || lump.filelen == 0 {
+            return Ok(None);
+        }
+
+        let (ofs, len) = (lump.fileofs as u64, lump.filelen as u64);
+        let file_size = self.file_size()?;
+        if ofs + len > file_size {
+            let error = BspError::LumpOutOfBounds {
+                lump: self.format().lump_name(index),
+                end: ofs + len,
+                file_size,
+            };
+            if ofs >= file_size || !self.context.tolerate(&error) {
+                return Err(error);
+            }
+            return Ok(Some((ofs, file_size - ofs)));
+        }
+
+        Ok(Some((ofs, len)))
+    }
+
+    fn read_raw_lump(&mut self, index: usize) -> Result<Option<Cow<'a, [u8]>>, BspError> {
+        if let Some(path) = self.external_lump_path(index) {
+            return Ok(Some(Cow::Owned(std::fs::read(path)?)));
+        }
+
+        let Some((ofs, len)) = self.lump_range(index)? else {
+            return Ok(None);
+        };
+        if let Some(memory) = self.memory {
+            return Ok(Some(Cow::Borrowed(
+                &memory[ofs as usize..(ofs + len) as usize],
+            )));
+        }
+
+        self.reader.seek(io::SeekFrom::Start(ofs))?;
+        let mut buf = vec![];
+        self.reader.by_ref().take(len).read_to_end(&mut buf)?;
+
+        Ok(Some(Cow::Owned(buf)))
+    }
+
+    /// Reads the lump, decompressing it if it's compressed. Like
+    /// [`get_raw_lump`](Self::get_raw_lump), uncompressed lumps of maps in memory are borrowed.
+    pub fn get_lump(&mut self, lump: LumpType) -> Option<Cow<'a, [u8]>> {
+        self.get_lump_by_index(lump as usize)
+    }
+
+    /// Like [`get_lump`](Self::get_lump), but saying why the lump couldn't be read
+    pub fn try_get_lump(&mut self, lump: LumpType) -> Result<Cow<'a, [u8]>, BspError> {
+        self.read_lump(lump as usize)?
+            .ok_or(BspError::MissingLump(lump))
+    }
+
+    pub fn get_lump_by_index(&mut self, index: usize) -> Option<Cow<'a, [u8]>> {
+        self.read_lump(index).ok().flatten()
+    }
+
+    fn read_lump(&mut self, index: usize) -> Result<Option<Cow<'a, [u8]>>, BspError> {
+        let compressed = self.format() == BspFormat::Valve
+            && self
+                .header
+                .lumps
+                .get(index)
+                .is_some_and(|lump| lump.uncompressed_size != 0);
+        let Some(buf) = self.read_raw_lump(index)? else {
+            return Ok(None);
+        };
+
+        if compressed {
+            self.decompress_lump(index, &buf)
+                .map(|data| Some(Cow::Owned(data)))
+        } else {
+            Ok(Some(buf))
+        }
+    }
+
+    /// Decompresses a lump, holding its LZMA header to the size the lump directory gives
+    fn decompress_lump(&self, index: usize, data: &[u8]) -> Result<Vec<u8>, BspError> {
+        let (actual, _) = lzma_header(data).ok_or(BspError::Lzma("no LZMA header"))?;
+        let declared = self.lump_info_by_index(index).uncompressed_size;
+        if actual != declared {
+            let error = BspError::LumpSize {
+                lump: self.format().lump_name(index),
+                actual,
+                declared,
+            };
+            if !self.context.tolerate(&error) {
+                return Err(error);
+            }
+        }
+
+        decompress_lzma(data)
+    }
+}
+
+impl<'a, 'b: 'a> BspFile<'a, Cursor<&'b [u8]>> {
+    /// Like [`new`](Self::new), for a map in memory such as a [`Mmap`](crate::mmap::Mmap).
+    /// Lumps are borrowed from it rather than copied, except those that are compressed or
+    /// stored in external lump files.
+    pub fn from_slice(reader: &'a mut Cursor<&'b [u8]>) -> binrw::BinResult<Self> {
+        let memory: &'b [u8] = reader.get_ref();
+        let mut bsp = Self::new(reader)?;
+        bsp.memory = Some(memory);
+        Ok(bsp)
+    }
+}
+
+/// Size of the header preceding compressed data: ident, sizes and the LZMA properties
+pub const LZMA_HEADER_SIZE: usize = 17;
+
+/// Largest size compressed data is decompressed to. The LZMA header gives the size, which a
+/// hostile map can set to anything up to 4 GiB.
+pub const MAX_DECOMPRESSED_SIZE: u32 = 256 * 1024 * 1024;
+
+/// Reads the decompressed and compressed sizes from a Valve LZMA header.
+pub fn lzma_header(data: &[u8]) -> Option<(u32, u32)> {
+    let mut reader = Cursor::new(data);
+
+    if b"LZMA" != &<[u8; 4]>::read(&mut reader).ok()? {
+        return None;
+    }
+
+    let actual_size: u32 = reader.read_u32::<LittleEndian>().ok()?;
+    let lzma_size: u32 = reader.read_u32::<LittleEndian>().ok()?;
+
+    Some((actual_size, lzma_size))
+}
+
+/// Decompresses a buffer in Valve's LZMA container format, as used by compressed lumps and
+/// game lumps.
+pub fn decompress_lzma(data: &[u8]) -> Result<Vec<u8>, BspError> {
+    let (actual_size, _lzma_size) = lzma_header(data).ok_or(BspError::Lzma("no LZMA header"))?;
+    if actual_size > MAX_DECOMPRESSED_SIZE {
+        return Err(BspError::TooLarge {
+            size: actual_size.into(),
+            limit: MAX_DECOMPRESSED_SIZE.into(),
+        });
+    }
+    let mut reader = Cursor::new(&data[12..]);
+
+    // Adapted from https://github.com/icewind1991/vbsp/blob/0850bb8dbd695a770d39a06f2cc880aa9d626bf7/src/lib.rs#L545
+    // extra 8 byte because game lumps need some padding for reasons
+    let mut buf: Vec<u8> =
+        Vec::with_capacity(std::cmp::min(actual_size as usize + 8, 8 * 1024 * 1024));
+
+    lzma_rs::lzma_decompress_with_options(
+        &mut reader,
+        &mut buf,
+        &lzma_rs::decompress::Options {
+            unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(actual_size as u64)),
+            allow_incomplete: false,
+            memlimit: None,
+        },
+    )
+    .map_err(|_| BspError::Lzma("invalid LZMA data"))?;
+
+    Ok(buf)
+}
+
+/// Compresses a buffer into Valve's LZMA container format. lzma-rs only has an encoder for
+/// literals, so this doesn't compress as well as vbsp or bspzip, but any LZMA decoder reads it.
+pub fn compress_lzma(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = vec![];
+    let options = lzma_rs::compress::Options {
+        unpacked_size: lzma_rs::compress::UnpackedSize::SkipWritingToHeader,
+    };
+    lzma_rs::lzma_compress_with_options(&mut Cursor::new(data), &mut stream, &options)?;
+
+    // lzma-rs starts with the 5 bytes of LZMA properties, which Valve's header ends with
+    let (props, body) = stream.split_at(5);
+    let mut out = Vec::with_capacity(LZMA_HEADER_SIZE + body.len());
+    out.extend_from_slice(b"LZMA");
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(props);
+    out.extend_from_slice(body);
+    Ok(out)
+}
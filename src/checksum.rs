@@ -0,0 +1,198 @@
+//! Map and file checksums: the engine's map CRC, MD5 and SHA-256.
+
+use std::borrow::Cow;
+use std::io::{self, Read, Seek};
+
+use crate::bsp::{BspFile, BspFormat, LumpType};
+use crate::gamelump;
+
+/// Computes the CRC the engine uses for `map_crc` and sv_pure consistency checks: a CRC32 of
+/// the raw (possibly compressed) contents of every lump except the entity lump, in lump order.
+/// The header itself isn't included, so entity edits and lump reordering don't change it.
+pub fn map_crc<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<u32> {
+    if bsp.format() != BspFormat::Valve {
+        return None;
+    }
+
+    let mut hasher = crc32fast::Hasher::new();
+    for i in 0..bsp.lump_count() {
+        if i == LumpType::ENTITIES as usize || bsp.lump_info_by_index(i).filelen == 0 {
+            continue;
+        }
+
+        hasher.update(&bsp.get_raw_lump_by_index(i)?);
+    }
+
+    Some(hasher.finalize())
+}
+
+/// The MD5 newer engines send to clients in place of the map CRC, over the same lump data
+pub fn map_md5<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<[u8; 16]> {
+    if bsp.format() != BspFormat::Valve {
+        return None;
+    }
+
+    let mut md5 = Md5::new();
+    for i in 0..bsp.lump_count() {
+        if i == LumpType::ENTITIES as usize || bsp.lump_info_by_index(i).filelen == 0 {
+            continue;
+        }
+
+        md5.update(&bsp.get_raw_lump_by_index(i)?);
+    }
+
+    Some(md5.finalize())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+const MD5_SHIFTS: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+    14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+    21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+/// Streaming MD5, as used by sv_pure file hashes
+pub struct Md5 {
+    state: [u32; 4],
+    buffer: Vec<u8>,
+    len: u64,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: Vec::with_capacity(64),
+            len: 0,
+        }
+    }
+
+    fn process_block(&mut self, block: &[u8]) {
+        let m: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = self.state;
+
+        for (i, &shift) in MD5_SHIFTS.iter().enumerate() {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let k = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k)
+                .wrapping_add(m[g])
+                .rotate_left(shift);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(rotated);
+        }
+
+        for (s, v) in self.state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+
+        if !self.buffer.is_empty() {
+            let take = data.len().min(64 - self.buffer.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+
+            if self.buffer.len() < 64 {
+                return;
+            }
+            let block = std::mem::take(&mut self.buffer);
+            self.process_block(&block);
+        }
+
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            self.process_block(block);
+        }
+        self.buffer.extend_from_slice(blocks.remainder());
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        let bits = self.len.wrapping_mul(8);
+
+        let mut padding = vec![0x80];
+        padding.resize((119 - self.len % 64) as usize % 64 + 1, 0);
+        padding.extend_from_slice(&bits.to_le_bytes());
+        self.update(&padding);
+
+        let mut digest = [0; 16];
+        for (out, s) in digest.chunks_exact_mut(4).zip(self.state) {
+            out.copy_from_slice(&s.to_le_bytes());
+        }
+        digest
+    }
+}
+
+/// MD5 and SHA-256 of everything read from `reader`
+pub fn file_digests<R: Read>(mut reader: R) -> io::Result<([u8; 16], [u8; 32])> {
+    use sha2::{Digest, Sha256};
+
+    let mut md5 = Md5::new();
+    let mut sha256 = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        md5.update(&buf[..n]);
+        sha256.update(&buf[..n]);
+    }
+
+    Ok((md5.finalize(), sha256.finalize().into()))
+}
+
+pub struct LumpDigest {
+    pub index: usize,
+    pub size: usize,
+    pub crc32: u32,
+    pub sha256: [u8; 32],
+}
+
+/// CRC32 and SHA-256 of every non-empty lump after decompression, so recompressing a map
+/// doesn't change them. The game lump is hashed with its entries decompressed and offsets made
+/// relative to the lump. Lumps that fail to read are skipped.
+pub fn lump_digests<R: Read + Seek>(bsp: &mut BspFile<R>) -> Vec<LumpDigest> {
+    use sha2::{Digest, Sha256};
+
+    (0..bsp.lump_count())
+        .filter_map(|index| {
+            let data = if bsp.format() == BspFormat::Valve && index == LumpType::GAME_LUMP as usize
+            {
+                Cow::Owned(gamelump::serialize(&gamelump::read(bsp).ok()?).ok()?)
+            } else {
+                bsp.get_lump_by_index(index)?
+            };
+            Some(LumpDigest {
+                index,
+                size: data.len(),
+                crc32: crc32fast::hash(&data),
+                sha256: Sha256::digest(&data).into(),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,629 @@
+//! The commands the binary has, with the help, errors and shell completions generated from
+//! them. Argument parsing itself stays with each command in `main.rs`.
+//!
+//! This stands in for clap, which the crate can't depend on as its builds only have the
+//! crates already vendored for it. It keeps clap's behaviour scripts rely on: `--help` prints
+//! to stdout and exits with 0, and a wrong command line, including an option the command
+//! doesn't have, prints an error to stderr and exits with [`Exit::Usage`].
+
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use bspinfo::mmap;
+
+/// Exit status of the binary, so scripts can tell failures apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The map was read, and has the problems the command checks for
+    Findings = 1,
+    /// The command line is wrong
+    Usage = 2,
+    /// A file named on the command line doesn't exist
+    NotFound = 3,
+    /// A file isn't what the command expects, or part of it couldn't be parsed
+    Parse = 4,
+    /// The map doesn't have a lump the command needs
+    MissingLump = 5,
+    /// Reading or writing a file failed
+    Io = 6,
+}
+
+const EXIT_CODES: &[(Exit, &str)] = &[
+    (
+        Exit::Findings,
+        "the map has the problems the command checks for",
+    ),
+    (Exit::Usage, "the command line is wrong"),
+    (Exit::NotFound, "a file doesn't exist"),
+    (
+        Exit::Parse,
+        "a file isn't a map, or part of it couldn't be parsed",
+    ),
+    (
+        Exit::MissingLump,
+        "the map doesn't have a lump the command needs",
+    ),
+    (Exit::Io, "reading or writing a file failed"),
+];
+
+/// Prints `message` as an error and exits with `exit`
+pub fn fail(exit: Exit, message: impl Display) -> ! {
+    eprintln!("error: {message}");
+    std::process::exit(exit as i32)
+}
+
+/// Exits for an IO error with the file it happened on, telling missing files from other errors
+pub fn fail_io(path: impl Display, e: io::Error) -> ! {
+    match e.kind() {
+        io::ErrorKind::NotFound => fail(Exit::NotFound, format!("{path} not found")),
+        _ => fail(Exit::Io, format!("{path}: {e}")),
+    }
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Arguments after the command name, as shown in its usage
+    pub args: &'static str,
+    pub about: &'static str,
+    /// Whether the first argument is a map the command is run on. Commands that take several
+    /// maps, or none, open them themselves.
+    pub opens_map: bool,
+    /// Whether the command lists rows, which `--format` chooses the output of
+    pub tabular: bool,
+}
+
+impl Command {
+    /// The flags named in the command's usage
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![];
+        for word in self
+            .args
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        {
+            if word.starts_with("--") && word.len() > 2 && !flags.contains(&word) {
+                flags.push(word);
+            }
+        }
+        flags
+    }
+
+    /// The first of `args` that looks like a flag but isn't one of the command's, which would
+    /// otherwise be ignored or taken as a file name
+    pub fn unknown_flag<'a>(&self, args: &'a [String]) -> Option<&'a str> {
+        let flags = self.flags();
+        args.iter()
+            .map(String::as_str)
+            .find(|arg| arg.starts_with("--") && !flags.contains(arg))
+    }
+
+    pub fn print_usage<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "usage: bspinfo {} {}", self.name, self.args)?;
+        writeln!(w)?;
+        writeln!(w, "{}", self.about)?;
+        if !self.aliases.is_empty() {
+            writeln!(w, "Also run as {}.", self.aliases.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+const fn map(name: &'static str, args: &'static str, about: &'static str) -> Command {
+    Command {
+        name,
+        aliases: &[],
+        args,
+        about,
+        opens_map: true,
+        tabular: false,
+    }
+}
+
+const fn table(name: &'static str, args: &'static str, about: &'static str) -> Command {
+    Command {
+        tabular: true,
+        ..map(name, args, about)
+    }
+}
+
+const fn other(name: &'static str, args: &'static str, about: &'static str) -> Command {
+    Command {
+        opens_map: false,
+        ..map(name, args, about)
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    map(
+        "info",
+        "<mapname.bsp>",
+        "Prints the format, lump usage and size of the map. Runs when only a map is given.",
+    ),
+    table(
+        "lumps",
+        "<mapname.bsp> [--format table|csv|json|plain]",
+        "Lists every lump with its offset, size and version.",
+    ),
+    Command {
+        aliases: &["ls"],
+        ..table(
+            "files",
+            "<mapname.bsp> [--format table|csv|json|plain]",
+            "Lists the files packed into the map.",
+        )
+    },
+    table(
+        "textures",
+        "<mapname.bsp> [--format table|csv|json|plain]",
+        "Lists the materials faces use, with their size and how many texinfos use them.",
+    ),
+    table(
+        "faceids",
+        "<mapname.bsp> [--format table|csv|json|plain]",
+        "Lists the Hammer side each face was built from, with its material and macro texture.",
+    ),
+    table(
+        "staticprops",
+        "<mapname.bsp> [--format table|csv|json|plain] [--remove-model <model.mdl>]... [--replace-model <old.mdl=new.mdl>]... [--out <out.bsp>] [--backup] [--dry-run]",
+        "Lists the static props with their model, position, angles, skin and solidity, or removes and swaps their models.",
+    ),
+    map(
+        "extract",
+        "<mapname.bsp> <out_dir>",
+        "Extracts the files packed into the map.",
+    ),
+    Command {
+        aliases: &["ents"],
+        ..map(
+            "entities",
+            "<mapname.bsp> [--stats | --check-fgd game.fgd]",
+            "Prints the entity lump, counts entities by class or checks them against an FGD.",
+        )
+    },
+    map(
+        "validate",
+        "<mapname.bsp>",
+        "Checks the map for structural problems, exiting with 1 if it finds errors.",
+    ),
+    map(
+        "clipgaps",
+        "<mapname.bsp>",
+        "Finds ledges players can stand on that playerclip doesn't cover.",
+    ),
+    map("brushes", "<mapname.bsp>", "Counts brushes by contents and lists the materials on their sides."),
+    map("physics", "<mapname.bsp> [--keyvalues]", "Summarizes the collision data of each brush model."),
+    map("worldlights", "<mapname.bsp>", "Lists the lights vrad stored for lighting models."),
+    map("tree", "<mapname.bsp>", "Prints the depth of the BSP tree, leaves per cluster and leaf contents."),
+    map(
+        "portallinks",
+        "<mapname.bsp>",
+        "Checks func_areaportal entities against the area portals.",
+    ),
+    map(
+        "areaportals",
+        "<mapname.bsp>",
+        "Checks how the areas are connected, exiting with 1 on errors.",
+    ),
+    map(
+        "compileinfo",
+        "<mapname.bsp>",
+        "Reports how the map was built: worldspawn settings, compile passes, map flags and tool signatures.",
+    ),
+    map(
+        "leaks",
+        "<mapname.bsp>",
+        "Looks for signs the map leaked or was compiled with a cordon, exiting with 1 on a likely leak.",
+    ),
+    map("crc", "<mapname.bsp>", "Prints the map CRC clients check, and the file hashes."),
+    map(
+        "hash",
+        "<mapname.bsp> [--per-lump]",
+        "Prints the MD5 and SHA256 of the map, or of each lump.",
+    ),
+    map(
+        "hexdump",
+        "<mapname.bsp> --lump <index|name> [--raw] [--offset n] [--length n]",
+        "Prints the bytes of any lump, decompressed unless --raw is given.",
+    ),
+    map(
+        "deps",
+        "<mapname.bsp> [--game-dir dir]... [--format tree|dot|json] [--max-depth n] [--root asset] [--target asset] [--strict-paths]",
+        "Finds the assets the map depends on and where they come from.",
+    ),
+    map(
+        "reslist",
+        "<mapname.bsp> [--game-dir dir]... [--stock dir|list.txt]... [--out-dir dir]",
+        "Writes the map's .res file and sv_pure whitelist entries for its custom content.",
+    ),
+    map(
+        "vis",
+        "<mapname.bsp> [--cluster n | --at x y z] --out <pvs.png> [--size px]",
+        "Summarizes visibility, or draws what a cluster can see.",
+    ),
+    map("sky", "<mapname.bsp> [--leaves]", "Finds the parts of the map that can see the sky."),
+    map("occluders", "<mapname.bsp>", "Summarizes the func_occluder brushes."),
+    map(
+        "water",
+        "<mapname.bsp>",
+        "Reports the bodies of water, their surfaces and the leaves under them.",
+    ),
+    map(
+        "splits",
+        "<mapname.bsp>",
+        "Compares the faces with the original faces they were split from.",
+    ),
+    map(
+        "primitives",
+        "<mapname.bsp>",
+        "Reports the t-junction and water primitives and their limits.",
+    ),
+    map(
+        "cubemaps",
+        "<mapname.bsp>",
+        "Lists the cubemap samples and whether their textures are packed.",
+    ),
+    map("displacements", "<mapname.bsp>", "Summarizes displacements and checks their neighbor links."),
+    map("lighting", "<mapname.bsp>", "Summarizes the baked lighting and the lightmap data faces use."),
+    map(
+        "limits",
+        "<mapname.bsp> [--game tf2|css]",
+        "Compares the map to a game's engine limits, exiting with 1 if it's over one.",
+    ),
+    map(
+        "offset-entities",
+        "<mapname.bsp> <out.bsp> [--translate x y z] [--rotate yaw] [--static-props] [--backup] [--dry-run]",
+        "Moves or rotates every entity, and optionally the static props.",
+    ),
+    map(
+        "pack",
+        "<mapname.bsp> <content_dir> <out.bsp> [--sync] [--dry-run]",
+        "Packs a directory of content into the map.",
+    ),
+    map(
+        "restore",
+        "<mapname.bsp> <out.bsp>",
+        "Undoes an edit from the original lumps it backed up in the pakfile.",
+    ),
+    map(
+        "strip",
+        "<mapname.bsp> --out <out.bsp> [--remove LUMP]... [--preset server|ldr] [--dry-run]",
+        "Removes lumps the game doesn't need, changing the map CRC: clients with the original \
+         map get \"map differs from server\" on a server running the stripped one.",
+    ),
+    map(
+        "repack",
+        "<mapname.bsp> --out <out.bsp> [--decompress] [--dry-run]",
+        "LZMA compresses every lump, or decompresses them all.",
+    ),
+    map(
+        "optimize",
+        "<mapname.bsp> --out <out.bsp> [--dry-run]",
+        "Removes deprecated lumps.",
+    ),
+    map(
+        "normalize",
+        "<mapname.bsp> --out <out.bsp> [--lzma] [--dry-run]",
+        "Rewrites the pakfile so the same files always give the same map.",
+    ),
+    map(
+        "pak-optimize",
+        "<mapname.bsp> --out <out.bsp> [--method lzma|deflate] [--dry-run]",
+        "Removes duplicate pakfile entries and compresses stored ones.",
+    ),
+    map(
+        "redact",
+        "<mapname.bsp> --out <out.bsp> [--dry-run]",
+        "Removes compile machine paths, entity comments and pakfile metadata.",
+    ),
+    map(
+        "retexture",
+        "<mapname.bsp> --map <old=new>... --out <out.bsp> [--pak] [--dry-run]",
+        "Renames materials the map uses, and their packed files with --pak.",
+    ),
+    map(
+        "bz2",
+        "<mapname.bsp> [--repack <out.bsp>]",
+        "Writes a bzip2 compressed copy of the map for fast download servers.",
+    ),
+    map(
+        "lightmaps",
+        "<mapname.bsp> --out <dir> [--format png|hdr] [--hdr]",
+        "Exports the lightmaps as images.",
+    ),
+    map(
+        "io",
+        "<mapname.bsp> [--dot]",
+        "Prints the entity I/O connections, or a Graphviz graph of them.",
+    ),
+    map(
+        "locate",
+        "<mapname.bsp> x y z",
+        "Finds the leaf, cluster and area a point is in.",
+    ),
+    map(
+        "overlays",
+        "<mapname.bsp> [--game-dir dir]...",
+        "Lists overlays and checks their materials.",
+    ),
+    map(
+        "decals",
+        "<mapname.bsp> [--game-dir dir]...",
+        "Lists decals and checks their materials.",
+    ),
+    map(
+        "thumbnails",
+        "<mapname.bsp> <out_dir>",
+        "Exports the menu photos and loading screens packed into the map.",
+    ),
+    map(
+        "export-mesh",
+        "<mapname.bsp> <out> [--format obj|gltf]",
+        "Exports the world geometry.",
+    ),
+    map(
+        "overview",
+        "<mapname.bsp> --out <overview.png> [--scale auto|units] [--size px] [--displacements] [--max-z z]",
+        "Draws the map from above for the game's overview and radar, with the script placing it.",
+    ),
+    map(
+        "export-csv",
+        "<mapname.bsp> props|entities <out.csv>",
+        "Exports static props or entities to a spreadsheet.",
+    ),
+    map(
+        "import-csv",
+        "<mapname.bsp> props|entities <in.csv> <out.bsp> [--add-models] [--backup] [--dry-run]",
+        "Imports static props or entities edited in a spreadsheet.",
+    ),
+    other(
+        "diff",
+        "<a.bsp> <b.bsp> [--json]",
+        "Compares two maps, exiting with 1 if they differ.",
+    ),
+    other(
+        "demo",
+        "<demo.dem> [maps_dir]",
+        "Finds the map a demo was recorded on and checks it's the same version.",
+    ),
+    other(
+        "watch",
+        "<mapname.bsp> [--run <command,...>]",
+        "Runs commands each time the map is compiled, printing how their output changed.",
+    ),
+    other(
+        "jobs",
+        "<jobs.json|jobs.yaml>",
+        "Runs the steps of a jobs file over many maps.",
+    ),
+    other(
+        "batch",
+        "<dir|pattern|mapname.bsp>... [--report csv|json] [--jobs n]",
+        "Reports on many maps at once.",
+    ),
+    other(
+        "index",
+        "<mapname.bsp>...",
+        "Prints the searchable text of each map as JSON Lines, for full text search.",
+    ),
+    other(
+        "completions",
+        "bash|zsh|fish",
+        "Prints a shell completion script.",
+    ),
+    other("help", "[command]", "Prints help for bspinfo or a command."),
+];
+
+/// Looks up a command by its name or an alias
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name))
+}
+
+/// Number of single character edits turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The command an unknown name is most likely a typo of
+pub fn suggest(name: &str) -> Option<&'static str> {
+    COMMANDS
+        .iter()
+        .map(|command| (edit_distance(name, command.name), command.name))
+        .filter(|&(distance, _)| distance <= 2)
+        .min()
+        .map(|(_, name)| name)
+}
+
+pub fn print_help<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w, "usage: bspinfo <command> <mapname.bsp> [options]")?;
+    writeln!(w, "       bspinfo <mapname.bsp>")?;
+    writeln!(w)?;
+    writeln!(w, "commands:")?;
+    let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    for command in COMMANDS {
+        writeln!(w, "  {:width$}  {}", command.name, command.about)?;
+    }
+    writeln!(w)?;
+    writeln!(w, "options:")?;
+    writeln!(
+        w,
+        "  --lenient  read maps with unknown lump versions or short lumps, with warnings instead of errors"
+    )?;
+    writeln!(w, "  --strict   fail on those instead, the default")?;
+    writeln!(w, "  --help     print help for bspinfo, or for the command")?;
+    writeln!(
+        w,
+        "  --format   print the rows commands list as a table, csv, json or plain tab separated lines"
+    )?;
+    writeln!(w, "  --version  print the version")?;
+    writeln!(
+        w,
+        "  --         end of options, for map names starting with -"
+    )?;
+    writeln!(w)?;
+    writeln!(
+        w,
+        "<mapname.bsp> can be - to read the map from stdin, and maps compressed with bzip2 are decompressed as they're read."
+    )?;
+    writeln!(
+        w,
+        "Maps of {} MiB and more are memory mapped, and one that's truncated while it's read crashes bspinfo with SIGBUS. Set {} to read them into memory instead, such as while a compiler may rewrite them.",
+        mmap::MIN_MAPPED_SIZE / (1024 * 1024),
+        mmap::NO_MMAP_VAR
+    )?;
+    writeln!(w)?;
+    writeln!(w, "exit codes:")?;
+    for (exit, meaning) in EXIT_CODES {
+        writeln!(w, "  {}  {meaning}", *exit as i32)?;
+    }
+    Ok(())
+}
+
+const GLOBAL_FLAGS: &[&str] = &["--lenient", "--strict", "--help"];
+
+fn command_names() -> String {
+    COMMANDS
+        .iter()
+        .flat_map(|c| std::iter::once(c.name).chain(c.aliases.iter().copied()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_bash<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w, "_bspinfo() {{")?;
+    writeln!(w, "    local cur=${{COMP_WORDS[COMP_CWORD]}} flags")?;
+    writeln!(w, "    if [ \"$COMP_CWORD\" -eq 1 ]; then")?;
+    writeln!(
+        w,
+        "        COMPREPLY=($(compgen -W \"{} --version {}\" -- \"$cur\"))",
+        command_names(),
+        GLOBAL_FLAGS.join(" ")
+    )?;
+    writeln!(w, "        return")?;
+    writeln!(w, "    fi")?;
+    writeln!(w, "    case \"${{COMP_WORDS[1]}}\" in")?;
+    for command in COMMANDS {
+        let names = std::iter::once(command.name)
+            .chain(command.aliases.iter().copied())
+            .collect::<Vec<_>>()
+            .join("|");
+        writeln!(
+            w,
+            "        {names}) flags=\"{}\" ;;",
+            command.flags().join(" ")
+        )?;
+    }
+    writeln!(w, "    esac")?;
+    writeln!(w, "    if [[ $cur == -* ]]; then")?;
+    writeln!(
+        w,
+        "        COMPREPLY=($(compgen -W \"$flags {}\" -- \"$cur\"))",
+        GLOBAL_FLAGS.join(" ")
+    )?;
+    writeln!(w, "    else")?;
+    writeln!(w, "        COMPREPLY=($(compgen -f -- \"$cur\"))")?;
+    writeln!(w, "    fi")?;
+    writeln!(w, "}}")?;
+    writeln!(w, "complete -o filenames -F _bspinfo bspinfo")
+}
+
+fn write_zsh<W: Write>(w: &mut W) -> io::Result<()> {
+    writeln!(w, "#compdef bspinfo")?;
+    writeln!(w)?;
+    writeln!(w, "_bspinfo() {{")?;
+    writeln!(w, "    local -a commands flags")?;
+    writeln!(w, "    commands=(")?;
+    for command in COMMANDS {
+        let about = command.about.replace(':', "\\:").replace('\'', "'\\''");
+        writeln!(w, "        '{}:{about}'", command.name)?;
+    }
+    writeln!(w, "    )")?;
+    writeln!(w, "    if (( CURRENT == 2 )); then")?;
+    writeln!(w, "        _describe 'command' commands")?;
+    writeln!(w, "        return")?;
+    writeln!(w, "    fi")?;
+    writeln!(w, "    case $words[2] in")?;
+    for command in COMMANDS {
+        let names = std::iter::once(command.name)
+            .chain(command.aliases.iter().copied())
+            .collect::<Vec<_>>()
+            .join("|");
+        writeln!(
+            w,
+            "        {names}) flags=({}) ;;",
+            command.flags().join(" ")
+        )?;
+    }
+    writeln!(w, "    esac")?;
+    writeln!(w, "    if [[ $PREFIX == -* ]]; then")?;
+    writeln!(w, "        compadd -- $flags {}", GLOBAL_FLAGS.join(" "))?;
+    writeln!(w, "    else")?;
+    writeln!(w, "        _files")?;
+    writeln!(w, "    fi")?;
+    writeln!(w, "}}")?;
+    writeln!(w)?;
+    writeln!(w, "_bspinfo \"$@\"")
+}
+
+fn write_fish<W: Write>(w: &mut W) -> io::Result<()> {
+    for flag in GLOBAL_FLAGS.iter().chain(&["--version"]) {
+        writeln!(w, "complete -c bspinfo -l {}", &flag[2..])?;
+    }
+    for command in COMMANDS {
+        let about = command.about.replace('\'', "\\'");
+        writeln!(
+            w,
+            "complete -c bspinfo -f -n __fish_use_subcommand -a {} -d '{about}'",
+            command.name
+        )?;
+        for flag in command.flags() {
+            writeln!(
+                w,
+                "complete -c bspinfo -n '__fish_seen_subcommand_from {}' -l {}",
+                command.name,
+                &flag[2..]
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the completion script for `shell`, returning false for shells it can't write one for
+pub fn write_completions<W: Write>(shell: &str, w: &mut W) -> io::Result<bool> {
+    match shell {
+        "bash" => write_bash(w)?,
+        "zsh" => write_zsh(w)?,
+        "fish" => write_fish(w)?,
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// Unwrapping of what commands read, exiting with an error saying what failed instead of
+/// panicking
+pub trait OrExit<T> {
+    fn or_exit(self, exit: Exit, what: impl Display) -> T;
+}
+
+impl<T> OrExit<T> for Option<T> {
+    fn or_exit(self, exit: Exit, what: impl Display) -> T {
+        self.unwrap_or_else(|| fail(exit, what))
+    }
+}
+
+impl<T, E: Display> OrExit<T> for Result<T, E> {
+    fn or_exit(self, exit: Exit, what: impl Display) -> T {
+        self.unwrap_or_else(|e| fail(exit, format!("{what}: {e}")))
+    }
+}
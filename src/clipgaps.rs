@@ -0,0 +1,206 @@
+//! Heuristic search for ledges that players can stand on but that aren't covered by
+//! playerclip. This can't know the mapper's intent, so findings are candidates to check in
+//! game rather than definite exploits.
+
+use std::io::{self, Write};
+
+use crate::lumps::{
+    Vector, World, CONTENTS_PLAYERCLIP, SURF_NODRAW, SURF_SKIP, SURF_SKY, SURF_SKY2D, SURF_TRIGGER,
+};
+
+/// Minimum normal Z the engine treats as standable ground
+const WALKABLE_NORMAL_Z: f32 = 0.7;
+/// Surfaces wider than this are considered floors rather than ledges
+const MAX_LEDGE_WIDTH: f32 = 64.0;
+/// Ledges this close to the lowest floor can simply be walked onto
+const MIN_LEDGE_HEIGHT: f32 = 64.0;
+const PLAYER_HALF_WIDTH: f32 = 16.0;
+const PLAYER_HEIGHT: f32 = 72.0;
+
+pub struct Ledge {
+    pub face: usize,
+    pub material: String,
+    pub center: Vector,
+    pub width: f32,
+    pub height: f32,
+    pub displacement: bool,
+}
+
+pub struct Report {
+    pub playerclip_brushes: usize,
+    pub walkable_faces: usize,
+    pub ledges: Vec<Ledge>,
+}
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(v: Vector) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+/// Approximates how wide a polygon is as its area divided by its longest edge, which works for
+/// diagonal strips where the bounding box doesn't.
+fn polygon_width(vertices: &[Vector]) -> f32 {
+    let mut area = [0.0; 3];
+    for i in 1..vertices.len().saturating_sub(1) {
+        let c = cross(
+            sub(vertices[i], vertices[0]),
+            sub(vertices[i + 1], vertices[0]),
+        );
+        area = [area[0] + c[0], area[1] + c[1], area[2] + c[2]];
+    }
+    let area = length(area) / 2.0;
+
+    let longest = (0..vertices.len())
+        .map(|i| length(sub(vertices[(i + 1) % vertices.len()], vertices[i])))
+        .fold(0.0, f32::max);
+
+    if longest == 0.0 {
+        0.0
+    } else {
+        area / longest
+    }
+}
+
+fn bounds(vertices: &[Vector]) -> (Vector, Vector) {
+    let mut mins = [f32::MAX; 3];
+    let mut maxs = [f32::MIN; 3];
+    for v in vertices {
+        for axis in 0..3 {
+            mins[axis] = mins[axis].min(v[axis]);
+            maxs[axis] = maxs[axis].max(v[axis]);
+        }
+    }
+
+    (mins, maxs)
+}
+
+fn intersects(a: &(Vector, Vector), b: &(Vector, Vector)) -> bool {
+    (0..3).all(|axis| a.0[axis] <= b.1[axis] && b.0[axis] <= a.1[axis])
+}
+
+pub fn analyze(world: &World) -> Report {
+    let clips: Vec<_> = world
+        .brushes
+        .iter()
+        .filter(|brush| brush.contents & CONTENTS_PLAYERCLIP != 0)
+        .filter_map(|brush| world.brush_bounds(brush))
+        .collect();
+
+    let mut walkable = vec![];
+    for (i, face) in world.model_faces(0).iter().enumerate() {
+        let Some(texinfo) = world.face_texinfo(face) else {
+            continue;
+        };
+        if texinfo.flags & (SURF_SKY | SURF_SKY2D | SURF_NODRAW | SURF_TRIGGER | SURF_SKIP) != 0 {
+            continue;
+        }
+
+        let material = world.texinfo_name(face.texinfo).unwrap_or_default();
+        if material.to_ascii_lowercase().starts_with("tools/") {
+            continue;
+        }
+
+        let Some(plane) = world.planes.get(face.planenum as usize) else {
+            continue;
+        };
+        let normal_z = if face.side == 0 {
+            plane.normal[2]
+        } else {
+            -plane.normal[2]
+        };
+        if normal_z < WALKABLE_NORMAL_Z {
+            continue;
+        }
+
+        let vertices = world.face_vertices(face);
+        if vertices.len() < 3 {
+            continue;
+        }
+
+        walkable.push((i, face, material.to_string(), vertices));
+    }
+
+    let floor = walkable
+        .iter()
+        .flat_map(|(_, _, _, vertices)| vertices.iter().map(|v| v[2]))
+        .fold(f32::MAX, f32::min);
+
+    let mut ledges = vec![];
+    for (i, face, material, vertices) in &walkable {
+        let width = polygon_width(vertices);
+        let (mins, maxs) = bounds(vertices);
+        let height = maxs[2] - floor;
+
+        if !(1.0..=MAX_LEDGE_WIDTH).contains(&width) || height < MIN_LEDGE_HEIGHT {
+            continue;
+        }
+
+        // Space a player standing anywhere on the ledge would occupy
+        let standing = (
+            [
+                mins[0] - PLAYER_HALF_WIDTH,
+                mins[1] - PLAYER_HALF_WIDTH,
+                mins[2],
+            ],
+            [
+                maxs[0] + PLAYER_HALF_WIDTH,
+                maxs[1] + PLAYER_HALF_WIDTH,
+                maxs[2] + PLAYER_HEIGHT,
+            ],
+        );
+        if clips.iter().any(|clip| intersects(clip, &standing)) {
+            continue;
+        }
+
+        ledges.push(Ledge {
+            face: *i,
+            material: material.clone(),
+            center: std::array::from_fn(|axis| (mins[axis] + maxs[axis]) / 2.0),
+            width,
+            height,
+            displacement: face.dispinfo != -1,
+        });
+    }
+
+    ledges.sort_by(|a, b| b.height.total_cmp(&a.height));
+
+    Report {
+        playerclip_brushes: clips.len(),
+        walkable_faces: walkable.len(),
+        ledges,
+    }
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Playerclip brushes: {}", self.playerclip_brushes)?;
+        writeln!(w, "Walkable faces: {}", self.walkable_faces)?;
+        writeln!(w, "Unclipped ledges: {}", self.ledges.len())?;
+
+        for ledge in &self.ledges {
+            let [x, y, z] = ledge.center;
+            write!(
+                w,
+                "  face {} ({}) at {x:.0} {y:.0} {z:.0}: {:.0} units wide, {:.0} units above the lowest floor",
+                ledge.face, ledge.material, ledge.width, ledge.height
+            )?;
+            if ledge.displacement {
+                write!(w, " (displacement base face)")?;
+            }
+            writeln!(w)?;
+        }
+
+        Ok(())
+    }
+}
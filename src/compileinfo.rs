@@ -0,0 +1,260 @@
+//! What a map gives away about how it was built: the worldspawn settings the compile tools
+//! read, the passes that were run, MAP_FLAGS and any tool names left in the file.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::entities::Entity;
+use crate::{lighting, redact};
+
+/// Worldspawn keys vbsp and vrad read or write
+const WORLDSPAWN_KEYS: &[&str] = &[
+    "mapversion",
+    "skyname",
+    "detailmaterial",
+    "detailvbsp",
+    "maxpropscreenwidth",
+    "minpropscreenwidth",
+    "world_mins",
+    "world_maxs",
+];
+
+/// Lumps only vrad's HDR pass writes
+const HDR_LUMPS: &[LumpType] = &[
+    LumpType::LIGHTING_HDR,
+    LumpType::WORLD_LIGHTS_HDR,
+    LumpType::LEAF_AMBIENT_INDEX_HDR,
+    LumpType::LEAF_AMBIENT_LIGHTING_HDR,
+    LumpType::FACES_HDR,
+];
+
+/// Names of compile tools and the front ends running them, searched for in the parts of the
+/// file no lump owns
+const TOOL_NAMES: &[&str] = &[
+    "vbsp",
+    "vvis",
+    "vrad",
+    "bspzip",
+    "compilepal",
+    "compile pal",
+    "slammin",
+    "hammer",
+];
+
+/// Shortest run of printable characters taken as a string
+const MIN_STRING_LENGTH: usize = 4;
+
+pub struct Signature {
+    pub offset: u64,
+    pub text: String,
+}
+
+pub struct Report {
+    /// Values of [`WORLDSPAWN_KEYS`] the map has
+    pub worldspawn: Vec<(&'static str, String)>,
+    pub map_flags: Option<u32>,
+    /// Whether each of [`HDR_LUMPS`] has data
+    pub hdr_lumps: Vec<(LumpType, bool)>,
+    /// Clusters in the visibility data, if vvis was run
+    pub vis_clusters: Option<u32>,
+    pub ldr_lighting: bool,
+    pub hdr_lighting: bool,
+    /// Entity keys holding paths on the compile machine, with the entity's classname
+    pub paths: Vec<(String, String, String)>,
+    pub signatures: Vec<Signature>,
+}
+
+/// Strings in `data` naming a compile tool
+fn find_signatures(data: &[u8], base: u64, signatures: &mut Vec<Signature>) {
+    let mut start = 0;
+    for (i, &byte) in data.iter().chain([&0]).enumerate() {
+        if byte.is_ascii_graphic() || byte == b' ' {
+            continue;
+        }
+
+        if i - start >= MIN_STRING_LENGTH {
+            let text = String::from_utf8_lossy(&data[start..i]).into_owned();
+            let lower = text.to_ascii_lowercase();
+            if TOOL_NAMES.iter().any(|name| lower.contains(name)) {
+                signatures.push(Signature {
+                    offset: base + start as u64,
+                    text,
+                });
+            }
+        }
+        start = i + 1;
+    }
+}
+
+/// Searches the gaps between lumps, where tools that add to a map leave data, and the lump
+/// slots the engine doesn't use
+fn unowned_signatures<R: Read + Seek>(bsp: &mut BspFile<R>) -> io::Result<Vec<Signature>> {
+    let mut lumps: Vec<(u64, u64)> = (0..bsp.lump_count())
+        .map(|i| *bsp.lump_info_by_index(i))
+        .filter(|lump| lump.filelen > 0)
+        .map(|lump| {
+            (
+                lump.fileofs as u64,
+                lump.fileofs as u64 + lump.filelen as u64,
+            )
+        })
+        .collect();
+    lumps.sort();
+
+    let mut data = vec![];
+    let reader = bsp.get_mut();
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_to_end(&mut data)?;
+
+    // Everything before the first lump is the header
+    let mut signatures = vec![];
+    let mut end = lumps.first().map_or(data.len() as u64, |&(start, _)| start);
+    for &(start, lump_end) in &lumps {
+        if start > end {
+            let gap = data.get(end as usize..start as usize).unwrap_or_default();
+            find_signatures(gap, end, &mut signatures);
+        }
+        end = end.max(lump_end);
+    }
+    if let Some(tail) = data.get(end as usize..) {
+        find_signatures(tail, end, &mut signatures);
+    }
+
+    for i in 0..bsp.lump_count() {
+        let unused = LumpType::try_from(i as u32)
+            .is_ok_and(|lump| format!("{lump:?}").starts_with("UNUSED"));
+        if unused {
+            let lump = *bsp.lump_info_by_index(i);
+            let start = lump.fileofs as usize;
+            if let Some(data) = data.get(start..start + lump.filelen as usize) {
+                find_signatures(data, start as u64, &mut signatures);
+            }
+        }
+    }
+
+    Ok(signatures)
+}
+
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>, entities: &[Entity]) -> io::Result<Report> {
+    let worldspawn = entities
+        .iter()
+        .find(|e| e.get("classname") == Some("worldspawn"));
+    let worldspawn = WORLDSPAWN_KEYS
+        .iter()
+        .filter_map(|&key| Some((key, worldspawn?.get(key)?.to_string())))
+        .collect();
+
+    let mut paths = vec![];
+    for entity in entities {
+        for (key, value) in &entity.properties {
+            if redact::has_absolute_path(value) {
+                let classname = entity.get("classname").unwrap_or_default().to_string();
+                paths.push((classname, key.clone(), value.clone()));
+            }
+        }
+    }
+
+    let map_flags = bsp
+        .get_lump(LumpType::MAP_FLAGS)
+        .and_then(|data| (&data[..]).read_u32::<LittleEndian>().ok());
+    let hdr_lumps = HDR_LUMPS
+        .iter()
+        .map(|&lump| (lump, bsp.lump_info(lump).filelen > 0))
+        .collect();
+
+    // Only vvis writes visibility data, starting with the number of clusters
+    let vis_clusters = bsp
+        .get_lump(LumpType::VISIBILITY)
+        .and_then(|data| (&data[..]).read_u32::<LittleEndian>().ok());
+
+    Ok(Report {
+        worldspawn,
+        map_flags,
+        hdr_lumps,
+        vis_clusters,
+        ldr_lighting: bsp.lump_info(LumpType::LIGHTING).filelen > 0,
+        hdr_lighting: bsp.lump_info(LumpType::LIGHTING_HDR).filelen > 0,
+        paths,
+        signatures: unowned_signatures(bsp)?,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if self.worldspawn.is_empty() {
+            writeln!(w, "Worldspawn: no compile settings")?;
+        } else {
+            writeln!(w, "Worldspawn:")?;
+            for (key, value) in &self.worldspawn {
+                writeln!(w, "  {key}: {value}")?;
+            }
+        }
+
+        match self.vis_clusters {
+            Some(clusters) => writeln!(w, "vvis: run, {clusters} clusters")?,
+            None => writeln!(w, "vvis: not run, the map has no visibility data")?,
+        }
+
+        let passes: Vec<&str> = [(self.ldr_lighting, "LDR"), (self.hdr_lighting, "HDR")]
+            .into_iter()
+            .filter(|&(compiled, _)| compiled)
+            .map(|(_, name)| name)
+            .collect();
+        if passes.is_empty() {
+            writeln!(w, "vrad: not run, the map has no lighting")?;
+        } else {
+            writeln!(w, "vrad: run, {} lighting", passes.join(" and "))?;
+        }
+
+        let (present, missing): (Vec<_>, Vec<_>) =
+            self.hdr_lumps.iter().partition(|&&(_, present)| present);
+        let names = |lumps: Vec<&(LumpType, bool)>| {
+            lumps
+                .iter()
+                .map(|(lump, _)| format!("{lump:?}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (present.is_empty(), missing.is_empty()) {
+            (true, _) => writeln!(w, "HDR lumps: none")?,
+            (false, true) => writeln!(w, "HDR lumps: all ({})", names(present))?,
+            (false, false) => writeln!(
+                w,
+                "HDR lumps: {}, missing {}",
+                names(present),
+                names(missing)
+            )?,
+        }
+
+        match self.map_flags {
+            Some(flags) => {
+                let names = lighting::map_flag_names(flags);
+                if names.is_empty() {
+                    writeln!(w, "Map flags: {flags:#x}")?;
+                } else {
+                    writeln!(w, "Map flags: {flags:#x} ({})", names.join(", "))?;
+                }
+            }
+            None => writeln!(w, "Map flags: none")?,
+        }
+
+        if !self.paths.is_empty() {
+            writeln!(w, "Paths from the compile machine:")?;
+            for (classname, key, value) in &self.paths {
+                writeln!(w, "  {classname} {key}: {value}")?;
+            }
+        }
+
+        if self.signatures.is_empty() {
+            writeln!(w, "Tool signatures: none")?;
+        } else {
+            writeln!(w, "Tool signatures:")?;
+            for signature in &self.signatures {
+                writeln!(w, "  {:#x}: {}", signature.offset, signature.text)?;
+            }
+        }
+
+        Ok(())
+    }
+}
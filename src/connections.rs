@@ -0,0 +1,180 @@
+//! The graph of entity I/O connections, built from the outputs stored on each entity.
+
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::entities::{name_matches, Entity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The target names at least one entity, by targetname or classname
+    Found,
+    /// A special name such as `!activator`, resolved when the output fires
+    Dynamic,
+    Missing,
+}
+
+pub struct Connection {
+    /// Node of the entity the output is on
+    pub source: String,
+    pub output: String,
+    pub target: String,
+    pub input: String,
+    pub parameter: String,
+    pub delay: f32,
+    /// Times the output can fire, or -1 for unlimited
+    pub times: i32,
+    pub resolution: Resolution,
+    /// Nodes of the entities the target resolved to
+    pub targets: Vec<String>,
+}
+
+/// Name an entity appears under in the graph. Entities sharing a targetname share a node.
+fn node_name(index: usize, entity: &Entity) -> String {
+    match entity.get("targetname").filter(|name| !name.is_empty()) {
+        Some(name) => name.to_string(),
+        None => format!(
+            "{}#{index}",
+            entity.get("classname").unwrap_or("<no classname>")
+        ),
+    }
+}
+
+/// Nodes of the entities `target` names. Outputs can also target every entity of a class.
+fn resolve(entities: &[Entity], target: &str) -> Vec<String> {
+    let mut nodes = vec![];
+    for (i, entity) in entities.iter().enumerate() {
+        let matches = entity
+            .get("targetname")
+            .is_some_and(|name| name_matches(target, name))
+            || entity
+                .get("classname")
+                .is_some_and(|class| name_matches(target, class));
+
+        let node = node_name(i, entity);
+        if matches && !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+
+    nodes
+}
+
+pub fn connections(entities: &[Entity]) -> Vec<Connection> {
+    let mut connections = vec![];
+    for (i, entity) in entities.iter().enumerate() {
+        for output in entity.outputs() {
+            let targets = resolve(entities, output.target);
+            let resolution = if output.target.starts_with('!') {
+                Resolution::Dynamic
+            } else if targets.is_empty() {
+                Resolution::Missing
+            } else {
+                Resolution::Found
+            };
+
+            connections.push(Connection {
+                source: node_name(i, entity),
+                output: output.name.to_string(),
+                target: output.target.to_string(),
+                input: output.input.to_string(),
+                parameter: output.parameter.to_string(),
+                delay: output.delay,
+                times: output.times,
+                resolution,
+                targets,
+            });
+        }
+    }
+
+    connections
+}
+
+/// Quotes a DOT identifier
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Connection {
+    fn describe_input(&self) -> String {
+        let mut input = self.input.clone();
+        if !self.parameter.is_empty() {
+            input += &format!("({})", self.parameter);
+        }
+        if self.delay != 0.0 {
+            input += &format!(" after {}s", self.delay);
+        }
+        if self.times > 0 {
+            input += &format!(" x{}", self.times);
+        }
+        input
+    }
+}
+
+pub fn write_text<W: Write>(connections: &[Connection], w: &mut W) -> io::Result<()> {
+    for c in connections {
+        writeln!(
+            w,
+            "{} {} -> {} {}",
+            c.source,
+            c.output,
+            c.target,
+            c.describe_input()
+        )?;
+    }
+
+    writeln!(w, "{} connections", connections.len())?;
+    for c in connections
+        .iter()
+        .filter(|c| c.resolution == Resolution::Missing)
+    {
+        writeln!(
+            w,
+            "warning: {} {} targets nonexistent entity {}",
+            c.source, c.output, c.target
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Writes the connections as a Graphviz graph. Missing targets are drawn in red and special
+/// targets dashed.
+pub fn write_dot<W: Write>(connections: &[Connection], w: &mut W) -> io::Result<()> {
+    writeln!(w, "digraph io {{")?;
+    writeln!(w, "  rankdir=LR;")?;
+
+    let mut targets = BTreeSet::new();
+    for c in connections {
+        if c.resolution != Resolution::Found && targets.insert(&c.target) {
+            let style = match c.resolution {
+                Resolution::Missing => "color=red, fontcolor=red",
+                _ => "style=dashed",
+            };
+            writeln!(w, "  {} [{style}];", quote(&c.target))?;
+        }
+    }
+
+    for c in connections {
+        let label = quote(&format!("{} -> {}", c.output, c.describe_input()));
+        let source = quote(&c.source);
+
+        if c.resolution == Resolution::Found {
+            for target in &c.targets {
+                writeln!(w, "  {source} -> {} [label={label}];", quote(target))?;
+            }
+        } else {
+            let color = match c.resolution {
+                Resolution::Missing => ", color=red",
+                _ => "",
+            };
+            writeln!(
+                w,
+                "  {source} -> {} [label={label}{color}];",
+                quote(&c.target)
+            )?;
+        }
+    }
+
+    writeln!(w, "}}")
+}
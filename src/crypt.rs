@@ -0,0 +1,88 @@
+//! Tactical Intervention ships its maps XOR encrypted with a per-map 32 byte key. The key
+//! covers the whole file, and can be recovered from the header since the directory entries of
+//! the unused lumps 23-25 are zeroed in every unencrypted map.
+
+use std::borrow::Cow;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+const KEY_LENGTH: usize = 32;
+const KEY_OFFSET: u64 = 384;
+
+/// Reader that transparently decrypts Tactical Intervention maps, and passes every other file
+/// through unchanged.
+pub struct XorReader<R> {
+    inner: R,
+    key: Option<[u8; KEY_LENGTH]>,
+    pos: u64,
+}
+
+impl<R: Read + Seek> XorReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let key = detect_key(&mut inner)?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        Ok(Self { inner, key, pos: 0 })
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// The reader of the file as it is stored, still encrypted
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+}
+
+/// Decrypts a map in memory, borrowing it unchanged if it isn't encrypted
+pub fn decrypt(data: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    let mut reader = XorReader::new(Cursor::new(data))?;
+    if !reader.is_encrypted() {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    let mut decrypted = Vec::with_capacity(data.len());
+    reader.read_to_end(&mut decrypted)?;
+    Ok(Cow::Owned(decrypted))
+}
+
+fn detect_key<R: Read + Seek>(reader: &mut R) -> io::Result<Option<[u8; KEY_LENGTH]>> {
+    let mut ident = [0u8; 4];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut ident)?;
+
+    if &ident == b"VBSP" || &ident == b"rBSP" {
+        return Ok(None);
+    }
+
+    let mut key = [0u8; KEY_LENGTH];
+    reader.seek(SeekFrom::Start(KEY_OFFSET))?;
+    if reader.read_exact(&mut key).is_err() {
+        return Ok(None);
+    }
+
+    let decrypted: Vec<u8> = ident.iter().zip(key).map(|(c, k)| c ^ k).collect();
+    Ok((decrypted == b"VBSP").then_some(key))
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Some(key) = &self.key {
+            for (i, c) in buf[..read].iter_mut().enumerate() {
+                *c ^= key[((self.pos + i as u64) % KEY_LENGTH as u64) as usize];
+            }
+        }
+
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for XorReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
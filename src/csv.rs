@@ -0,0 +1,57 @@
+use std::io::{self, Write};
+
+fn write_field<W: Write>(w: &mut W, field: &str) -> io::Result<()> {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(w, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        write!(w, "{field}")
+    }
+}
+
+pub fn write_record<W: Write, S: AsRef<str>>(w: &mut W, record: &[S]) -> io::Result<()> {
+    for (i, field) in record.iter().enumerate() {
+        if i != 0 {
+            write!(w, ",")?;
+        }
+        write_field(w, field.as_ref())?;
+    }
+
+    writeln!(w)
+}
+
+/// Parses RFC 4180 style CSV, returning `None` if a quoted field is never closed.
+pub fn parse(text: &str) -> Option<Vec<Vec<String>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next()? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            },
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Some(records)
+}
@@ -0,0 +1,119 @@
+//! Lists the map's cubemap samples and whether `buildcubemaps` packed their textures.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::assets::normalize;
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, CubemapSample};
+use crate::pak;
+
+/// Size the engine builds cubemaps with when a sample doesn't specify one
+const DEFAULT_SIZE: u32 = 32;
+
+pub struct Cubemap {
+    pub sample: CubemapSample,
+    pub ldr: bool,
+    pub hdr: bool,
+}
+
+impl Cubemap {
+    /// Width of each face in pixels
+    pub fn size(&self) -> u32 {
+        match self.sample.size {
+            1.. => 1 << (self.sample.size - 1).min(31),
+            _ => DEFAULT_SIZE,
+        }
+    }
+}
+
+pub struct Report {
+    pub cubemaps: Vec<Cubemap>,
+    /// Whether the map has HDR lighting, which needs HDR cubemaps too
+    pub has_hdr: bool,
+}
+
+/// `map_name` is the map's file name without extension, which names the directory
+/// buildcubemaps packs the textures into.
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>, map_name: &str) -> Option<Report> {
+    let samples: Vec<CubemapSample> = lumps::read_array(bsp, LumpType::CUBEMAPS)?;
+    let packed: Vec<String> = match bsp.get_lump(LumpType::PAKFILE) {
+        Some(data) => pak::entries(&data)
+            .ok()?
+            .iter()
+            .map(|entry| normalize(&entry.name))
+            .collect(),
+        None => vec![],
+    };
+
+    let dir = normalize(&format!("materials/maps/{map_name}"));
+    let cubemaps = samples
+        .into_iter()
+        .map(|sample| {
+            let [x, y, z] = sample.origin;
+            let base = format!("{dir}/c{x}_{y}_{z}");
+            Cubemap {
+                sample,
+                ldr: packed.contains(&format!("{base}.vtf")),
+                hdr: packed.contains(&format!("{base}.hdr.vtf")),
+            }
+        })
+        .collect();
+
+    Some(Report {
+        cubemaps,
+        has_hdr: bsp.lump_info(LumpType::LIGHTING_HDR).filelen != 0,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Cubemaps: {}", self.cubemaps.len())?;
+        for cubemap in &self.cubemaps {
+            let [x, y, z] = cubemap.sample.origin;
+            write!(w, "  ({x}, {y}, {z}) {0}x{0}", cubemap.size())?;
+
+            let built: Vec<&str> = [("LDR", cubemap.ldr), ("HDR", cubemap.hdr)]
+                .into_iter()
+                .filter_map(|(name, built)| built.then_some(name))
+                .collect();
+            if built.is_empty() {
+                writeln!(w, " not built")?;
+            } else {
+                writeln!(w, " built: {}", built.join(", "))?;
+            }
+        }
+
+        if self.cubemaps.is_empty() {
+            return Ok(());
+        }
+
+        let ldr = self.cubemaps.iter().filter(|c| c.ldr).count();
+        let hdr = self.cubemaps.iter().filter(|c| c.hdr).count();
+        if ldr == 0 && hdr == 0 {
+            writeln!(
+                w,
+                "warning: no cubemaps have been built, run buildcubemaps before shipping"
+            )?;
+            return Ok(());
+        }
+
+        if ldr < self.cubemaps.len() {
+            writeln!(
+                w,
+                "warning: {} of {} LDR cubemaps are missing",
+                self.cubemaps.len() - ldr,
+                self.cubemaps.len()
+            )?;
+        }
+        if self.has_hdr && hdr < self.cubemaps.len() {
+            writeln!(
+                w,
+                "warning: {} of {} HDR cubemaps are missing, run buildcubemaps with HDR enabled",
+                self.cubemaps.len() - hdr,
+                self.cubemaps.len()
+            )?;
+        }
+
+        Ok(())
+    }
+}
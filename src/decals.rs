@@ -0,0 +1,122 @@
+//! Materials used by decals and overlays, kept apart from brush face materials because they're
+//! the ones most often left out when packing a map.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Read, Seek, Write};
+
+use crate::assets::{normalize, AssetStore};
+use crate::bsp::BspFile;
+use crate::entities::Entity;
+use crate::lumps::World;
+use crate::overlays;
+
+#[derive(Default)]
+pub struct Usage {
+    pub infodecals: usize,
+    pub overlays: usize,
+    pub water_overlays: usize,
+    /// Whether brush faces use the material too, so it's packed along with them anyway
+    pub on_faces: bool,
+    /// Whether the material exists, if checked
+    pub found: Option<bool>,
+}
+
+/// Decal and overlay materials by name, with what uses them
+pub fn collect<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    entities: &[Entity],
+) -> Option<BTreeMap<String, Usage>> {
+    let mut materials: BTreeMap<String, Usage> = BTreeMap::new();
+
+    let decals = entities
+        .iter()
+        .filter(|e| e.get("classname") == Some("infodecal"))
+        .filter_map(|e| e.get("texture"))
+        .filter(|texture| !texture.is_empty());
+    for texture in decals {
+        materials.entry(texture.to_string()).or_default().infodecals += 1;
+    }
+
+    for entry in overlays::read(bsp)? {
+        let Some(material) = entry.material else {
+            continue;
+        };
+        let usage = materials.entry(material).or_default();
+        match entry.water {
+            true => usage.water_overlays += 1,
+            false => usage.overlays += 1,
+        }
+    }
+
+    // Overlay texinfos aren't used by faces, so only faces count here
+    let world = World::read(bsp)?;
+    let face_materials: HashSet<String> = world
+        .faces
+        .iter()
+        .filter_map(|face| world.texinfo_name(face.texinfo))
+        .map(normalize)
+        .collect();
+    for (name, usage) in &mut materials {
+        usage.on_faces = face_materials.contains(&normalize(name));
+    }
+
+    Some(materials)
+}
+
+/// Marks whether each material can be found in the pakfile or game directories.
+pub fn check_materials(materials: &mut BTreeMap<String, Usage>, store: &AssetStore) {
+    for (name, usage) in materials {
+        usage.found = Some(store.exists(&store.matching.material(name)));
+    }
+}
+
+pub fn print<W: Write>(materials: &BTreeMap<String, Usage>, w: &mut W) -> io::Result<()> {
+    let infodecals: usize = materials.values().map(|u| u.infodecals).sum();
+    let overlays: usize = materials
+        .values()
+        .map(|u| u.overlays + u.water_overlays)
+        .sum();
+    writeln!(
+        w,
+        "Decal materials: {} ({infodecals} infodecals, {overlays} overlays)",
+        materials.len()
+    )?;
+    if materials.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(
+        w,
+        "  {:>9} {:>8} {:>6}  material",
+        "infodecal", "overlay", "water"
+    )?;
+    for (name, usage) in materials {
+        let mut notes = vec![];
+        if usage.on_faces {
+            notes.push("also on brush faces");
+        }
+        if usage.found == Some(false) {
+            notes.push("missing");
+        }
+        let notes = match notes.is_empty() {
+            true => String::new(),
+            false => format!(" ({})", notes.join(", ")),
+        };
+
+        writeln!(
+            w,
+            "  {:>9} {:>8} {:>6}  {name}{notes}",
+            usage.infodecals, usage.overlays, usage.water_overlays
+        )?;
+    }
+
+    for (name, usage) in materials.iter().filter(|(_, u)| u.found == Some(false)) {
+        let users = usage.infodecals + usage.overlays + usage.water_overlays;
+        writeln!(
+            w,
+            "warning: decal material {name} is missing, {users} decals or overlays will show as missing textures"
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,228 @@
+//! Reads the header of a Source demo (.dem) and the map checksum from its signon data, so a
+//! demo can be matched with the map it was recorded on.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{Cursor, Read};
+
+const MAGIC: &[u8; 8] = b"HL2DEMO\0";
+const MAX_PATH: usize = 260;
+
+/// Demo commands. Signon and packet commands carry network messages.
+const DEM_SIGNON: u8 = 1;
+const DEM_PACKET: u8 = 2;
+const DEM_SYNCTICK: u8 = 3;
+const DEM_CONSOLECMD: u8 = 4;
+const DEM_USERCMD: u8 = 5;
+const DEM_DATATABLES: u8 = 6;
+const DEM_STRINGTABLES: u8 = 9;
+/// Size of the view origins and angles stored before each packet
+const CMD_INFO_SIZE: usize = 76;
+/// Demo protocol that added a player slot to each command. Its messages are protobufs, which
+/// aren't decoded.
+const PROTOBUF_DEMO_PROTOCOL: i32 = 4;
+/// Server info protocol after which the map CRC was replaced by an MD5
+const MD5_PROTOCOL: u16 = 17;
+
+/// Protocol after which a message type took 6 bits instead of 5, when more messages were added
+const WIDE_MESSAGE_PROTOCOL: i32 = 14;
+
+const NET_NOP: u32 = 0;
+const NET_DISCONNECT: u32 = 1;
+const NET_FILE: u32 = 2;
+const NET_TICK: u32 = 3;
+const NET_STRINGCMD: u32 = 4;
+const NET_SETCONVAR: u32 = 5;
+const NET_SIGNONSTATE: u32 = 6;
+const SVC_PRINT: u32 = 7;
+const SVC_SERVERINFO: u32 = 8;
+
+pub struct Header {
+    pub demo_protocol: i32,
+    pub network_protocol: i32,
+    pub server_name: String,
+    pub client_name: String,
+    pub map_name: String,
+    pub game_dir: String,
+    /// Length of the demo in seconds
+    pub playback_time: f32,
+    pub ticks: i32,
+}
+
+/// Checksum of the map the server was running, as sent in its server info message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapChecksum {
+    Crc(u32),
+    Md5([u8; 16]),
+}
+
+pub struct Demo {
+    pub header: Header,
+    /// None if the signon data couldn't be decoded
+    pub checksum: Option<MapChecksum>,
+}
+
+fn read_string(reader: &mut Cursor<&[u8]>) -> Option<String> {
+    let mut buf = [0; MAX_PATH];
+    reader.read_exact(&mut buf).ok()?;
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(MAX_PATH);
+
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+pub fn parse(data: &[u8]) -> Option<Demo> {
+    if data.get(..8)? != MAGIC {
+        return None;
+    }
+
+    let mut reader = Cursor::new(data);
+    reader.set_position(8);
+    let header = Header {
+        demo_protocol: reader.read_i32::<LittleEndian>().ok()?,
+        network_protocol: reader.read_i32::<LittleEndian>().ok()?,
+        server_name: read_string(&mut reader)?,
+        client_name: read_string(&mut reader)?,
+        map_name: read_string(&mut reader)?,
+        game_dir: read_string(&mut reader)?,
+        playback_time: reader.read_f32::<LittleEndian>().ok()?,
+        ticks: reader.read_i32::<LittleEndian>().ok()?,
+    };
+    // Frame count and signon length
+    reader.set_position(reader.position() + 8);
+
+    let checksum = if header.demo_protocol < PROTOBUF_DEMO_PROTOCOL {
+        find_checksum(&mut reader, header.network_protocol)
+    } else {
+        None
+    };
+
+    Some(Demo { header, checksum })
+}
+
+/// Walks the demo's commands until a packet holding the server info message
+fn find_checksum(reader: &mut Cursor<&[u8]>, network_protocol: i32) -> Option<MapChecksum> {
+    let type_bits = if network_protocol > WIDE_MESSAGE_PROTOCOL {
+        6
+    } else {
+        5
+    };
+
+    loop {
+        let command = reader.read_u8().ok()?;
+        let _tick = reader.read_i32::<LittleEndian>().ok()?;
+
+        match command {
+            DEM_SIGNON | DEM_PACKET => {
+                reader.set_position(reader.position() + CMD_INFO_SIZE as u64 + 8);
+                let data = read_chunk(reader)?;
+                if let Some(checksum) = server_info_checksum(&data, type_bits) {
+                    return Some(checksum);
+                }
+            }
+            DEM_SYNCTICK => {}
+            DEM_CONSOLECMD | DEM_DATATABLES | DEM_STRINGTABLES => {
+                read_chunk(reader)?;
+            }
+            DEM_USERCMD => {
+                reader.read_i32::<LittleEndian>().ok()?;
+                read_chunk(reader)?;
+            }
+            // The stop command, or something we can't skip
+            _ => return None,
+        }
+    }
+}
+
+fn read_chunk(reader: &mut Cursor<&[u8]>) -> Option<Vec<u8>> {
+    let len = usize::try_from(reader.read_i32::<LittleEndian>().ok()?).ok()?;
+    let start = reader.position() as usize;
+    let data = reader
+        .get_ref()
+        .get(start..start.checked_add(len)?)?
+        .to_vec();
+    reader.set_position((start + len) as u64);
+
+    Some(data)
+}
+
+/// Reads values packed least significant bit first, as the engine's bf_read does
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl BitReader<'_> {
+    /// Reads up to 32 bits
+    fn bits(&mut self, count: usize) -> Option<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self.data.get(self.pos / 8)?;
+            value |= ((*byte as u32 >> (self.pos % 8)) & 1) << i;
+            self.pos += 1;
+        }
+        Some(value)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut bytes = vec![];
+        loop {
+            match self.bits(8)? as u8 {
+                0 => return Some(String::from_utf8_lossy(&bytes).into_owned()),
+                c => bytes.push(c),
+            }
+        }
+    }
+}
+
+/// Skips the messages servers send before their server info. Any other message means the
+/// packet can't be parsed without knowing every message's layout.
+fn server_info_checksum(data: &[u8], type_bits: usize) -> Option<MapChecksum> {
+    let mut bits = BitReader { data, pos: 0 };
+
+    loop {
+        match bits.bits(type_bits)? {
+            NET_NOP => {}
+            NET_DISCONNECT | NET_STRINGCMD | SVC_PRINT => {
+                bits.string()?;
+            }
+            NET_FILE => {
+                bits.bits(32)?;
+                bits.string()?;
+                bits.bits(1)?;
+            }
+            NET_TICK => {
+                bits.bits(32)?;
+                bits.bits(16)?;
+                bits.bits(16)?;
+            }
+            NET_SETCONVAR => {
+                for _ in 0..bits.bits(8)? {
+                    bits.string()?;
+                    bits.string()?;
+                }
+            }
+            NET_SIGNONSTATE => {
+                bits.bits(8)?;
+                bits.bits(32)?;
+            }
+            SVC_SERVERINFO => {
+                let protocol = bits.bits(16)? as u16;
+                // Server count, HLTV and dedicated flags, client CRC and class count
+                bits.bits(32)?;
+                bits.bits(2)?;
+                bits.bits(32)?;
+                bits.bits(16)?;
+
+                return if protocol > MD5_PROTOCOL {
+                    let mut md5 = [0; 16];
+                    for byte in &mut md5 {
+                        *byte = bits.bits(8)? as u8;
+                    }
+                    Some(MapChecksum::Md5(md5))
+                } else {
+                    Some(MapChecksum::Crc(bits.bits(32)?))
+                };
+            }
+            _ => return None,
+        }
+    }
+}
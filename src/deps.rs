@@ -0,0 +1,352 @@
+//! Asset dependency graph of a map: map → materials → textures, and map → models → materials.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::{self, Write},
+};
+
+use crate::assets::{AssetStore, PathMatching};
+use crate::entities::Entity;
+use crate::json::Json;
+use crate::mdl::Mdl;
+use crate::vmt::Vmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    Map,
+    Material,
+    Texture,
+    Model,
+}
+
+impl AssetKind {
+    fn name(self) -> &'static str {
+        match self {
+            AssetKind::Map => "map",
+            AssetKind::Material => "material",
+            AssetKind::Texture => "texture",
+            AssetKind::Model => "model",
+        }
+    }
+}
+
+pub struct Node {
+    pub kind: AssetKind,
+    /// Whether the asset exists in the pakfile or a content directory
+    pub found: bool,
+}
+
+#[derive(Default)]
+pub struct Graph {
+    pub root: String,
+    pub nodes: BTreeMap<String, Node>,
+    pub edges: BTreeMap<String, BTreeSet<String>>,
+    matching: PathMatching,
+}
+
+const SKYBOX_SIDES: [&str; 6] = ["rt", "lf", "bk", "ft", "up", "dn"];
+
+/// Assets referenced directly by the map's brush faces, entities and static props.
+pub fn map_references(
+    texture_names: &[String],
+    entities: &[Entity],
+    static_prop_models: &[String],
+    matching: PathMatching,
+) -> Vec<(String, AssetKind)> {
+    let mut refs = vec![];
+
+    for name in texture_names {
+        refs.push((matching.material(name), AssetKind::Material));
+    }
+
+    for entity in entities {
+        for (key, value) in &entity.properties {
+            if value.is_empty() {
+                continue;
+            }
+
+            match key.to_ascii_lowercase().as_str() {
+                "model" if value.starts_with('*') => {}
+                "model" => {
+                    let extension = value.rsplit('.').next().unwrap_or_default();
+                    if extension.eq_ignore_ascii_case("mdl") {
+                        refs.push((matching.path(value), AssetKind::Model));
+                    } else if extension.eq_ignore_ascii_case("vmt") {
+                        refs.push((matching.material(value), AssetKind::Material));
+                    } else if extension.eq_ignore_ascii_case("spr") {
+                        // Sprites are materials with the same name
+                        let name = &value[..value.len() - 4];
+                        refs.push((matching.material(name), AssetKind::Material));
+                    }
+                }
+                "texture" | "material" | "ropematerial" | "detailmaterial" => {
+                    refs.push((matching.material(value), AssetKind::Material));
+                }
+                "skyname" => {
+                    for side in SKYBOX_SIDES {
+                        refs.push((
+                            matching.material(&format!("skybox/{value}{side}")),
+                            AssetKind::Material,
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for model in static_prop_models {
+        refs.push((matching.path(model), AssetKind::Model));
+    }
+
+    refs
+}
+
+impl Graph {
+    fn add_node(&mut self, path: &str, kind: AssetKind, store: &AssetStore) -> bool {
+        if self.nodes.contains_key(path) {
+            return false;
+        }
+
+        let found = kind == AssetKind::Map || store.exists(path);
+        self.nodes.insert(path.to_string(), Node { kind, found });
+        true
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str) {
+        self.edges
+            .entry(from.to_string())
+            .or_default()
+            .insert(to.to_string());
+    }
+
+    /// Builds the full graph, following materials and models through the asset store.
+    pub fn build(map: &str, refs: Vec<(String, AssetKind)>, store: &mut AssetStore) -> Self {
+        let mut graph = Graph {
+            root: map.to_string(),
+            matching: store.matching,
+            ..Default::default()
+        };
+        graph.add_node(map, AssetKind::Map, store);
+
+        let mut queue = VecDeque::new();
+        for (path, kind) in refs {
+            graph.add_edge(map, &path);
+            if graph.add_node(&path, kind, store) {
+                queue.push_back((path, kind));
+            }
+        }
+
+        while let Some((path, kind)) = queue.pop_front() {
+            let children = match kind {
+                AssetKind::Material => store
+                    .read(&path)
+                    .and_then(|data| Vmt::parse(&String::from_utf8_lossy(&data)))
+                    .map(|vmt| {
+                        let textures = vmt
+                            .textures(store.matching)
+                            .into_iter()
+                            .map(|t| (t, AssetKind::Texture));
+                        let materials = vmt
+                            .materials(store.matching)
+                            .into_iter()
+                            .map(|m| (m, AssetKind::Material));
+                        textures.chain(materials).collect()
+                    })
+                    .unwrap_or_default(),
+                AssetKind::Model => store
+                    .read(&path)
+                    .and_then(|data| Mdl::parse(&data))
+                    .map(|mdl| {
+                        mdl.material_candidates(store.matching)
+                            .into_iter()
+                            .filter_map(|candidates| {
+                                let found = candidates.iter().find(|c| store.exists(c));
+                                found.or(candidates.first()).cloned()
+                            })
+                            .map(|m| (m, AssetKind::Material))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                AssetKind::Map | AssetKind::Texture => vec![],
+            };
+
+            for (child, kind) in children {
+                graph.add_edge(&path, &child);
+                if graph.add_node(&child, kind, store) {
+                    queue.push_back((child, kind));
+                }
+            }
+        }
+
+        graph
+    }
+
+    fn children(&self, node: &str) -> impl Iterator<Item = &String> {
+        self.edges.get(node).into_iter().flatten()
+    }
+
+    /// Keeps only nodes within `max_depth` edges of `root`, and if `target` is given, only
+    /// nodes on a path to it.
+    pub fn filter(
+        &self,
+        root: Option<&str>,
+        max_depth: Option<usize>,
+        target: Option<&str>,
+    ) -> Self {
+        let root = root
+            .map(|root| self.matching.path(root))
+            .unwrap_or_else(|| self.root.clone());
+
+        let mut keep = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        if self.nodes.contains_key(&root) {
+            keep.insert(root.clone());
+            queue.push_back((root.clone(), 0));
+        }
+        while let Some((node, depth)) = queue.pop_front() {
+            if max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            for child in self.children(&node) {
+                if keep.insert(child.clone()) {
+                    queue.push_back((child.clone(), depth + 1));
+                }
+            }
+        }
+
+        if let Some(target) = target {
+            let mut parents: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+            for (from, to) in &self.edges {
+                for to in to {
+                    parents.entry(to).or_default().push(from);
+                }
+            }
+
+            let target = self.matching.path(target);
+            let mut leads_to = BTreeSet::new();
+            let mut stack = vec![target.as_str()];
+            while let Some(node) = stack.pop() {
+                if leads_to.insert(node.to_string()) {
+                    stack.extend(parents.get(node).into_iter().flatten());
+                }
+            }
+
+            keep.retain(|node| leads_to.contains(node));
+        }
+
+        let mut graph = Graph {
+            root,
+            matching: self.matching,
+            ..Default::default()
+        };
+        for name in &keep {
+            let node = &self.nodes[name];
+            graph.nodes.insert(
+                name.clone(),
+                Node {
+                    kind: node.kind,
+                    found: node.found,
+                },
+            );
+            for child in self.children(name).filter(|c| keep.contains(*c)) {
+                graph.add_edge(name, child);
+            }
+        }
+
+        graph
+    }
+
+    pub fn write_tree<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        fn visit<W: Write>(
+            graph: &Graph,
+            w: &mut W,
+            node: &str,
+            depth: usize,
+            path: &mut Vec<String>,
+        ) -> io::Result<()> {
+            let missing = if graph.nodes[node].found {
+                ""
+            } else {
+                " (missing)"
+            };
+            writeln!(w, "{}{node}{missing}", "  ".repeat(depth))?;
+
+            if path.iter().any(|p| p == node) {
+                return Ok(());
+            }
+
+            path.push(node.to_string());
+            for child in graph.children(node) {
+                visit(graph, w, child, depth + 1, path)?;
+            }
+            path.pop();
+
+            Ok(())
+        }
+
+        if self.nodes.contains_key(&self.root) {
+            visit(self, w, &self.root, 0, &mut vec![])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_dot<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "digraph dependencies {{")?;
+        writeln!(w, "  rankdir=LR;")?;
+
+        for (name, node) in &self.nodes {
+            let shape = match node.kind {
+                AssetKind::Map => "doubleoctagon",
+                AssetKind::Material => "box",
+                AssetKind::Texture => "note",
+                AssetKind::Model => "component",
+            };
+            let style = if node.found { "solid" } else { "dashed" };
+            writeln!(w, "  \"{name}\" [shape={shape}, style={style}];")?;
+        }
+
+        for (from, to) in &self.edges {
+            for to in to {
+                writeln!(w, "  \"{from}\" -> \"{to}\";")?;
+            }
+        }
+
+        writeln!(w, "}}")
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object([
+            ("root", self.root.as_str().into()),
+            (
+                "nodes",
+                Json::Array(
+                    self.nodes
+                        .iter()
+                        .map(|(name, node)| {
+                            Json::object([
+                                ("name", name.as_str().into()),
+                                ("kind", node.kind.name().into()),
+                                ("found", node.found.into()),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "edges",
+                Json::Array(
+                    self.edges
+                        .iter()
+                        .flat_map(|(from, to)| {
+                            to.iter().map(move |to| {
+                                Json::Array(vec![from.as_str().into(), to.as_str().into()])
+                            })
+                        })
+                        .collect(),
+                ),
+            ),
+        ])
+    }
+}
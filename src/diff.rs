@@ -0,0 +1,358 @@
+//! Lump, pakfile and entity level comparison of two maps.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::{self, Read, Seek, Write},
+};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::entities::{self, Entity};
+use crate::json::Json;
+use crate::pak;
+
+#[derive(Clone, Copy)]
+struct LumpSummary {
+    /// Size of the decompressed lump
+    size: usize,
+    crc32: u32,
+}
+
+/// Everything about a map that takes part in the comparison
+pub struct Snapshot {
+    version: u32,
+    map_revision: u32,
+    lumps: Vec<(String, Option<LumpSummary>)>,
+    files: BTreeMap<String, u32>,
+    entities: Vec<Entity>,
+}
+
+impl Snapshot {
+    pub fn new<R: Read + Seek>(bsp: &mut BspFile<R>) -> Self {
+        let format = bsp.format();
+        let lumps = (0..bsp.lump_count())
+            .map(|i| {
+                let summary = bsp.get_lump_by_index(i).map(|data| LumpSummary {
+                    size: data.len(),
+                    crc32: crc32fast::hash(&data),
+                });
+
+                (format.lump_name(i), summary)
+            })
+            .collect();
+
+        let files = bsp
+            .get_lump(LumpType::PAKFILE)
+            .and_then(|data| pak::entries(&data).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| (entry.name, entry.crc32))
+            .collect();
+
+        let entities = bsp
+            .get_lump(LumpType::ENTITIES)
+            .and_then(|data| entities::parse(&data).ok())
+            .unwrap_or_default();
+
+        Self {
+            version: bsp.version(),
+            map_revision: bsp.map_revision(),
+            lumps,
+            files,
+            entities,
+        }
+    }
+}
+
+pub struct LumpChange {
+    name: String,
+    old: Option<LumpSummary>,
+    new: Option<LumpSummary>,
+}
+
+pub struct EntityChange {
+    description: String,
+    removed: Vec<(String, String)>,
+    added: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct Diff {
+    version: Option<(u32, u32)>,
+    map_revision: Option<(u32, u32)>,
+    lumps: Vec<LumpChange>,
+    files_added: Vec<String>,
+    files_removed: Vec<String>,
+    files_changed: Vec<String>,
+    entities_added: Vec<String>,
+    entities_removed: Vec<String>,
+    entities_changed: Vec<EntityChange>,
+}
+
+/// Key used to pair up entities between the two maps. Hammer IDs are unique and survive
+/// recompiles, everything else falls back to the identifying keyvalues.
+fn entity_key(entity: &Entity) -> String {
+    if let Some(id) = entity.get("hammerid") {
+        return format!("hammerid {id}");
+    }
+
+    ["classname", "targetname", "model", "origin"]
+        .iter()
+        .map(|key| entity.get(key).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+fn describe(entity: &Entity) -> String {
+    let mut description = entity
+        .get("classname")
+        .unwrap_or("<no classname>")
+        .to_string();
+
+    if let Some(name) = entity.get("targetname") {
+        description.push_str(&format!(" \"{name}\""));
+    }
+    if let Some(id) = entity.get("hammerid") {
+        description.push_str(&format!(" (hammerid {id})"));
+    }
+    if let Some(origin) = entity.get("origin") {
+        description.push_str(&format!(" at {origin}"));
+    }
+
+    description
+}
+
+fn diff_entities(old: &Entity, new: &Entity) -> Option<EntityChange> {
+    let old_props: BTreeSet<_> = old.properties.iter().cloned().collect();
+    let new_props: BTreeSet<_> = new.properties.iter().cloned().collect();
+
+    let removed: Vec<_> = old_props.difference(&new_props).cloned().collect();
+    let added: Vec<_> = new_props.difference(&old_props).cloned().collect();
+
+    (!removed.is_empty() || !added.is_empty()).then(|| EntityChange {
+        description: describe(new),
+        removed,
+        added,
+    })
+}
+
+impl Diff {
+    pub fn new(a: &Snapshot, b: &Snapshot) -> Self {
+        let mut diff = Diff::default();
+
+        if a.version != b.version {
+            diff.version = Some((a.version, b.version));
+        }
+        if a.map_revision != b.map_revision {
+            diff.map_revision = Some((a.map_revision, b.map_revision));
+        }
+
+        let mut lumps_b: BTreeMap<_, _> = b.lumps.iter().map(|(n, l)| (n, l)).collect();
+        for (name, old) in &a.lumps {
+            let new = lumps_b.remove(name).and_then(|l| l.as_ref());
+            let old = old.as_ref();
+
+            let same = match (old, new) {
+                (Some(old), Some(new)) => old.size == new.size && old.crc32 == new.crc32,
+                (None, None) => true,
+                _ => false,
+            };
+
+            if !same {
+                diff.lumps.push(LumpChange {
+                    name: name.clone(),
+                    old: old.copied(),
+                    new: new.copied(),
+                });
+            }
+        }
+
+        for (name, crc) in &a.files {
+            match b.files.get(name) {
+                None => diff.files_removed.push(name.clone()),
+                Some(new) if new != crc => diff.files_changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        for name in b.files.keys() {
+            if !a.files.contains_key(name) {
+                diff.files_added.push(name.clone());
+            }
+        }
+
+        let mut entities_b: BTreeMap<String, Vec<&Entity>> = BTreeMap::new();
+        for entity in &b.entities {
+            entities_b
+                .entry(entity_key(entity))
+                .or_default()
+                .push(entity);
+        }
+
+        for old in &a.entities {
+            let candidates = entities_b.entry(entity_key(old)).or_default();
+            if candidates.is_empty() {
+                diff.entities_removed.push(describe(old));
+                continue;
+            }
+
+            let new = candidates.remove(0);
+            diff.entities_changed.extend(diff_entities(old, new));
+        }
+
+        for entity in entities_b.into_values().flatten() {
+            diff.entities_added.push(describe(entity));
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.version.is_none()
+            && self.map_revision.is_none()
+            && self.lumps.is_empty()
+            && self.files_added.is_empty()
+            && self.files_removed.is_empty()
+            && self.files_changed.is_empty()
+            && self.entities_added.is_empty()
+            && self.entities_removed.is_empty()
+            && self.entities_changed.is_empty()
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        if let Some((a, b)) = self.version {
+            writeln!(w, "Version: {a} -> {b}")?;
+        }
+        if let Some((a, b)) = self.map_revision {
+            writeln!(w, "Revision: {a} -> {b}")?;
+        }
+
+        if !self.lumps.is_empty() {
+            writeln!(w, "Lumps:")?;
+            for lump in &self.lumps {
+                let fmt = |l: &Option<LumpSummary>| match l {
+                    Some(l) => format!("{} bytes, crc32 {:08x}", l.size, l.crc32),
+                    None => "missing".to_string(),
+                };
+                writeln!(
+                    w,
+                    "  {}: {} -> {}",
+                    lump.name,
+                    fmt(&lump.old),
+                    fmt(&lump.new)
+                )?;
+            }
+        }
+
+        if !(self.files_added.is_empty()
+            && self.files_removed.is_empty()
+            && self.files_changed.is_empty())
+        {
+            writeln!(w, "Files:")?;
+            for name in &self.files_removed {
+                writeln!(w, "  - {name}")?;
+            }
+            for name in &self.files_added {
+                writeln!(w, "  + {name}")?;
+            }
+            for name in &self.files_changed {
+                writeln!(w, "  ~ {name}")?;
+            }
+        }
+
+        if !(self.entities_added.is_empty()
+            && self.entities_removed.is_empty()
+            && self.entities_changed.is_empty())
+        {
+            writeln!(w, "Entities:")?;
+            for entity in &self.entities_removed {
+                writeln!(w, "  - {entity}")?;
+            }
+            for entity in &self.entities_added {
+                writeln!(w, "  + {entity}")?;
+            }
+            for change in &self.entities_changed {
+                writeln!(w, "  ~ {}", change.description)?;
+                for (k, v) in &change.removed {
+                    writeln!(w, "      - \"{k}\" \"{v}\"")?;
+                }
+                for (k, v) in &change.added {
+                    writeln!(w, "      + \"{k}\" \"{v}\"")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Json {
+        let pair = |v: Option<(u32, u32)>| {
+            v.map_or(Json::Null, |(a, b)| Json::Array(vec![a.into(), b.into()]))
+        };
+        let lump = |l: &Option<LumpSummary>| {
+            l.as_ref().map_or(Json::Null, |l| {
+                Json::object([
+                    ("size", l.size.into()),
+                    ("crc32", format!("{:08x}", l.crc32).into()),
+                ])
+            })
+        };
+        let props = |props: &[(String, String)]| {
+            Json::Array(
+                props
+                    .iter()
+                    .map(|(k, v)| Json::Array(vec![k.as_str().into(), v.as_str().into()]))
+                    .collect(),
+            )
+        };
+
+        Json::object([
+            ("identical", self.is_empty().into()),
+            ("version", pair(self.version)),
+            ("revision", pair(self.map_revision)),
+            (
+                "lumps",
+                Json::Array(
+                    self.lumps
+                        .iter()
+                        .map(|l| {
+                            Json::object([
+                                ("name", l.name.as_str().into()),
+                                ("old", lump(&l.old)),
+                                ("new", lump(&l.new)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "files",
+                Json::object([
+                    ("added", self.files_added.clone().into()),
+                    ("removed", self.files_removed.clone().into()),
+                    ("changed", self.files_changed.clone().into()),
+                ]),
+            ),
+            (
+                "entities",
+                Json::object([
+                    ("added", self.entities_added.clone().into()),
+                    ("removed", self.entities_removed.clone().into()),
+                    (
+                        "changed",
+                        Json::Array(
+                            self.entities_changed
+                                .iter()
+                                .map(|c| {
+                                    Json::object([
+                                        ("entity", c.description.as_str().into()),
+                                        ("removed", props(&c.removed)),
+                                        ("added", props(&c.added)),
+                                    ])
+                                })
+                                .collect(),
+                        ),
+                    ),
+                ]),
+            ),
+        ])
+    }
+}
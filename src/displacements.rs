@@ -0,0 +1,231 @@
+//! Summary of a map's displacements and sanity checks of their neighbor links.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, DispInfo, DispVert, Vector};
+
+/// Neighbor index meaning there is no neighbor
+const NO_NEIGHBOR: u16 = 0xFFFF;
+/// DispSubNeighbor spans: corner to corner, corner to midpoint and midpoint to corner
+const MAX_SPAN: u8 = 2;
+const MAX_CORNER_NEIGHBORS: u8 = 4;
+/// Size of a DispVert record on disk
+const DISP_VERT_SIZE: usize = 20;
+
+pub struct Report {
+    pub count: usize,
+    /// Number of displacements of power 2, 3 and 4
+    pub powers: [usize; 3],
+    /// Displacements with any other power, as (index, power)
+    pub invalid_powers: Vec<(usize, i32)>,
+    pub verts: usize,
+    /// Vertices the displacements' grids need, which should match `verts`
+    pub expected_verts: usize,
+    pub tris: usize,
+    /// Problems with individual displacements, as (index, start position, description)
+    pub problems: Vec<(usize, Vector, String)>,
+}
+
+fn neighbor_problem(index: usize, neighbor: u16, count: usize) -> Option<String> {
+    if neighbor == NO_NEIGHBOR {
+        None
+    } else if neighbor as usize >= count {
+        Some(format!("references missing displacement {neighbor}"))
+    } else if neighbor as usize == index {
+        Some("is its own neighbor".to_string())
+    } else {
+        None
+    }
+}
+
+fn check(index: usize, disp: &DispInfo, infos: &[DispInfo], num_verts: usize) -> Vec<String> {
+    let mut problems = vec![];
+
+    for (edge, subs) in disp.edge_neighbors.iter().enumerate() {
+        for sub in subs {
+            if let Some(problem) = neighbor_problem(index, sub.neighbor, infos.len()) {
+                problems.push(format!("edge {edge} neighbor {problem}"));
+            } else if sub.neighbor != NO_NEIGHBOR
+                && (sub.orientation > 3 || sub.span > MAX_SPAN || sub.neighbor_span > MAX_SPAN)
+            {
+                problems.push(format!(
+                    "edge {edge} neighbor {} has invalid orientation {} or spans {}/{}",
+                    sub.neighbor, sub.orientation, sub.span, sub.neighbor_span
+                ));
+            }
+        }
+    }
+
+    for (corner, neighbors) in disp.corner_neighbors.iter().enumerate() {
+        if neighbors.num_neighbors > MAX_CORNER_NEIGHBORS {
+            problems.push(format!(
+                "corner {corner} claims {} neighbors",
+                neighbors.num_neighbors
+            ));
+            continue;
+        }
+
+        for &neighbor in &neighbors.neighbors[..neighbors.num_neighbors as usize] {
+            if let Some(problem) = neighbor_problem(index, neighbor, infos.len()) {
+                problems.push(format!("corner {corner} neighbor {problem}"));
+            }
+        }
+    }
+
+    problems.extend(sewing_problems(index, disp, infos));
+
+    if (2..=4).contains(&disp.power) {
+        let needed = disp.side_length().pow(2);
+        let in_range =
+            usize::try_from(disp.disp_vert_start).is_ok_and(|start| start + needed <= num_verts);
+        if !in_range {
+            problems.push(format!(
+                "vertices {}..{} are out of range",
+                disp.disp_vert_start,
+                disp.disp_vert_start as i64 + needed as i64
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Checks that neighbors link back with matching orientations and spans. Links that only go one
+/// way, or disagree, leave the edges unsewn, which shows as cracks in the terrain.
+fn sewing_problems(index: usize, disp: &DispInfo, infos: &[DispInfo]) -> Vec<String> {
+    let mut problems = vec![];
+
+    for (edge, subs) in disp.edge_neighbors.iter().enumerate() {
+        for sub in subs {
+            let Some(neighbor) = infos.get(sub.neighbor as usize) else {
+                continue;
+            };
+            if sub.neighbor as usize == index {
+                continue;
+            }
+
+            let back_links: Vec<_> = neighbor
+                .edge_neighbors
+                .iter()
+                .flatten()
+                .filter(|back| back.neighbor as usize == index)
+                .collect();
+            if back_links.is_empty() {
+                problems.push(format!(
+                    "edge {edge} neighbor {} doesn't link back",
+                    sub.neighbor
+                ));
+                continue;
+            }
+
+            // Rotating into the neighbor's space and back must cancel out, and each side's
+            // span is the other's neighbor span
+            let consistent = back_links.iter().any(|back| {
+                sub.orientation.wrapping_add(back.orientation) % 4 == 0
+                    && back.span == sub.neighbor_span
+                    && back.neighbor_span == sub.span
+            });
+            if !consistent {
+                problems.push(format!(
+                    "edge {edge} neighbor {} disagrees on orientation or span",
+                    sub.neighbor
+                ));
+            }
+        }
+    }
+
+    for (corner, neighbors) in disp.corner_neighbors.iter().enumerate() {
+        let count = (neighbors.num_neighbors as usize).min(neighbors.neighbors.len());
+        for &neighbor in &neighbors.neighbors[..count] {
+            let Some(other) = infos.get(neighbor as usize) else {
+                continue;
+            };
+
+            let links_back = other.corner_neighbors.iter().any(|back| {
+                let count = (back.num_neighbors as usize).min(back.neighbors.len());
+                back.neighbors[..count].contains(&(index as u16))
+            });
+            if neighbor as usize != index && !links_back {
+                problems.push(format!(
+                    "corner {corner} neighbor {neighbor} doesn't link back"
+                ));
+            }
+        }
+    }
+
+    problems
+}
+
+pub fn summarize<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    let infos: Vec<DispInfo> = lumps::read_array(bsp, LumpType::DISPLACEMENT_INFO)?;
+    let verts: Vec<DispVert> = lumps::read_array(bsp, LumpType::DISPLACEMENT_VERTICES)?;
+    // A flags word for each triangle
+    let tris: Vec<u16> = lumps::read_array(bsp, LumpType::DISPLACEMENT_TRIS)?;
+
+    let mut powers = [0; 3];
+    let mut invalid_powers = vec![];
+    let mut expected_verts = 0;
+    let mut problems = vec![];
+
+    for (i, disp) in infos.iter().enumerate() {
+        match disp.power {
+            2..=4 => {
+                powers[disp.power as usize - 2] += 1;
+                expected_verts += disp.side_length().pow(2);
+            }
+            _ => invalid_powers.push((i, disp.power)),
+        }
+
+        problems.extend(
+            check(i, disp, &infos, verts.len())
+                .into_iter()
+                .map(|problem| (i, disp.start_position, problem)),
+        );
+    }
+
+    Some(Report {
+        count: infos.len(),
+        powers,
+        invalid_powers,
+        verts: verts.len(),
+        expected_verts,
+        tris: tris.len(),
+        problems,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Displacements: {}", self.count)?;
+        if self.count == 0 {
+            return Ok(());
+        }
+
+        for (power, count) in self.powers.iter().enumerate() {
+            writeln!(w, "  Power {}: {count}", power + 2)?;
+        }
+
+        let vert_bytes = self.verts * DISP_VERT_SIZE;
+        let tri_bytes = self.tris * 2;
+        writeln!(w, "Vertices: {} ({vert_bytes} bytes)", self.verts)?;
+        writeln!(w, "Triangles: {} ({tri_bytes} bytes)", self.tris)?;
+        writeln!(w, "Vertex memory: {} bytes", vert_bytes + tri_bytes)?;
+
+        for (i, power) in &self.invalid_powers {
+            writeln!(w, "error: displacement {i} has invalid power {power}")?;
+        }
+        if self.verts != self.expected_verts {
+            writeln!(
+                w,
+                "warning: displacements need {} vertices, but the lump has {}",
+                self.expected_verts, self.verts
+            )?;
+        }
+        for (i, [x, y, z], problem) in &self.problems {
+            writeln!(w, "error: displacement {i} at ({x}, {y}, {z}) {problem}")?;
+        }
+
+        Ok(())
+    }
+}
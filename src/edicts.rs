@@ -0,0 +1,144 @@
+//! Entity counts by classname, split by whether the entity takes one of the engine's edicts.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::entities::Entity;
+
+/// Edicts available to a Source server, including those taken by the world and players
+pub const MAX_EDICTS: usize = 2048;
+/// Entities listed by keyvalue size
+const LARGEST: usize = 10;
+
+/// Classname prefixes of server-only entities, which don't take an edict
+const LOGICAL_PREFIXES: &[&str] = &["logic_", "math_", "filter_", "ai_", "info_node"];
+/// Other server-only entities, and entities the game removes when the map loads
+const LOGICAL_CLASSES: &[&str] = &[
+    "point_template",
+    "point_devshot_camera",
+    "info_hint",
+    "env_global",
+    "game_weapon_manager",
+];
+
+/// Whether an entity of this class runs without an edict. Only an estimate: which entities are
+/// networked depends on the game.
+pub fn is_logical(classname: &str) -> bool {
+    let classname = classname.to_ascii_lowercase();
+    LOGICAL_PREFIXES
+        .iter()
+        .any(|prefix| classname.starts_with(prefix))
+        || LOGICAL_CLASSES.contains(&classname.as_str())
+}
+
+/// Bytes the entity takes in the entity lump, as `"key" "value"` lines between braces
+fn keyvalue_size(entity: &Entity) -> usize {
+    let properties: usize = entity
+        .properties
+        .iter()
+        .map(|(key, value)| key.len() + value.len() + 6)
+        .sum();
+    properties + 4
+}
+
+pub struct Stats {
+    pub total: usize,
+    /// Entities expected to take an edict when the map loads
+    pub edicts: usize,
+    pub logical: usize,
+    /// Entities of each class as (classname, count, logical), most common first
+    pub classes: Vec<(String, usize, bool)>,
+    /// The entities with the most keyvalue data, as (index, classname, targetname, bytes)
+    pub largest: Vec<(usize, String, Option<String>, usize)>,
+}
+
+impl Stats {
+    pub fn new(entities: &[Entity]) -> Self {
+        let mut classes: HashMap<&str, usize> = HashMap::new();
+        for entity in entities {
+            *classes
+                .entry(entity.get("classname").unwrap_or("<no classname>"))
+                .or_default() += 1;
+        }
+        let mut classes: Vec<(String, usize, bool)> = classes
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count, is_logical(name)))
+            .collect();
+        classes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let logical = classes
+            .iter()
+            .filter(|(_, _, logical)| *logical)
+            .map(|(_, count, _)| count)
+            .sum();
+
+        let mut largest: Vec<_> = entities
+            .iter()
+            .enumerate()
+            .map(|(i, entity)| {
+                (
+                    i,
+                    entity
+                        .get("classname")
+                        .unwrap_or("<no classname>")
+                        .to_string(),
+                    entity.get("targetname").map(str::to_string),
+                    keyvalue_size(entity),
+                )
+            })
+            .collect();
+        largest.sort_by(|a, b| b.3.cmp(&a.3).then_with(|| a.0.cmp(&b.0)));
+        largest.truncate(LARGEST);
+
+        Self {
+            total: entities.len(),
+            edicts: entities.len() - logical,
+            logical,
+            classes,
+            largest,
+        }
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Entities: {}", self.total)?;
+        writeln!(
+            w,
+            "Edict entities: {} of {MAX_EDICTS} ({:.1}%)",
+            self.edicts,
+            self.edicts as f64 / MAX_EDICTS as f64 * 100.0
+        )?;
+        writeln!(w, "Logical entities: {}", self.logical)?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        writeln!(w, "Classes:")?;
+        for (name, count, logical) in &self.classes {
+            let note = if *logical { " (logical)" } else { "" };
+            writeln!(w, "  {count:8} {name}{note}")?;
+        }
+
+        writeln!(w, "Largest entities:")?;
+        for (i, classname, targetname, bytes) in &self.largest {
+            match targetname {
+                Some(name) => writeln!(w, "  {bytes:8} bytes  #{i} {classname} \"{name}\"")?,
+                None => writeln!(w, "  {bytes:8} bytes  #{i} {classname}")?,
+            }
+        }
+
+        // Players, their weapons and anything spawned at runtime take edicts too
+        if self.edicts >= MAX_EDICTS {
+            writeln!(
+                w,
+                "error: the map needs more edicts than the engine has before anything spawns"
+            )?;
+        } else if self.edicts * 4 >= MAX_EDICTS * 3 {
+            writeln!(
+                w,
+                "warning: the map uses over 75% of the edict limit, leaving little room for players and spawned entities"
+            )?;
+        }
+
+        Ok(())
+    }
+}
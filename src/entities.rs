@@ -0,0 +1,274 @@
+use std::fmt;
+use std::io::{Read, Seek};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::error::BspError;
+use crate::policy::Policy;
+
+/// A single entity from the entity lump. Keys are kept in their original order, and may repeat
+/// (entity outputs are stored as repeated keys).
+#[derive(Debug, Clone, Default)]
+pub struct Entity {
+    pub properties: Vec<(String, String)>,
+}
+
+impl Entity {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Replaces the value of the first property named `key`, or appends it if it doesn't exist.
+    pub fn set(&mut self, key: &str, value: String) {
+        match self
+            .properties
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            Some((_, v)) => *v = value,
+            None => self.properties.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.properties
+            .retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+}
+
+/// An entity output, stored as `"OnTrigger" "target,input,parameter,delay,times"`. Newer games
+/// separate the fields with ESC instead of commas.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct Output<'a> {
+    pub name: &'a str,
+    pub target: &'a str,
+    pub input: &'a str,
+    pub parameter: &'a str,
+    pub delay: f32,
+    pub times: i32,
+}
+
+impl Entity {
+    pub fn outputs(&self) -> impl Iterator<Item = Output<'_>> {
+        self.properties.iter().filter_map(|(key, value)| {
+            let separator = if value.contains('\x1b') { '\x1b' } else { ',' };
+            let fields: Vec<&str> = value.split(separator).collect();
+            let [target, input, parameter, delay, times] = fields[..] else {
+                return None;
+            };
+
+            Some(Output {
+                name: key,
+                target,
+                input,
+                parameter,
+                delay: delay.trim().parse().ok()?,
+                times: times.trim().parse().ok()?,
+            })
+        })
+    }
+}
+
+/// The worldspawn's `mapversion`, which vbsp sets to the map revision it writes to the header.
+/// None if it's missing or not a number.
+pub fn map_version(entities: &[Entity]) -> Option<u32> {
+    entities
+        .iter()
+        .find(|e| e.get("classname") == Some("worldspawn"))?
+        .get("mapversion")?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Matches a targetname against an output target or target key, which may end in a `*`
+/// wildcard. Names are case insensitive.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+        None => pattern.eq_ignore_ascii_case(name),
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+struct Tokenizer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+enum Token<'a> {
+    Open,
+    Close,
+    String(&'a [u8]),
+}
+
+impl<'a> Tokenizer<'a> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Token<'a>>, ParseError> {
+        while let Some(&c) = self.data.get(self.pos) {
+            // The lump is NUL terminated, anything after it is padding
+            if c == 0 {
+                self.pos = self.data.len();
+                break;
+            }
+
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        let Some(&c) = self.data.get(self.pos) else {
+            return Ok(None);
+        };
+
+        match c {
+            b'{' => {
+                self.pos += 1;
+                Ok(Some(Token::Open))
+            }
+            b'}' => {
+                self.pos += 1;
+                Ok(Some(Token::Close))
+            }
+            b'"' => {
+                let start = self.pos + 1;
+                let len = self.data[start..]
+                    .iter()
+                    .position(|&c| c == b'"')
+                    .ok_or_else(|| self.error("unterminated string"))?;
+
+                self.pos = start + len + 1;
+                Ok(Some(Token::String(&self.data[start..start + len])))
+            }
+            _ => Err(self.error("unexpected character")),
+        }
+    }
+}
+
+/// Parses entities into `entities` until the end of the lump or the first error, so the entities
+/// before a syntax error are kept.
+fn parse_into(data: &[u8], entities: &mut Vec<Entity>) -> Result<(), ParseError> {
+    let mut tokens = Tokenizer { data, pos: 0 };
+
+    while let Some(token) = tokens.next()? {
+        if !matches!(token, Token::Open) {
+            return Err(tokens.error("expected '{'"));
+        }
+
+        let mut entity = Entity::default();
+        loop {
+            let key = match tokens.next()? {
+                Some(Token::Close) => break,
+                Some(Token::String(key)) => key,
+                Some(Token::Open) => return Err(tokens.error("unexpected '{'")),
+                None => return Err(tokens.error("unexpected end of entity lump")),
+            };
+
+            let Some(Token::String(value)) = tokens.next()? else {
+                return Err(tokens.error("expected value"));
+            };
+
+            entity.properties.push((
+                String::from_utf8_lossy(key).into_owned(),
+                String::from_utf8_lossy(value).into_owned(),
+            ));
+        }
+
+        entities.push(entity);
+    }
+
+    Ok(())
+}
+
+pub fn parse(data: &[u8]) -> Result<Vec<Entity>, ParseError> {
+    let mut entities = vec![];
+    parse_into(data, &mut entities)?;
+    Ok(entities)
+}
+
+/// Reads the entity lump of a map. A syntax error fails under the strict policy, and keeps the
+/// entities before it under the lenient one.
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Vec<Entity>> {
+    let data = bsp.get_lump(LumpType::ENTITIES)?;
+
+    let mut entities = vec![];
+    if let Err(e) = parse_into(&data, &mut entities) {
+        let problem = format!("entity lump: {e}, after {} entities", entities.len());
+        if !bsp.context().tolerate(problem) {
+            return None;
+        }
+    }
+
+    Some(entities)
+}
+
+/// Like [`read`], but saying why the entities couldn't be read. A syntax error is returned under
+/// the strict policy, and under the lenient one is reported and the entities before it kept.
+pub fn try_read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Vec<Entity>, BspError> {
+    let data = bsp.try_get_lump(LumpType::ENTITIES)?;
+
+    let mut entities = vec![];
+    if let Err(e) = parse_into(&data, &mut entities) {
+        if bsp.context().policy == Policy::Strict {
+            return Err(e.into());
+        }
+        bsp.context().tolerate(format!(
+            "entity lump: {e}, after {} entities",
+            entities.len()
+        ));
+    }
+
+    Ok(entities)
+}
+
+/// Whether the entity lump `data` is UTF-8, which [`serialize`] gives back unchanged after
+/// parsing. Lumps written by older tools can have Latin-1 or CP1252 keyvalues, which are parsed
+/// with U+FFFD in place of their non-ASCII characters, so rewriting them would lose those.
+pub fn is_utf8(data: &[u8]) -> bool {
+    let end = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    std::str::from_utf8(&data[..end]).is_ok()
+}
+
+/// Serializes entities back into the format used by the entity lump, including the trailing
+/// NUL terminator.
+pub fn serialize(entities: &[Entity]) -> Vec<u8> {
+    let mut out = String::new();
+
+    for entity in entities {
+        out.push_str("{\n");
+        for (key, value) in &entity.properties {
+            out.push_str(&format!("\"{key}\" \"{value}\"\n"));
+        }
+        out.push_str("}\n");
+    }
+
+    let mut out = out.into_bytes();
+    out.push(0);
+    out
+}
@@ -0,0 +1,147 @@
+//! The error type of the crate, collecting the errors of the formats a map is made of and the
+//! problems hostile maps are refused for. Parsers report problems through
+//! [`ParseContext`](crate::policy::ParseContext) and return them as this, so callers can say why a
+//! map couldn't be read. Some older helpers still return `Option`, which loses the reason; new
+//! parsers shouldn't.
+
+use std::{fmt, io};
+
+use zip::result::ZipError;
+
+use crate::bsp::LumpType;
+use crate::entities;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BspError {
+    Io(io::Error),
+    /// The file isn't a map, or its header is cut short
+    Header(binrw::Error),
+    /// The map doesn't have the lump
+    MissingLump(LumpType),
+    /// The lump directory points past the end of the file
+    LumpOutOfBounds {
+        lump: String,
+        end: u64,
+        file_size: u64,
+    },
+    /// A compressed lump's LZMA header gives a different size than the lump directory
+    LumpSize {
+        lump: String,
+        actual: u32,
+        declared: u32,
+    },
+    /// A lump of fixed size records has bytes left over after its last record
+    LeftoverBytes {
+        lump: String,
+        rest: usize,
+    },
+    /// A lump has a version whose layout the crate doesn't know
+    UnknownVersion {
+        lump: String,
+        version: u32,
+    },
+    /// Data inside a lump is cut short or contradicts itself, such as a count running past the
+    /// end of the data or an offset pointing outside it
+    Malformed {
+        what: String,
+        problem: String,
+    },
+    /// Compressed data is missing its LZMA header or doesn't decompress
+    Lzma(&'static str),
+    /// Compressed data claims to decompress to more than the crate reads
+    TooLarge {
+        size: u64,
+        limit: u64,
+    },
+    Entities(entities::ParseError),
+    Pakfile(ZipError),
+}
+
+/// The name [`BspError`] was first exported under
+pub type Error = BspError;
+
+impl fmt::Display for BspError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Header(e) => write!(f, "invalid BSP header: {e}"),
+            Self::MissingLump(lump) => write!(f, "the map has no {lump:?} lump"),
+            Self::LumpOutOfBounds {
+                lump,
+                end,
+                file_size,
+            } => write!(
+                f,
+                "{lump} lump ends at {end}, past the end of the file at {file_size}"
+            ),
+            Self::LumpSize {
+                lump,
+                actual,
+                declared,
+            } => write!(
+                f,
+                "{lump} lump decompresses to {actual} bytes, but the lump directory gives {declared}"
+            ),
+            Self::LeftoverBytes { lump, rest } => {
+                write!(f, "{lump} lump has {rest} bytes left over after its last record")
+            }
+            Self::UnknownVersion { lump, version } => {
+                write!(f, "{lump} lump has unknown version {version}")
+            }
+            Self::Malformed { what, problem } => write!(f, "{what}: {problem}"),
+            Self::Lzma(e) => write!(f, "{e}"),
+            Self::TooLarge { size, limit } => write!(
+                f,
+                "data claims to decompress to {size} bytes, more than the {limit} read"
+            ),
+            Self::Entities(e) => write!(f, "entity lump: {e}"),
+            Self::Pakfile(e) => write!(f, "pakfile: {e}"),
+        }
+    }
+}
+
+impl BspError {
+    pub(crate) fn malformed(what: impl Into<String>, problem: impl Into<String>) -> Self {
+        Self::Malformed {
+            what: what.into(),
+            problem: problem.into(),
+        }
+    }
+}
+
+impl std::error::Error for BspError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Header(e) => Some(e),
+            Self::Entities(e) => Some(e),
+            Self::Pakfile(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BspError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<binrw::Error> for BspError {
+    fn from(e: binrw::Error) -> Self {
+        Self::Header(e)
+    }
+}
+
+impl From<entities::ParseError> for BspError {
+    fn from(e: entities::ParseError) -> Self {
+        Self::Entities(e)
+    }
+}
+
+impl From<ZipError> for BspError {
+    fn from(e: ZipError) -> Self {
+        Self::Pakfile(e)
+    }
+}
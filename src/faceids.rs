@@ -0,0 +1,61 @@
+//! FACE_IDS, which records the Hammer side each face was built from, and
+//! FACE_MACRO_TEXTURE_INFO, the macro texture each face is drawn with. Face numbers in reports
+//! and engine errors can be traced back to the side to fix in Hammer through the first.
+//!
+//! vbsp stores side ids as 16 bits, so in maps with more than 65535 sides they wrap around.
+
+use std::io::{Read, Seek};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, World};
+
+/// Macro texture index of faces without one
+pub const NO_MACRO_TEXTURE: u16 = 0xffff;
+
+pub struct FaceId {
+    /// Id of the Hammer side, if FACE_IDS covers the face
+    pub hammer_id: Option<u16>,
+    pub material: Option<String>,
+    pub macro_texture: Option<String>,
+}
+
+pub struct Report {
+    /// Every face of FACES, in order
+    pub faces: Vec<FaceId>,
+    /// Entries of FACE_IDS, which has one for each face when it's complete
+    pub ids: usize,
+    /// Entries of FACE_MACRO_TEXTURE_INFO, which is empty in maps without macro textures
+    pub macro_textures: usize,
+}
+
+/// Returns `None` if the map has no FACE_IDS lump
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    if !bsp.has_lump(LumpType::FACE_IDS as usize) {
+        return None;
+    }
+    let ids: Vec<u16> = lumps::read_array(bsp, LumpType::FACE_IDS)?;
+    let macro_textures: Vec<u16> = lumps::read_array(bsp, LumpType::FACE_MACRO_TEXTURE_INFO)?;
+    let strings = lumps::texture_strings(bsp)?;
+    let world = World::read(bsp)?;
+
+    let faces = world
+        .faces
+        .iter()
+        .enumerate()
+        .map(|(i, face)| FaceId {
+            hammer_id: ids.get(i).copied(),
+            material: world.texinfo_name(face.texinfo).map(str::to_string),
+            macro_texture: macro_textures
+                .get(i)
+                .filter(|&&index| index != NO_MACRO_TEXTURE)
+                .and_then(|&index| strings.get(index as usize))
+                .cloned(),
+        })
+        .collect();
+
+    Some(Report {
+        faces,
+        ids: ids.len(),
+        macro_textures: macro_textures.len(),
+    })
+}
@@ -0,0 +1,22 @@
+//! bzip2 compressed copies of maps for fast download servers, which clients fetch from
+//! sv_downloadurl as `maps/<map>.bsp.bz2` and decompress themselves.
+
+use std::ffi::OsString;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+
+pub fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = BzEncoder::new(vec![], Compression::best());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Where the compressed copy of the map at `map` goes, which is next to it
+pub fn path(map: &Path) -> PathBuf {
+    let mut path = OsString::from(map);
+    path.push(".bz2");
+    path.into()
+}
@@ -0,0 +1,650 @@
+//! Parses Hammer's game data files (.fgd) and checks a map's entities against the classes they
+//! define.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::entities::Entity;
+
+/// Keys the compiler writes to entities whether or not the FGD declares them
+const COMPILER_KEYS: &[&str] = &[
+    "classname",
+    "hammerid",
+    "origin",
+    "model",
+    "mapversion",
+    "world_mins",
+    "world_maxs",
+];
+/// Largest edit distance at which an unknown key is reported as a misspelling
+const MAX_TYPO_DISTANCE: usize = 2;
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    String(String),
+    Word(String),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Vec<(Token, usize)> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '/' => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    while chars.next_if(|&c| c != '\n').is_some() {}
+                } else {
+                    tokens.push((Token::Punct('/'), line));
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                for c in chars.by_ref() {
+                    match c {
+                        '"' => break,
+                        '\n' => {
+                            line += 1;
+                            string.push(c);
+                        }
+                        c => string.push(c),
+                    }
+                }
+                tokens.push((Token::String(string), line));
+            }
+            '(' | ')' | '[' | ']' | '=' | ':' | ',' | '+' => {
+                chars.next();
+                tokens.push((Token::Punct(c), line));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) =
+                    chars.next_if(|&c| !c.is_whitespace() && !"()[]=:,+\"".contains(c) && c != '/')
+                {
+                    word.push(c);
+                }
+                tokens.push((Token::Word(word), line));
+            }
+        }
+    }
+
+    tokens
+}
+
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub name: String,
+    /// Type such as `string`, `integer`, `choices` or `flags`, lowercase
+    pub kind: String,
+    /// Allowed values of a `choices` property
+    pub choices: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Class {
+    pub name: String,
+    /// `PointClass`, `SolidClass`, `BaseClass` and so on
+    pub kind: String,
+    pub bases: Vec<String>,
+    pub properties: Vec<Property>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+/// The classes of one FGD and the files it includes, keyed by lowercase name
+#[derive(Debug, Default)]
+pub struct Fgd {
+    pub classes: HashMap<String, Class>,
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    path: &'a Path,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        let line = self
+            .tokens
+            .get(self.pos.min(self.tokens.len().saturating_sub(1)))
+            .map_or(0, |(_, line)| *line);
+        ParseError {
+            path: self.path.to_path_buf(),
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token, ParseError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        let found = self.peek() == Some(&Token::Punct(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), ParseError> {
+        if self.eat(punct) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{punct}'")))
+        }
+    }
+
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    /// A value: a word, number or string. Strings may be joined with `+`.
+    fn value(&mut self) -> Result<String, ParseError> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            Token::String(mut string) => {
+                while self.eat('+') {
+                    match self.next()? {
+                        Token::String(more) => string += &more,
+                        _ => return Err(self.error("expected a string after '+'")),
+                    }
+                }
+                Ok(string)
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Skips a balanced group that starts at the current `open` token
+    fn skip_group(&mut self, open: char, close: char) -> Result<(), ParseError> {
+        self.expect(open)?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct(c) if c == open => depth += 1,
+                Token::Punct(c) if c == close => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Skips the `: "display" : default : "description"` fields after a name
+    fn skip_fields(&mut self) -> Result<(), ParseError> {
+        while self.eat(':') {
+            if !matches!(self.peek(), Some(Token::Punct(':' | '=' | ']')) | None) {
+                self.value()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn class(&mut self, kind: String) -> Result<Class, ParseError> {
+        let mut class = Class {
+            kind,
+            ..Default::default()
+        };
+
+        // Options such as base(A, B), size(-8 -8 -8, 8 8 8) or halfgridsnap
+        while !self.eat('=') {
+            let option = self.word()?;
+            if self.peek() != Some(&Token::Punct('(')) {
+                continue;
+            }
+            if !option.eq_ignore_ascii_case("base") {
+                self.skip_group('(', ')')?;
+                continue;
+            }
+
+            self.expect('(')?;
+            while !self.eat(')') {
+                match self.next()? {
+                    Token::Word(base) => class.bases.push(base),
+                    Token::Punct(',') => {}
+                    _ => return Err(self.error("expected a base class name")),
+                }
+            }
+        }
+
+        class.name = self.word()?;
+        self.skip_fields()?;
+
+        self.expect('[')?;
+        while !self.eat(']') {
+            let name = self.word()?;
+            match name.to_ascii_lowercase().as_str() {
+                kind @ ("input" | "output") => {
+                    let name = self.word()?;
+                    self.skip_group('(', ')')?;
+                    self.skip_fields()?;
+                    match kind {
+                        "input" => class.inputs.push(name),
+                        _ => class.outputs.push(name),
+                    }
+                }
+                _ => class.properties.push(self.property(name)?),
+            }
+        }
+
+        Ok(class)
+    }
+
+    fn property(&mut self, name: String) -> Result<Property, ParseError> {
+        self.expect('(')?;
+        let kind = self.word()?.to_ascii_lowercase();
+        self.expect(')')?;
+
+        // Modifiers such as readonly or report
+        while matches!(self.peek(), Some(Token::Word(_))) {
+            self.pos += 1;
+        }
+        self.skip_fields()?;
+
+        let mut choices = vec![];
+        if self.eat('=') {
+            self.expect('[')?;
+            while !self.eat(']') {
+                choices.push(self.value()?);
+                self.skip_fields()?;
+            }
+        }
+
+        Ok(Property {
+            name,
+            kind,
+            choices,
+        })
+    }
+
+    fn parse(&mut self, fgd: &mut Fgd, seen: &mut HashSet<PathBuf>) -> Result<(), ParseError> {
+        while let Some(token) = self.peek().cloned() {
+            let Token::Word(directive) = token else {
+                return Err(self.error("expected a directive"));
+            };
+            let Some(directive) = directive.strip_prefix('@') else {
+                return Err(self.error(format!("unexpected '{directive}'")));
+            };
+            self.pos += 1;
+
+            if directive.eq_ignore_ascii_case("include") {
+                let Token::String(file) = self.next()? else {
+                    return Err(self.error("expected a file name after @include"));
+                };
+                let dir = self.path.parent().unwrap_or(Path::new(""));
+                load_into(&dir.join(file), fgd, seen)?;
+            } else if directive.to_ascii_lowercase().ends_with("class") {
+                let class = self.class(directive.to_string())?;
+                fgd.classes.insert(class.name.to_ascii_lowercase(), class);
+            } else {
+                // @mapsize(...), @MaterialExclusion [...], @AutoVisGroup = "name" [...] and the like
+                while let Some(token) = self.peek() {
+                    match token {
+                        Token::Punct('(') => self.skip_group('(', ')')?,
+                        Token::Punct('[') => {
+                            self.skip_group('[', ']')?;
+                            break;
+                        }
+                        Token::Word(word) if word.starts_with('@') => break,
+                        _ => self.pos += 1,
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn load_into(path: &Path, fgd: &mut Fgd, seen: &mut HashSet<PathBuf>) -> Result<(), ParseError> {
+    // Included files are only read once, even if several files include them
+    if !seen.insert(path.to_path_buf()) {
+        return Ok(());
+    }
+
+    let data = std::fs::read(path).map_err(|e| ParseError {
+        path: path.to_path_buf(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+
+    let mut parser = Parser {
+        tokens: tokenize(&String::from_utf8_lossy(&data)),
+        pos: 0,
+        path,
+    };
+    parser.parse(fgd, seen)
+}
+
+impl Fgd {
+    /// Loads an FGD along with the files it `@include`s, relative to its directory
+    pub fn load(path: &Path) -> Result<Self, ParseError> {
+        let mut fgd = Fgd::default();
+        load_into(path, &mut fgd, &mut HashSet::new())?;
+        Ok(fgd)
+    }
+
+    pub fn class(&self, name: &str) -> Option<&Class> {
+        self.classes.get(&name.to_ascii_lowercase())
+    }
+
+    /// Properties and outputs of a class, including those of its base classes, keyed by
+    /// lowercase name
+    fn resolve<'a>(&'a self, class: &'a Class) -> (HashMap<String, &'a Property>, HashSet<String>) {
+        let (mut properties, mut outputs) = (HashMap::new(), HashSet::new());
+        let mut pending = vec![class];
+        let mut visited = HashSet::new();
+
+        while let Some(class) = pending.pop() {
+            if !visited.insert(class.name.to_ascii_lowercase()) {
+                continue;
+            }
+
+            for property in &class.properties {
+                properties
+                    .entry(property.name.to_ascii_lowercase())
+                    .or_insert(property);
+            }
+            outputs.extend(class.outputs.iter().map(|o| o.to_ascii_lowercase()));
+            pending.extend(class.bases.iter().filter_map(|base| self.class(base)));
+        }
+
+        (properties, outputs)
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Whether a value is one of a property's choices, comparing numbers by value
+fn is_choice(value: &str, choices: &[String]) -> bool {
+    let value = value.trim();
+    choices.iter().any(|choice| {
+        choice == value
+            || matches!(
+                (choice.parse::<f64>(), value.parse::<f64>()),
+                (Ok(a), Ok(b)) if a == b
+            )
+    })
+}
+
+pub struct Report {
+    pub checked: usize,
+    /// Classnames the FGD doesn't define, with the number of entities using each
+    pub unknown_classes: BTreeMap<String, usize>,
+    /// Problems with individual entities, as (index, classname, targetname, description)
+    pub problems: Vec<(usize, String, Option<String>, String)>,
+}
+
+pub fn check(entities: &[Entity], fgd: &Fgd) -> Report {
+    let mut unknown_classes = BTreeMap::new();
+    let mut problems = vec![];
+    let mut checked = 0;
+
+    for (i, entity) in entities.iter().enumerate() {
+        let Some(classname) = entity.get("classname") else {
+            continue;
+        };
+        let Some(class) = fgd.class(classname) else {
+            *unknown_classes.entry(classname.to_string()).or_default() += 1;
+            continue;
+        };
+        checked += 1;
+
+        let (properties, outputs) = fgd.resolve(class);
+        let outputs_of_entity: HashSet<&str> = entity.outputs().map(|o| o.name).collect();
+        let mut entity_problems = vec![];
+
+        for (key, value) in &entity.properties {
+            let lower = key.to_ascii_lowercase();
+            if COMPILER_KEYS.contains(&lower.as_str()) {
+                continue;
+            }
+
+            if outputs_of_entity.contains(key.as_str()) {
+                if !outputs.contains(&lower) {
+                    entity_problems.push(format!("unknown output {key}"));
+                }
+                continue;
+            }
+
+            let Some(property) = properties.get(&lower) else {
+                let suggestion = properties
+                    .values()
+                    .map(|p| (edit_distance(&lower, &p.name.to_ascii_lowercase()), &p.name))
+                    .filter(|&(distance, _)| distance <= MAX_TYPO_DISTANCE)
+                    .min();
+                entity_problems.push(match suggestion {
+                    Some((_, name)) => format!("unknown key {key} (did you mean {name}?)"),
+                    None => format!("unknown key {key}"),
+                });
+                continue;
+            };
+
+            if property.kind == "choices"
+                && !property.choices.is_empty()
+                && !is_choice(value, &property.choices)
+            {
+                entity_problems.push(format!(
+                    "{key} is \"{value}\", not one of {}",
+                    property.choices.join(", ")
+                ));
+            }
+        }
+
+        problems.extend(entity_problems.into_iter().map(|problem| {
+            (
+                i,
+                classname.to_string(),
+                entity.get("targetname").map(str::to_string),
+                problem,
+            )
+        }));
+    }
+
+    Report {
+        checked,
+        unknown_classes,
+        problems,
+    }
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.unknown_classes.is_empty() && self.problems.is_empty()
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Entities checked: {}", self.checked)?;
+
+        for (classname, count) in &self.unknown_classes {
+            writeln!(
+                w,
+                "warning: unknown classname {classname} ({count} entities)"
+            )?;
+        }
+        for (i, classname, targetname, problem) in &self.problems {
+            match targetname {
+                Some(name) => {
+                    writeln!(w, "warning: entity #{i} {classname} \"{name}\": {problem}")?
+                }
+                None => writeln!(w, "warning: entity #{i} {classname}: {problem}")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Fgd, ParseError> {
+        let mut fgd = Fgd::default();
+        let mut parser = Parser {
+            tokens: tokenize(text),
+            pos: 0,
+            path: Path::new("test.fgd"),
+        };
+        parser.parse(&mut fgd, &mut HashSet::new())?;
+        Ok(fgd)
+    }
+
+    fn entity(properties: &[(&str, &str)]) -> Entity {
+        let mut entity = Entity::default();
+        for (key, value) in properties {
+            entity.set(key, value.to_string());
+        }
+        entity
+    }
+
+    fn problems(fgd: &Fgd, entity: Entity) -> Vec<String> {
+        let report = check(&[entity], fgd);
+        report.problems.into_iter().map(|p| p.3).collect()
+    }
+
+    #[test]
+    fn includes_are_read_relative_to_the_including_file_once() {
+        let dir = std::env::temp_dir().join(format!("bspinfo-fgd-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("base")).unwrap();
+        std::fs::write(
+            dir.join("base/targetname.fgd"),
+            "@BaseClass = Targetname [ targetname(target_source) : \"Name\" ]",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("base/point.fgd"),
+            "@include \"targetname.fgd\"\n@PointClass base(Targetname) = info_target []",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("game.fgd"),
+            "@include \"base/point.fgd\"\n@include \"base/targetname.fgd\"\n@include \"base/point.fgd\"",
+        )
+        .unwrap();
+
+        let fgd = Fgd::load(&dir.join("game.fgd"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        let fgd = fgd.unwrap();
+
+        assert_eq!(fgd.classes.len(), 2);
+        assert_eq!(fgd.class("INFO_TARGET").unwrap().bases, ["Targetname"]);
+    }
+
+    #[test]
+    fn missing_include_names_the_file() {
+        let error = parse("\n@include \"missing.fgd\"").unwrap_err();
+        assert_eq!(error.path, Path::new("missing.fgd"));
+    }
+
+    #[test]
+    fn base_classes_supply_properties_and_outputs() {
+        let fgd = parse(
+            "
+@BaseClass = Targetname [ targetname(target_source) ]
+@BaseClass base(Targetname) = Parent [ parentname(target_destination) ]
+@BaseClass = Toggle [ output OnEnabled(void) ]
+@PointClass base(Parent, Toggle) size(-8 -8 -8, 8 8 8) = prop_dynamic : \"Prop\" [
+    model(studio)
+]",
+        )
+        .unwrap();
+
+        let prop = entity(&[
+            ("classname", "prop_dynamic"),
+            ("targetname", "door"),
+            ("parentname", "frame"),
+            ("model", "models/door.mdl"),
+        ]);
+        assert_eq!(problems(&fgd, prop), Vec::<String>::new());
+
+        let typo = entity(&[("classname", "prop_dynamic"), ("parentnam", "frame")]);
+        assert_eq!(
+            problems(&fgd, typo),
+            ["unknown key parentnam (did you mean parentname?)"]
+        );
+    }
+
+    #[test]
+    fn strings_continue_across_lines() {
+        let fgd = parse(
+            "
+@PointClass = light : \"A light \" +
+    \"that shines\" [
+    style(choices) : \"Style\" : 0 : \"How the \" +
+        \"light looks\" =
+    [
+        0 : \"Normal\"
+        1 : \"Flicker \" + \"A\"
+    ]
+]",
+        )
+        .unwrap();
+        let style = &fgd.class("light").unwrap().properties[0];
+        assert_eq!(style.choices, ["0", "1"]);
+
+        let light = entity(&[("classname", "light"), ("style", "1.0")]);
+        assert_eq!(problems(&fgd, light), Vec::<String>::new());
+        let light = entity(&[("classname", "light"), ("style", "2")]);
+        assert_eq!(problems(&fgd, light), ["style is \"2\", not one of 0, 1"]);
+
+        let error = parse("@PointClass = light : \"A\" +\n\n  5 []").unwrap_err();
+        assert_eq!(error.line, 3);
+    }
+}
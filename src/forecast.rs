@@ -0,0 +1,112 @@
+//! Size impact of lump edits, reported before a modified map is written.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::writer::LumpWriter;
+
+pub struct LumpChange {
+    pub lump: LumpType,
+    pub old_len: u32,
+    /// Uncompressed size of the original lump, if it was LZMA compressed
+    pub old_uncompressed: Option<u32>,
+    pub new_len: usize,
+}
+
+pub struct Forecast {
+    pub changes: Vec<LumpChange>,
+    /// Lumps that will be written at a different offset, with the distance they move
+    pub moved: Vec<(LumpType, i64)>,
+    pub old_file_size: u64,
+    pub new_file_size: usize,
+}
+
+impl Forecast {
+    pub fn new<R: Read + Seek>(
+        bsp: &mut BspFile<R>,
+        writer: &LumpWriter,
+        edited: &[LumpType],
+    ) -> io::Result<Self> {
+        let changes = edited
+            .iter()
+            .map(|&lump| {
+                let info = bsp.lump_info(lump);
+                LumpChange {
+                    lump,
+                    old_len: info.filelen,
+                    old_uncompressed: (info.uncompressed_size != 0)
+                        .then_some(info.uncompressed_size),
+                    new_len: writer.lump_len(lump),
+                }
+            })
+            .collect();
+
+        let (offsets, new_file_size) = writer.layout();
+        let moved = offsets
+            .iter()
+            .enumerate()
+            .filter(|&(_, &ofs)| ofs != 0)
+            .filter_map(|(i, &ofs)| {
+                let lump = LumpType::try_from(i as u32).ok()?;
+                let old = bsp.lump_info(lump).fileofs;
+                (old != 0 && old != ofs).then_some((lump, ofs as i64 - old as i64))
+            })
+            .collect();
+
+        Ok(Self {
+            changes,
+            moved,
+            old_file_size: bsp.file_size()?,
+            new_file_size,
+        })
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for change in &self.changes {
+            let delta = change.new_len as i64 - change.old_len as i64;
+            match change.old_uncompressed {
+                Some(uncompressed) => writeln!(
+                    w,
+                    "{:?}: {} bytes LZMA compressed ({uncompressed} uncompressed) -> {} bytes ({delta:+}), edited lumps are written uncompressed",
+                    change.lump, change.old_len, change.new_len
+                )?,
+                None => writeln!(
+                    w,
+                    "{:?}: {} -> {} bytes ({delta:+})",
+                    change.lump, change.old_len, change.new_len
+                )?,
+            }
+        }
+
+        if self.moved.is_empty() {
+            writeln!(w, "No other lumps move")?;
+        } else {
+            let moved: Vec<String> = self
+                .moved
+                .iter()
+                .map(|(lump, delta)| format!("{lump:?} {delta:+}"))
+                .collect();
+            writeln!(
+                w,
+                "Lumps moved to new 4-byte aligned offsets: {}",
+                moved.join(", ")
+            )?;
+
+            if self
+                .moved
+                .iter()
+                .any(|&(lump, _)| lump == LumpType::GAME_LUMP)
+            {
+                writeln!(w, "Game lump offsets will be relocated")?;
+            }
+        }
+
+        writeln!(
+            w,
+            "File size: {} -> {} bytes ({:+})",
+            self.old_file_size,
+            self.new_file_size,
+            self.new_file_size as i64 - self.old_file_size as i64
+        )
+    }
+}
@@ -0,0 +1,145 @@
+//! A fuzz target running every parser that reads a map over arbitrary bytes, so hostile maps
+//! can be shown to be rejected rather than panicking or running out of memory.
+//!
+//! `fuzz/` has a cargo-fuzz target calling [`run`], and `tests/fuzz.rs` runs it over mutations
+//! of a small map with every run of the tests. It's hidden from the documentation, as it isn't
+//! part of the stable API.
+
+use std::io::{self, Cursor};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::pak::PakFile;
+use crate::policy::Policy;
+use crate::staticprops::StaticProps;
+use crate::vis::Visibility;
+use crate::{
+    brushes, checksum, clipgaps, cubemaps, decals, diff, displacements, edicts, entities, faceids,
+    gamelump, index, leaks, lighting, lightmaps, limits, lumps, mesh, occluders, overlays, physics,
+    portals, primitives, sky, splits, tree, validate, water, worldlights,
+};
+
+/// Reads `data` as a map with the lenient policy, which takes parsers furthest into broken
+/// lumps, and runs every analysis on it, discarding the reports.
+pub fn run(data: &[u8]) {
+    let mut reader = Cursor::new(data);
+    let Ok(bsp) = BspFile::new(&mut reader) else {
+        return;
+    };
+    let mut bsp = bsp.with_policy(Policy::Lenient);
+    let w = &mut io::sink();
+
+    let entities = entities::read(&mut bsp).unwrap_or_default();
+    edicts::Stats::new(&entities).print(w).ok();
+
+    validate::validate(&mut bsp).print(w).ok();
+    checksum::map_crc(&mut bsp);
+    checksum::map_md5(&mut bsp);
+    checksum::lump_digests(&mut bsp);
+    diff::Snapshot::new(&mut bsp);
+    index::Document::new(&mut bsp, "fuzz").tokens();
+    if let Some(report) = limits::check(&mut bsp, "tf2") {
+        report.print(w).ok();
+    }
+
+    if let Ok(Some(mut pak)) = PakFile::read(&mut bsp) {
+        pak.entries().ok();
+        pak.files().ok();
+    }
+    for lump in gamelump::read(&mut bsp).unwrap_or_default() {
+        if lump.id == gamelump::STATIC_PROPS {
+            if let Ok(props) = StaticProps::parse(&lump.data) {
+                props.serialize().ok();
+            }
+        }
+    }
+    if let Some(visibility) = bsp
+        .get_lump(LumpType::VISIBILITY)
+        .and_then(|data| Visibility::parse(&data))
+    {
+        visibility.print(w).ok();
+    }
+    if let Some(materials) = decals::collect(&mut bsp, &entities) {
+        decals::print(&materials, w).ok();
+    }
+    if let Some(entries) = overlays::read(&mut bsp) {
+        overlays::print(&entries, w).ok();
+    }
+    faceids::read(&mut bsp);
+    lightmaps::read(&mut bsp, false);
+    lightmaps::read(&mut bsp, true);
+
+    if let Ok(report) = physics::summarize(&mut bsp) {
+        report.print(w, true).ok();
+    }
+    if let Some(report) = worldlights::read(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = tree::analyze(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = sky::analyze(&mut bsp, &entities) {
+        report.print(w, true).ok();
+    }
+    if let Ok(report) = occluders::read(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = water::read(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = splits::analyze(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = primitives::read(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = cubemaps::analyze(&mut bsp, "fuzz") {
+        report.print(w).ok();
+    }
+    if let Some(report) = displacements::summarize(&mut bsp) {
+        report.print(w).ok();
+    }
+    if let Some(report) = lighting::analyze(&mut bsp) {
+        report.print(w).ok();
+    }
+
+    let areas = lumps::read_array(&mut bsp, LumpType::AREAS);
+    let area_portals = lumps::read_array(&mut bsp, LumpType::AREA_PORTALS);
+    if let Some(area_portals) = &area_portals {
+        portals::check_links(&entities, area_portals).print(w).ok();
+        if let Some(areas) = &areas {
+            portals::areas(&entities, areas, area_portals).print(w).ok();
+        }
+    }
+
+    let Some(world) = lumps::World::read(&mut bsp) else {
+        return;
+    };
+    clipgaps::analyze(&world).print(w).ok();
+    brushes::Stats::new(&world).print(w).ok();
+
+    if let (Some(leaves), Some(nodes), Some(area_portals), Some(clip_portal_verts)) = (
+        lumps::read_leaves(&mut bsp),
+        lumps::read_array(&mut bsp, LumpType::NODES),
+        area_portals,
+        lumps::read_array(&mut bsp, LumpType::CLIP_PORTAL_VERTICES),
+    ) {
+        let tree = leaks::TreeData {
+            nodes: &nodes,
+            planes: &world.planes,
+            leaves: &leaves,
+            area_portals: &area_portals,
+            clip_portal_verts: &clip_portal_verts,
+            has_vis: bsp.lump_info(LumpType::VISIBILITY).filelen > 0,
+        };
+        leaks::check(&world, &tree, &entities).print(w).ok();
+    }
+
+    let texdata: Vec<lumps::TexData> =
+        lumps::read_array(&mut bsp, LumpType::TEXTURE_DATA).unwrap_or_default();
+    let displacements = mesh::Displacements {
+        info: lumps::read_array(&mut bsp, LumpType::DISPLACEMENT_INFO).unwrap_or_default(),
+        verts: lumps::read_array(&mut bsp, LumpType::DISPLACEMENT_VERTICES).unwrap_or_default(),
+    };
+    let mesh = mesh::Mesh::build(&world, &texdata, &displacements);
+    mesh::write_obj(&mesh, w, "fuzz.mtl").ok();
+}
@@ -0,0 +1,211 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read, Seek, Write};
+
+use crate::bsp::{compress_lzma, decompress_lzma, BspFile, LumpType};
+use crate::error::BspError;
+use crate::policy::ParseContext;
+
+/// Static prop game lump ("sprp")
+pub const STATIC_PROPS: u32 = u32::from_be_bytes(*b"sprp");
+
+pub struct GameLump {
+    pub id: u32,
+    pub flags: u16,
+    pub version: u16,
+    /// Decompressed contents of the game lump
+    pub data: Vec<u8>,
+}
+
+pub const COMPRESSED: u16 = 1;
+
+/// Entry in the game lump directory, as stored in the file
+pub struct DirectoryEntry {
+    pub id: u32,
+    pub flags: u16,
+    pub version: u16,
+    pub fileofs: u32,
+    pub filelen: u32,
+}
+
+impl DirectoryEntry {
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.id.to_be_bytes()).into_owned()
+    }
+
+    /// Compressed game lumps are terminated by an empty entry marking the end of the last
+    /// lump's data
+    pub fn is_terminator(&self) -> bool {
+        self.id == 0 && self.filelen == 0
+    }
+}
+
+pub fn parse_directory(lump: &[u8]) -> Result<Vec<DirectoryEntry>, BspError> {
+    let mut reader = Cursor::new(lump);
+    let mut read = || -> io::Result<Vec<DirectoryEntry>> {
+        let count = reader.read_i32::<LittleEndian>()?;
+
+        (0..count)
+            .map(|_| {
+                Ok(DirectoryEntry {
+                    id: reader.read_u32::<LittleEndian>()?,
+                    flags: reader.read_u16::<LittleEndian>()?,
+                    version: reader.read_u16::<LittleEndian>()?,
+                    fileofs: reader.read_u32::<LittleEndian>()?,
+                    filelen: reader.read_u32::<LittleEndian>()?,
+                })
+            })
+            .collect()
+    };
+
+    read().map_err(|_| BspError::malformed("GAME_LUMP lump", "the directory is cut short"))
+}
+
+/// Parses the game lump directory and extracts every game lump. `lump_ofs` is the offset of the
+/// game lump in the file, since the directory stores absolute file offsets. Under the lenient
+/// policy, entries whose data is outside the lump or doesn't decompress are skipped.
+pub fn parse(
+    lump: &[u8],
+    lump_ofs: u32,
+    context: &ParseContext,
+) -> Result<Vec<GameLump>, BspError> {
+    let mut lumps = vec![];
+    for entry in parse_directory(lump)? {
+        if entry.is_terminator() {
+            continue;
+        }
+
+        let data = entry
+            .fileofs
+            .checked_sub(lump_ofs)
+            .and_then(|start| lump.get(start as usize..start as usize + entry.filelen as usize));
+        let Some(data) = data else {
+            let error = BspError::malformed(
+                format!("game lump {}", entry.name()),
+                format!("its data at {} is outside the game lump", entry.fileofs),
+            );
+            match context.tolerate(&error) {
+                true => continue,
+                false => return Err(error),
+            }
+        };
+
+        let data = if entry.flags & COMPRESSED != 0 {
+            match decompress_lzma(data) {
+                Ok(data) => data,
+                Err(e) => {
+                    let error =
+                        BspError::malformed(format!("game lump {}", entry.name()), e.to_string());
+                    match context.tolerate(&error) {
+                        true => continue,
+                        false => return Err(error),
+                    }
+                }
+            }
+        } else {
+            data.to_vec()
+        };
+
+        lumps.push(GameLump {
+            id: entry.id,
+            flags: entry.flags & !COMPRESSED,
+            version: entry.version,
+            data,
+        });
+    }
+
+    Ok(lumps)
+}
+
+/// Reads every game lump from a map.
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Vec<GameLump>, BspError> {
+    let ofs = bsp.lump_info(LumpType::GAME_LUMP).fileofs;
+    let lump = bsp.try_get_lump(LumpType::GAME_LUMP)?;
+
+    parse(&lump, ofs, bsp.context())
+}
+
+/// Serializes game lumps uncompressed. Offsets in the directory are relative to the start of
+/// the lump, and have to be relocated once the lump's position in the file is known.
+pub fn serialize(lumps: &[GameLump]) -> io::Result<Vec<u8>> {
+    const ENTRY_SIZE: usize = 16;
+
+    let mut out = vec![];
+    out.write_i32::<LittleEndian>(lumps.len() as i32)?;
+
+    let mut offset = 4 + lumps.len() * ENTRY_SIZE;
+    for lump in lumps {
+        out.write_u32::<LittleEndian>(lump.id)?;
+        out.write_u16::<LittleEndian>(lump.flags)?;
+        out.write_u16::<LittleEndian>(lump.version)?;
+        out.write_u32::<LittleEndian>(offset as u32)?;
+        out.write_u32::<LittleEndian>(lump.data.len() as u32)?;
+        offset += lump.data.len();
+    }
+
+    for lump in lumps {
+        out.extend_from_slice(&lump.data);
+    }
+
+    Ok(out)
+}
+
+/// Serializes game lumps with each one LZMA compressed, followed by the empty entry that marks
+/// the end of the last one. Offsets are relative to the start of the lump, like [`serialize`].
+pub fn serialize_compressed(lumps: &[GameLump]) -> io::Result<Vec<u8>> {
+    const ENTRY_SIZE: usize = 16;
+
+    let compressed = lumps
+        .iter()
+        .map(|lump| compress_lzma(&lump.data))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let mut out = vec![];
+    out.write_i32::<LittleEndian>(lumps.len() as i32 + 1)?;
+
+    let mut offset = 4 + (lumps.len() + 1) * ENTRY_SIZE;
+    for (lump, data) in lumps.iter().zip(&compressed) {
+        out.write_u32::<LittleEndian>(lump.id)?;
+        out.write_u16::<LittleEndian>(lump.flags | COMPRESSED)?;
+        out.write_u16::<LittleEndian>(lump.version)?;
+        out.write_u32::<LittleEndian>(offset as u32)?;
+        out.write_u32::<LittleEndian>(data.len() as u32)?;
+        offset += data.len();
+    }
+    // The terminator
+    out.write_all(&[0; 8])?;
+    out.write_u32::<LittleEndian>(offset as u32)?;
+    out.write_u32::<LittleEndian>(0)?;
+
+    for data in compressed {
+        out.extend_from_slice(&data);
+    }
+
+    Ok(out)
+}
+
+/// Adds `delta` to every offset in a serialized, uncompressed game lump directory.
+pub fn relocate(lump: &mut [u8], delta: i64) -> Result<(), BspError> {
+    let short = || BspError::malformed("GAME_LUMP lump", "the directory is cut short");
+    let count = Cursor::new(&*lump)
+        .read_i32::<LittleEndian>()
+        .map_err(|_| short())?;
+
+    for i in 0..count as usize {
+        let pos = 4 + i * 16 + 8;
+        let entry: &mut [u8; 4] = lump
+            .get_mut(pos..pos + 4)
+            .and_then(|entry| entry.try_into().ok())
+            .ok_or_else(short)?;
+
+        let fileofs = u32::from_le_bytes(*entry) as i64 + delta;
+        let fileofs = u32::try_from(fileofs).map_err(|_| {
+            BspError::malformed(
+                "GAME_LUMP lump",
+                format!("offset {fileofs} is out of range"),
+            )
+        })?;
+        *entry = fileofs.to_le_bytes();
+    }
+
+    Ok(())
+}
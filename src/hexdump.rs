@@ -0,0 +1,47 @@
+//! A view of lumps as bytes, in the layout of `hexdump -C`, for lumps nothing else in the crate
+//! reads yet.
+
+use std::io::{self, Write};
+
+const ROW: usize = 16;
+
+/// Writes `data` as rows of 16 bytes in hex and ASCII, numbered from `offset`. Runs of rows
+/// repeating the one before are shown as a single `*`, as lumps are often mostly zeroes.
+pub fn write<W: Write>(w: &mut W, data: &[u8], offset: u64) -> io::Result<()> {
+    let mut previous: Option<&[u8]> = None;
+    let mut skipping = false;
+
+    for (i, row) in data.chunks(ROW).enumerate() {
+        if previous == Some(row) && row.len() == ROW {
+            if !skipping {
+                writeln!(w, "*")?;
+                skipping = true;
+            }
+            continue;
+        }
+        previous = Some(row);
+        skipping = false;
+
+        write!(w, "{:08x} ", offset + (i * ROW) as u64)?;
+        for column in 0..ROW {
+            if column % 8 == 0 {
+                write!(w, " ")?;
+            }
+            match row.get(column) {
+                Some(byte) => write!(w, "{byte:02x} ")?,
+                None => write!(w, "   ")?,
+            }
+        }
+
+        let text: String = row
+            .iter()
+            .map(|&c| match c {
+                0x20..=0x7e => c as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(w, " |{text}|")?;
+    }
+
+    writeln!(w, "{:08x}", offset + data.len() as u64)
+}
@@ -0,0 +1,72 @@
+//! Minimal writers for 8-bit RGB PNG and floating point Radiance HDR images.
+
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::{self, Write};
+
+fn png_chunk<W: Write>(w: &mut W, ty: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(ty)?;
+    w.write_all(data)?;
+
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(ty);
+    crc.update(data);
+    w.write_all(&crc.finalize().to_be_bytes())
+}
+
+/// Writes `pixels`, `width * height` RGB triples in rows from the top, as a PNG.
+pub fn write_png<W: Write>(
+    w: &mut W,
+    width: u32,
+    height: u32,
+    pixels: &[[u8; 3]],
+) -> io::Result<()> {
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGB, default compression, filter and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    png_chunk(w, b"IHDR", &header)?;
+
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    for row in pixels.chunks_exact(width as usize) {
+        // Each row starts with its filter type, which is always none here
+        encoder.write_all(&[0])?;
+        encoder.write_all(row.as_flattened())?;
+    }
+    png_chunk(w, b"IDAT", &encoder.finish()?)?;
+
+    png_chunk(w, b"IEND", &[])
+}
+
+/// Writes `pixels`, `width * height` linear RGB triples in rows from the top, as an
+/// uncompressed Radiance RGBE image.
+pub fn write_hdr<W: Write>(
+    w: &mut W,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 3]],
+) -> io::Result<()> {
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n"
+    )?;
+
+    for pixel in pixels {
+        let max = pixel.iter().copied().fold(0.0, f32::max);
+        if max < 1e-32 {
+            w.write_all(&[0; 4])?;
+            continue;
+        }
+
+        // frexp: max = mantissa * 2^exponent with mantissa in [0.5, 1)
+        let exponent = max.log2().floor() as i32 + 1;
+        let scale = 256.0 / 2f32.powi(exponent);
+        let [r, g, b] = pixel.map(|c| (c * scale).min(255.0) as u8);
+        w.write_all(&[r, g, b, (exponent + 128) as u8])?;
+    }
+
+    Ok(())
+}
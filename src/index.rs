@@ -0,0 +1,160 @@
+//! Flattens the searchable text of a map into one document, for full text search servers that
+//! take JSON Lines.
+
+use std::collections::BTreeSet;
+use std::io::{Read, Seek};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::entities::{self, Entity};
+use crate::json::Json;
+use crate::{lumps, pak};
+
+/// Packed scripts that define soundscapes
+const SOUNDSCAPE_PREFIX: &str = "scripts/soundscapes_";
+/// Entity keys naming a soundscape
+const SOUNDSCAPE_KEYS: &[&str] = &["soundscape", "position0", "position1"];
+/// Tokens shorter than this are left out
+const MIN_TOKEN_LENGTH: usize = 2;
+
+pub struct Document {
+    pub map: String,
+    pub version: u32,
+    pub revision: u32,
+    /// Every entity key and value, one `key value` line each
+    pub entities_text: String,
+    pub classnames: BTreeSet<String>,
+    pub targetnames: BTreeSet<String>,
+    pub materials: BTreeSet<String>,
+    pub packed_files: Vec<String>,
+    pub soundscapes: BTreeSet<String>,
+}
+
+/// Names of the top level blocks of a KeyValues file, which in a soundscape script are the
+/// soundscape names
+fn top_level_names(text: &str) -> Vec<String> {
+    let mut names = vec![];
+    let mut depth = 0;
+    let mut last = None;
+
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        for token in line.split_whitespace() {
+            match token {
+                "{" => {
+                    if depth == 0 {
+                        names.extend(last.take());
+                    }
+                    depth += 1;
+                }
+                "}" => depth -= 1,
+                token if depth == 0 => last = Some(token.trim_matches('"').to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    names
+}
+
+fn entity_soundscapes(entities: &[Entity]) -> impl Iterator<Item = String> + '_ {
+    entities
+        .iter()
+        .filter(|e| {
+            e.get("classname")
+                .is_some_and(|c| c.starts_with("env_soundscape"))
+        })
+        .flat_map(|e| SOUNDSCAPE_KEYS.iter().filter_map(|key| e.get(key)))
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+impl Document {
+    pub fn new<R: Read + Seek>(bsp: &mut BspFile<R>, map: &str) -> Self {
+        let entities = bsp
+            .get_lump(LumpType::ENTITIES)
+            .and_then(|lump| entities::parse(&lump).ok())
+            .unwrap_or_default();
+
+        let mut entities_text = String::new();
+        for (key, value) in entities.iter().flat_map(|e| &e.properties) {
+            entities_text += &format!("{key} {value}\n");
+        }
+
+        let names = |key| {
+            entities
+                .iter()
+                .filter_map(|e| e.get(key))
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        let pakfile = bsp.get_lump(LumpType::PAKFILE);
+        let packed_files = pakfile
+            .clone()
+            .and_then(|data| pak::entries(&data).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+
+        let mut soundscapes: BTreeSet<String> = entity_soundscapes(&entities).collect();
+        let scripts = pakfile
+            .and_then(|data| pak::read_prefixed(&data, SOUNDSCAPE_PREFIX).ok())
+            .unwrap_or_default();
+        for (_, script) in scripts {
+            soundscapes.extend(top_level_names(&String::from_utf8_lossy(&script)));
+        }
+
+        Self {
+            map: map.to_string(),
+            version: bsp.version(),
+            revision: bsp.map_revision(),
+            entities_text,
+            classnames: names("classname"),
+            targetnames: names("targetname"),
+            materials: lumps::texture_names(bsp)
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            packed_files,
+            soundscapes,
+        }
+    }
+
+    /// Unique lowercase words from every field, split at anything that isn't a letter or digit.
+    /// Entity keys are left out, only their values are searchable.
+    pub fn tokens(&self) -> BTreeSet<String> {
+        let values = self
+            .entities_text
+            .lines()
+            .filter_map(|line| Some(line.split_once(' ')?.1));
+        let text = values
+            .chain([self.map.as_str()])
+            .chain(self.materials.iter().map(String::as_str))
+            .chain(self.packed_files.iter().map(String::as_str))
+            .chain(self.soundscapes.iter().map(String::as_str));
+
+        text.flat_map(|text| text.split(|c: char| !c.is_alphanumeric()))
+            .filter(|token| token.chars().count() >= MIN_TOKEN_LENGTH)
+            .map(str::to_lowercase)
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Json {
+        let set = |set: &BTreeSet<String>| Json::from(set.iter().cloned().collect::<Vec<_>>());
+
+        Json::object([
+            ("map", Json::from(self.map.as_str())),
+            ("version", self.version.into()),
+            ("revision", self.revision.into()),
+            ("entities_text", self.entities_text.as_str().into()),
+            ("classnames", set(&self.classnames)),
+            ("targetnames", set(&self.targetnames)),
+            ("materials", set(&self.materials)),
+            ("packed_files", self.packed_files.clone().into()),
+            ("soundscapes", set(&self.soundscapes)),
+            ("tokens", set(&self.tokens())),
+        ])
+    }
+}
@@ -0,0 +1,416 @@
+//! Pipelines that run the same steps over many maps, described by a jobs file, for building the
+//! copies of a map pool that get deployed to servers and fast download hosts.
+//!
+//! A jobs file is JSON, or YAML when it ends in `.yaml` or `.yml`:
+//!
+//! ```yaml
+//! output: dist
+//! parallel: 4
+//! maps:
+//!   - maps/ctf_2fort.bsp
+//!   - maps/pool          # every .bsp in the directory
+//! steps:
+//!   - validate
+//!   - strip: ldr         # a preset, or a list of lump names
+//!   - optimize           # removes deprecated lumps
+//!   - repack             # or `repack: decompress`
+//!   - bz2
+//!   - manifest
+//! ```
+//!
+//! Each map goes through the steps in order and is written to the output directory, `bz2` also
+//! writing the map as it is at that point next to it. `manifest` records the map in
+//! `manifest.json` in the output directory, or wherever the `manifest` key says. A map stops at
+//! the first step that fails, which for `validate` is any error. Paths are relative to the jobs
+//! file.
+
+use std::fs;
+use std::io::{self, Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::checksum::hex;
+use crate::json::Json;
+use crate::policy::Policy;
+use crate::validate::Severity;
+use crate::writer::LumpWriter;
+use crate::{crypt, fastdl, parallel, repack, strip, validate, yaml};
+
+pub enum Step {
+    Validate,
+    Strip(Vec<LumpType>),
+    Repack { compress: bool },
+    Bz2,
+    Manifest,
+}
+
+pub struct Jobs {
+    pub maps: Vec<PathBuf>,
+    pub steps: Vec<Step>,
+    pub output: PathBuf,
+    pub manifest: PathBuf,
+    pub parallel: usize,
+}
+
+fn step(value: &Json) -> Result<Step, String> {
+    let (name, argument) = match value {
+        Json::String(name) => (name.as_str(), None),
+        Json::Object(fields) if fields.len() == 1 => (fields[0].0.as_str(), Some(&fields[0].1)),
+        _ => return Err("steps are a name, or a name and its argument".to_string()),
+    };
+
+    Ok(match (name, argument) {
+        ("validate", None) => Step::Validate,
+        ("strip", Some(Json::String(preset))) => match strip::preset(preset) {
+            Some(lumps) => Step::Strip(lumps.to_vec()),
+            None => return Err(format!("unknown strip preset {preset}")),
+        },
+        ("strip", Some(Json::Array(names))) => Step::Strip(
+            names
+                .iter()
+                .map(|name| {
+                    let name = name.as_str().unwrap_or_default();
+                    LumpType::from_name(name).ok_or_else(|| format!("unknown lump {name}"))
+                })
+                .collect::<Result<_, _>>()?,
+        ),
+        ("strip", _) => return Err("strip takes a preset or a list of lumps".to_string()),
+        ("repack", None) => Step::Repack { compress: true },
+        ("repack", Some(Json::String(mode))) if mode == "decompress" => {
+            Step::Repack { compress: false }
+        }
+        ("repack", Some(_)) => return Err("repack only takes decompress".to_string()),
+        ("optimize", None) => Step::Strip(strip::LEGACY.to_vec()),
+        ("bz2", None) => Step::Bz2,
+        ("manifest", None) => Step::Manifest,
+        (name, None) => return Err(format!("unknown step {name}")),
+        (name, Some(_)) => return Err(format!("step {name} takes no argument")),
+    })
+}
+
+/// The maps in a directory, or a map by itself
+fn expand(path: PathBuf) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path]);
+    }
+
+    let entries = fs::read_dir(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut maps: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("bsp"))
+        })
+        .collect();
+    maps.sort();
+    Ok(maps)
+}
+
+impl Jobs {
+    /// Reads the jobs file at `path`, with paths in it relative to its directory
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let config = match is_yaml {
+            true => yaml::parse(&text),
+            false => Json::parse(&text),
+        }
+        .map_err(|e| e.to_string())?;
+
+        Self::from_config(&config, path.parent().unwrap_or(Path::new("")))
+    }
+
+    pub fn from_config(config: &Json, base: &Path) -> Result<Self, String> {
+        let path = |key: &str| -> Result<Option<PathBuf>, String> {
+            match config.get(key) {
+                None => Ok(None),
+                Some(Json::String(path)) => Ok(Some(base.join(path))),
+                Some(_) => Err(format!("{key} should be a path")),
+            }
+        };
+
+        let Some(output) = path("output")? else {
+            return Err("the jobs file needs an output directory".to_string());
+        };
+        let manifest = path("manifest")?.unwrap_or_else(|| output.join("manifest.json"));
+
+        let mut maps = vec![];
+        for map in config
+            .get("maps")
+            .and_then(Json::as_array)
+            .unwrap_or_default()
+        {
+            let Some(map) = map.as_str() else {
+                return Err("maps should be a list of paths".to_string());
+            };
+            maps.extend(expand(base.join(map))?);
+        }
+
+        let steps = match config.get("steps") {
+            Some(Json::Array(steps)) => steps.iter().map(step).collect::<Result<_, _>>()?,
+            _ => return Err("the jobs file needs a list of steps".to_string()),
+        };
+
+        let parallel = match config.get("parallel") {
+            None => parallel::default_threads(),
+            Some(Json::Number(n)) if *n >= 1.0 => *n as usize,
+            Some(_) => return Err("parallel should be a number of maps".to_string()),
+        };
+
+        Ok(Self {
+            maps,
+            steps,
+            output,
+            manifest,
+            parallel,
+        })
+    }
+
+    fn needs_manifest(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, Step::Manifest))
+    }
+}
+
+pub struct Artifact {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: [u8; 32],
+}
+
+impl Artifact {
+    fn write(path: PathBuf, data: &[u8]) -> io::Result<Self> {
+        fs::write(&path, data)?;
+        Ok(Self {
+            path,
+            size: data.len() as u64,
+            sha256: Sha256::digest(data).into(),
+        })
+    }
+
+    fn to_json(&self) -> Json {
+        Json::object([
+            (
+                "file",
+                Json::from(
+                    self.path
+                        .file_name()
+                        .unwrap_or(self.path.as_os_str())
+                        .to_string_lossy()
+                        .as_ref(),
+                ),
+            ),
+            ("size", self.size.into()),
+            ("sha256", hex(&self.sha256).into()),
+        ])
+    }
+}
+
+#[derive(Default)]
+pub struct Processed {
+    pub input_size: u64,
+    pub warnings: usize,
+    pub map: Option<Artifact>,
+    pub bz2: Option<Artifact>,
+    /// Set by the manifest step, with the map as it was then
+    pub manifest_entry: Option<Json>,
+}
+
+pub struct Outcome {
+    pub map: PathBuf,
+    pub result: Result<Processed, String>,
+    pub elapsed: Duration,
+}
+
+/// Applies an edit to the map in `data`, returning the edited map or `None` if nothing changed
+fn rewrite(
+    data: &[u8],
+    policy: Policy,
+    edit: impl FnOnce(&mut BspFile<Cursor<&[u8]>>, &mut LumpWriter) -> io::Result<bool>,
+) -> Result<Option<Vec<u8>>, String> {
+    let data = crypt::decrypt(data).map_err(|e| e.to_string())?;
+    let mut reader = Cursor::new(&data[..]);
+    let mut bsp = BspFile::from_slice(&mut reader)
+        .map_err(|e| e.to_string())?
+        .with_policy(policy);
+    let mut writer = LumpWriter::from_bsp(&mut bsp).map_err(|e| e.to_string())?;
+    if !edit(&mut bsp, &mut writer).map_err(|e| e.to_string())? {
+        return Ok(None);
+    }
+
+    let mut out = vec![];
+    writer.write_to(&mut out).map_err(|e| e.to_string())?;
+    Ok(Some(out))
+}
+
+fn process(jobs: &Jobs, path: &Path, policy: Policy) -> Result<Processed, String> {
+    let name = path.file_name().ok_or("not a file")?;
+    let out_path = jobs.output.join(name);
+    if fs::canonicalize(&out_path).ok() == fs::canonicalize(path).ok() {
+        return Err("the output would overwrite the map".to_string());
+    }
+
+    let mut data = fs::read(path).map_err(|e| e.to_string())?;
+    let mut processed = Processed {
+        input_size: data.len() as u64,
+        ..Default::default()
+    };
+
+    for step in &jobs.steps {
+        let edited = match step {
+            Step::Validate => {
+                let data = crypt::decrypt(&data).map_err(|e| e.to_string())?;
+                let mut reader = Cursor::new(&data[..]);
+                let bsp = BspFile::from_slice(&mut reader).map_err(|e| e.to_string())?;
+                let report = validate::validate(&mut bsp.with_policy(policy));
+
+                let mut errors = report
+                    .findings
+                    .iter()
+                    .filter(|f| f.severity == Severity::Error);
+                if let Some(first) = errors.next() {
+                    return Err(format!("validation failed, {}", first.message));
+                }
+                processed.warnings += report.findings.len();
+                None
+            }
+            Step::Strip(lumps) => rewrite(&data, policy, |_, writer| {
+                Ok(!strip::strip(writer, lumps).is_empty())
+            })?,
+            Step::Repack { compress } => rewrite(&data, policy, |bsp, writer| {
+                Ok(!repack::repack(bsp, writer, *compress)?.is_empty())
+            })?,
+            Step::Bz2 => {
+                let compressed = fastdl::compress(&data).map_err(|e| e.to_string())?;
+                let bz2_path = fastdl::path(&out_path);
+                processed.bz2 =
+                    Some(Artifact::write(bz2_path, &compressed).map_err(|e| e.to_string())?);
+                None
+            }
+            Step::Manifest => {
+                processed.manifest_entry = Some(Json::object([
+                    ("map", Json::from(name.to_string_lossy().as_ref())),
+                    ("size", data.len().into()),
+                    ("sha256", hex(&Sha256::digest(&data)).into()),
+                    ("bz2", processed.bz2.as_ref().map(Artifact::to_json).into()),
+                ]));
+                None
+            }
+        };
+
+        if let Some(edited) = edited {
+            data = edited;
+        }
+    }
+
+    processed.map = Some(Artifact::write(out_path, &data).map_err(|e| e.to_string())?);
+    Ok(processed)
+}
+
+/// Runs the jobs over every map, `parallel` maps at a time, and writes the manifest. Outcomes
+/// are in the order of the maps.
+pub fn run(jobs: &Jobs, policy: Policy) -> io::Result<Vec<Outcome>> {
+    fs::create_dir_all(&jobs.output)?;
+
+    let outcomes = parallel::map(&jobs.maps, jobs.parallel, |map| {
+        let start = Instant::now();
+        Outcome {
+            map: map.clone(),
+            result: process(jobs, map, policy),
+            elapsed: start.elapsed(),
+        }
+    });
+
+    if jobs.needs_manifest() {
+        let entries = outcomes
+            .iter()
+            .filter_map(|outcome| outcome.result.as_ref().ok()?.manifest_entry.as_ref())
+            .cloned()
+            .collect();
+        let manifest = Json::object([("maps", Json::Array(entries))]);
+        fs::write(&jobs.manifest, format!("{manifest}\n"))?;
+    }
+
+    Ok(outcomes)
+}
+
+pub fn print_summary<W: Write>(outcomes: &[Outcome], w: &mut W) -> io::Result<()> {
+    for outcome in outcomes {
+        let map = outcome.map.display();
+        let seconds = outcome.elapsed.as_secs_f64();
+        match &outcome.result {
+            Ok(processed) => {
+                let size = processed.map.as_ref().map_or(0, |a| a.size);
+                write!(w, "{map}: {} -> {size} bytes", processed.input_size)?;
+                if let Some(bz2) = &processed.bz2 {
+                    write!(w, ", {} bytes compressed", bz2.size)?;
+                }
+                writeln!(w, ", {} warnings ({seconds:.1}s)", processed.warnings)?;
+            }
+            Err(message) => writeln!(w, "error: {map}: {message} ({seconds:.1}s)")?,
+        }
+    }
+
+    let failed = outcomes.iter().filter(|o| o.result.is_err()).count();
+    writeln!(w, "{} maps, {failed} failed", outcomes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jobs(text: &str) -> Result<Jobs, String> {
+        let config = yaml::parse(text).map_err(|e| e.to_string())?;
+        Jobs::from_config(&config, Path::new("pool"))
+    }
+
+    #[test]
+    fn yaml_jobs_file_is_read() {
+        let jobs = jobs(
+            "
+output: dist
+parallel: 2
+maps: [ctf_2fort.bsp]
+steps:
+  - validate
+  - strip: [LIGHTING, LIGHTING_HDR]
+  - repack: decompress
+  - bz2
+",
+        )
+        .unwrap();
+
+        assert_eq!(jobs.maps, [Path::new("pool/ctf_2fort.bsp")]);
+        assert_eq!(jobs.output, Path::new("pool/dist"));
+        assert_eq!(jobs.manifest, Path::new("pool/dist/manifest.json"));
+        assert_eq!(jobs.parallel, 2);
+        assert!(matches!(
+            jobs.steps[..],
+            [
+                Step::Validate,
+                Step::Strip(ref lumps),
+                Step::Repack { compress: false },
+                Step::Bz2,
+            ] if lumps[..] == [LumpType::LIGHTING, LumpType::LIGHTING_HDR]
+        ));
+    }
+
+    #[test]
+    fn bad_steps_are_refused() {
+        for step in [
+            "compress",
+            "strip: [NOT_A_LUMP]",
+            "strip: true",
+            "repack: compress",
+            "bz2: fast",
+        ] {
+            let text = format!("output: dist\nsteps:\n  - {step}");
+            assert!(jobs(&text).is_err(), "{step}");
+        }
+        assert!(jobs("output: dist\nsteps: validate").is_err());
+    }
+}
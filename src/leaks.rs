@@ -0,0 +1,244 @@
+//! Signs a map leaked or was compiled with a cordon, which reviewers otherwise find by opening
+//! it in Hammer. These are heuristics read off the compiled tree, so findings are leads to
+//! check rather than proof.
+
+use crate::entities::Entity;
+use crate::lumps::{AreaPortal, Leaf, Model, Node, Plane, Vector, World};
+use crate::tree;
+use crate::validate::Report;
+
+const CONTENTS_SOLID: i32 = 0x1;
+/// Slack on leaf bounds, which are rounded out to whole units
+const TOLERANCE: f32 = 1.0;
+/// Share of the world's extent a leaf spans on every axis to count as the whole world
+const GIANT_LEAF_SHARE: f32 = 0.9;
+/// Hammer's default cordon material
+const CORDON_MATERIAL: &str = "tools/toolsskybox";
+/// Clip portals with less area than this, in square units, can't be seen through
+const MIN_PORTAL_AREA: f32 = 1.0;
+
+/// Leaves of the world's tree, found by walking it from the world's head node
+fn world_leaves(nodes: &[Node], models: &[Model]) -> Vec<usize> {
+    let mut leaves = vec![];
+    let mut stack = models
+        .first()
+        .map(|m| m.headnode)
+        .into_iter()
+        .collect::<Vec<_>>();
+    let mut visited = vec![false; nodes.len()];
+    while let Some(child) = stack.pop() {
+        if child < 0 {
+            leaves.push((-1 - child) as usize);
+            continue;
+        }
+
+        // A node reached twice means the tree is malformed, which validate reports
+        match visited.get_mut(child as usize) {
+            Some(seen @ false) => *seen = true,
+            _ => continue,
+        }
+        stack.extend(nodes[child as usize].children);
+    }
+
+    leaves.sort_unstable();
+    leaves.dedup();
+    leaves
+}
+
+/// Whether a leaf reaches the edge of the world's bounds. Compiling fills everything outside a
+/// sealed map with solid, so an empty leaf out there means the void is open.
+fn touches_bounds(leaf: &Leaf, world: &Model) -> bool {
+    (0..3).any(|axis| {
+        leaf.mins[axis] as f32 <= world.mins[axis] + TOLERANCE
+            || leaf.maxs[axis] as f32 >= world.maxs[axis] - TOLERANCE
+    })
+}
+
+fn parse_origin(value: &str) -> Option<Vector> {
+    let mut parts = value.split_whitespace().map(str::parse::<f32>);
+    let origin = [
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    ];
+    parts.next().is_none().then_some(origin)
+}
+
+fn describe(index: usize, entity: &Entity) -> String {
+    let classname = entity.get("classname").unwrap_or_default();
+    match entity.get("targetname") {
+        Some(name) => format!("{classname} \"{name}\" (entity {index})"),
+        None => format!("{classname} (entity {index})"),
+    }
+}
+
+fn polygon_area(vertices: &[Vector]) -> f32 {
+    let mut sum = [0.0f32; 3];
+    for i in 1..vertices.len().saturating_sub(1) {
+        let [a, b, c] = [vertices[0], vertices[i], vertices[i + 1]];
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        sum[0] += u[1] * v[2] - u[2] * v[1];
+        sum[1] += u[2] * v[0] - u[0] * v[2];
+        sum[2] += u[0] * v[1] - u[1] * v[0];
+    }
+    (sum[0] * sum[0] + sum[1] * sum[1] + sum[2] * sum[2]).sqrt() / 2.0
+}
+
+/// Finds the sides of the world boxed in by brushes made only of the cordon material, as
+/// Hammer adds around the cordoned area when compiling with a cordon
+fn cordon_sides(world: &World, bounds: &Model) -> usize {
+    let mut sides = [false; 6];
+    for brush in &world.brushes {
+        let start = usize::try_from(brush.firstside).unwrap_or(usize::MAX);
+        let Some(brush_sides) = world
+            .brush_sides
+            .get(start..start.saturating_add(brush.numsides.max(0) as usize))
+        else {
+            continue;
+        };
+        let cordon = !brush_sides.is_empty()
+            && brush_sides
+                .iter()
+                .filter(|side| side.bevel == 0)
+                .all(|side| {
+                    world
+                        .texinfo_name(side.texinfo)
+                        .is_some_and(|name| name.eq_ignore_ascii_case(CORDON_MATERIAL))
+                });
+        let Some((mins, maxs)) = cordon.then(|| world.brush_bounds(brush)).flatten() else {
+            continue;
+        };
+
+        for axis in 0..3 {
+            sides[axis * 2] |= mins[axis] <= bounds.mins[axis] + TOLERANCE;
+            sides[axis * 2 + 1] |= maxs[axis] >= bounds.maxs[axis] - TOLERANCE;
+        }
+    }
+    sides.iter().filter(|&&side| side).count()
+}
+
+/// Lumps the checks read, beyond the world geometry
+pub struct TreeData<'a> {
+    pub nodes: &'a [Node],
+    pub planes: &'a [Plane],
+    pub leaves: &'a [Leaf],
+    pub area_portals: &'a [AreaPortal],
+    pub clip_portal_verts: &'a [Vector],
+    pub has_vis: bool,
+}
+
+pub fn check(world: &World, tree: &TreeData, entities: &[Entity]) -> Report {
+    let mut report = Report::default();
+    let Some(bounds) = world.models.first() else {
+        report.error("map has no world model");
+        return report;
+    };
+    if tree.nodes.is_empty() {
+        report.warning("map has no BSP tree to look for leaks in");
+        return report;
+    }
+
+    let leaves: Vec<usize> = world_leaves(tree.nodes, &world.models)
+        .into_iter()
+        .filter(|&i| {
+            tree.leaves
+                .get(i)
+                .is_some_and(|l| l.contents & CONTENTS_SOLID == 0)
+        })
+        .collect();
+    let outside: Vec<usize> = leaves
+        .iter()
+        .copied()
+        .filter(|&i| touches_bounds(&tree.leaves[i], bounds))
+        .collect();
+
+    if !outside.is_empty() {
+        let vis = if tree.has_vis {
+            ""
+        } else {
+            ", which also keeps vvis from running"
+        };
+        report.error(format!(
+            "{} empty leaves reach the edge of the world, so the map probably leaked{vis}",
+            outside.len()
+        ));
+    }
+
+    let extent = |axis: usize| (bounds.maxs[axis] - bounds.mins[axis]).max(1.0);
+    for &i in &leaves {
+        let leaf = &tree.leaves[i];
+        let giant = (0..3).all(|axis| {
+            (leaf.maxs[axis] as f32 - leaf.mins[axis] as f32) >= extent(axis) * GIANT_LEAF_SHARE
+        });
+        if giant {
+            report.warning(format!(
+                "leaf {i} spans nearly the whole world, so the map's {} empty leaves barely split it, as when everything is func_detail or the map leaked",
+                leaves.len()
+            ));
+        }
+    }
+
+    // The pointfile of a leak leads from an entity out into the void, and entities in solid
+    // are lost to the engine as well
+    for (i, entity) in entities.iter().enumerate() {
+        if entity
+            .get("model")
+            .is_some_and(|model| model.starts_with('*'))
+            || entity.get("classname") == Some("worldspawn")
+        {
+            continue;
+        }
+        let Some(origin) = entity.get("origin").and_then(parse_origin) else {
+            continue;
+        };
+
+        match tree::locate(tree.nodes, tree.planes, &world.models, origin) {
+            Some(leaf) if outside.contains(&leaf) => report.error(format!(
+                "{} at ({} {} {}) is in a leaf reaching the edge of the world, a leak starts here",
+                describe(i, entity),
+                origin[0],
+                origin[1],
+                origin[2]
+            )),
+            Some(leaf)
+                if tree
+                    .leaves
+                    .get(leaf)
+                    .is_some_and(|l| l.contents & CONTENTS_SOLID == 0) => {}
+            _ => report.warning(format!(
+                "{} at ({} {} {}) is inside solid or outside the world",
+                describe(i, entity),
+                origin[0],
+                origin[1],
+                origin[2]
+            )),
+        }
+    }
+
+    let sides = cordon_sides(world, bounds);
+    if sides == 6 {
+        report.warning(format!(
+            "the world is boxed in by {CORDON_MATERIAL} brushes on every side, as compiling with a cordon does"
+        ));
+    }
+
+    // Portal 0 is a placeholder no area refers to
+    for portal in tree.area_portals.iter().skip(1) {
+        let start = portal.first_clip_portal_vert as usize;
+        let verts = tree
+            .clip_portal_verts
+            .get(start..start + portal.clip_portal_verts as usize)
+            .unwrap_or_default();
+        if polygon_area(verts) < MIN_PORTAL_AREA {
+            report.warning(format!(
+                "area portal {} into area {} has {} vertices and no area, so it can't be seen through",
+                portal.portal_key,
+                portal.other_area,
+                verts.len()
+            ));
+        }
+    }
+
+    report
+}
@@ -0,0 +1,106 @@
+//! Reading, analysis and rewriting of Source engine BSP maps.
+//!
+//! The `bspinfo` binary is a thin command line front end over these modules.
+//!
+//! # Stable API
+//!
+//! The types re-exported at the crate root are the part of the crate other tools can depend
+//! on: they only change in breaking ways with a new major version. Enums and report structs that
+//! are likely to grow are `#[non_exhaustive]`, so new formats, policies and fields can be added
+//! in minor versions. The modules themselves follow the needs of the command line tool and may
+//! change between any two releases.
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! let mut file = File::open("ctf_2fort.bsp")?;
+//! let mut bsp = bspinfo::BspFile::new(&mut file)?.with_policy(bspinfo::Policy::Lenient);
+//! for entity in bspinfo::entities::try_read(&mut bsp)? {
+//!     println!("{}", entity.get("classname").unwrap_or_default());
+//! }
+//! if let Some(mut pak) = bspinfo::PakFile::read(&mut bsp)? {
+//!     println!("{} packed files", pak.entries()?.len());
+//! }
+//! # Ok::<(), bspinfo::BspError>(())
+//! ```
+
+pub mod assets;
+pub mod backup;
+pub mod batch;
+pub mod brushes;
+pub mod bsp;
+pub mod checksum;
+pub mod clipgaps;
+pub mod compileinfo;
+pub mod connections;
+pub mod crypt;
+pub mod csv;
+pub mod cubemaps;
+pub mod decals;
+pub mod demo;
+pub mod deps;
+pub mod diff;
+pub mod displacements;
+pub mod edicts;
+pub mod entities;
+pub mod error;
+pub mod faceids;
+pub mod fastdl;
+pub mod fgd;
+pub mod forecast;
+#[doc(hidden)]
+pub mod fuzz;
+pub mod gamelump;
+pub mod hexdump;
+pub mod image;
+pub mod index;
+pub mod jobs;
+pub mod json;
+pub mod leaks;
+pub mod lighting;
+pub mod lightmaps;
+pub mod limits;
+pub mod lumps;
+pub mod magic;
+pub mod mdl;
+pub mod mesh;
+pub mod mmap;
+pub mod occluders;
+pub mod output;
+pub mod overlays;
+pub mod overview;
+pub mod pak;
+pub mod parallel;
+pub mod physics;
+pub mod placement;
+pub mod policy;
+pub mod portals;
+pub mod primitives;
+pub mod redact;
+pub mod repack;
+pub mod reslist;
+pub mod retexture;
+pub mod sky;
+pub mod splits;
+pub mod staticprops;
+pub mod stream;
+pub mod strip;
+pub mod thumbnails;
+pub mod transform;
+pub mod tree;
+pub mod validate;
+pub mod vis;
+pub mod vmt;
+pub mod vtf;
+pub mod watch;
+pub mod water;
+pub mod worldlights;
+pub mod writer;
+pub mod xzp;
+pub mod yaml;
+
+pub use bsp::{BspFile, BspFormat, LumpInfo, LumpType};
+pub use entities::Entity;
+pub use error::{BspError, Error};
+pub use pak::{PakEntry, PakFile};
+pub use policy::{ParseContext, Policy};
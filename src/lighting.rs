@@ -0,0 +1,332 @@
+//! Summary of a map's baked lighting: which of the LDR and HDR passes were compiled, the
+//! lights they contain, how much lightmap data the faces use and the ambient samples props are
+//! lit with.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lightmaps::decode_sample;
+use crate::lumps::{
+    self, Face, Leaf, LeafAmbientIndex, LeafAmbientSample, TexInfo, SURF_BUMPLIGHT,
+};
+
+const LVLFLAGS_BAKED_STATIC_PROP_LIGHTING_NONHDR: u32 = 0x1;
+const LVLFLAGS_BAKED_STATIC_PROP_LIGHTING_HDR: u32 = 0x2;
+
+const WORLD_LIGHT_TYPES: [&str; 6] = ["surface", "point", "spot", "sky", "quake", "sky ambient"];
+
+pub struct Pass {
+    pub name: &'static str,
+    pub lighting_size: Option<usize>,
+    /// Light counts by emit type, indexed like [`WORLD_LIGHT_TYPES`]
+    pub world_lights: [usize; WORLD_LIGHT_TYPES.len()],
+    pub lit_faces: usize,
+    pub luxels: u64,
+    /// None for maps whose leaves store their ambient lighting themselves, or where it couldn't
+    /// be read, which the parse context reports
+    pub ambient: Option<Ambient>,
+}
+
+/// The pass's leaf ambient lighting, which props take their lighting from
+pub struct Ambient {
+    pub samples: usize,
+    /// Leaves that aren't solid, which should all have samples
+    pub open_leaves: usize,
+    /// Open leaves without samples, where props are lit black
+    pub unsampled_leaves: usize,
+    /// Index entries that point past the end of the samples
+    pub bad_indices: usize,
+    /// Mean light of every sample's cube, where 1.0 is full brightness
+    pub average: [f32; 3],
+}
+
+impl Ambient {
+    pub fn samples_per_leaf(&self) -> f64 {
+        let sampled = self.open_leaves - self.unsampled_leaves;
+        if sampled == 0 {
+            0.0
+        } else {
+            self.samples as f64 / sampled as f64
+        }
+    }
+}
+
+const CONTENTS_SOLID: i32 = 0x1;
+
+fn read_ambient<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    [index_lump, samples_lump]: [LumpType; 2],
+    leaves: &[Leaf],
+) -> Option<Ambient> {
+    let indices: Vec<LeafAmbientIndex> = lumps::read_array(bsp, index_lump)?;
+    let samples: Vec<LeafAmbientSample> = lumps::read_array(bsp, samples_lump)?;
+
+    let mut open_leaves = 0;
+    let mut unsampled_leaves = 0;
+    for (i, leaf) in leaves.iter().enumerate() {
+        if leaf.contents & CONTENTS_SOLID != 0 {
+            continue;
+        }
+        open_leaves += 1;
+        if indices
+            .get(i)
+            .is_none_or(|index| index.ambient_sample_count == 0)
+        {
+            unsampled_leaves += 1;
+        }
+    }
+
+    let bad_indices = indices
+        .iter()
+        .filter(|index| {
+            index.first_ambient_sample as usize + index.ambient_sample_count as usize
+                > samples.len()
+        })
+        .count();
+
+    let mut total = [0.0; 3];
+    for sample in &samples {
+        for color in &sample.cube {
+            let light = decode_sample(color);
+            for c in 0..3 {
+                total[c] += light[c];
+            }
+        }
+    }
+    let count = (samples.len() * 6).max(1) as f32;
+
+    Some(Ambient {
+        samples: samples.len(),
+        open_leaves,
+        unsampled_leaves,
+        bad_indices,
+        average: total.map(|c| c / count),
+    })
+}
+
+pub struct Report {
+    pub passes: [Pass; 2],
+    pub map_flags: Option<u32>,
+}
+
+fn count_world_lights(data: &[u8], version: u32) -> Option<[usize; WORLD_LIGHT_TYPES.len()]> {
+    // Version 1 added a shadow cast offset after the normal
+    let (size, type_ofs) = if version >= 1 { (100, 52) } else { (88, 40) };
+
+    let mut counts = [0; WORLD_LIGHT_TYPES.len()];
+    for light in data.chunks_exact(size) {
+        let ty = (&light[type_ofs..]).read_i32::<LittleEndian>().ok()?;
+        if let Some(count) = usize::try_from(ty).ok().and_then(|ty| counts.get_mut(ty)) {
+            *count += 1;
+        }
+    }
+
+    Some(counts)
+}
+
+fn read_pass<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    name: &'static str,
+    [lighting, world_lights, faces, ambient_index, ambient]: [LumpType; 5],
+    texinfo: &[TexInfo],
+) -> Option<Pass> {
+    let lighting_size = bsp.get_lump(lighting).map(|data| data.len());
+
+    let version = bsp.lump_info(world_lights).version;
+    let world_lights = match bsp.get_lump(world_lights) {
+        Some(data) => count_world_lights(&data, version)?,
+        None => [0; WORLD_LIGHT_TYPES.len()],
+    };
+
+    let faces: Vec<Face> = lumps::read_array(bsp, faces)?;
+    let mut lit_faces = 0;
+    let mut luxels = 0;
+    for face in faces.iter().filter(|f| f.lightofs != -1) {
+        let [w, h] = face
+            .lightmap_texture_size_in_luxels
+            .map(|s| u64::try_from(s).map_or(0, |s| s + 1));
+        let styles = face.styles.iter().filter(|&&s| s != 255).count() as u64;
+        let bumped = usize::try_from(face.texinfo)
+            .ok()
+            .and_then(|i| texinfo.get(i))
+            .is_some_and(|t| t.flags & SURF_BUMPLIGHT != 0);
+
+        // Bumpmapped faces store a lightmap for each of the three basis directions as well
+        lit_faces += 1;
+        let count = w
+            .saturating_mul(h)
+            .saturating_mul(styles * if bumped { 4 } else { 1 });
+        luxels = u64::saturating_add(luxels, count);
+    }
+
+    // Version 0 leaves store their ambient lighting themselves
+    let ambient = match bsp.lump_info(LumpType::LEAVES).version {
+        0 => None,
+        _ => lumps::read_leaves(bsp)
+            .and_then(|leaves| read_ambient(bsp, [ambient_index, ambient], &leaves)),
+    };
+
+    Some(Pass {
+        name,
+        lighting_size,
+        world_lights,
+        lit_faces,
+        luxels,
+        ambient,
+    })
+}
+
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    let texinfo: Vec<TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)?;
+
+    let ldr = read_pass(
+        bsp,
+        "LDR",
+        [
+            LumpType::LIGHTING,
+            LumpType::WORLD_LIGHTS,
+            LumpType::FACES,
+            LumpType::LEAF_AMBIENT_INDEX,
+            LumpType::LEAF_AMBIENT_LIGHTING,
+        ],
+        &texinfo,
+    )?;
+    let hdr = read_pass(
+        bsp,
+        "HDR",
+        [
+            LumpType::LIGHTING_HDR,
+            LumpType::WORLD_LIGHTS_HDR,
+            LumpType::FACES_HDR,
+            LumpType::LEAF_AMBIENT_INDEX_HDR,
+            LumpType::LEAF_AMBIENT_LIGHTING_HDR,
+        ],
+        &texinfo,
+    )?;
+
+    let map_flags = bsp
+        .get_lump(LumpType::MAP_FLAGS)
+        .and_then(|data| (&data[..]).read_u32::<LittleEndian>().ok());
+
+    Some(Report {
+        passes: [ldr, hdr],
+        map_flags,
+    })
+}
+
+/// Names of the MAP_FLAGS bits set in `flags`
+pub fn map_flag_names(flags: u32) -> Vec<&'static str> {
+    let mut names = vec![];
+    if flags & LVLFLAGS_BAKED_STATIC_PROP_LIGHTING_NONHDR != 0 {
+        names.push("baked LDR static prop lighting");
+    }
+    if flags & LVLFLAGS_BAKED_STATIC_PROP_LIGHTING_HDR != 0 {
+        names.push("baked HDR static prop lighting");
+    }
+    names
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for pass in &self.passes {
+            match pass.lighting_size {
+                Some(size) => writeln!(w, "{} lighting: {size} bytes", pass.name)?,
+                None => writeln!(w, "{} lighting: not compiled", pass.name)?,
+            }
+
+            let lights: Vec<String> = WORLD_LIGHT_TYPES
+                .iter()
+                .zip(pass.world_lights)
+                .filter(|&(_, count)| count != 0)
+                .map(|(ty, count)| format!("{count} {ty}"))
+                .collect();
+            let total: usize = pass.world_lights.iter().sum();
+            if lights.is_empty() {
+                writeln!(w, "  World lights: 0")?;
+            } else {
+                writeln!(w, "  World lights: {total} ({})", lights.join(", "))?;
+            }
+
+            writeln!(
+                w,
+                "  Lightmapped faces: {}, {} luxels ({} bytes)",
+                pass.lit_faces,
+                pass.luxels,
+                pass.luxels.saturating_mul(4)
+            )?;
+
+            let compiled = pass.lighting_size.is_some();
+            if let Some(ambient) = pass.ambient.as_ref().filter(|a| compiled || a.samples != 0) {
+                let [r, g, b] = ambient.average;
+                writeln!(
+                    w,
+                    "  Leaf ambient samples: {} in {} of {} open leaves, {:.1} per leaf, average light {r:.3} {g:.3} {b:.3}",
+                    ambient.samples,
+                    ambient.open_leaves - ambient.unsampled_leaves,
+                    ambient.open_leaves,
+                    ambient.samples_per_leaf()
+                )?;
+            }
+        }
+
+        match self.map_flags {
+            Some(flags) => {
+                let names = map_flag_names(flags);
+                if names.is_empty() {
+                    writeln!(w, "Map flags: {flags:#x}")?;
+                } else {
+                    writeln!(w, "Map flags: {flags:#x} ({})", names.join(", "))?;
+                }
+            }
+            None => writeln!(w, "Map flags: none")?,
+        }
+
+        let [ldr, hdr] = &self.passes;
+        match (ldr.lighting_size, hdr.lighting_size) {
+            (Some(_), None) => writeln!(
+                w,
+                "warning: map was compiled with LDR lighting only, HDR is unavailable on it"
+            )?,
+            (None, Some(_)) => writeln!(
+                w,
+                "warning: map was compiled with HDR lighting only, it is fullbright with HDR disabled"
+            )?,
+            (None, None) => writeln!(w, "warning: map has no lighting, it will be fullbright")?,
+            (Some(_), Some(_)) => {}
+        }
+
+        // Props are only dark where the pass was compiled but has no ambient lighting
+        for pass in &self.passes {
+            let Some(ambient) = pass
+                .ambient
+                .as_ref()
+                .filter(|_| pass.lighting_size.is_some())
+            else {
+                continue;
+            };
+            if ambient.samples == 0 {
+                writeln!(
+                    w,
+                    "warning: map has {} lighting but no leaf ambient lighting for it, props will be black",
+                    pass.name
+                )?;
+            } else if ambient.unsampled_leaves != 0 {
+                writeln!(
+                    w,
+                    "warning: {} open leaves have no {} ambient samples, props in them will be black",
+                    ambient.unsampled_leaves, pass.name
+                )?;
+            }
+            if ambient.bad_indices != 0 {
+                writeln!(
+                    w,
+                    "warning: {} leaves point past the end of the {} ambient samples",
+                    ambient.bad_indices, pass.name
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+//! Decoding of face lightmaps and packing them into pages for export as images.
+
+use std::io::{Read, Seek};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, Face};
+
+/// Width and height of an exported lightmap page
+pub const PAGE_SIZE: u32 = 1024;
+/// Gap left between lightmaps on a page so neighbours don't blend when filtered
+const PADDING: u32 = 1;
+
+pub struct Lightmap {
+    pub face: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Linear light values, in rows
+    pub samples: Vec<[f32; 3]>,
+}
+
+/// Decodes a ColorRGBExp32 sample to linear light, where 1.0 is full brightness.
+pub fn decode_sample(sample: &[u8]) -> [f32; 3] {
+    let scale = 2f32.powi(sample[3] as i8 as i32) / 255.0;
+    [sample[0], sample[1], sample[2]].map(|c| c as f32 * scale)
+}
+
+/// Reads the first light style's lightmap of every lit face. Bumpmapped faces store theirs
+/// first, followed by one for each bump basis vector, which are skipped.
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>, hdr: bool) -> Option<Vec<Lightmap>> {
+    let (lighting, faces) = if hdr {
+        (LumpType::LIGHTING_HDR, LumpType::FACES_HDR)
+    } else {
+        (LumpType::LIGHTING, LumpType::FACES)
+    };
+
+    let lighting = bsp.get_lump(lighting)?;
+    let mut faces: Vec<Face> = lumps::read_array(bsp, faces)?;
+    // Maps without separate HDR faces use the LDR faces' offsets into the HDR lighting
+    if faces.is_empty() {
+        faces = lumps::read_array(bsp, LumpType::FACES)?;
+    }
+
+    let lightmaps = faces
+        .iter()
+        .enumerate()
+        .filter(|(_, face)| face.lightofs != -1 && face.styles[0] != 255)
+        .filter_map(|(i, face)| {
+            let [width, height] = face
+                .lightmap_texture_size_in_luxels
+                .map(|s| u32::try_from(s.checked_add(1)?).ok());
+            let (width, height) = (width?, height?);
+
+            let ofs = usize::try_from(face.lightofs).ok()?;
+            let len = (width as usize)
+                .checked_mul(height as usize)?
+                .checked_mul(4)?;
+            let data = lighting.get(ofs..ofs.checked_add(len)?)?;
+
+            Some(Lightmap {
+                face: i,
+                width,
+                height,
+                samples: data.chunks_exact(4).map(decode_sample).collect(),
+            })
+        })
+        .collect();
+
+    Some(lightmaps)
+}
+
+pub struct Placement {
+    pub face: usize,
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Page {
+    pub pixels: Vec<[f32; 3]>,
+}
+
+/// Packs lightmaps onto pages in shelves, tallest first. Lightmaps too large for a page are
+/// left out.
+pub fn pack(lightmaps: &[Lightmap]) -> (Vec<Page>, Vec<Placement>) {
+    let mut order: Vec<&Lightmap> = lightmaps
+        .iter()
+        .filter(|l| l.width <= PAGE_SIZE && l.height <= PAGE_SIZE)
+        .collect();
+    order.sort_by_key(|l| (std::cmp::Reverse(l.height), l.face));
+
+    let mut pages: Vec<Page> = vec![];
+    let mut placements = vec![];
+    let (mut x, mut y, mut shelf_height) = (PAGE_SIZE, PAGE_SIZE, 0);
+
+    for lightmap in order {
+        if x + lightmap.width > PAGE_SIZE {
+            x = 0;
+            y += shelf_height + PADDING;
+            shelf_height = lightmap.height;
+        }
+        // Empty lightmaps fit on a full page, so the first page isn't left to them
+        if pages.is_empty() || y + lightmap.height > PAGE_SIZE {
+            pages.push(Page {
+                pixels: vec![[0.0; 3]; (PAGE_SIZE * PAGE_SIZE) as usize],
+            });
+            (x, y, shelf_height) = (0, 0, lightmap.height);
+        }
+
+        let Some(page) = pages.last_mut() else {
+            continue;
+        };
+        for (row, samples) in lightmap
+            .samples
+            .chunks_exact(lightmap.width as usize)
+            .enumerate()
+        {
+            let start = ((y + row as u32) * PAGE_SIZE + x) as usize;
+            page.pixels[start..start + samples.len()].copy_from_slice(samples);
+        }
+
+        placements.push(Placement {
+            face: lightmap.face,
+            page: pages.len() - 1,
+            x,
+            y,
+            width: lightmap.width,
+            height: lightmap.height,
+        });
+        x += lightmap.width + PADDING;
+    }
+
+    placements.sort_by_key(|p| p.face);
+    (pages, placements)
+}
+
+/// Converts linear light to sRGB-ish 8-bit values with the engine's 2.2 gamma.
+pub fn to_rgb8(pixel: [f32; 3]) -> [u8; 3] {
+    pixel.map(|c| (c.max(0.0).powf(1.0 / 2.2) * 255.0).min(255.0) as u8)
+}
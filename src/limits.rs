@@ -0,0 +1,205 @@
+//! Map size limits of each engine branch, and how much of them a map uses.
+//!
+//! The per-game MAX_MAP_* values live in `limits.csv`, one column per game, so they can be
+//! corrected or extended without touching the code. Only games whose values were taken from
+//! their branch's `bspfile.h` have a column, which so far are the Source SDK 2013 ones; branches
+//! such as CS:GO and Portal 2 raised several limits and shouldn't be added by copying these.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::csv;
+use crate::entities;
+
+const DATABASE: &str = include_str!("limits.csv");
+
+enum Measure {
+    /// Number of fixed-size records in a lump
+    Records(LumpType, usize),
+    Bytes(LumpType),
+    Leaves,
+    WorldLights,
+    Entities,
+}
+
+fn measure_of(limit: &str) -> Option<Measure> {
+    use LumpType::*;
+    use Measure::*;
+
+    Some(match limit {
+        "MAX_MAP_MODELS" => Records(MODELS, 48),
+        "MAX_MAP_BRUSHES" => Records(BRUSHES, 12),
+        "MAX_MAP_BRUSHSIDES" => Records(BRUSH_SIDES, 8),
+        "MAX_MAP_PLANES" => Records(PLANES, 20),
+        "MAX_MAP_VERTS" => Records(VERTICES, 12),
+        "MAX_MAP_EDGES" => Records(EDGES, 4),
+        "MAX_MAP_SURFEDGES" => Records(SURFEDGES, 4),
+        "MAX_MAP_FACES" => Records(FACES, 56),
+        "MAX_MAP_TEXINFO" => Records(TEXTURE_INFO, 72),
+        "MAX_MAP_TEXDATA" => Records(TEXTURE_DATA, 32),
+        "MAX_MAP_TEXDATA_STRING_DATA" => Bytes(TEXTURE_DATA_STRING_DATA),
+        "MAX_MAP_NODES" => Records(NODES, 32),
+        "MAX_MAP_LEAFS" => Leaves,
+        "MAX_MAP_AREAPORTALS" => Records(AREA_PORTALS, 12),
+        "MAX_MAP_DISPINFO" => Records(DISPLACEMENT_INFO, 176),
+        "MAX_MAP_OVERLAYS" => Records(OVERLAYS, 352),
+        "MAX_MAP_PRIMITIVES" => Records(PRIMITIVES, 10),
+        "MAX_MAP_PRIMVERTS" => Records(PRIMITIVE_VERTICES, 12),
+        "MAX_MAP_PRIMINDICES" => Records(PRIMITIVE_INDICES, 2),
+        "MAX_MAP_WORLDLIGHTS" => WorldLights,
+        "MAX_MAP_ENTITIES" => Entities,
+        "MAX_MAP_ENTSTRING" => Bytes(ENTITIES),
+        "MAX_MAP_LIGHTING" => Bytes(LIGHTING),
+        "MAX_MAP_VISIBILITY" => Bytes(VISIBILITY),
+        _ => return None,
+    })
+}
+
+fn lump_len<R: Read + Seek>(bsp: &mut BspFile<R>, lump: LumpType) -> u64 {
+    bsp.get_lump(lump).map_or(0, |data| data.len() as u64)
+}
+
+fn measure<R: Read + Seek>(bsp: &mut BspFile<R>, measure: Measure) -> Option<u64> {
+    Some(match measure {
+        Measure::Records(lump, size) => lump_len(bsp, lump) / size as u64,
+        Measure::Bytes(lump) => lump_len(bsp, lump),
+        Measure::Leaves => {
+            // Version 0 leaves still contain their ambient lighting
+            let size = if bsp.lump_info(LumpType::LEAVES).version == 0 {
+                56
+            } else {
+                32
+            };
+            lump_len(bsp, LumpType::LEAVES) / size
+        }
+        Measure::WorldLights => {
+            // The LDR and HDR lights count separately
+            [LumpType::WORLD_LIGHTS, LumpType::WORLD_LIGHTS_HDR]
+                .into_iter()
+                .map(|lump| {
+                    let size = if bsp.lump_info(lump).version >= 1 {
+                        100
+                    } else {
+                        88
+                    };
+                    lump_len(bsp, lump) / size
+                })
+                .fold(0, u64::max)
+        }
+        Measure::Entities => match bsp.get_lump(LumpType::ENTITIES) {
+            Some(data) => entities::parse(&data).ok()?.len() as u64,
+            None => 0,
+        },
+    })
+}
+
+pub struct Usage {
+    pub limit: String,
+    pub used: u64,
+    pub max: u64,
+}
+
+pub struct Report {
+    pub game: String,
+    pub usage: Vec<Usage>,
+}
+
+/// Rows of the database, its header of game names first
+fn database() -> Vec<Vec<String>> {
+    csv::parse(DATABASE).unwrap_or_default()
+}
+
+/// Games with a column in the database
+pub fn games() -> Vec<String> {
+    let records = database();
+    let header = records.first().and_then(|header| header.get(1..));
+    header.unwrap_or_default().to_vec()
+}
+
+pub fn check<R: Read + Seek>(bsp: &mut BspFile<R>, game: &str) -> Option<Report> {
+    let records = database();
+    let header = records.first()?;
+    // The first column names the limit
+    let column = header
+        .iter()
+        .skip(1)
+        .position(|g| g.eq_ignore_ascii_case(game))?
+        + 1;
+
+    let mut usage = vec![];
+    for record in &records[1..] {
+        let Some(limit) = record.first() else {
+            continue;
+        };
+        let Some(kind) = measure_of(limit) else {
+            continue;
+        };
+        let Some(max) = record.get(column).and_then(|max| max.parse().ok()) else {
+            continue;
+        };
+
+        // Limits that couldn't be measured are left out rather than reported as zero
+        let Some(used) = measure(bsp, kind) else {
+            continue;
+        };
+
+        usage.push(Usage {
+            limit: limit.clone(),
+            used,
+            max,
+        });
+    }
+
+    Some(Report {
+        game: header[column].clone(),
+        usage,
+    })
+}
+
+impl Report {
+    pub fn over_limit(&self) -> bool {
+        self.usage.iter().any(|u| u.used > u.max)
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Limits for {}:", self.game)?;
+        for usage in &self.usage {
+            writeln!(
+                w,
+                "{:<28} {:>10} / {:<10} {:6.1}%",
+                usage.limit,
+                usage.used,
+                usage.max,
+                usage.used as f64 * 100.0 / usage.max as f64
+            )?;
+        }
+
+        for usage in self.usage.iter().filter(|u| u.used > u.max) {
+            writeln!(
+                w,
+                "error: {} exceeded ({} > {})",
+                usage.limit, usage.used, usage.max
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_game_has_every_limit() {
+        let records = database();
+        assert_eq!(records[0], ["limit", "tf2", "css"]);
+        for record in &records[1..] {
+            assert!(measure_of(&record[0]).is_some(), "{}", record[0]);
+            assert_eq!(record.len(), records[0].len(), "{}", record[0]);
+            for max in &record[1..] {
+                assert!(max.parse::<u64>().is_ok(), "{}: {max}", record[0]);
+            }
+        }
+    }
+}
@@ -0,0 +1,712 @@
+//! Typed parsers for the fixed-size structs stored in array lumps.
+
+use binrw::BinRead;
+use std::borrow::Cow;
+use std::io::{Cursor, Read, Seek};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::error::BspError;
+
+pub type Vector = [f32; 3];
+
+pub const CONTENTS_SLIME: i32 = 0x10;
+pub const CONTENTS_WATER: i32 = 0x20;
+pub const CONTENTS_PLAYERCLIP: i32 = 0x10000;
+
+/// Names of the contents flags reported in brush and leaf statistics
+pub const CONTENTS_NAMES: &[(i32, &str)] = &[
+    (0x1, "solid"),
+    (0x2, "window"),
+    (0x8, "grate"),
+    (CONTENTS_SLIME, "slime"),
+    (CONTENTS_WATER, "water"),
+    (0x40, "blocklos"),
+    (0x80, "opaque"),
+    (0x2000, "ignore nodraw opaque"),
+    (0x4000, "moveable"),
+    (0x8000, "areaportal"),
+    (CONTENTS_PLAYERCLIP, "playerclip"),
+    (0x20000, "monsterclip"),
+    (0x1000000, "origin"),
+    (0x4000000, "debris"),
+    (0x8000000, "detail"),
+    (0x10000000, "translucent"),
+    (0x20000000, "ladder"),
+];
+
+pub const SURF_SKY2D: i32 = 0x2;
+pub const SURF_SKY: i32 = 0x4;
+pub const SURF_WARP: i32 = 0x8;
+pub const SURF_TRIGGER: i32 = 0x40;
+pub const SURF_NODRAW: i32 = 0x80;
+pub const SURF_HINT: i32 = 0x100;
+pub const SURF_SKIP: i32 = 0x200;
+pub const SURF_BUMPLIGHT: i32 = 0x800;
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Plane {
+    pub normal: Vector,
+    pub dist: f32,
+    pub ty: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Edge {
+    pub v: [u16; 2],
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Face {
+    pub planenum: u16,
+    pub side: u8,
+    pub on_node: u8,
+    pub firstedge: i32,
+    pub numedges: i16,
+    pub texinfo: i16,
+    pub dispinfo: i16,
+    pub surface_fog_volume_id: i16,
+    pub styles: [u8; 4],
+    pub lightofs: i32,
+    pub area: f32,
+    pub lightmap_texture_mins_in_luxels: [i32; 2],
+    pub lightmap_texture_size_in_luxels: [i32; 2],
+    pub orig_face: i32,
+    pub num_prims: u16,
+    pub first_prim_id: u16,
+    pub smoothing_groups: u32,
+}
+
+/// High bit of a face's `num_prims`, which disables shadows on the face rather than counting
+/// primitives
+pub const FACE_NO_SHADOWS: u16 = 0x8000;
+
+pub const PRIM_TRILIST: u8 = 0;
+pub const PRIM_TRISTRIP: u8 = 1;
+
+/// Triangles vbsp emits for a face whose edges are split by t-junction fixes, or for water
+/// surfaces, drawn from PRIMITIVE_INDICES into PRIMITIVE_VERTICES
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Primitive {
+    #[br(pad_after = 1)]
+    pub kind: u8,
+    pub first_index: u16,
+    pub index_count: u16,
+    pub first_vert: u16,
+    pub vert_count: u16,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Brush {
+    pub firstside: i32,
+    pub numsides: i32,
+    pub contents: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct BrushSide {
+    pub planenum: u16,
+    pub texinfo: i16,
+    pub dispinfo: i16,
+    pub bevel: u8,
+    pub thin: u8,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct TexInfo {
+    pub texture_vecs: [[f32; 4]; 2],
+    pub lightmap_vecs: [[f32; 4]; 2],
+    pub flags: i32,
+    pub texdata: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct TexData {
+    pub reflectivity: Vector,
+    pub name_string_table_id: i32,
+    pub width: i32,
+    pub height: i32,
+    pub view_width: i32,
+    pub view_height: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Model {
+    pub mins: Vector,
+    pub maxs: Vector,
+    pub origin: Vector,
+    pub headnode: i32,
+    pub firstface: i32,
+    pub numfaces: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Node {
+    pub planenum: i32,
+    /// Node indices, or -1 - leaf index for leaves
+    pub children: [i32; 2],
+    pub mins: [i16; 3],
+    pub maxs: [i16; 3],
+    pub firstface: u16,
+    pub numfaces: u16,
+    #[br(pad_after = 2)]
+    pub area: i16,
+}
+
+/// A leaf of the BSP tree. Version 0 leaves are followed by 24 bytes of ambient lighting,
+/// which [`read_leaves`] skips.
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Leaf {
+    pub contents: i32,
+    pub cluster: i16,
+    /// Area in the low 9 bits and flags in the top 7
+    pub area_flags: u16,
+    pub mins: [i16; 3],
+    pub maxs: [i16; 3],
+    pub first_leaf_face: u16,
+    pub num_leaf_faces: u16,
+    pub first_leaf_brush: u16,
+    pub num_leaf_brushes: u16,
+    #[br(pad_after = 2)]
+    pub leaf_water_data_id: i16,
+}
+
+/// A body of water, which every leaf under its surface refers to
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct LeafWaterData {
+    pub surface_z: f32,
+    /// Bottom of the lowest leaf in the water
+    pub min_z: f32,
+    #[br(pad_after = 2)]
+    pub surface_texinfo: i16,
+}
+
+/// The leaf can see the 3D skybox
+pub const LEAF_FLAGS_SKY: u16 = 0x1;
+/// The leaf can see the 2D skybox
+pub const LEAF_FLAGS_SKY2D: u16 = 0x4;
+
+impl Leaf {
+    pub fn area(&self) -> u16 {
+        self.area_flags & 0x1FF
+    }
+
+    pub fn flags(&self) -> u16 {
+        self.area_flags >> 9
+    }
+}
+
+/// A region of the map sealed off from the others by areaportals. Area 0 is unused.
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Area {
+    pub num_area_portals: i32,
+    pub first_area_portal: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct AreaPortal {
+    /// Matches the `portalnumber` key of func_areaportal entities
+    pub portal_key: u16,
+    pub other_area: u16,
+    pub first_clip_portal_vert: u16,
+    pub clip_portal_verts: u16,
+    pub planenum: i32,
+}
+
+/// An overlay, with room for `N` faces: 64 for OVERLAYS and 256 for WATER_OVERLAYS
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct Overlay<const N: usize> {
+    pub id: i32,
+    pub texinfo: i16,
+    /// Face count in the low 14 bits and render order in the top 2
+    pub face_count_and_render_order: u16,
+    pub faces: [i32; N],
+    pub u: [f32; 2],
+    pub v: [f32; 2],
+    pub uv_points: [Vector; 4],
+    pub origin: Vector,
+    pub basis_normal: Vector,
+}
+
+impl<const N: usize> Overlay<N> {
+    pub fn face_count(&self) -> usize {
+        (self.face_count_and_render_order & 0x3FFF) as usize
+    }
+
+    pub fn render_order(&self) -> u16 {
+        self.face_count_and_render_order >> 14
+    }
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct LeafAmbientIndex {
+    pub ambient_sample_count: u16,
+    pub first_ambient_sample: u16,
+}
+
+/// Light reaching a point in a leaf from each direction along the axes, which props standing
+/// near it are lit with
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct LeafAmbientSample {
+    /// ColorRGBExp32 light from +X, -X, +Y, -Y, +Z and -Z
+    pub cube: [[u8; 4]; 6],
+    /// Position within the leaf's bounds, in 255ths of them
+    #[br(pad_after = 1)]
+    pub position: [u8; 3],
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct CubemapSample {
+    pub origin: [i32; 3],
+    /// Log2 of the cubemap's size plus one, or 0 for the default size
+    pub size: i32,
+}
+
+/// A light vrad baked into the map and the engine keeps for lighting models and props.
+/// Version 1 of the lump adds `shadow_cast_offset`.
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+#[br(import(has_shadow_offset: bool))]
+pub struct WorldLight {
+    pub origin: Vector,
+    pub intensity: Vector,
+    /// Direction of surface lights and spotlights
+    pub normal: Vector,
+    #[br(if(has_shadow_offset))]
+    pub shadow_cast_offset: Vector,
+    pub cluster: i32,
+    pub emit_type: i32,
+    pub style: i32,
+    /// Cosines of the spotlight's inner and outer cone angles
+    pub stopdot: f32,
+    pub stopdot2: f32,
+    pub exponent: f32,
+    pub radius: f32,
+    pub constant_attn: f32,
+    pub linear_attn: f32,
+    pub quadratic_attn: f32,
+    pub flags: i32,
+    /// Texinfo of the emitting face, for surface lights
+    pub texinfo: i32,
+    pub owner: i32,
+}
+
+/// Occluders marked inactive are only switched on by map logic
+pub const OCCLUDER_INACTIVE: i32 = 0x1;
+
+/// A func_occluder brush. Version 2 of the lump adds `area`.
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+#[br(import(has_area: bool))]
+pub struct Occluder {
+    pub flags: i32,
+    pub first_poly: i32,
+    pub poly_count: i32,
+    pub mins: Vector,
+    pub maxs: Vector,
+    #[br(if(has_area))]
+    pub area: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct OccluderPoly {
+    /// Index into the occluder vertex index list, which indexes VERTICES
+    pub first_vertex_index: i32,
+    pub vertex_count: i32,
+    pub planenum: i32,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct DispSubNeighbor {
+    /// Index of the neighboring displacement, or 0xFFFF for none
+    pub neighbor: u16,
+    pub orientation: u8,
+    pub span: u8,
+    #[br(pad_after = 1)]
+    pub neighbor_span: u8,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct DispCornerNeighbors {
+    pub neighbors: [u16; 4],
+    #[br(pad_after = 1)]
+    pub num_neighbors: u8,
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct DispInfo {
+    pub start_position: Vector,
+    pub disp_vert_start: i32,
+    pub disp_tri_start: i32,
+    pub power: i32,
+    pub min_tess: i32,
+    pub smoothing_angle: f32,
+    pub contents: i32,
+    #[br(pad_after = 2)]
+    pub map_face: u16,
+    pub lightmap_alpha_start: i32,
+    pub lightmap_sample_position_start: i32,
+    /// Two sub-neighbors for each of the four edges
+    pub edge_neighbors: [[DispSubNeighbor; 2]; 4],
+    pub corner_neighbors: [DispCornerNeighbors; 4],
+    pub allowed_verts: [u32; 10],
+}
+
+impl DispInfo {
+    /// Vertices along each side of the displacement's grid
+    pub fn side_length(&self) -> usize {
+        (1 << self.power) + 1
+    }
+}
+
+#[allow(unused)]
+#[derive(BinRead, Debug, Clone, Copy)]
+pub struct DispVert {
+    /// Direction the vertex is offset from the base surface, scaled by `dist`
+    pub vec: Vector,
+    pub dist: f32,
+    pub alpha: f32,
+}
+
+/// Parses a lump made of an array of `T`. Fails if the lump isn't a whole number of structs.
+/// Parses records until the data runs out, returning them along with the number of bytes at the
+/// end that don't make up a whole record.
+fn parse_records<T>(data: &[u8]) -> (Vec<T>, usize)
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Default,
+{
+    let mut reader = Cursor::new(data);
+    let mut out = vec![];
+
+    while (reader.position() as usize) < data.len() {
+        let start = reader.position();
+        match T::read_le(&mut reader) {
+            Ok(record) => out.push(record),
+            Err(_) => return (out, data.len() - start as usize),
+        }
+    }
+
+    (out, 0)
+}
+
+pub fn parse_array<T>(data: &[u8]) -> Option<Vec<T>>
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Default,
+{
+    let (out, rest) = parse_records(data);
+    (rest == 0).then_some(out)
+}
+
+/// Reads a lump, treating a lump the map doesn't have as empty.
+fn read_lump<'a, R: Read + Seek>(
+    bsp: &mut BspFile<'a, R>,
+    lump: LumpType,
+) -> Result<Cow<'a, [u8]>, BspError> {
+    match bsp.try_get_lump(lump) {
+        Err(BspError::MissingLump(_)) => Ok(Cow::Borrowed(&[])),
+        result => result,
+    }
+}
+
+/// Checks a lump of fixed size records for leftover bytes at the end, which the lenient policy
+/// drops.
+fn check_records<R: Read + Seek>(
+    bsp: &BspFile<R>,
+    lump: LumpType,
+    rest: usize,
+) -> Result<(), BspError> {
+    if rest == 0 {
+        return Ok(());
+    }
+
+    let error = BspError::LeftoverBytes {
+        lump: bsp.format().lump_name(lump as usize),
+        rest,
+    };
+    match bsp.context().tolerate(&error) {
+        true => Ok(()),
+        false => Err(error),
+    }
+}
+
+/// Reads an array lump, treating a missing lump as empty.
+pub fn read_array<T, R>(bsp: &mut BspFile<R>, lump: LumpType) -> Option<Vec<T>>
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Default,
+    R: Read + Seek,
+{
+    try_read_array(bsp, lump).ok()
+}
+
+/// Like [`read_array`], but saying why the lump couldn't be read
+pub fn try_read_array<T, R>(bsp: &mut BspFile<R>, lump: LumpType) -> Result<Vec<T>, BspError>
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Default,
+    R: Read + Seek,
+{
+    let data = read_lump(bsp, lump)?;
+    let (records, rest) = parse_records(&data);
+    check_records(bsp, lump, rest)?;
+
+    Ok(records)
+}
+
+/// Checks the version of a lump whose record size depends on it, where `newest` is the newest
+/// version the crate knows. The lenient policy reads unknown versions as the newest one.
+pub(crate) fn check_version<R: Read + Seek>(
+    bsp: &BspFile<R>,
+    lump: LumpType,
+    newest: u32,
+) -> Result<u32, BspError> {
+    let version = bsp.lump_info(lump).version;
+    if version <= newest {
+        return Ok(version);
+    }
+
+    let error = BspError::UnknownVersion {
+        lump: bsp.format().lump_name(lump as usize),
+        version,
+    };
+    match bsp.context().tolerate(&error) {
+        true => Ok(newest),
+        false => Err(error),
+    }
+}
+
+/// Parses records read with `args` from each `size` byte chunk of a lump, checking for leftover
+/// bytes like [`try_read_array`].
+fn read_sized_records<T, R>(
+    bsp: &mut BspFile<R>,
+    lump: LumpType,
+    size: usize,
+    args: T::Args<'_>,
+) -> Result<Vec<T>, BspError>
+where
+    T: BinRead,
+    for<'a> T::Args<'a>: Clone,
+    R: Read + Seek,
+{
+    let data = read_lump(bsp, lump)?;
+    check_records(bsp, lump, data.len() % size)?;
+
+    data.chunks_exact(size)
+        .map(|chunk| {
+            T::read_le_args(&mut Cursor::new(chunk), args.clone()).map_err(|e| {
+                BspError::malformed(
+                    bsp.format().lump_name(lump as usize) + " lump",
+                    e.to_string(),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Reads the LEAVES lump, whose record size depends on its version.
+pub fn read_leaves<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Vec<Leaf>> {
+    try_read_leaves(bsp).ok()
+}
+
+/// Like [`read_leaves`], but saying why the lump couldn't be read
+pub fn try_read_leaves<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Vec<Leaf>, BspError> {
+    let size = match check_version(bsp, LumpType::LEAVES, 1)? {
+        0 => 56,
+        _ => 32,
+    };
+
+    read_sized_records(bsp, LumpType::LEAVES, size, ())
+}
+
+/// Reads WORLD_LIGHTS or WORLD_LIGHTS_HDR, whose record size depends on the lump version.
+pub fn read_world_lights<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    lump: LumpType,
+) -> Option<Vec<WorldLight>> {
+    try_read_world_lights(bsp, lump).ok()
+}
+
+/// Like [`read_world_lights`], but saying why the lump couldn't be read
+pub fn try_read_world_lights<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    lump: LumpType,
+) -> Result<Vec<WorldLight>, BspError> {
+    let has_shadow_offset = check_version(bsp, lump, 1)? == 1;
+    let size = if has_shadow_offset { 100 } else { 88 };
+
+    read_sized_records(bsp, lump, size, (has_shadow_offset,))
+}
+
+/// Reads the string of every TEXTURE_DATA_STRING_TABLE entry, which texdata entries and macro
+/// textures refer to by index. Entries pointing outside the string data are empty.
+pub fn texture_strings<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Vec<String>> {
+    try_texture_strings(bsp).ok()
+}
+
+/// Like [`texture_strings`], but saying why the lumps couldn't be read
+pub fn try_texture_strings<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Vec<String>, BspError> {
+    let table: Vec<i32> = try_read_array(bsp, LumpType::TEXTURE_DATA_STRING_TABLE)?;
+    let strings = bsp
+        .try_get_lump(LumpType::TEXTURE_DATA_STRING_DATA)
+        .unwrap_or_default();
+
+    let strings = table
+        .iter()
+        .map(|&ofs| {
+            let string = usize::try_from(ofs)
+                .ok()
+                .and_then(|ofs| strings.get(ofs..))
+                .unwrap_or_default();
+            let len = string.iter().position(|&c| c == 0).unwrap_or(string.len());
+
+            String::from_utf8_lossy(&string[..len]).into_owned()
+        })
+        .collect();
+
+    Ok(strings)
+}
+
+/// Resolves the material name of every texdata entry through the string table.
+pub fn texture_names<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Vec<String>> {
+    try_texture_names(bsp).ok()
+}
+
+/// Like [`texture_names`], but saying why the lumps couldn't be read
+pub fn try_texture_names<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Vec<String>, BspError> {
+    let texdata: Vec<TexData> = try_read_array(bsp, LumpType::TEXTURE_DATA)?;
+    let strings = try_texture_strings(bsp)?;
+
+    let names = texdata
+        .iter()
+        .map(|texdata| {
+            strings
+                .get(texdata.name_string_table_id as usize)
+                .cloned()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    Ok(names)
+}
+
+/// Geometry shared by everything that works with faces and brushes
+pub struct World {
+    pub planes: Vec<Plane>,
+    pub vertices: Vec<Vector>,
+    pub edges: Vec<Edge>,
+    pub surfedges: Vec<i32>,
+    pub faces: Vec<Face>,
+    pub texinfo: Vec<TexInfo>,
+    pub texture_names: Vec<String>,
+    pub brushes: Vec<Brush>,
+    pub brush_sides: Vec<BrushSide>,
+    pub models: Vec<Model>,
+}
+
+impl World {
+    pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Self> {
+        Self::try_read(bsp).ok()
+    }
+
+    /// Like [`World::read`], but saying why the lumps couldn't be read
+    pub fn try_read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Self, BspError> {
+        Ok(Self {
+            planes: try_read_array(bsp, LumpType::PLANES)?,
+            vertices: try_read_array(bsp, LumpType::VERTICES)?,
+            edges: try_read_array(bsp, LumpType::EDGES)?,
+            surfedges: try_read_array(bsp, LumpType::SURFEDGES)?,
+            faces: try_read_array(bsp, LumpType::FACES)?,
+            texinfo: try_read_array(bsp, LumpType::TEXTURE_INFO)?,
+            texture_names: try_texture_names(bsp)?,
+            brushes: try_read_array(bsp, LumpType::BRUSHES)?,
+            brush_sides: try_read_array(bsp, LumpType::BRUSH_SIDES)?,
+            models: try_read_array(bsp, LumpType::MODELS)?,
+        })
+    }
+
+    /// Vertices of a face's polygon, in winding order
+    pub fn face_vertices(&self, face: &Face) -> Vec<Vector> {
+        (0..face.numedges as i32)
+            .filter_map(|i| {
+                let index = usize::try_from(face.firstedge.checked_add(i)?).ok()?;
+                let surfedge = *self.surfedges.get(index)?;
+                let edge = self.edges.get(surfedge.unsigned_abs() as usize)?;
+                let v = if surfedge >= 0 { edge.v[0] } else { edge.v[1] };
+
+                self.vertices.get(v as usize).copied()
+            })
+            .collect()
+    }
+
+    pub fn face_texinfo(&self, face: &Face) -> Option<&TexInfo> {
+        self.texinfo.get(usize::try_from(face.texinfo).ok()?)
+    }
+
+    pub fn texinfo_name(&self, texinfo: i16) -> Option<&str> {
+        let texinfo = self.texinfo.get(usize::try_from(texinfo).ok()?)?;
+        self.texture_names
+            .get(usize::try_from(texinfo.texdata).ok()?)
+            .map(String::as_str)
+    }
+
+    /// Faces that belong to a model. Model 0 is the world.
+    pub fn model_faces(&self, model: usize) -> &[Face] {
+        let Some(model) = self.models.get(model) else {
+            return &[];
+        };
+
+        let start = (model.firstface as usize).min(self.faces.len());
+        let end = start
+            .saturating_add(model.numfaces as usize)
+            .min(self.faces.len());
+        &self.faces[start..end]
+    }
+
+    /// Axis aligned bounds of a brush. The compiler always adds axial bevel planes to brushes,
+    /// so the bounds can be read straight from the sides.
+    pub fn brush_bounds(&self, brush: &Brush) -> Option<(Vector, Vector)> {
+        let mut mins = [f32::MIN; 3];
+        let mut maxs = [f32::MAX; 3];
+
+        let start = usize::try_from(brush.firstside).ok()?;
+        let sides = self
+            .brush_sides
+            .get(start..start + usize::try_from(brush.numsides).ok()?)?;
+        for side in sides {
+            let plane = self.planes.get(side.planenum as usize)?;
+            for axis in 0..3 {
+                if plane.normal[axis] == 1.0 {
+                    maxs[axis] = plane.dist;
+                } else if plane.normal[axis] == -1.0 {
+                    mins[axis] = -plane.dist;
+                }
+            }
+        }
+
+        (mins.iter().all(|&v| v != f32::MIN) && maxs.iter().all(|&v| v != f32::MAX))
+            .then_some((mins, maxs))
+    }
+}
@@ -0,0 +1,72 @@
+//! Recognizes the other Source engine files people mistake for maps, so the tool can say what a
+//! file is instead of failing to parse it as a BSP.
+
+/// Offset of the map name in a demo header, after the magic, protocols, server and client name
+const DEMO_MAP_OFFSET: usize = 8 + 4 + 4 + 260 * 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileKind {
+    Vpk,
+    Gma,
+    Nav,
+    /// A demo, with the name of the map it was recorded on
+    Demo(String),
+    Zip,
+    Bzip2,
+    /// A Quake or GoldSrc map, which has a different layout
+    GoldSrc,
+}
+
+impl FileKind {
+    pub fn description(&self) -> String {
+        match self {
+            FileKind::Vpk => "a VPK package".to_string(),
+            FileKind::Gma => "a Garry's Mod addon (GMA)".to_string(),
+            FileKind::Nav => "a navigation mesh (NAV)".to_string(),
+            FileKind::Demo(map) if map.is_empty() => "a demo (DEM)".to_string(),
+            FileKind::Demo(map) => format!("a demo (DEM) recorded on {map}"),
+            FileKind::Zip => "a zip archive".to_string(),
+            FileKind::Bzip2 => "a bzip2 compressed file".to_string(),
+            FileKind::GoldSrc => "a GoldSrc or Quake BSP, not a Source map".to_string(),
+        }
+    }
+
+    /// What to use on the file instead
+    pub fn hint(&self) -> &'static str {
+        match self {
+            FileKind::Vpk => {
+                "extract the map with a VPK tool such as vpk or GCFScape, then run bspinfo on the .bsp"
+            }
+            FileKind::Gma => "extract it with `gmad extract -file <addon.gma>` and run bspinfo on the maps/*.bsp inside",
+            FileKind::Nav => {
+                "nav files are generated by the game with nav_generate and can be inspected in game with nav_edit"
+            }
+            FileKind::Demo(_) => "use `bspinfo demo <file.dem> <maps_dir>` to find and check the map it was recorded on",
+            FileKind::Zip => {
+                "extract the map from the archive first; to add files to a map's pakfile use `bspinfo pack`"
+            }
+            FileKind::Bzip2 => "it was compressed twice, decompress it with bunzip2 first",
+            FileKind::GoldSrc => "bspinfo only reads Source engine (VBSP) and Respawn (rBSP) maps",
+        }
+    }
+}
+
+/// Identifies a file from its first bytes. None for anything unrecognized, including maps.
+pub fn identify(header: &[u8]) -> Option<FileKind> {
+    let magic = header.get(..4)?;
+
+    Some(match magic {
+        [0x34, 0x12, 0xAA, 0x55] => FileKind::Vpk,
+        b"GMAD" => FileKind::Gma,
+        [0xCE, 0xFA, 0xED, 0xFE] => FileKind::Nav,
+        b"HL2D" if header.starts_with(b"HL2DEMO\0") => {
+            let name = header.get(DEMO_MAP_OFFSET..).unwrap_or_default();
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            FileKind::Demo(String::from_utf8_lossy(name).into_owned())
+        }
+        [b'P', b'K', 3, 4] | [b'P', b'K', 5, 6] => FileKind::Zip,
+        [b'B', b'Z', b'h', b'1'..=b'9'] => FileKind::Bzip2,
+        b"IBSP" | [29 | 30, 0, 0, 0] => FileKind::GoldSrc,
+        _ => return None,
+    })
+}
@@ -1,211 +1,2513 @@
-use binrw::BinRead;
-use byteorder::{LittleEndian, ReadBytesExt};
 use std::{
+    borrow::Cow,
     fs::File,
-    io::{self, BufReader, Cursor, Read, Seek, Write, BufWriter},
+    io::{self, BufWriter, Cursor, IsTerminal, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::OnceLock,
 };
-use zip::ZipArchive;
-
-#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-#[derive(Debug, num_enum::TryFromPrimitive, num_enum::IntoPrimitive)]
-#[repr(u32)]
-enum LumpType {
-    ENTITIES = 0,
-    PLANES = 1,
-    TEXTURE_DATA = 2,
-    VERTICES = 3,
-    VISIBILITY = 4,
-    NODES = 5,
-    TEXTURE_INFO = 6,
-    FACES = 7,
-    LIGHTING = 8,
-    OCCLUSION = 9,
-    LEAVES = 10,
-    FACE_IDS = 11,
-    EDGES = 12,
-    SURFEDGES = 13,
-    MODELS = 14,
-    WORLD_LIGHTS = 15,
-    LEAF_FACES = 16,
-    LEAF_BRUSHES = 17,
-    BRUSHES = 18,
-    BRUSH_SIDES = 19,
-    AREAS = 20,
-    AREA_PORTALS = 21,
-    UNUSED_22 = 22,
-    UNUSED_23 = 23,
-    UNUSED_24 = 24,
-    UNUSED_25 = 25,
-    DISPLACEMENT_INFO = 26,
-    ORIGINAL_FACES = 27,
-    PHYSICS_DISPLACEMENT = 28,
-    PHYSICS_COLLIDE = 29,
-    VERTEX_NORMALS = 30,
-    VERTEX_NORMAL_INDICES = 31,
-    DISPLACEMENT_LIGHTMAP_ALPHAS = 32,
-    DISPLACEMENT_VERTICES = 33,
-    DISPLACEMENT_LIGHTMAP_SAMPLE_POSITIONS = 34,
-    GAME_LUMP = 35,
-    LEAF_WATER_DATA = 36,
-    PRIMITIVES = 37,
-    PRIMITIVE_VERTICES = 38,
-    PRIMITIVE_INDICES = 39,
-    PAKFILE = 40,
-    CLIP_PORTAL_VERTICES = 41,
-    CUBEMAPS = 42,
-    TEXTURE_DATA_STRING_DATA = 43,
-    TEXTURE_DATA_STRING_TABLE = 44,
-    OVERLAYS = 45,
-    LEAF_MIN_DIST_TO_WATER = 46,
-    FACE_MACRO_TEXTURE_INFO = 47,
-    DISPLACEMENT_TRIS = 48,
-    PHYSICS_COLLIDE_SURFACE = 49,
-    WATER_OVERLAYS = 50,
-    LEAF_AMBIENT_INDEX_HDR = 51,
-    LEAF_AMBIENT_INDEX = 52,
-    LIGHTING_HDR = 53,
-    WORLD_LIGHTS_HDR = 54,
-    LEAF_AMBIENT_LIGHTING_HDR = 55,
-    LEAF_AMBIENT_LIGHTING = 56,
-    XZIP_PAKFILE = 57,
-    FACES_HDR = 58,
-    MAP_FLAGS = 59,
-    OVERLAY_FADES = 60,
-    UNUSED_61 = 61,
-    PHYSICS_LEVEL = 62,
-    UNUSED_63 = 63,
-}
-
-const HEADER_LUMPS: usize = 64;
-
-#[allow(unused)]
-#[derive(BinRead, Debug)]
-struct BspHeader {
-    ident: u32,
-    version: u32,
-    lumps: [LumpInfo; HEADER_LUMPS],
-    map_revision: u32,
-}
-
-#[allow(unused)]
-#[derive(BinRead, Debug)]
-struct LumpInfo {
-    fileofs: u32,
-    filelen: u32,
-    version: u32,
-    uncompressed_size: u32,
-}
-
-struct BspFile<'a, R> {
-    header: BspHeader,
-    reader: &'a mut R,
-}
-
-impl<'a, R: Read + Seek> BspFile<'a, R> {
-    fn new(reader: &'a mut R) -> binrw::BinResult<BspFile<R>> {
-        Ok(Self {
-            header: BspHeader::read_le(reader)?,
-            reader,
-        })
-    }
-
-    fn version(&self) -> u32 {
-        self.header.version
-    }
-
-    fn map_revision(&self) -> u32 {
-        self.header.map_revision
-    }
-
-    fn get_lump(&mut self, lump: LumpType) -> Option<Vec<u8>> {
-        let lump = self.header.lumps.get(lump as usize)?;
-
-        if lump.fileofs == 0 || lump.filelen == 0 {
-            return None;
-        }
-
-        self.reader
-            .seek(io::SeekFrom::Start(lump.fileofs.into()))
-            .ok()?;
-        // Compressed
-        Some(if lump.uncompressed_size != 0 {
-            // Adapted from https://github.com/icewind1991/vbsp/blob/0850bb8dbd695a770d39a06f2cc880aa9d626bf7/src/lib.rs#L545
-            // extra 8 byte because game lumps need some padding for reasons
-            let mut buf: Vec<u8> = Vec::with_capacity(std::cmp::min(
-                lump.uncompressed_size as usize + 8,
-                8 * 1024 * 1024,
-            ));
-            if b"LZMA" != &<[u8; 4]>::read(&mut self.reader).ok()? {
-                return None;
-            }
-
-            let actual_size: u32 = self.reader.read_u32::<LittleEndian>().ok()?;
-            let _lzma_size: u32 = self.reader.read_u32::<LittleEndian>().ok()?;
-
-            lzma_rs::lzma_decompress_with_options(
-                &mut BufReader::new(&mut self.reader),
-                &mut buf,
-                &lzma_rs::decompress::Options {
-                    unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(
-                        actual_size as u64,
-                    )),
-                    allow_incomplete: false,
-                    memlimit: None,
-                },
-            )
-            .ok()?;
 
-            buf
+mod cli;
+
+use bspinfo::{
+    assets, backup, batch, brushes, bsp, checksum, clipgaps, compileinfo, connections, crypt, csv,
+    cubemaps, decals, demo, deps, diff, displacements, edicts, entities, faceids, fastdl, fgd,
+    forecast, gamelump, hexdump, image, index, jobs, leaks, lighting, lightmaps, limits, lumps,
+    magic, mesh, mmap, occluders, output, overlays, overview, pak, parallel, physics, placement,
+    policy, portals, primitives, redact, repack, reslist, retexture, sky, splits, staticprops,
+    stream, strip, thumbnails, transform, tree, validate, vis, watch, water, worldlights, writer,
+    BspError,
+};
+
+use assets::{AssetStore, PathMatching};
+use bsp::{BspFile, BspFormat, LumpType};
+use cli::{fail, fail_io, Exit, OrExit};
+use magic::FileKind;
+use mmap::Mmap;
+use output::{Cell, Format, Table};
+use pak::PakFile;
+use policy::Policy;
+use staticprops::StaticProps;
+use transform::Transform;
+use writer::LumpWriter;
+
+/// The command being run, for usage errors
+static COMMAND: OnceLock<&'static cli::Command> = OnceLock::new();
+
+/// Prints the usage of the command being run and exits, for command lines it can't make sense of
+fn usage() -> ! {
+    let mut w = io::stderr().lock();
+    match COMMAND.get() {
+        Some(command) => {
+            writeln!(w, "usage: bspinfo {} {}", command.name, command.args).unwrap();
+            writeln!(w, "see `bspinfo help {}` for more", command.name).unwrap();
+        }
+        None => cli::print_help(&mut w).unwrap(),
+    }
+    std::process::exit(Exit::Usage as i32)
+}
+
+/// Creates an output file, exiting if it can't be
+fn create(path: impl AsRef<Path>) -> BufWriter<File> {
+    let path = path.as_ref();
+    BufWriter::new(File::create(path).unwrap_or_else(|e| fail_io(path.display(), e)))
+}
+
+fn write_file(path: impl AsRef<Path>, data: &[u8]) {
+    let path = path.as_ref();
+    std::fs::write(path, data).unwrap_or_else(|e| fail_io(path.display(), e));
+}
+
+fn read_file(path: impl AsRef<Path>) -> Vec<u8> {
+    let path = path.as_ref();
+    std::fs::read(path).unwrap_or_else(|e| fail_io(path.display(), e))
+}
+
+fn info<R: Read + Seek>(bsp: &mut BspFile<R>) {
+    let used: Vec<_> = (0..bsp.lump_count())
+        .map(|i| *bsp.lump_info_by_index(i))
+        .filter(|lump| lump.filelen != 0)
+        .collect();
+
+    println!("Format: {}", bsp.format().ident());
+    println!("Lumps: {} of {}", used.len(), bsp.lump_count());
+    if bsp.format() == BspFormat::Valve {
+        let compressed = used.iter().filter(|l| l.uncompressed_size != 0).count();
+        println!("Compressed lumps: {compressed}");
+    }
+    println!(
+        "Lump data: {} bytes",
+        used.iter().map(|l| l.filelen as u64).sum::<u64>()
+    );
+
+    let entities = bsp
+        .get_lump(LumpType::ENTITIES)
+        .and_then(|lump| entities::parse(&lump).ok())
+        .unwrap_or_default();
+    if let Some(version) = entities::map_version(&entities) {
+        if version == bsp.map_revision() {
+            println!("Entity mapversion: {version}");
+        } else {
+            println!(
+                "Entity mapversion: {version} (doesn't match revision {})",
+                bsp.map_revision()
+            );
+        }
+    }
+}
+
+/// Prints rows in the format chosen with `--format`, in color if stdout is a terminal
+fn print_table(table: &Table, format: Format) {
+    let mut w = BufWriter::new(io::stdout().lock());
+    let color = io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    table.print(&mut w, format, color).unwrap();
+}
+
+/// Takes `--format` out of the arguments of a command that lists rows
+fn take_format(args: &mut Vec<String>) -> Format {
+    let Some(i) = args.iter().skip(2).position(|arg| arg == "--format") else {
+        return Format::Table;
+    };
+    let i = i + 2;
+    if i + 1 == args.len() {
+        usage();
+    }
+
+    let name = args.remove(i + 1);
+    args.remove(i);
+    Format::from_name(&name).unwrap_or_else(|| {
+        fail(
+            Exit::Usage,
+            format!(
+                "unknown format {name}, expected one of: {}",
+                Format::NAMES.join(", ")
+            ),
+        )
+    })
+}
+
+fn lumps<R: Read + Seek>(bsp: &mut BspFile<R>, format: Format) {
+    let mut table = Table::new(&[
+        "index",
+        "name",
+        "offset",
+        "length",
+        "version",
+        "uncompressed",
+        "external",
+    ]);
+    let bsp_format = bsp.format();
+
+    for i in 0..bsp.lump_count() {
+        let lump = *bsp.lump_info_by_index(i);
+        if lump.filelen == 0 {
+            continue;
+        }
+
+        // Only VBSP lumps are LZMA compressed, other formats use the field for something else
+        let uncompressed = (bsp_format == BspFormat::Valve && lump.uncompressed_size != 0)
+            .then_some(lump.uncompressed_size);
+        let external = bsp
+            .external_lump_path(i)
+            .map(|path| path.display().to_string());
+
+        table.push(vec![
+            i.into(),
+            bsp_format.lump_name(i).into(),
+            lump.fileofs.into(),
+            lump.filelen.into(),
+            lump.version.into(),
+            uncompressed.into(),
+            external.into(),
+        ]);
+    }
+
+    print_table(&table, format);
+}
+
+fn files<R: Read + Seek>(bsp: &mut BspFile<R>, format: Format) {
+    let mut table = Table::new(&["name", "crc32"]);
+    if let Some(mut pak) = PakFile::read(bsp).or_exit(Exit::Parse, "couldn't read the pakfile") {
+        for file in pak
+            .entries()
+            .or_exit(Exit::Parse, "couldn't read the pakfile")
+        {
+            table.push(vec![file.name.into(), format!("{:08x}", file.crc32).into()]);
+        }
+    }
+
+    print_table(&table, format);
+}
+
+fn textures<R: Read + Seek>(bsp: &mut BspFile<R>, format: Format) {
+    let texdata: Vec<lumps::TexData> = lumps::read_array(bsp, LumpType::TEXTURE_DATA)
+        .or_exit(Exit::Parse, "couldn't read the TEXTURE_DATA lump");
+    let names =
+        lumps::try_texture_names(bsp).or_exit(Exit::Parse, "couldn't read the texture names");
+    let texinfo: Vec<lumps::TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)
+        .or_exit(Exit::Parse, "couldn't read the TEXTURE_INFO lump");
+
+    let mut uses = vec![0usize; texdata.len()];
+    for info in &texinfo {
+        if let Some(count) = usize::try_from(info.texdata)
+            .ok()
+            .and_then(|i| uses.get_mut(i))
+        {
+            *count += 1;
+        }
+    }
+
+    let mut table = Table::new(&["index", "name", "width", "height", "texinfos"]);
+    for (i, (data, name)) in texdata.iter().zip(names).enumerate() {
+        table.push(vec![
+            i.into(),
+            name.into(),
+            data.width.into(),
+            data.height.into(),
+            uses[i].into(),
+        ]);
+    }
+
+    print_table(&table, format);
+}
+
+fn faceids<R: Read + Seek>(bsp: &mut BspFile<R>, format: Format) {
+    let Some(report) = faceids::read(bsp) else {
+        if bsp.has_lump(LumpType::FACE_IDS as usize) {
+            fail(Exit::Parse, "couldn't read the face IDs");
+        }
+        fail(Exit::MissingLump, "the map has no FACE_IDS lump");
+    };
+    if report.ids != report.faces.len() {
+        eprintln!(
+            "warning: FACE_IDS has {} entries for {} faces",
+            report.ids,
+            report.faces.len()
+        );
+    }
+    if report.macro_textures != 0 && report.macro_textures != report.faces.len() {
+        eprintln!(
+            "warning: FACE_MACRO_TEXTURE_INFO has {} entries for {} faces",
+            report.macro_textures,
+            report.faces.len()
+        );
+    }
+
+    let mut table = Table::new(&["face", "hammer_id", "material", "macro_texture"]);
+    for (i, face) in report.faces.into_iter().enumerate() {
+        table.push(vec![
+            i.into(),
+            face.hammer_id.into(),
+            face.material.into(),
+            face.macro_texture.into(),
+        ]);
+    }
+
+    print_table(&table, format);
+}
+
+fn staticprops<R: Read + Seek>(bsp: &mut BspFile<R>, format: Format) {
+    let props = read_static_props(bsp);
+
+    let mut table = Table::new(&[
+        "index", "model", "x", "y", "z", "pitch", "yaw", "roll", "skin", "solid",
+    ]);
+    for (i, prop) in props.props.iter().enumerate() {
+        let [x, y, z] = prop.origin();
+        let [pitch, yaw, roll] = prop.angles();
+        let model: Cell = props.model_name(prop).into();
+        table.push(vec![
+            i.into(),
+            model,
+            x.into(),
+            y.into(),
+            z.into(),
+            pitch.into(),
+            yaw.into(),
+            roll.into(),
+            prop.skin().into(),
+            prop.solid().into(),
+        ]);
+    }
+
+    print_table(&table, format);
+}
+
+/// Removes or swaps the models of static props
+fn edit_staticprops<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut remove, mut replace) = (None, vec![], vec![]);
+    let (mut backup, mut dry_run) = (false, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--remove-model" => remove.push(args.next().unwrap_or_else(|| usage())),
+            "--replace-model" => {
+                let arg = args.next().map_or("", String::as_str);
+                let Some((from, to)) = arg
+                    .split_once('=')
+                    .filter(|(a, b)| !a.is_empty() && !b.is_empty())
+                else {
+                    fail(
+                        Exit::Usage,
+                        format!("expected --replace-model old.mdl=new.mdl, got {arg}"),
+                    );
+                };
+                replace.push((from, to));
+            }
+            "--backup" => backup = true,
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let mut lumps = gamelump::read(bsp).or_exit(Exit::Parse, "couldn't read the game lump");
+    let lump = lumps
+        .iter_mut()
+        .find(|lump| lump.id == gamelump::STATIC_PROPS)
+        .or_exit(Exit::MissingLump, "the map has no static props");
+    let mut props =
+        StaticProps::parse(&lump.data).or_exit(Exit::Parse, "couldn't read the static props");
+
+    let mut changed = 0;
+    for model in remove {
+        let path = staticprops::model_path(model);
+        let removed = props.remove_props(|props, prop| {
+            props
+                .model_name(prop)
+                .is_some_and(|name| staticprops::model_path(name).eq_ignore_ascii_case(&path))
+        });
+        println!("Removing {removed} props of {path}");
+        changed += removed;
+    }
+    for (from, to) in replace {
+        let replaced = props.replace_model(from, to);
+        println!(
+            "Replacing {} with {} on {replaced} props",
+            staticprops::model_path(from),
+            staticprops::model_path(to)
+        );
+        changed += replaced;
+    }
+    if changed == 0 {
+        println!("No props matched");
+        return;
+    }
+    lump.data = props
+        .serialize()
+        .or_exit(Exit::Parse, "couldn't write the static props");
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let game_lump =
+        gamelump::serialize(&lumps).or_exit(Exit::Parse, "couldn't write the game lump");
+    writer.replace_lump(LumpType::GAME_LUMP, game_lump);
+    if backup {
+        backup::stash(bsp, &mut writer, &[LumpType::GAME_LUMP])
+            .or_exit(Exit::Parse, "couldn't back up the lump");
+    }
+
+    finish_edit(bsp, &writer, &[LumpType::GAME_LUMP], out_path, dry_run);
+    warn_crc_change(&[LumpType::GAME_LUMP]);
+}
+
+fn offset_entities<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let Some(out_path) = args.first() else {
+        usage();
+    };
+
+    let mut transform = Transform {
+        translation: [0.0; 3],
+        yaw: 0.0,
+    };
+    let mut static_props = false;
+    let mut backup = false;
+    let mut dry_run = false;
+
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut number = || -> f64 {
+            args.next()
+                .and_then(|a| a.parse().ok())
+                .unwrap_or_else(|| usage())
+        };
+
+        match arg.as_ref() {
+            "--translate" => transform.translation = [number(), number(), number()],
+            "--rotate" => transform.yaw = number(),
+            "--static-props" => static_props = true,
+            "--backup" => backup = true,
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+
+    if let Some(lump) = bsp.get_lump(LumpType::ENTITIES) {
+        check_rewritable(&lump);
+        let mut entities = entities::parse(&lump).or_exit(Exit::Parse, "entity lump");
+        for entity in &mut entities {
+            transform.apply_to_entity(entity);
+        }
+
+        writer.replace_lump(LumpType::ENTITIES, entities::serialize(&entities));
+    }
+
+    if static_props {
+        if let Ok(mut lumps) = gamelump::read(bsp) {
+            let nodes: Vec<lumps::Node> = lumps::read_array(bsp, LumpType::NODES)
+                .or_exit(Exit::Parse, "couldn't read the NODES lump");
+            let planes: Vec<lumps::Plane> = lumps::read_array(bsp, LumpType::PLANES)
+                .or_exit(Exit::Parse, "couldn't read the PLANES lump");
+            let models: Vec<lumps::Model> = lumps::read_array(bsp, LumpType::MODELS)
+                .or_exit(Exit::Parse, "couldn't read the MODELS lump");
+            let leaves =
+                lumps::try_read_leaves(bsp).or_exit(Exit::Parse, "couldn't read the LEAVES lump");
+            let tree = transform::Tree {
+                nodes: &nodes,
+                planes: &planes,
+                models: &models,
+                leaves: &leaves,
+            };
+
+            for lump in &mut lumps {
+                if lump.id != gamelump::STATIC_PROPS {
+                    continue;
+                }
+
+                let mut props = StaticProps::parse(&lump.data)
+                    .or_exit(Exit::Parse, "couldn't read the static props");
+                transform.apply_to_static_props(&mut props, &tree).or_exit(
+                    Exit::Parse,
+                    "the map's BSP tree is missing or malformed, so the moved static props can't be given their leaves",
+                );
+                lump.data = props
+                    .serialize()
+                    .or_exit(Exit::Parse, "couldn't write the static props");
+            }
+
+            let game_lump =
+                gamelump::serialize(&lumps).or_exit(Exit::Parse, "couldn't write the game lump");
+            writer.replace_lump(LumpType::GAME_LUMP, game_lump);
+        }
+    }
+
+    let mut lumps = vec![LumpType::ENTITIES];
+    if static_props {
+        lumps.push(LumpType::GAME_LUMP);
+    }
+    if backup {
+        backup::stash(bsp, &mut writer, &lumps).or_exit(Exit::Parse, "couldn't back up the lumps");
+    }
+
+    finish_edit(bsp, &writer, &lumps, out_path, dry_run);
+    warn_crc_change(&lumps);
+}
+
+/// Reports how the edited lumps change the file's layout, then writes it unless this is a dry
+/// run.
+fn finish_edit<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    writer: &LumpWriter,
+    edited: &[LumpType],
+    out_path: &str,
+    dry_run: bool,
+) {
+    let forecast = forecast::Forecast::new(bsp, writer, edited)
+        .or_exit(Exit::Parse, "couldn't read the map's lumps");
+    forecast.print(&mut io::stdout().lock()).unwrap();
+
+    if dry_run {
+        return;
+    }
+
+    let mut out = create(out_path);
+    writer.write_to(&mut out).or_exit(Exit::Io, out_path);
+}
+
+fn strip<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let mut out_path = None;
+    let mut lumps = vec![];
+    let mut dry_run = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--remove" => {
+                let name = args.next().map_or("", String::as_str);
+                let Some(lump) = LumpType::from_name(name) else {
+                    fail(Exit::Usage, format!("unknown lump {name}"));
+                };
+                lumps.push(lump);
+            }
+            "--preset" => {
+                let name = args.next().map_or("", String::as_str);
+                let Some(preset) = strip::preset(name) else {
+                    fail(Exit::Usage, format!("unknown preset {name}"));
+                };
+                lumps.extend_from_slice(preset);
+            }
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let removed = strip::strip(&mut writer, &lumps);
+    if removed.is_empty() {
+        println!("None of the lumps to remove are in the map");
+        return;
+    }
+
+    for (lump, size) in &removed {
+        println!("Removing {lump:?}: {size} bytes");
+    }
+    let edited: Vec<LumpType> = removed.iter().map(|&(lump, _)| lump).collect();
+    finish_edit(bsp, &writer, &edited, out_path, dry_run);
+    warn_crc_change(&edited);
+}
+
+/// Clients compare the CRC of every lump but the entities with the server's
+fn warn_crc_change(edited: &[LumpType]) {
+    if edited.iter().any(|&lump| lump != LumpType::ENTITIES) {
+        println!(
+            "warning: the map's CRC changes, so clients with the original map will be told it differs from the server's"
+        );
+    }
+}
+
+/// Removes data the engine never reads
+fn optimize<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut dry_run) = (None, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let removed = strip::strip(&mut writer, strip::LEGACY);
+    if removed.is_empty() {
+        println!("Nothing to optimize");
+        return;
+    }
+
+    for (lump, size) in &removed {
+        println!("Removing deprecated {lump:?}: {size} bytes");
+    }
+    let edited: Vec<LumpType> = removed.iter().map(|&(lump, _)| lump).collect();
+    finish_edit(bsp, &writer, &edited, out_path, dry_run);
+    warn_crc_change(&edited);
+}
+
+fn pak_optimize<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut method, mut dry_run) = (None, pak::Recompression::Lzma, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--method" => match args.next().map(String::as_str) {
+                Some("lzma") => method = pak::Recompression::Lzma,
+                Some("deflate") => method = pak::Recompression::Deflate,
+                _ => usage(),
+            },
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let Some(pakfile) = bsp.get_lump(LumpType::PAKFILE) else {
+        println!("Map has no pakfile");
+        return;
+    };
+    let original = pakfile.len();
+    let (optimized, summary) =
+        pak::optimize(&pakfile, method).or_exit(Exit::Parse, "couldn't read the pakfile");
+
+    for name in &summary.duplicates {
+        println!("Removing duplicate {name}");
+    }
+    println!("Compressed {} stored entries", summary.compressed);
+    for names in &summary.shared_contents {
+        println!("Same contents: {}", names.join(", "));
+    }
+    println!(
+        "Pakfile: {original} -> {} bytes, saved {}",
+        optimized.len(),
+        original as i64 - optimized.len() as i64
+    );
+    for name in &summary.conflicts {
+        println!("warning: {name} is packed more than once with different contents");
+    }
+    if method == pak::Recompression::Deflate && summary.compressed != 0 {
+        println!("warning: the engine can't read deflate compressed entries, use lzma for maps that will be played");
+    }
+
+    if optimized.len() >= original && summary.duplicates.is_empty() {
+        println!("Nothing to optimize");
+        return;
+    }
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    writer.replace_lump(LumpType::PAKFILE, optimized);
+    finish_edit(bsp, &writer, &[LumpType::PAKFILE], out_path, dry_run);
+    warn_crc_change(&[LumpType::PAKFILE]);
+}
+
+/// Removes compile machine paths, editor comments and zip metadata before a map is shared
+fn redact<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut dry_run) = (None, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let mut edited = vec![];
+
+    let mut entities = read_entities_to_rewrite(bsp);
+    let redactions = redact::redact_entities(&mut entities);
+    for r in &redactions {
+        println!(
+            "Removing {} from entity {} ({}): {}",
+            r.key, r.entity, r.classname, r.reason
+        );
+    }
+    if !redactions.is_empty() {
+        writer.replace_lump(LumpType::ENTITIES, entities::serialize(&entities));
+        edited.push(LumpType::ENTITIES);
+    }
+
+    if let Some(pakfile) = bsp.get_lump(LumpType::PAKFILE) {
+        let redacted =
+            pak::redact_archive(&pakfile).or_exit(Exit::Parse, "couldn't read the pakfile");
+        if bsp.get_raw_lump(LumpType::PAKFILE).as_deref() != Some(&redacted[..]) {
+            println!("Clearing timestamps, extra fields and comments from the pakfile");
+            writer.replace_lump(LumpType::PAKFILE, redacted);
+            edited.push(LumpType::PAKFILE);
+        }
+    }
+
+    if edited.is_empty() {
+        println!("Nothing to redact");
+        return;
+    }
+
+    finish_edit(bsp, &writer, &edited, out_path, dry_run);
+    warn_crc_change(&edited);
+}
+
+fn retexture<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut mappings, mut rename_packed, mut dry_run) = (None, vec![], false, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--map" => {
+                let arg = args.next().map_or("", String::as_str);
+                let Some(mapping) = retexture::Mapping::parse(arg) else {
+                    fail(
+                        Exit::Usage,
+                        format!("expected --map old/material=new/material, got {arg}"),
+                    );
+                };
+                mappings.push(mapping);
+            }
+            "--pak" => rename_packed = true,
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+    if mappings.is_empty() {
+        usage();
+    }
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let mut edited = vec![];
+
+    let table: Vec<i32> = lumps::read_array(bsp, LumpType::TEXTURE_DATA_STRING_TABLE)
+        .or_exit(Exit::Parse, "couldn't read the texture string table");
+    let data = bsp
+        .get_lump(LumpType::TEXTURE_DATA_STRING_DATA)
+        .unwrap_or_default();
+    let strings = retexture::rewrite_strings(&table, &data, &mappings);
+    for renamed in &strings.renamed {
+        println!("Renaming material {} to {}", renamed.from, renamed.to);
+    }
+    if !strings.renamed.is_empty() {
+        let table = strings.table.iter().flat_map(|o| o.to_le_bytes()).collect();
+        writer.replace_lump(LumpType::TEXTURE_DATA_STRING_TABLE, table);
+        writer.replace_lump(LumpType::TEXTURE_DATA_STRING_DATA, strings.data);
+        edited.extend([
+            LumpType::TEXTURE_DATA_STRING_TABLE,
+            LumpType::TEXTURE_DATA_STRING_DATA,
+        ]);
+    }
+
+    if let Some(pakfile) = bsp.get_lump(LumpType::PAKFILE).filter(|_| rename_packed) {
+        let (pakfile, renamed) =
+            pak::rename_archive(&pakfile, |name| retexture::rename_packed(&mappings, name))
+                .or_exit(Exit::Parse, "couldn't read the pakfile");
+        for file in &renamed {
+            println!("Renaming packed file {} to {}", file.from, file.to);
+        }
+        if !renamed.is_empty() {
+            writer.replace_lump(LumpType::PAKFILE, pakfile);
+            edited.push(LumpType::PAKFILE);
+        }
+    }
+
+    if edited.is_empty() {
+        println!("No materials matched");
+        return;
+    }
+
+    finish_edit(bsp, &writer, &edited, out_path, dry_run);
+    warn_crc_change(&edited);
+}
+
+/// Makes the pakfile byte for byte the same whenever it has the same files
+fn normalize<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut compress, mut dry_run) = (None, false, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--lzma" => compress = true,
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let Some(pakfile) = bsp.get_lump(LumpType::PAKFILE) else {
+        println!("Map has no pakfile");
+        return;
+    };
+    let normalized = pak::normalize_archive(&pakfile, compress)
+        .or_exit(Exit::Parse, "couldn't read the pakfile");
+    if bsp.get_raw_lump(LumpType::PAKFILE).as_deref() == Some(&normalized[..]) {
+        println!("Pakfile is already normalized");
+        return;
+    }
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    writer.replace_lump(LumpType::PAKFILE, normalized);
+    finish_edit(bsp, &writer, &[LumpType::PAKFILE], out_path, dry_run);
+    warn_crc_change(&[LumpType::PAKFILE]);
+}
+
+/// Writes the map compressed for fast download servers, repacking it first if asked. A repacked
+/// map has a different CRC, so it's written out too for the game server to run.
+fn bz2<R: Read + Seek>(bsp: &mut BspFile<R>, stored: &[u8], map_path: &str, args: &[String]) {
+    let (map_path, data) = match args {
+        [] if map_path == "-" => {
+            fail(
+                Exit::Usage,
+                "a map read from stdin has nowhere to be written next to, use --repack <out.bsp>",
+            );
+        }
+        [] => (map_path, Cow::Borrowed(stored)),
+        [flag, out_path] if flag == "--repack" => {
+            let mut writer =
+                LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+            for lump in repack::repack(bsp, &mut writer, true)
+                .or_exit(Exit::Parse, "couldn't repack the map")
+            {
+                println!("Repacked {lump:?}");
+            }
+
+            let mut data = vec![];
+            writer
+                .write_to(&mut data)
+                .or_exit(Exit::Parse, "couldn't write the map");
+            write_file(out_path, &data);
+            println!("Wrote {out_path}");
+            (out_path.as_str(), Cow::Owned(data))
+        }
+        _ => usage(),
+    };
+
+    let compressed = fastdl::compress(&data).or_exit(Exit::Parse, "couldn't compress the map");
+    let path = fastdl::path(Path::new(map_path));
+    write_file(&path, &compressed);
+    println!(
+        "Wrote {}: {} -> {} bytes",
+        path.display(),
+        data.len(),
+        compressed.len()
+    );
+}
+
+fn repack<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut out_path, mut compress, mut dry_run) = (None, true, false);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next(),
+            "--decompress" => compress = false,
+            "--dry-run" => dry_run = true,
+            _ => usage(),
+        }
+    }
+    let Some(out_path) = out_path else {
+        usage();
+    };
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let changed =
+        repack::repack(bsp, &mut writer, compress).or_exit(Exit::Parse, "couldn't repack the map");
+    if changed.is_empty() {
+        println!(
+            "Nothing to {}",
+            if compress { "compress" } else { "decompress" }
+        );
+        return;
+    }
+
+    finish_edit(bsp, &writer, &changed, out_path, dry_run);
+    warn_crc_change(&changed);
+}
+
+fn export_csv<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let [kind, out_path] = args else {
+        usage();
+    };
+
+    let mut out = create(out_path);
+    match kind.as_ref() {
+        "props" => {
+            let props = read_static_props(bsp);
+
+            placement::export_props(&props, &mut out).or_exit(Exit::Io, out_path);
+        }
+        "entities" => {
+            let entities = read_entities(bsp);
+            placement::export_entities(&entities, &mut out).or_exit(Exit::Io, out_path);
+        }
+        _ => usage(),
+    }
+}
+
+fn entities<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    match args {
+        [] => {
+            if let Some(lump) = bsp.get_lump(LumpType::ENTITIES) {
+                std::io::copy(&mut Cursor::new(lump), &mut std::io::stdout()).unwrap();
+            }
+        }
+        [flag] if flag == "--stats" => {
+            let entities = read_entities(bsp);
+            edicts::Stats::new(&entities)
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+        [flag, fgd_path] if flag == "--check-fgd" => {
+            if !Path::new(fgd_path).is_file() {
+                fail(Exit::NotFound, format!("{fgd_path} not found"));
+            }
+            let fgd = fgd::Fgd::load(Path::new(fgd_path)).unwrap_or_else(|e| fail(Exit::Parse, e));
+
+            let entities = read_entities(bsp);
+            let report = fgd::check(&entities, &fgd);
+            report.print(&mut io::stdout().lock()).unwrap();
+
+            if !report.is_clean() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+        _ => usage(),
+    }
+}
+
+fn import_csv<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let [kind, csv_path, out_path, flags @ ..] = args else {
+        usage();
+    };
+
+    let (mut backup, mut dry_run, mut add_models) = (false, false, false);
+    for flag in flags {
+        match flag.as_ref() {
+            "--backup" => backup = true,
+            "--dry-run" => dry_run = true,
+            "--add-models" if kind == "props" => add_models = true,
+            _ => usage(),
+        }
+    }
+
+    let text = String::from_utf8_lossy(&read_file(csv_path)).into_owned();
+    let records = csv::parse(&text).or_exit(Exit::Parse, format!("{csv_path} is not valid CSV"));
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+
+    let lump = match kind.as_ref() {
+        "props" => {
+            let mut lumps = gamelump::read(bsp).or_exit(Exit::Parse, "couldn't read the game lump");
+            let lump = lumps
+                .iter_mut()
+                .find(|lump| lump.id == gamelump::STATIC_PROPS)
+                .or_exit(Exit::MissingLump, "the map has no static props");
+
+            let mut props = StaticProps::parse(&lump.data)
+                .or_exit(Exit::Parse, "couldn't read the static props");
+            placement::import_props(&mut props, &records, add_models)
+                .or_exit(Exit::Parse, csv_path);
+            lump.data = props
+                .serialize()
+                .or_exit(Exit::Parse, "couldn't write the static props");
+
+            let game_lump =
+                gamelump::serialize(&lumps).or_exit(Exit::Parse, "couldn't write the game lump");
+            writer.replace_lump(LumpType::GAME_LUMP, game_lump);
+            LumpType::GAME_LUMP
+        }
+        "entities" => {
+            let mut entities = read_entities_to_rewrite(bsp);
+            placement::import_entities(&mut entities, &records).or_exit(Exit::Parse, csv_path);
+
+            writer.replace_lump(LumpType::ENTITIES, entities::serialize(&entities));
+            LumpType::ENTITIES
+        }
+        _ => usage(),
+    };
+
+    if backup {
+        backup::stash(bsp, &mut writer, &[lump]).or_exit(Exit::Parse, "couldn't back up the lump");
+    }
+
+    finish_edit(bsp, &writer, &[lump], out_path, dry_run);
+}
+
+fn locate<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let point: Option<Vec<f32>> = args.iter().map(|a| a.parse().ok()).collect();
+    let Some(&[x, y, z]) = point.as_deref() else {
+        usage();
+    };
+
+    let nodes: Vec<lumps::Node> = lumps::read_array(bsp, LumpType::NODES)
+        .or_exit(Exit::Parse, "couldn't read the NODES lump");
+    let planes: Vec<lumps::Plane> = lumps::read_array(bsp, LumpType::PLANES)
+        .or_exit(Exit::Parse, "couldn't read the PLANES lump");
+    let models: Vec<lumps::Model> = lumps::read_array(bsp, LumpType::MODELS)
+        .or_exit(Exit::Parse, "couldn't read the MODELS lump");
+    let leaves = lumps::try_read_leaves(bsp).or_exit(Exit::Parse, "couldn't read the LEAVES lump");
+
+    let Some(leaf_index) = tree::locate(&nodes, &planes, &models, [x, y, z]) else {
+        fail(Exit::Parse, "the map's BSP tree is missing or malformed");
+    };
+    let Some(leaf) = leaves.get(leaf_index) else {
+        fail(
+            Exit::Parse,
+            format!("tree points to missing leaf {leaf_index}"),
+        );
+    };
+
+    println!("Leaf: {leaf_index}");
+    println!("Cluster: {}", leaf.cluster);
+    println!("Area: {}", leaf.area());
+    println!("Contents: {}", tree::contents_names(leaf.contents));
+    println!("Flags: {:#x}", leaf.flags());
+    println!("Bounds: {:?} to {:?}", leaf.mins, leaf.maxs);
+    if leaf.cluster < 0 {
+        println!("Point is outside the world or inside solid");
+    }
+}
+
+fn vis<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut cluster, mut at, mut out_path, mut size) = (None, None, None, 1024);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || -> Option<f32> { args.next()?.parse().ok() };
+
+        match arg.as_ref() {
+            "--cluster" => cluster = number().map(|n| n as usize),
+            "--at" => at = Some([number(), number(), number()]),
+            "--out" => out_path = args.next(),
+            "--size" => size = number().map_or(0, |n| n as u32),
+            _ => usage(),
+        }
+    }
+
+    let Some(data) = bsp.get_lump(LumpType::VISIBILITY) else {
+        if cluster.is_some() || at.is_some() {
+            fail(Exit::MissingLump, "the map has no visibility data");
+        }
+        println!("Map has no visibility data");
+        return;
+    };
+    let vis =
+        vis::Visibility::parse(&data).or_exit(Exit::Parse, "couldn't read the visibility data");
+    if cluster.is_none() && at.is_none() {
+        vis.print(&mut io::stdout().lock()).unwrap();
+        return;
+    }
+
+    let (Some(out_path), 1..) = (out_path, size) else {
+        usage();
+    };
+
+    let leaves = lumps::try_read_leaves(bsp).or_exit(Exit::Parse, "couldn't read the LEAVES lump");
+    if let Some(point) = at {
+        let [Some(x), Some(y), Some(z)] = point else {
+            usage();
+        };
+        let nodes: Vec<lumps::Node> = lumps::read_array(bsp, LumpType::NODES)
+            .or_exit(Exit::Parse, "couldn't read the NODES lump");
+        let planes: Vec<lumps::Plane> = lumps::read_array(bsp, LumpType::PLANES)
+            .or_exit(Exit::Parse, "couldn't read the PLANES lump");
+        let models: Vec<lumps::Model> = lumps::read_array(bsp, LumpType::MODELS)
+            .or_exit(Exit::Parse, "couldn't read the MODELS lump");
+
+        let leaf = tree::locate(&nodes, &planes, &models, [x, y, z]).and_then(|i| leaves.get(i));
+        match leaf.map(|leaf| leaf.cluster) {
+            Some(found @ 0..) => cluster = Some(found as usize),
+            _ => {
+                fail(
+                    Exit::Usage,
+                    format!("({x}, {y}, {z}) is outside the world or inside solid"),
+                );
+            }
+        }
+    }
+
+    let Some(cluster) = cluster else {
+        usage();
+    };
+    if cluster >= vis.num_clusters() {
+        fail(
+            Exit::Usage,
+            format!(
+                "cluster {cluster} doesn't exist, the map has {}",
+                vis.num_clusters()
+            ),
+        );
+    }
+
+    let visible = (0..vis.num_clusters())
+        .filter(|&other| vis.can_see(cluster, other))
+        .count();
+    println!(
+        "Cluster {cluster} can see {visible} of {} clusters",
+        vis.num_clusters()
+    );
+
+    let (width, height, pixels) = vis.render_pvs(&leaves, cluster, size);
+    let mut out = create(out_path);
+    image::write_png(&mut out, width, height, &pixels).or_exit(Exit::Io, out_path);
+}
+
+fn overlays<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let mut dirs = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_ref(), args.next()) {
+            ("--game-dir", Some(dir)) => dirs.push(dir.into()),
+            _ => usage(),
+        }
+    }
+
+    let mut entries = overlays::read(bsp).or_exit(Exit::Parse, "couldn't read the overlays");
+    let store = AssetStore::new(
+        bsp.get_lump(LumpType::PAKFILE).map(Cow::into_owned),
+        dirs,
+        PathMatching::Normalized,
+    );
+    overlays::check_materials(&mut entries, &store);
+
+    overlays::print(&entries, &mut io::stdout().lock()).unwrap();
+}
+
+fn decals<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let mut dirs = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match (arg.as_ref(), args.next()) {
+            ("--game-dir", Some(dir)) => dirs.push(dir.into()),
+            _ => usage(),
+        }
+    }
+
+    let entities = read_entities(bsp);
+    let mut materials =
+        decals::collect(bsp, &entities).or_exit(Exit::Parse, "couldn't read the decals");
+    let searched_game = !dirs.is_empty();
+    let store = AssetStore::new(
+        bsp.get_lump(LumpType::PAKFILE).map(Cow::into_owned),
+        dirs,
+        PathMatching::Normalized,
+    );
+    decals::check_materials(&mut materials, &store);
+
+    decals::print(&materials, &mut io::stdout().lock()).unwrap();
+    if !searched_game {
+        println!("note: only the pakfile was searched, pass --game-dir to include game content");
+    }
+}
+
+/// Everything the map depends on, looked up in its pakfile and `dirs`
+fn dependency_graph<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    map_path: &str,
+    dirs: Vec<PathBuf>,
+    matching: PathMatching,
+) -> (deps::Graph, AssetStore) {
+    let entities = bsp
+        .get_lump(LumpType::ENTITIES)
+        .and_then(|lump| entities::parse(&lump).ok())
+        .unwrap_or_default();
+    let texture_names = lumps::texture_names(bsp).unwrap_or_default();
+    let prop_models = gamelump::read(bsp)
+        .unwrap_or_default()
+        .iter()
+        .find(|lump| lump.id == gamelump::STATIC_PROPS)
+        .and_then(|lump| StaticProps::parse(&lump.data).ok())
+        .map(|props| props.models)
+        .unwrap_or_default();
+
+    let mut store = AssetStore::new(
+        bsp.get_lump(LumpType::PAKFILE).map(Cow::into_owned),
+        dirs,
+        matching,
+    );
+    let map_name = std::path::Path::new(map_path)
+        .file_name()
+        .map(|name| format!("maps/{}", name.to_string_lossy().to_ascii_lowercase()))
+        .or_exit(Exit::Usage, format!("{map_path} doesn't name a file"));
+
+    let refs = deps::map_references(&texture_names, &entities, &prop_models, matching);
+    let graph = deps::Graph::build(&map_name, refs, &mut store);
+    (graph, store)
+}
+
+fn reslist<R: Read + Seek>(bsp: &mut BspFile<R>, map_path: &str, args: &[String]) {
+    let (mut dirs, mut stock, mut out_dir) = (vec![], vec![], None);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(value) = args.next() else {
+            usage();
+        };
+        match arg.as_ref() {
+            "--game-dir" => dirs.push(PathBuf::from(value)),
+            "--stock" => stock.push(PathBuf::from(value)),
+            "--out-dir" => out_dir = Some(PathBuf::from(value)),
+            _ => usage(),
+        }
+    }
+    for path in &stock {
+        if !path.exists() {
+            fail(Exit::NotFound, format!("{} not found", path.display()));
+        }
+    }
+    let stock =
+        reslist::Stock::load(stock).unwrap_or_else(|e| fail_io("the stock content list", e));
+
+    let packed: Vec<String> = match bsp.get_lump(LumpType::PAKFILE) {
+        Some(data) => pak::entries(&data)
+            .or_exit(Exit::Parse, "couldn't read the pakfile")
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect(),
+        None => vec![],
+    };
+    let (graph, store) = dependency_graph(bsp, map_path, dirs, PathMatching::Normalized);
+    let list = reslist::collect(&graph, &store, &stock, &packed);
+
+    let map_path = Path::new(map_path);
+    let out_dir =
+        out_dir.unwrap_or_else(|| map_path.parent().unwrap_or(Path::new("")).to_path_buf());
+    let stem = map_path
+        .file_stem()
+        .or_exit(
+            Exit::Usage,
+            format!("{} doesn't name a file", map_path.display()),
+        )
+        .to_string_lossy();
+    let res_path = out_dir.join(format!("{stem}.res"));
+    let whitelist_path = out_dir.join(format!("{stem}_whitelist.txt"));
+
+    let mut out = create(&res_path);
+    list.write_res(&mut out)
+        .or_exit(Exit::Io, res_path.display());
+    let mut out = create(&whitelist_path);
+    list.write_whitelist(&mut out)
+        .or_exit(Exit::Io, whitelist_path.display());
+
+    println!("{} custom files", list.files.len());
+    println!("Wrote {}", res_path.display());
+    println!("Wrote {}", whitelist_path.display());
+    for path in &list.missing {
+        println!("warning: left out {path}, which isn't packed, in a game directory or stock");
+    }
+}
+
+fn deps<R: Read + Seek>(bsp: &mut BspFile<R>, map_path: &str, args: &[String]) {
+    let mut dirs = vec![];
+    let mut format = "tree";
+    let mut max_depth = None;
+    let mut root = None;
+    let mut target = None;
+    let mut matching = PathMatching::Normalized;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--strict-paths" {
+            matching = PathMatching::Strict;
+            continue;
+        }
+
+        let Some(value) = args.next() else {
+            usage();
+        };
+
+        match arg.as_ref() {
+            "--game-dir" => dirs.push(value.into()),
+            "--format" => format = value,
+            "--max-depth" => max_depth = Some(value.parse().unwrap_or_else(|_| usage())),
+            "--root" => root = Some(value.as_str()),
+            "--target" => target = Some(value.as_str()),
+            _ => usage(),
+        }
+    }
+
+    let (graph, _) = dependency_graph(bsp, map_path, dirs, matching);
+    let graph = graph.filter(root, max_depth, target);
+
+    let mut w = BufWriter::new(io::stdout().lock());
+    match format {
+        "tree" => graph.write_tree(&mut w).unwrap(),
+        "dot" => graph.write_dot(&mut w).unwrap(),
+        "json" => writeln!(w, "{}", graph.to_json()).unwrap(),
+        _ => usage(),
+    }
+}
+
+fn pack<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut paths, mut sync, mut dry_run) = (vec![], false, false);
+    for arg in args {
+        match arg.as_ref() {
+            "--sync" => sync = true,
+            "--dry-run" => dry_run = true,
+            _ => paths.push(arg),
+        }
+    }
+    let [dir, out_path] = paths[..] else {
+        usage();
+    };
+
+    if !Path::new(dir).is_dir() {
+        fail(Exit::NotFound, format!("{dir} not found"));
+    }
+    let (pakfile, summary) = pak::update(
+        bsp.get_lump(LumpType::PAKFILE).as_deref(),
+        dir.as_ref(),
+        sync,
+    )
+    .or_exit(Exit::Parse, "couldn't update the pakfile");
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    writer.append_lump(LumpType::PAKFILE, pakfile);
+
+    for (action, names) in [
+        ("added", &summary.added),
+        ("replaced", &summary.replaced),
+        ("deleted", &summary.deleted),
+    ] {
+        for name in names {
+            println!("{action}: {name}");
+        }
+    }
+    println!(
+        "{} added, {} replaced, {} deleted, {} unchanged",
+        summary.added.len(),
+        summary.replaced.len(),
+        summary.deleted.len(),
+        summary.unchanged.len()
+    );
+
+    finish_edit(bsp, &writer, &[LumpType::PAKFILE], out_path, dry_run);
+    warn_crc_change(&[LumpType::PAKFILE]);
+}
+
+/// `stored` is the map as it's stored, still encrypted, which is what servers and clients compare
+fn hash<R: Read + Seek>(bsp: &mut BspFile<R>, stored: &[u8], args: &[String]) {
+    match args {
+        [] => {
+            let (md5, sha256) =
+                checksum::file_digests(stored).or_exit(Exit::Io, "couldn't read the map");
+            println!("MD5: {}", checksum::hex(&md5));
+            println!("SHA256: {}", checksum::hex(&sha256));
+        }
+        [flag] if flag == "--per-lump" => {
+            let format = bsp.format();
+            let mut w = BufWriter::new(io::stdout().lock());
+            for lump in checksum::lump_digests(bsp) {
+                writeln!(
+                    w,
+                    "{:3} {:<40} length = {:10} crc32 = {:08x} sha256 = {}",
+                    lump.index,
+                    format.lump_name(lump.index),
+                    lump.size,
+                    lump.crc32,
+                    checksum::hex(&lump.sha256)
+                )
+                .unwrap();
+            }
+        }
+        _ => usage(),
+    }
+}
+
+fn hexdump<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (mut lump, mut raw, mut offset, mut length) = (None, false, 0, None);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || -> usize {
+            args.next()
+                .and_then(|n| n.parse().ok())
+                .unwrap_or_else(|| usage())
+        };
+
+        match arg.as_ref() {
+            "--lump" => lump = args.next(),
+            "--raw" => raw = true,
+            "--offset" => offset = number(),
+            "--length" => length = Some(number()),
+            _ => usage(),
+        }
+    }
+
+    // Lumps are given by index, or by name in maps whose lumps have them
+    let Some(lump) = lump else {
+        usage();
+    };
+    let format = bsp.format();
+    let index = lump
+        .parse::<usize>()
+        .ok()
+        .or_else(|| (0..bsp.lump_count()).find(|&i| format.lump_name(i).eq_ignore_ascii_case(lump)))
+        .filter(|&i| i < bsp.lump_count())
+        .unwrap_or_else(|| fail(Exit::Usage, format!("the map has no lump {lump}")));
+    let name = format.lump_name(index);
+
+    if !bsp.has_lump(index) {
+        fail(Exit::MissingLump, format!("the map has no {name} lump"));
+    }
+    let data = if raw {
+        bsp.get_raw_lump_by_index(index)
+    } else {
+        bsp.get_lump_by_index(index)
+    };
+    let data = data.or_exit(Exit::Parse, format!("couldn't read the {name} lump"));
+
+    let info = *bsp.lump_info_by_index(index);
+    let compressed = info.uncompressed_size != 0 && format == BspFormat::Valve;
+    println!(
+        "Lump {index} {name}: {} bytes{}, version {}",
+        data.len(),
+        match (compressed, raw) {
+            (true, true) => ", LZMA compressed",
+            (true, false) => ", decompressed",
+            _ => "",
+        },
+        info.version
+    );
+
+    let start = offset.min(data.len());
+    let end = length.map_or(data.len(), |length| {
+        start.saturating_add(length).min(data.len())
+    });
+    let mut w = BufWriter::new(io::stdout().lock());
+    hexdump::write(&mut w, &data[start..end], start as u64).unwrap();
+}
+
+fn restore<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let [out_path] = args else {
+        usage();
+    };
+
+    let mut writer =
+        LumpWriter::from_bsp(bsp).or_exit(Exit::Parse, "couldn't read the map's lumps");
+    let restored =
+        backup::restore(bsp, &mut writer).or_exit(Exit::Parse, "couldn't read the lump backups");
+    if restored.is_empty() {
+        println!("No lump backups found");
+        return;
+    }
+
+    let mut out = create(out_path);
+    writer.write_to(&mut out).or_exit(Exit::Io, out_path);
+
+    for lump in restored {
+        println!("Restored {lump:?}");
+    }
+}
+
+fn export_lightmaps<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let mut out_dir = None;
+    let mut format = "png";
+    let mut hdr = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_dir = args.next().map(std::path::PathBuf::from),
+            "--format" => format = args.next().map_or("", String::as_str),
+            "--hdr" => hdr = true,
+            _ => usage(),
+        }
+    }
+
+    let (Some(out_dir), "png" | "hdr") = (out_dir, format) else {
+        usage();
+    };
+
+    let Some(lightmaps) = lightmaps::read(bsp, hdr) else {
+        let lighting = if hdr { "HDR" } else { "LDR" };
+        fail(
+            Exit::MissingLump,
+            format!("the map has no {lighting} lighting"),
+        );
+    };
+
+    std::fs::create_dir_all(&out_dir).unwrap_or_else(|e| fail_io(out_dir.display(), e));
+    let (pages, placements) = lightmaps::pack(&lightmaps);
+    let size = lightmaps::PAGE_SIZE;
+
+    for (i, page) in pages.iter().enumerate() {
+        let path = out_dir.join(format!("lightmap_{i:03}.{format}"));
+        let mut out = create(&path);
+
+        let written = if format == "png" {
+            let pixels: Vec<_> = page.pixels.iter().map(|&p| lightmaps::to_rgb8(p)).collect();
+            image::write_png(&mut out, size, size, &pixels)
+        } else {
+            image::write_hdr(&mut out, size, size, &page.pixels)
+        };
+        written.or_exit(Exit::Io, path.display());
+    }
+
+    let index_path = out_dir.join("lightmaps.csv");
+    let mut index = create(&index_path);
+    csv::write_record(&mut index, &["face", "page", "x", "y", "width", "height"])
+        .or_exit(Exit::Io, index_path.display());
+    for p in &placements {
+        let record = [
+            p.face,
+            p.page,
+            p.x as usize,
+            p.y as usize,
+            p.width as usize,
+            p.height as usize,
+        ];
+        csv::write_record(&mut index, &record.map(|v| v.to_string()))
+            .or_exit(Exit::Io, index_path.display());
+    }
+
+    println!(
+        "Wrote {} lightmaps on {} pages",
+        placements.len(),
+        pages.len()
+    );
+    if placements.len() < lightmaps.len() {
+        println!(
+            "{} lightmaps larger than a page were skipped",
+            lightmaps.len() - placements.len()
+        );
+    }
+}
+
+fn export_thumbnails<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let [out_dir] = args else {
+        usage();
+    };
+
+    let pakfile = bsp.get_lump(LumpType::PAKFILE);
+    let names: Vec<String> = pakfile
+        .clone()
+        .and_then(|data| pak::entries(&data).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    let mut store = AssetStore::new(
+        pakfile.map(Cow::into_owned),
+        vec![],
+        PathMatching::Normalized,
+    );
+
+    let thumbnails = thumbnails::extract(&mut store, &names);
+    if thumbnails.is_empty() {
+        println!("Map has no packed menu photos or loading screens");
+        return;
+    }
+
+    let out_dir = std::path::Path::new(out_dir);
+    std::fs::create_dir_all(out_dir).unwrap_or_else(|e| fail_io(out_dir.display(), e));
+
+    for thumbnail in thumbnails {
+        let Some(image) = thumbnail.image else {
+            println!(
+                "warning: {} uses {}, which is missing or in an unsupported format",
+                thumbnail.source, thumbnail.texture
+            );
+            continue;
+        };
+
+        let stem = std::path::Path::new(&thumbnail.texture)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_ascii_lowercase();
+        let path = out_dir.join(format!("{stem}.png"));
+        let mut out = create(&path);
+        image::write_png(&mut out, image.width, image.height, &image.pixels)
+            .or_exit(Exit::Io, path.display());
+
+        println!(
+            "{} ({}x{}) -> {}",
+            thumbnail.texture,
+            image.width,
+            image.height,
+            path.display()
+        );
+    }
+}
+
+fn export_mesh<R: Read + Seek>(bsp: &mut BspFile<R>, args: &[String]) {
+    let (out_path, format) = match args {
+        [out_path] => (out_path, "obj"),
+        [out_path, flag, format] if flag == "--format" => (out_path, format.as_str()),
+        _ => usage(),
+    };
+
+    let world =
+        lumps::World::try_read(bsp).or_exit(Exit::Parse, "couldn't read the world geometry");
+    let texdata: Vec<lumps::TexData> = lumps::read_array(bsp, LumpType::TEXTURE_DATA)
+        .or_exit(Exit::Parse, "couldn't read the TEXTURE_DATA lump");
+    let displacements = mesh::Displacements {
+        info: lumps::read_array(bsp, LumpType::DISPLACEMENT_INFO)
+            .or_exit(Exit::Parse, "couldn't read the DISPLACEMENT_INFO lump"),
+        verts: lumps::read_array(bsp, LumpType::DISPLACEMENT_VERTICES)
+            .or_exit(Exit::Parse, "couldn't read the DISPLACEMENT_VERTICES lump"),
+    };
+    let mesh = mesh::Mesh::build(&world, &texdata, &displacements);
+
+    let out_path = std::path::Path::new(out_path);
+    let mut out = create(out_path);
+    match format {
+        "obj" => {
+            let mtl_path = out_path.with_extension("mtl");
+            let mtl_name = mtl_path
+                .file_name()
+                .or_exit(
+                    Exit::Usage,
+                    format!("{} doesn't name a file", out_path.display()),
+                )
+                .to_string_lossy();
+            mesh::write_obj(&mesh, &mut out, &mtl_name).or_exit(Exit::Io, out_path.display());
+
+            let mut mtl = create(&mtl_path);
+            mesh::write_mtl(&mesh, &mut mtl).or_exit(Exit::Io, mtl_path.display());
+        }
+        "gltf" => mesh::write_gltf(&mesh, &mut out).or_exit(Exit::Io, out_path.display()),
+        _ => usage(),
+    }
+
+    let triangles: usize = mesh.groups.iter().map(|g| g.indices.len() / 3).sum();
+    println!(
+        "Wrote {triangles} triangles in {} materials",
+        mesh.groups.len()
+    );
+}
+
+fn overview<R: Read + Seek>(bsp: &mut BspFile<R>, map_path: &str, args: &[String]) {
+    let mut out_path = None;
+    let mut scale = None;
+    let mut size = overview::GAME_SIZE;
+    let mut with_displacements = false;
+    let mut max_z = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--out" => out_path = args.next().map(std::path::PathBuf::from),
+            "--scale" => match args.next().map(String::as_str) {
+                Some("auto") => scale = None,
+                value => {
+                    scale = Some(
+                        value
+                            .and_then(|v| v.parse().ok())
+                            .unwrap_or_else(|| usage()),
+                    )
+                }
+            },
+            "--size" => {
+                size = args
+                    .next()
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or_else(|| usage())
+            }
+            "--displacements" => with_displacements = true,
+            "--max-z" => {
+                max_z = Some(
+                    args.next()
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            _ => usage(),
+        }
+    }
+    let (Some(out_path), 1..) = (out_path, size) else {
+        usage();
+    };
+    if scale.is_some_and(|scale: f32| scale <= 0.0) {
+        usage();
+    }
+
+    let world =
+        lumps::World::try_read(bsp).or_exit(Exit::Parse, "couldn't read the world geometry");
+    let texdata: Vec<lumps::TexData> = lumps::read_array(bsp, LumpType::TEXTURE_DATA)
+        .or_exit(Exit::Parse, "couldn't read the TEXTURE_DATA lump");
+    let displacements = if with_displacements {
+        mesh::Displacements {
+            info: lumps::read_array(bsp, LumpType::DISPLACEMENT_INFO)
+                .or_exit(Exit::Parse, "couldn't read the DISPLACEMENT_INFO lump"),
+            verts: lumps::read_array(bsp, LumpType::DISPLACEMENT_VERTICES)
+                .or_exit(Exit::Parse, "couldn't read the DISPLACEMENT_VERTICES lump"),
+        }
+    } else {
+        mesh::Displacements {
+            info: vec![],
+            verts: vec![],
+        }
+    };
+    let mesh = mesh::Mesh::build(&world, &texdata, &displacements);
+
+    let Some(overview) = overview::render(&mesh, size, scale, max_z) else {
+        fail(Exit::MissingLump, "the map has no floors to draw");
+    };
+
+    let mut out = create(&out_path);
+    image::write_png(&mut out, size, size, &overview.pixels).or_exit(Exit::Io, out_path.display());
+
+    // Named after the map, which the game finds the script by
+    let name_path = if map_path == "-" {
+        out_path.as_path()
+    } else {
+        Path::new(map_path)
+    };
+    let map_name = name_path
+        .file_stem()
+        .or_exit(
+            Exit::Usage,
+            format!("{} doesn't name a file", name_path.display()),
+        )
+        .to_string_lossy();
+    let script_path = out_path.with_extension("txt");
+    overview
+        .write_script(&mut create(&script_path), &map_name)
+        .or_exit(Exit::Io, script_path.display());
+
+    println!(
+        "Wrote {} ({size}x{size}, {} units per pixel) and {}",
+        out_path.display(),
+        overview.scale,
+        script_path.display()
+    );
+}
+
+/// Reads the entity lump, exiting if the map has none or the policy doesn't allow reading it
+fn read_entities<R: Read + Seek>(bsp: &mut BspFile<R>) -> Vec<entities::Entity> {
+    if !bsp.has_lump(LumpType::ENTITIES as usize) {
+        fail(Exit::MissingLump, "the map has no entity lump");
+    }
+    entities::read(bsp).or_exit(Exit::Parse, "couldn't read the entity lump")
+}
+
+/// Reads the static prop game lump, exiting if the map has none or it can't be read
+fn read_static_props<R: Read + Seek>(bsp: &mut BspFile<R>) -> StaticProps {
+    if !bsp.has_lump(LumpType::GAME_LUMP as usize) {
+        fail(Exit::MissingLump, "the map has no static props");
+    }
+    let lumps = gamelump::read(bsp).or_exit(Exit::Parse, "couldn't read the game lump");
+    let lump = lumps
+        .iter()
+        .find(|lump| lump.id == gamelump::STATIC_PROPS)
+        .or_exit(Exit::MissingLump, "the map has no static props");
+
+    StaticProps::parse(&lump.data).or_exit(Exit::Parse, "couldn't read the static props")
+}
+
+/// Like [`read_entities`], for commands writing the entities back
+fn read_entities_to_rewrite<R: Read + Seek>(bsp: &mut BspFile<R>) -> Vec<entities::Entity> {
+    let entities = read_entities(bsp);
+    if let Some(lump) = bsp.get_lump(LumpType::ENTITIES) {
+        check_rewritable(&lump);
+    }
+    entities
+}
+
+/// Exits rather than rewrite an entity lump whose non-ASCII characters parsing replaced
+fn check_rewritable(lump: &[u8]) {
+    if !entities::is_utf8(lump) {
+        fail(
+            Exit::Parse,
+            "the entity lump isn't UTF-8, and rewriting it would replace its non-ASCII characters",
+        );
+    }
+}
+
+/// Opens a map in memory for parsing under `policy`, with whatever external lumps its format
+/// uses
+fn load_map<'a, 'b: 'a>(
+    reader: &'a mut Cursor<&'b [u8]>,
+    path: &str,
+    policy: Policy,
+) -> BspFile<'a, Cursor<&'b [u8]>> {
+    let Ok(bsp) = BspFile::from_slice(reader) else {
+        fail(
+            Exit::Parse,
+            format!("{path} is not a map, or its header is cut short"),
+        );
+    };
+    let mut bsp = bsp.with_policy(policy);
+    if bsp.format() == BspFormat::Respawn {
+        bsp = bsp.with_external_lumps(path);
+    }
+    bsp
+}
+
+/// A map in memory, which commands borrow its lumps from
+struct OpenMap {
+    /// The map as it's stored, still encrypted if it is
+    stored: Mmap,
+    decrypted: Option<Vec<u8>>,
+}
+
+impl OpenMap {
+    /// The map as it's parsed
+    fn data(&self) -> &[u8] {
+        self.decrypted.as_deref().unwrap_or(&self.stored)
+    }
+
+    fn is_encrypted(&self) -> bool {
+        self.decrypted.is_some()
+    }
+}
+
+/// Opens the map at `path`, or reads it from stdin for `-`. Fast download copies are
+/// decompressed and encrypted maps decrypted on the way in.
+fn open_map(path: &str) -> OpenMap {
+    let stored = match path {
+        "-" => stream::buffer(io::stdin().lock()).map(Mmap::from),
+        _ => Mmap::open(path),
+    };
+    let mut stored = stored.unwrap_or_else(|e| fail_io(path, e));
+    if magic::identify(&stored) == Some(FileKind::Bzip2) {
+        stored = Mmap::from(stream::buffer(&stored[..]).unwrap_or_else(|e| fail_io(path, e)));
+    }
+    if let Some(kind) = magic::identify(&stored) {
+        eprintln!("error: {path} is {}, not a map", kind.description());
+        eprintln!("hint: {}", kind.hint());
+        std::process::exit(Exit::Parse as i32);
+    }
+
+    let decrypted = match crypt::decrypt(&stored) {
+        Ok(Cow::Borrowed(_)) => None,
+        Ok(Cow::Owned(data)) => Some(data),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            fail(Exit::Parse, format!("{path} is too short to be a map"))
+        }
+        Err(e) => fail_io(path, e),
+    };
+
+    OpenMap { stored, decrypted }
+}
+
+fn diff(args: &[String], policy: Policy) {
+    let (paths, json) = match args {
+        [a, b] => ([a, b], false),
+        [a, b, flag] if flag == "--json" => ([a, b], true),
+        _ => usage(),
+    };
+
+    let [a, b] = paths.map(|path| {
+        let map = open_map(path);
+        let mut reader = Cursor::new(map.data());
+        let mut bsp = load_map(&mut reader, path, policy);
+        diff::Snapshot::new(&mut bsp)
+    });
+
+    let diff = diff::Diff::new(&a, &b);
+    if json {
+        println!("{}", diff.to_json());
+    } else {
+        diff.print(&mut io::stdout().lock()).unwrap();
+    }
+
+    if !diff.is_empty() {
+        std::process::exit(Exit::Findings as i32);
+    }
+}
+
+/// Writes one JSON document per map, for search servers that take JSON Lines
+fn index(paths: &[String], policy: Policy) {
+    if paths.is_empty() {
+        usage();
+    }
+
+    let mut w = BufWriter::new(io::stdout().lock());
+    for path in paths {
+        let map = open_map(path);
+        let mut reader = Cursor::new(map.data());
+        let mut bsp = load_map(&mut reader, path, policy);
+
+        let map = Path::new(path)
+            .file_stem()
+            .or_exit(Exit::Usage, format!("{path} doesn't name a file"))
+            .to_string_lossy();
+        let document = index::Document::new(&mut bsp, &map);
+        writeln!(w, "{}", document.to_json()).unwrap();
+    }
+}
+
+fn batch(args: &[String], policy: Policy) {
+    let (mut paths, mut json, mut threads) = (vec![], false, parallel::default_threads());
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--report" => match args.next().map(String::as_str) {
+                Some("csv") => json = false,
+                Some("json") => json = true,
+                _ => usage(),
+            },
+            "--jobs" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => threads = n,
+                None => usage(),
+            },
+            arg => paths.extend(batch::expand(arg).unwrap_or_else(|e| fail_io(arg, e))),
+        }
+    }
+    if paths.is_empty() {
+        fail(Exit::NotFound, "no maps found");
+    }
+
+    let reports = batch::run(&paths, threads, policy);
+    let mut w = BufWriter::new(io::stdout().lock());
+    if json {
+        writeln!(w, "{}", batch::to_json(&reports)).unwrap();
+    } else {
+        batch::write_csv(&reports, &mut w).unwrap();
+    }
+}
+
+fn jobs(args: &[String], policy: Policy) {
+    let [path] = args else {
+        usage();
+    };
+
+    if !Path::new(path).is_file() {
+        fail(Exit::NotFound, format!("{path} not found"));
+    }
+    let jobs = jobs::Jobs::load(Path::new(path)).or_exit(Exit::Parse, path);
+
+    let outcomes = jobs::run(&jobs, policy).or_exit(Exit::Io, jobs.output.display());
+    jobs::print_summary(&outcomes, &mut io::stdout().lock()).unwrap();
+
+    if outcomes.iter().any(|outcome| outcome.result.is_err()) {
+        std::process::exit(Exit::Findings as i32);
+    }
+}
+
+/// Runs commands over a map each time it's rewritten, printing how their output changed
+fn watch(args: &[String], policy: Policy) {
+    let (mut path, mut commands) = (None, vec!["validate"]);
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_ref() {
+            "--run" => {
+                let list = args.next().unwrap_or_else(|| usage());
+                commands = list.split(',').map(str::trim).collect();
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else {
+        usage();
+    };
+    for name in &commands {
+        match cli::find(name) {
+            Some(command) if command.opens_map => {}
+            _ => fail(
+                Exit::Usage,
+                format!("{name} isn't a command that reads a map"),
+            ),
+        }
+    }
+    if !Path::new(path).is_file() {
+        fail(Exit::NotFound, format!("{path} not found"));
+    }
+
+    let exe = std::env::current_exe().or_exit(Exit::Io, "couldn't find the bspinfo executable");
+    let run = |name: &str| -> (String, Option<i32>) {
+        let mut command = std::process::Command::new(&exe);
+        // The map can be recompiled while a command still reads it, which a mapping can't survive
+        command.env(mmap::NO_MMAP_VAR, "1");
+        if policy == Policy::Lenient {
+            command.arg("--lenient");
+        }
+        let output = command
+            .args([name, "--", path])
+            .output()
+            .or_exit(Exit::Io, format!("couldn't run {name}"));
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        (text, output.status.code())
+    };
+
+    let mut watcher = watch::Watcher::new(Path::new(path)).or_exit(Exit::Io, path);
+    let mut previous: Vec<String> = vec![];
+    for compile in 0.. {
+        let mut w = io::stdout().lock();
+        if compile > 0 {
+            writeln!(w, "\n{path} was rewritten").unwrap();
+        }
+        let outputs: Vec<(String, Option<i32>)> = commands.iter().map(|name| run(name)).collect();
+        for (i, (name, (output, code))) in commands.iter().zip(&outputs).enumerate() {
+            let status = code.map_or("killed".to_string(), |code| format!("exit {code}"));
+            writeln!(w, "== {name} ({status}) ==").unwrap();
+            match previous.get(i) {
+                Some(old) => watch::print_diff(&mut w, &watch::diff(old, output)).unwrap(),
+                None => write!(w, "{output}").unwrap(),
+            }
+        }
+        previous = outputs.into_iter().map(|(output, _)| output).collect();
+        drop(w);
+
+        watcher.wait().or_exit(Exit::Io, path);
+    }
+}
+
+fn demo(args: &[String], policy: Policy) {
+    let (demo_path, maps_dir) = match args {
+        [demo] => (demo, None),
+        [demo, maps_dir] => (demo, Some(Path::new(maps_dir))),
+        _ => usage(),
+    };
+
+    let Some(demo) = demo::parse(&read_file(demo_path)) else {
+        fail(Exit::Parse, format!("{demo_path} is not a demo"));
+    };
+
+    let header = &demo.header;
+    println!("Map: {}", header.map_name);
+    println!("Server: {}", header.server_name);
+    println!("Recorded by: {}", header.client_name);
+    println!("Game: {}", header.game_dir);
+    println!(
+        "Protocol: demo {}, network {}",
+        header.demo_protocol, header.network_protocol
+    );
+    println!(
+        "Length: {:.1}s ({} ticks)",
+        header.playback_time, header.ticks
+    );
+    match demo.checksum {
+        Some(demo::MapChecksum::Crc(crc)) => println!("Map CRC: {crc:08x}"),
+        Some(demo::MapChecksum::Md5(md5)) => println!("Map MD5: {}", checksum::hex(&md5)),
+        None => println!("Map checksum: unavailable"),
+    }
+
+    let Some(maps_dir) = maps_dir else {
+        return;
+    };
+    let map_path = maps_dir.join(format!("{}.bsp", header.map_name));
+    if !map_path.is_file() {
+        fail(Exit::NotFound, format!("{} not found", map_path.display()));
+    }
+
+    let map_path_str = map_path.to_string_lossy();
+    let map = open_map(&map_path_str);
+    let mut reader = Cursor::new(map.data());
+    let mut bsp = load_map(&mut reader, &map_path_str, policy);
+    let matches = match demo.checksum {
+        Some(demo::MapChecksum::Crc(crc)) => checksum::map_crc(&mut bsp) == Some(crc),
+        Some(demo::MapChecksum::Md5(md5)) => checksum::map_md5(&mut bsp) == Some(md5),
+        None => {
+            println!(
+                "warning: found {}, but the demo has no checksum to verify it against",
+                map_path.display()
+            );
+            return;
+        }
+    };
+
+    if matches {
+        println!("Found {}, checksum matches", map_path.display());
+    } else {
+        println!(
+            "error: {} is a different version of the map than the demo was recorded on",
+            map_path.display()
+        );
+        std::process::exit(Exit::Findings as i32);
+    }
+}
+
+/// Options that can go anywhere before `--`
+#[derive(Default)]
+struct Options {
+    policy: Policy,
+    help: bool,
+    version: bool,
+    /// Index of the first argument after `--`, which are never options
+    literal: usize,
+}
+
+/// Normalizes the command line: takes out the options, drops the `--` that separates them from
+/// a map name starting with `-` and runs `info` when only a map is given.
+fn parse_args(mut args: Vec<String>) -> (Vec<String>, Options) {
+    let mut options = Options::default();
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    let mut i = 0;
+    args.retain(|arg| {
+        i += 1;
+        match arg.as_str() {
+            "--strict" if i <= end => options.policy = Policy::Strict,
+            "--lenient" if i <= end => options.policy = Policy::Lenient,
+            "--help" | "-h" if i <= end => options.help = true,
+            "--version" | "-V" if i <= end => options.version = true,
+            _ => return true,
         }
-        // Uncompressed
-        else {
-            let mut buf: Vec<u8> = vec![];
-            buf.resize(lump.filelen as usize, 0);
+        false
+    });
+
+    let separator = args.iter().position(|arg| arg == "--");
+    if let Some(i) = separator {
+        args.remove(i);
+    }
+    options.literal = separator.unwrap_or(args.len());
+
+    if args.len() == 2
+        && cli::find(&args[1]).is_none()
+        && (separator == Some(1) || args[1] == "-" || Path::new(&args[1]).is_file())
+    {
+        args.insert(1, "info".to_string());
+        options.literal += 1;
+    }
+
+    (args, options)
+}
 
-            self.reader.read_exact(&mut buf).ok()?;
+fn help(args: &[String]) {
+    let mut w = io::stdout().lock();
+    match args {
+        [] => cli::print_help(&mut w).unwrap(),
+        [name] => match cli::find(name) {
+            Some(command) => command.print_usage(&mut w).unwrap(),
+            None => fail(Exit::Usage, format!("unknown command {name}")),
+        },
+        _ => usage(),
+    }
+}
 
-            buf
-        })
+fn completions(args: &[String]) {
+    let [shell] = args else {
+        usage();
+    };
+    if !cli::write_completions(shell, &mut io::stdout().lock()).unwrap() {
+        fail(
+            Exit::Usage,
+            format!("no completions for {shell}, expected bash, zsh or fish"),
+        );
     }
 }
 
-fn usage() {
-    println!("usage: bspinfo files|entities <mapname.bsp>");
+/// Exits quietly when stdout is closed early, as by `bspinfo entities map.bsp | head`, instead
+/// of panicking on the next write
+fn reset_sigpipe() {
+    // SAFETY: restores the default handler before any other thread exists
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
+    reset_sigpipe();
+
+    let (mut args, options) = parse_args(std::env::args().collect());
+    let policy = options.policy;
+    if options.version {
+        println!("bspinfo {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    let Some(name) = args.get(1) else {
+        if options.help {
+            cli::print_help(&mut io::stdout().lock()).unwrap();
+            return;
+        }
         usage();
+    };
+    let Some(command) = cli::find(name) else {
+        match cli::suggest(name) {
+            Some(suggestion) => fail(
+                Exit::Usage,
+                format!("unknown command {name}, did you mean {suggestion}?"),
+            ),
+            None => fail(
+                Exit::Usage,
+                format!("unknown command {name}, see `bspinfo help`"),
+            ),
+        }
+    };
+    COMMAND.set(command).ok();
+    if options.help {
+        command.print_usage(&mut io::stdout().lock()).unwrap();
         return;
     }
+    if let Some(flag) = command.unknown_flag(&args[2.min(options.literal)..options.literal]) {
+        fail(
+            Exit::Usage,
+            format!(
+                "unknown option {flag} for {}, see `bspinfo help {}`",
+                command.name, command.name
+            ),
+        );
+    }
+
+    if !command.opens_map {
+        let args = &args[2..];
+        return match command.name {
+            "help" => help(args),
+            "completions" => completions(args),
+            "diff" => diff(args, policy),
+            "index" => index(args, policy),
+            "demo" => demo(args, policy),
+            "watch" => watch(args, policy),
+            "batch" => batch(args, policy),
+            "jobs" => jobs(args, policy),
+            name => unreachable!("{name} has no handler"),
+        };
+    }
+
+    let format = if command.tabular {
+        take_format(&mut args)
+    } else {
+        Format::Table
+    };
+    // Static props are edited with the options listing them doesn't take
+    let takes_options = command.name == "staticprops";
+    if args.len() < 3 || (command.tabular && !takes_options && args.len() > 3) {
+        usage();
+    }
+    let map = open_map(&args[2]);
+    let encrypted = map.is_encrypted();
+    let mut reader = Cursor::new(map.data());
+    let mut bsp = load_map(&mut reader, &args[2], policy);
 
-    let mut reader = File::open(&args[2]).unwrap();
-    let mut bsp = BspFile::new(&mut reader).unwrap();
+    // Scripts reading CSV or JSON get nothing but the rows
+    if !format.is_machine_readable() {
+        if encrypted {
+            println!("Encryption: Tactical Intervention");
+        }
+        println!("BSP Version: {}", bsp.version());
+        println!("Revision: {}", bsp.map_revision());
+    }
 
-    println!("BSP Version: {}", bsp.version());
-    println!("Revision: {}", bsp.map_revision());
+    match command.name {
+        "info" => info(&mut bsp),
 
-    match args[1].as_ref() {
-        "files" => {
-            if let Some(pak) = bsp.get_lump(LumpType::PAKFILE) {
-                let mut pakreader = &mut Cursor::new(pak);
-                let mut zip = ZipArchive::new(&mut pakreader).unwrap();
+        "lumps" => lumps(&mut bsp, format),
 
-                let mut w = BufWriter::new(io::stdout().lock());
-                for i in 0..zip.len() {
-                    let file = zip.by_index_raw(i).unwrap();
-                    writeln!(w, "{}: crc32 = {:08x}", file.name(), file.crc32()).unwrap();
-                }
+        "files" => files(&mut bsp, format),
+
+        "textures" => textures(&mut bsp, format),
+
+        "faceids" => faceids(&mut bsp, format),
+
+        "staticprops" if args.len() > 3 => edit_staticprops(&mut bsp, &args[3..]),
+
+        "staticprops" => staticprops(&mut bsp, format),
+
+        "extract" => {
+            let Some(dir) = args.get(3) else {
+                usage();
             };
+            let Some(mut pak) =
+                PakFile::read(&mut bsp).or_exit(Exit::Parse, "couldn't read the pakfile")
+            else {
+                println!("Map has no packed files");
+                return;
+            };
+
+            let skipped = pak.extract(Path::new(dir)).or_exit(Exit::Io, dir);
+            for name in &skipped {
+                println!("warning: skipped {name}, which would be written outside {dir}");
+            }
         }
 
-        "entities" => {
-            if let Some(entities) = bsp.get_lump(LumpType::ENTITIES) {
-                std::io::copy(&mut Cursor::new(entities), &mut std::io::stdout()).unwrap();
+        "entities" => entities(&mut bsp, &args[3..]),
+
+        "validate" => {
+            let report = validate::validate(&mut bsp);
+            report.print(&mut io::stdout().lock()).unwrap();
+
+            if report.has_errors() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "clipgaps" => {
+            let world = lumps::World::try_read(&mut bsp)
+                .or_exit(Exit::Parse, "couldn't read the world geometry");
+            clipgaps::analyze(&world)
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+
+        "brushes" => {
+            let world = lumps::World::try_read(&mut bsp)
+                .or_exit(Exit::Parse, "couldn't read the world geometry");
+            brushes::Stats::new(&world)
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+
+        "physics" => match physics::summarize(&mut bsp) {
+            Ok(report) => {
+                let keyvalues = args.get(3).is_some_and(|arg| arg == "--keyvalues");
+                report.print(&mut io::stdout().lock(), keyvalues).unwrap();
+            }
+            Err(BspError::MissingLump(_)) => println!("Map has no physics collision data"),
+            Err(e) => fail(
+                Exit::Parse,
+                format!("couldn't read the physics collision data: {e}"),
+            ),
+        },
+
+        "worldlights" => worldlights::read(&mut bsp)
+            .or_exit(Exit::Parse, "couldn't read the world lights")
+            .print(&mut io::stdout().lock())
+            .unwrap(),
+
+        "tree" => tree::analyze(&mut bsp)
+            .or_exit(Exit::Parse, "couldn't read the BSP tree")
+            .print(&mut io::stdout().lock())
+            .unwrap(),
+
+        "portallinks" => {
+            let entities = read_entities(&mut bsp);
+            let portals = lumps::read_array(&mut bsp, LumpType::AREA_PORTALS)
+                .or_exit(Exit::Parse, "couldn't read the AREA_PORTALS lump");
+
+            portals::check_links(&entities, &portals)
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+
+        "areaportals" => {
+            let entities = read_entities(&mut bsp);
+            let areas = lumps::read_array(&mut bsp, LumpType::AREAS)
+                .or_exit(Exit::Parse, "couldn't read the AREAS lump");
+            let portals = lumps::read_array(&mut bsp, LumpType::AREA_PORTALS)
+                .or_exit(Exit::Parse, "couldn't read the AREA_PORTALS lump");
+
+            let report = portals::areas(&entities, &areas, &portals);
+            report.print(&mut io::stdout().lock()).unwrap();
+            if report.findings.has_errors() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "compileinfo" => {
+            let entities = bsp
+                .get_lump(LumpType::ENTITIES)
+                .and_then(|lump| entities::parse(&lump).ok())
+                .unwrap_or_default();
+            compileinfo::analyze(&mut bsp, &entities)
+                .or_exit(Exit::Io, &args[2])
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+
+        "leaks" => {
+            let entities = read_entities(&mut bsp);
+            let world = lumps::World::try_read(&mut bsp)
+                .or_exit(Exit::Parse, "couldn't read the world geometry");
+            let leaves = lumps::try_read_leaves(&mut bsp)
+                .or_exit(Exit::Parse, "couldn't read the LEAVES lump");
+            let nodes: Vec<lumps::Node> = lumps::read_array(&mut bsp, LumpType::NODES)
+                .or_exit(Exit::Parse, "couldn't read the NODES lump");
+            let area_portals = lumps::read_array(&mut bsp, LumpType::AREA_PORTALS)
+                .or_exit(Exit::Parse, "couldn't read the AREA_PORTALS lump");
+            let clip_portal_verts = lumps::read_array(&mut bsp, LumpType::CLIP_PORTAL_VERTICES)
+                .or_exit(Exit::Parse, "couldn't read the CLIP_PORTAL_VERTICES lump");
+
+            let tree = leaks::TreeData {
+                nodes: &nodes,
+                planes: &world.planes,
+                leaves: &leaves,
+                area_portals: &area_portals,
+                clip_portal_verts: &clip_portal_verts,
+                has_vis: bsp.lump_info(LumpType::VISIBILITY).filelen > 0,
             };
+            let report = leaks::check(&world, &tree, &entities);
+            report.print(&mut io::stdout().lock()).unwrap();
+            if report.has_errors() {
+                std::process::exit(Exit::Findings as i32);
+            }
         }
 
-        _ => usage(),
+        "crc" => {
+            match checksum::map_crc(&mut bsp) {
+                Some(crc) => println!("Map CRC: {crc:08x} ({})", crc as i32),
+                None => println!("Map CRC: unavailable"),
+            }
+
+            hash(&mut bsp, &map.stored, &[]);
+        }
+
+        "hash" => hash(&mut bsp, &map.stored, &args[3..]),
+
+        "hexdump" => hexdump(&mut bsp, &args[3..]),
+
+        "deps" => deps(&mut bsp, &args[2], &args[3..]),
+
+        "reslist" => reslist(&mut bsp, &args[2], &args[3..]),
+
+        "vis" => vis(&mut bsp, &args[3..]),
+
+        "sky" => {
+            let entities = read_entities(&mut bsp);
+            let list_leaves = args.get(3).is_some_and(|arg| arg == "--leaves");
+            sky::analyze(&mut bsp, &entities)
+                .or_exit(Exit::Parse, "couldn't read the sky data")
+                .print(&mut io::stdout().lock(), list_leaves)
+                .unwrap();
+        }
+
+        "occluders" => occluders::read(&mut bsp)
+            .or_exit(Exit::Parse, "couldn't read the occluders")
+            .print(&mut io::stdout().lock())
+            .unwrap(),
+
+        "water" => {
+            let report = water::read(&mut bsp).or_exit(Exit::Parse, "couldn't read the water data");
+            report.print(&mut io::stdout().lock()).unwrap();
+            if report.has_errors() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "splits" => {
+            let report = splits::analyze(&mut bsp).or_exit(Exit::Parse, "couldn't read the faces");
+            report.print(&mut io::stdout().lock()).unwrap();
+            if !report.invalid.is_empty() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "primitives" => {
+            let report =
+                primitives::read(&mut bsp).or_exit(Exit::Parse, "couldn't read the primitives");
+            report.print(&mut io::stdout().lock()).unwrap();
+            if report.has_errors() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "cubemaps" => {
+            let map_name = std::path::Path::new(&args[2])
+                .file_stem()
+                .or_exit(Exit::Usage, format!("{} doesn't name a file", args[2]))
+                .to_string_lossy()
+                .into_owned();
+            cubemaps::analyze(&mut bsp, &map_name)
+                .or_exit(Exit::Parse, "couldn't read the cubemaps")
+                .print(&mut io::stdout().lock())
+                .unwrap();
+        }
+
+        "displacements" => displacements::summarize(&mut bsp)
+            .or_exit(Exit::Parse, "couldn't read the displacements")
+            .print(&mut io::stdout().lock())
+            .unwrap(),
+
+        "lighting" => lighting::analyze(&mut bsp)
+            .or_exit(Exit::Parse, "couldn't read the lighting")
+            .print(&mut io::stdout().lock())
+            .unwrap(),
+
+        "limits" => {
+            let game = match &args[3..] {
+                [] => "tf2",
+                [flag, game] if flag == "--game" => game,
+                _ => usage(),
+            };
+
+            let report = limits::check(&mut bsp, game).unwrap_or_else(|| {
+                fail(
+                    Exit::Usage,
+                    format!(
+                        "unknown game {game}, expected one of: {}",
+                        limits::games().join(", ")
+                    ),
+                )
+            });
+
+            report.print(&mut io::stdout().lock()).unwrap();
+            if report.over_limit() {
+                std::process::exit(Exit::Findings as i32);
+            }
+        }
+
+        "offset-entities" => offset_entities(&mut bsp, &args[3..]),
+
+        "pack" => pack(&mut bsp, &args[3..]),
+
+        "restore" => restore(&mut bsp, &args[3..]),
+
+        "strip" => strip(&mut bsp, &args[3..]),
+
+        "repack" => repack(&mut bsp, &args[3..]),
+
+        "optimize" => optimize(&mut bsp, &args[3..]),
+
+        "normalize" => normalize(&mut bsp, &args[3..]),
+
+        "pak-optimize" => pak_optimize(&mut bsp, &args[3..]),
+
+        "redact" => redact(&mut bsp, &args[3..]),
+
+        "retexture" => retexture(&mut bsp, &args[3..]),
+
+        "bz2" => bz2(&mut bsp, &map.stored, &args[2], &args[3..]),
+
+        "lightmaps" => export_lightmaps(&mut bsp, &args[3..]),
+
+        "io" => {
+            let dot = match &args[3..] {
+                [] => false,
+                [flag] if flag == "--dot" => true,
+                _ => usage(),
+            };
+
+            let entities = read_entities(&mut bsp);
+            let connections = connections::connections(&entities);
+            let mut w = BufWriter::new(io::stdout().lock());
+            if dot {
+                connections::write_dot(&connections, &mut w).unwrap();
+            } else {
+                connections::write_text(&connections, &mut w).unwrap();
+            }
+        }
+
+        "locate" => locate(&mut bsp, &args[3..]),
+        "overlays" => overlays(&mut bsp, &args[3..]),
+        "decals" => decals(&mut bsp, &args[3..]),
+        "thumbnails" => export_thumbnails(&mut bsp, &args[3..]),
+        "export-mesh" => export_mesh(&mut bsp, &args[3..]),
+        "overview" => overview(&mut bsp, &args[2], &args[3..]),
+
+        "export-csv" => export_csv(&mut bsp, &args[3..]),
+
+        "import-csv" => import_csv(&mut bsp, &args[3..]),
+
+        name => unreachable!("{name} has no handler"),
     }
 }
@@ -0,0 +1,83 @@
+//! Reads the material references out of studio model (.mdl) headers.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Cursor;
+
+use crate::assets::PathMatching;
+
+const NUM_TEXTURES_OFFSET: u64 = 204;
+const TEXTURE_STRUCT_SIZE: usize = 64;
+
+fn read_i32(data: &[u8], ofs: u64) -> Option<i32> {
+    let mut reader = Cursor::new(data);
+    reader.set_position(ofs);
+    reader.read_i32::<LittleEndian>().ok()
+}
+
+fn read_string(data: &[u8], ofs: usize) -> Option<String> {
+    let string = data.get(ofs..)?;
+    let len = string.iter().position(|&c| c == 0)?;
+
+    Some(String::from_utf8_lossy(&string[..len]).into_owned())
+}
+
+pub struct Mdl {
+    pub textures: Vec<String>,
+    /// Directories under `materials/` searched for the textures
+    pub cd_materials: Vec<String>,
+}
+
+impl Mdl {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(..4)? != b"IDST" {
+            return None;
+        }
+
+        let num_textures = usize::try_from(read_i32(data, NUM_TEXTURES_OFFSET)?).ok()?;
+        let texture_index = usize::try_from(read_i32(data, NUM_TEXTURES_OFFSET + 4)?).ok()?;
+        let num_cd = usize::try_from(read_i32(data, NUM_TEXTURES_OFFSET + 8)?).ok()?;
+        let cd_index = read_i32(data, NUM_TEXTURES_OFFSET + 12)?;
+
+        let textures = (0..num_textures)
+            .map(|i| {
+                let base = texture_index + i * TEXTURE_STRUCT_SIZE;
+                let name_ofs = usize::try_from(read_i32(data, base as u64)?).ok()?;
+                read_string(data, base + name_ofs)
+            })
+            .collect::<Option<_>>()?;
+
+        let cd_materials = (0..num_cd)
+            .map(|i| {
+                let ofs = read_i32(data, cd_index as u64 + i as u64 * 4)?;
+                read_string(data, usize::try_from(ofs).ok()?)
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self {
+            textures,
+            cd_materials,
+        })
+    }
+
+    /// Candidate `materials/....vmt` paths for each texture, in search order. The engine uses
+    /// the first one that exists.
+    pub fn material_candidates(&self, matching: PathMatching) -> Vec<Vec<String>> {
+        self.textures
+            .iter()
+            .map(|texture| {
+                self.cd_materials
+                    .iter()
+                    .map(|dir| {
+                        let dir = dir.replace('\\', "/");
+                        let dir = dir.trim_matches('/');
+                        if dir.is_empty() {
+                            matching.material(texture)
+                        } else {
+                            matching.material(&format!("{dir}/{texture}"))
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,461 @@
+//! Reconstruction of the world's render geometry, including tessellated displacements, and
+//! export to OBJ and glTF.
+
+use std::io::{self, Write};
+
+use crate::json::Json;
+use crate::lumps::{
+    DispInfo, DispVert, Face, TexData, Vector, World, SURF_HINT, SURF_NODRAW, SURF_SKIP,
+    SURF_TRIGGER,
+};
+
+/// Tool surfaces that are never drawn
+const HIDDEN_SURFACES: i32 = SURF_NODRAW | SURF_SKIP | SURF_TRIGGER | SURF_HINT;
+/// Largest displacement power the engine supports
+const MAX_DISP_POWER: i32 = 4;
+
+pub struct Vertex {
+    pub position: Vector,
+    pub normal: Vector,
+    pub uv: [f32; 2],
+}
+
+/// Triangles sharing a material. Triangles wind counterclockwise seen from the front.
+pub struct Group {
+    pub material: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+#[derive(Default)]
+pub struct Mesh {
+    pub groups: Vec<Group>,
+}
+
+fn sub(a: Vector, b: Vector) -> Vector {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: Vector, b: Vector) -> Vector {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: Vector, s: f32) -> Vector {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn lerp(a: Vector, b: Vector, t: f32) -> Vector {
+    add(a, scale(sub(b, a), t))
+}
+
+fn cross(a: Vector, b: Vector) -> Vector {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: Vector) -> Vector {
+    let len = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    if len == 0.0 {
+        a
+    } else {
+        scale(a, 1.0 / len)
+    }
+}
+
+struct Mapping {
+    s: [f32; 4],
+    t: [f32; 4],
+    size: [f32; 2],
+}
+
+impl Mapping {
+    fn uv(&self, p: Vector) -> [f32; 2] {
+        let project = |v: [f32; 4]| p[0] * v[0] + p[1] * v[1] + p[2] * v[2] + v[3];
+        [
+            project(self.s) / self.size[0],
+            project(self.t) / self.size[1],
+        ]
+    }
+}
+
+/// Displacement data needed to tessellate displacement faces
+pub struct Displacements {
+    pub info: Vec<DispInfo>,
+    pub verts: Vec<DispVert>,
+}
+
+impl Mesh {
+    fn group(&mut self, material: &str) -> &mut Group {
+        let index = match self.groups.iter().position(|g| g.material == material) {
+            Some(index) => index,
+            None => {
+                self.groups.push(Group {
+                    material: material.to_string(),
+                    vertices: vec![],
+                    indices: vec![],
+                });
+                self.groups.len() - 1
+            }
+        };
+
+        &mut self.groups[index]
+    }
+
+    /// Builds the mesh of the world model, skipping tool surfaces.
+    pub fn build(world: &World, texdata: &[TexData], displacements: &Displacements) -> Self {
+        let mut mesh = Mesh::default();
+
+        for face in world.model_faces(0) {
+            let Some(texinfo) = world.face_texinfo(face) else {
+                continue;
+            };
+            if texinfo.flags & HIDDEN_SURFACES != 0 {
+                continue;
+            }
+
+            let material = world.texinfo_name(face.texinfo).unwrap_or_default();
+            let size = usize::try_from(texinfo.texdata)
+                .ok()
+                .and_then(|i| texdata.get(i))
+                .map_or([1.0; 2], |t| {
+                    [t.width.max(1) as f32, t.height.max(1) as f32]
+                });
+            let mapping = Mapping {
+                s: texinfo.texture_vecs[0],
+                t: texinfo.texture_vecs[1],
+                size,
+            };
+
+            let polygon = world.face_vertices(face);
+            let group = mesh.group(material);
+            match usize::try_from(face.dispinfo) {
+                Ok(disp) => {
+                    if let Some(info) = displacements.info.get(disp) {
+                        add_displacement(group, &polygon, info, &displacements.verts, &mapping);
+                    }
+                }
+                Err(_) => add_polygon(group, world, face, &polygon, &mapping),
+            }
+        }
+
+        mesh.groups.retain(|g| !g.indices.is_empty());
+        mesh
+    }
+}
+
+fn add_polygon(
+    group: &mut Group,
+    world: &World,
+    face: &Face,
+    polygon: &[Vector],
+    mapping: &Mapping,
+) {
+    if polygon.len() < 3 {
+        return;
+    }
+
+    let normal = world
+        .planes
+        .get(face.planenum as usize)
+        .map_or([0.0, 0.0, 1.0], |plane| {
+            if face.side != 0 {
+                scale(plane.normal, -1.0)
+            } else {
+                plane.normal
+            }
+        });
+
+    let base = group.vertices.len() as u32;
+    group
+        .vertices
+        .extend(polygon.iter().map(|&position| Vertex {
+            position,
+            normal,
+            uv: mapping.uv(position),
+        }));
+
+    // Faces wind clockwise, so the fan is reversed
+    for i in 1..polygon.len() as u32 - 1 {
+        group.indices.extend([base, base + i + 1, base + i]);
+    }
+}
+
+fn add_displacement(
+    group: &mut Group,
+    polygon: &[Vector],
+    info: &DispInfo,
+    verts: &[DispVert],
+    mapping: &Mapping,
+) {
+    if polygon.len() != 4 || !(0..=MAX_DISP_POWER).contains(&info.power) {
+        return;
+    }
+
+    // The grid starts at the corner closest to the start position
+    let distance = |p: Vector| {
+        let d = sub(p, info.start_position);
+        d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+    };
+    let Some(first) = (0..4).min_by(|&a, &b| distance(polygon[a]).total_cmp(&distance(polygon[b])))
+    else {
+        return;
+    };
+    let corners: Vec<Vector> = (0..4).map(|i| polygon[(first + i) % 4]).collect();
+
+    let n = info.side_length();
+    let Some(verts) = usize::try_from(info.disp_vert_start)
+        .ok()
+        .and_then(|start| verts.get(start..start + n * n))
+    else {
+        return;
+    };
+
+    let base = group.vertices.len() as u32;
+    let step = 1.0 / (n - 1) as f32;
+    for row in 0..n {
+        let start = lerp(corners[0], corners[1], row as f32 * step);
+        let end = lerp(corners[3], corners[2], row as f32 * step);
+        for column in 0..n {
+            let flat = lerp(start, end, column as f32 * step);
+            let vert = &verts[row * n + column];
+
+            group.vertices.push(Vertex {
+                position: add(flat, scale(vert.vec, vert.dist)),
+                normal: [0.0; 3],
+                // Textures are mapped onto the undisplaced surface
+                uv: mapping.uv(flat),
+            });
+        }
+    }
+
+    let index = |row: usize, column: usize| base + (row * n + column) as u32;
+    let first_index = group.indices.len();
+    for row in 0..n - 1 {
+        for column in 0..n - 1 {
+            let [a, b, c, d] = [
+                index(row, column),
+                index(row + 1, column),
+                index(row + 1, column + 1),
+                index(row, column + 1),
+            ];
+
+            // The grid winds clockwise like the base face, so triangles are reversed. The
+            // diagonal alternates between cells like the engine's tessellation.
+            if (row + column) % 2 == 0 {
+                group.indices.extend([a, c, b, a, d, c]);
+            } else {
+                group.indices.extend([a, d, b, b, d, c]);
+            }
+        }
+    }
+
+    // Smooth normals from the triangles around each vertex
+    for triangle in group.indices[first_index..].chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| group.vertices[triangle[i] as usize].position);
+        let normal = cross(sub(b, a), sub(c, a));
+        for &i in triangle {
+            let vertex = &mut group.vertices[i as usize];
+            vertex.normal = add(vertex.normal, normal);
+        }
+    }
+    for vertex in &mut group.vertices[base as usize..] {
+        vertex.normal = normalize(vertex.normal);
+    }
+}
+
+/// Writes the mesh as OBJ in the map's Z-up coordinates, referencing materials from `mtl_name`.
+pub fn write_obj<W: Write>(mesh: &Mesh, w: &mut W, mtl_name: &str) -> io::Result<()> {
+    writeln!(w, "mtllib {mtl_name}")?;
+
+    let mut base = 1;
+    for (i, group) in mesh.groups.iter().enumerate() {
+        writeln!(w, "o group_{i}")?;
+        writeln!(w, "usemtl {}", group.material)?;
+
+        for v in &group.vertices {
+            writeln!(w, "v {} {} {}", v.position[0], v.position[1], v.position[2])?;
+        }
+        for v in &group.vertices {
+            // OBJ texture coordinates start at the bottom
+            writeln!(w, "vt {} {}", v.uv[0], 1.0 - v.uv[1])?;
+        }
+        for v in &group.vertices {
+            writeln!(w, "vn {} {} {}", v.normal[0], v.normal[1], v.normal[2])?;
+        }
+
+        for triangle in group.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize + base);
+            writeln!(w, "f {a}/{a}/{a} {b}/{b}/{b} {c}/{c}/{c}")?;
+        }
+
+        base += group.vertices.len();
+    }
+
+    Ok(())
+}
+
+pub fn write_mtl<W: Write>(mesh: &Mesh, w: &mut W) -> io::Result<()> {
+    for group in &mesh.groups {
+        writeln!(w, "newmtl {}", group.material)?;
+        writeln!(w, "Kd 1 1 1")?;
+    }
+
+    Ok(())
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Source is Z-up, glTF is Y-up
+fn to_gltf(v: Vector) -> Vector {
+    [v[0], v[2], -v[1]]
+}
+
+/// Writes the mesh as a self-contained glTF 2.0 file, with one primitive per material and the
+/// buffer embedded as a data URI.
+pub fn write_gltf<W: Write>(mesh: &Mesh, w: &mut W) -> io::Result<()> {
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+    let mut buffer: Vec<u8> = vec![];
+    let mut views = vec![];
+    let mut accessors = vec![];
+    let mut primitives = vec![];
+    let mut materials = vec![];
+
+    let mut add_view = |buffer: &mut Vec<u8>, data: Vec<u8>, target: u32| {
+        views.push(Json::object([
+            ("buffer", 0.into()),
+            ("byteOffset", buffer.len().into()),
+            ("byteLength", data.len().into()),
+            ("target", target.into()),
+        ]));
+        buffer.extend(data);
+        views.len() - 1
+    };
+
+    for (i, group) in mesh.groups.iter().enumerate() {
+        let positions: Vec<Vector> = group.vertices.iter().map(|v| to_gltf(v.position)).collect();
+        let normals: Vec<Vector> = group.vertices.iter().map(|v| to_gltf(v.normal)).collect();
+
+        let mut mins = [f32::MAX; 3];
+        let mut maxs = [f32::MIN; 3];
+        for p in &positions {
+            for axis in 0..3 {
+                mins[axis] = mins[axis].min(p[axis]);
+                maxs[axis] = maxs[axis].max(p[axis]);
+            }
+        }
+
+        let floats =
+            |values: Vec<f32>| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+        let attributes = [
+            ("POSITION", floats(positions.concat()), "VEC3"),
+            ("NORMAL", floats(normals.concat()), "VEC3"),
+            (
+                "TEXCOORD_0",
+                floats(group.vertices.iter().flat_map(|v| v.uv).collect()),
+                "VEC2",
+            ),
+        ];
+
+        let mut primitive_attributes = vec![];
+        for (name, data, ty) in attributes {
+            let view = add_view(&mut buffer, data, ARRAY_BUFFER);
+            let mut accessor = vec![
+                ("bufferView", view.into()),
+                ("componentType", FLOAT.into()),
+                ("count", group.vertices.len().into()),
+                ("type", ty.into()),
+            ];
+            if name == "POSITION" {
+                accessor.push(("min", mins.to_vec().into()));
+                accessor.push(("max", maxs.to_vec().into()));
+            }
+
+            accessors.push(Json::object(accessor));
+            primitive_attributes.push((name, (accessors.len() - 1).into()));
+        }
+
+        let indices = group.indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let view = add_view(&mut buffer, indices, ELEMENT_ARRAY_BUFFER);
+        accessors.push(Json::object([
+            ("bufferView", view.into()),
+            ("componentType", UNSIGNED_INT.into()),
+            ("count", group.indices.len().into()),
+            ("type", "SCALAR".into()),
+        ]));
+
+        primitives.push(Json::object([
+            ("attributes", Json::object(primitive_attributes)),
+            ("indices", (accessors.len() - 1).into()),
+            ("material", i.into()),
+        ]));
+        materials.push(Json::object([("name", group.material.as_str().into())]));
+    }
+
+    // Meshes must have at least one primitive, so an empty world gets an empty node
+    let (node, meshes) = if primitives.is_empty() {
+        (Json::object::<&str>([]), vec![])
+    } else {
+        (
+            Json::object([("mesh", 0.into())]),
+            vec![Json::object([("primitives", Json::Array(primitives))])],
+        )
+    };
+
+    let gltf = Json::object([
+        (
+            "asset",
+            Json::object([("version", "2.0".into()), ("generator", "bspinfo".into())]),
+        ),
+        ("scene", 0.into()),
+        (
+            "scenes",
+            Json::Array(vec![Json::object([("nodes", vec![0].into())])]),
+        ),
+        ("nodes", Json::Array(vec![node])),
+        ("meshes", Json::Array(meshes)),
+        ("materials", Json::Array(materials)),
+        ("accessors", Json::Array(accessors)),
+        ("bufferViews", Json::Array(views)),
+        (
+            "buffers",
+            Json::Array(vec![Json::object([
+                ("byteLength", buffer.len().into()),
+                (
+                    "uri",
+                    format!("data:application/octet-stream;base64,{}", base64(&buffer)).into(),
+                ),
+            ])]),
+        ),
+    ]);
+
+    writeln!(w, "{gltf}")
+}
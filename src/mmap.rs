@@ -0,0 +1,120 @@
+//! Read-only memory mapped files, so maps processed in bulk can be parsed straight from the page
+//! cache instead of being copied into buffers lump by lump. Small files, and every file on
+//! platforms without `mmap`, are read into memory instead.
+//!
+//! A file that another process truncates while it's mapped can't be read past its new end:
+//! touching those pages raises SIGBUS, which kills the process instead of failing a read. Maps
+//! are rewritten in place by vbsp and the other compile tools, so one recompiled while a command
+//! reads it, such as by `watch` starting a compile before the commands it ran have finished, can
+//! take the command down with it. Only files of at least [`MIN_MAPPED_SIZE`] are mapped, where
+//! not reading the whole file makes up for that, and setting [`NO_MMAP_VAR`] turns mapping off.
+
+use std::fs::File;
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Size from which files are mapped rather than read. A mapped file that's truncated while it's
+/// read raises SIGBUS instead of an error, as described in the module documentation.
+pub const MIN_MAPPED_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Environment variable that makes [`Mmap::open`] read every file, for maps that may be
+/// rewritten while they're read
+pub const NO_MMAP_VAR: &str = "BSPINFO_NO_MMAP";
+
+pub struct Mmap {
+    data: Data,
+}
+
+enum Data {
+    #[cfg(unix)]
+    Mapped {
+        ptr: *mut libc::c_void,
+        len: usize,
+    },
+    Read(Vec<u8>),
+}
+
+// The mapping is read-only and private, and the pointer is only used to read it
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        #[cfg(unix)]
+        if file.metadata()?.len() >= MIN_MAPPED_SIZE && std::env::var_os(NO_MMAP_VAR).is_none() {
+            return Self::map(&file);
+        }
+
+        let mut data = vec![];
+        io::Read::read_to_end(&mut &file, &mut data)?;
+        Ok(Self::from(data))
+    }
+
+    #[cfg(unix)]
+    fn map(file: &File) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "file too large to map"))?;
+
+        // Safety: the file is mapped read-only and stays mapped until the Mmap is dropped. The
+        // slice it's read through assumes its contents don't change, which only holds while no
+        // other process writes to the file: the mapping is private, but pages that haven't been
+        // read yet still show such writes, and truncation makes them raise SIGBUS (see the
+        // module documentation).
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            data: Data::Mapped { ptr, len },
+        })
+    }
+}
+
+/// Contents already in memory, such as decompressed or decrypted maps, to be used wherever a
+/// mapped file is
+impl From<Vec<u8>> for Mmap {
+    fn from(data: Vec<u8>) -> Self {
+        Self {
+            data: Data::Read(data),
+        }
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.data {
+            // Safety: the mapping is `len` bytes long and lives as long as self
+            #[cfg(unix)]
+            Data::Mapped { ptr, len } => unsafe { std::slice::from_raw_parts(ptr.cast(), *len) },
+            Data::Read(data) => data,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if let Data::Mapped { ptr, len } = self.data {
+            // Safety: the mapping came from mmap with this length and isn't used after this
+            unsafe {
+                libc::munmap(ptr, len);
+            }
+        }
+    }
+}
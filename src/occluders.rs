@@ -0,0 +1,171 @@
+//! Summary of the func_occluder brushes vbsp stores in the OCCLUSION lump, which hide props and
+//! other models behind them.
+
+use binrw::BinRead;
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Cursor, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::error::BspError;
+use crate::lumps::{self, Occluder, OccluderPoly, Vector, OCCLUDER_INACTIVE};
+
+/// Maps with at least this many visleaves are big enough that occluders usually pay off
+const HEAVY_VISLEAVES: usize = 1000;
+
+pub struct Entry {
+    pub occluder: Occluder,
+    /// Area of each of the occluder's polygons
+    pub polygon_areas: Vec<f32>,
+}
+
+impl Entry {
+    pub fn is_active(&self) -> bool {
+        self.occluder.flags & OCCLUDER_INACTIVE == 0
+    }
+
+    pub fn area(&self) -> f32 {
+        self.polygon_areas.iter().fold(0.0, |sum, area| sum + area)
+    }
+}
+
+pub struct Report {
+    pub occluders: Vec<Entry>,
+    pub vertex_indices: usize,
+    /// Leaves in a visibility cluster
+    pub visleaves: usize,
+    /// Occluders whose polygons or vertices are outside the lump
+    pub invalid: Vec<usize>,
+}
+
+fn read_vec<T, R: Read + Seek>(
+    reader: &mut R,
+    read: impl Fn(&mut R) -> binrw::BinResult<T>,
+) -> Result<Vec<T>, BspError> {
+    let short = || BspError::malformed("OCCLUSION lump", "it's cut short");
+    let count = reader.read_i32::<LittleEndian>().map_err(|_| short())?;
+    (0..count)
+        .map(|_| read(reader).map_err(|_| short()))
+        .collect()
+}
+
+fn polygon_area(points: &[Vector]) -> f32 {
+    let Some(&[ox, oy, oz]) = points.first() else {
+        return 0.0;
+    };
+
+    let mut sum = [0.0; 3];
+    for pair in points[1..].windows(2) {
+        let [ax, ay, az] = [pair[0][0] - ox, pair[0][1] - oy, pair[0][2] - oz];
+        let [bx, by, bz] = [pair[1][0] - ox, pair[1][1] - oy, pair[1][2] - oz];
+        sum[0] += ay * bz - az * by;
+        sum[1] += az * bx - ax * bz;
+        sum[2] += ax * by - ay * bx;
+    }
+
+    sum.iter().map(|c| c * c).sum::<f32>().sqrt() / 2.0
+}
+
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Report, BspError> {
+    let has_area = lumps::check_version(bsp, LumpType::OCCLUSION, 2)? == 2;
+
+    let data = bsp.get_lump(LumpType::OCCLUSION).unwrap_or_default();
+    let (occluders, polys, indices) = if data.is_empty() {
+        (vec![], vec![], vec![])
+    } else {
+        let mut reader = Cursor::new(&data[..]);
+        let occluders = read_vec(&mut reader, |r| Occluder::read_le_args(r, (has_area,)))?;
+        let polys = read_vec(&mut reader, OccluderPoly::read_le)?;
+        let indices = read_vec(&mut reader, i32::read_le)?;
+        (occluders, polys, indices)
+    };
+
+    let vertices: Vec<Vector> = lumps::try_read_array(bsp, LumpType::VERTICES)?;
+    let visleaves = lumps::try_read_leaves(bsp)?
+        .iter()
+        .filter(|leaf| leaf.cluster >= 0)
+        .count();
+
+    let mut invalid = vec![];
+    let occluders = occluders
+        .into_iter()
+        .enumerate()
+        .map(|(i, occluder)| {
+            let areas = (occluder.first_poly
+                ..occluder.first_poly.saturating_add(occluder.poly_count))
+                .map(|p| {
+                    let poly = polys.get(usize::try_from(p).ok()?)?;
+                    let first = usize::try_from(poly.first_vertex_index).ok()?;
+                    let points = indices
+                        .get(first..first + usize::try_from(poly.vertex_count).ok()?)?
+                        .iter()
+                        .map(|&v| vertices.get(usize::try_from(v).ok()?).copied())
+                        .collect::<Option<Vec<_>>>()?;
+                    Some(polygon_area(&points))
+                })
+                .collect::<Option<Vec<_>>>();
+
+            let polygon_areas = areas.unwrap_or_else(|| {
+                invalid.push(i);
+                vec![]
+            });
+            Entry {
+                occluder,
+                polygon_areas,
+            }
+        })
+        .collect();
+
+    Ok(Report {
+        occluders,
+        vertex_indices: indices.len(),
+        visleaves,
+        invalid,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let inactive = self.occluders.iter().filter(|o| !o.is_active()).count();
+        let polygons: usize = self.occluders.iter().map(|o| o.polygon_areas.len()).sum();
+        writeln!(
+            w,
+            "Occluders: {} ({inactive} inactive)",
+            self.occluders.len()
+        )?;
+        writeln!(w, "  Polygons: {polygons}")?;
+        writeln!(w, "  Vertex indices: {}", self.vertex_indices)?;
+        writeln!(w, "  Visleaves: {}", self.visleaves)?;
+
+        if !self.occluders.is_empty() {
+            writeln!(w, "  {:>5} {:>6} {:>10}  bounds", "index", "polys", "area")?;
+        }
+        for (i, entry) in self.occluders.iter().enumerate() {
+            let o = &entry.occluder;
+            let [x0, y0, z0] = o.mins;
+            let [x1, y1, z1] = o.maxs;
+            writeln!(
+                w,
+                "  {i:>5} {:>6} {:>10.0}  ({x0}, {y0}, {z0}) - ({x1}, {y1}, {z1}){}",
+                o.poly_count,
+                entry.area(),
+                if entry.is_active() { "" } else { " inactive" }
+            )?;
+        }
+
+        for i in &self.invalid {
+            writeln!(
+                w,
+                "error: occluder {i} refers to polygons or vertices outside the lump"
+            )?;
+        }
+        if self.occluders.is_empty() && self.visleaves >= HEAVY_VISLEAVES {
+            writeln!(
+                w,
+                "warning: the map has {} visleaves but no occluders, func_occluder brushes could hide props in large open areas",
+                self.visleaves
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+//! Rows of output that commands list, printed as an aligned table for people or as CSV, JSON
+//! or tab separated lines for scripts.
+
+use std::io::{self, Write};
+
+use crate::csv;
+use crate::json::Json;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Aligned columns under a header
+    #[default]
+    Table,
+    /// A header record, then a record per row
+    Csv,
+    /// An array with an object per row
+    Json,
+    /// Tab separated fields without a header, for `cut` and `awk`
+    Plain,
+}
+
+impl Format {
+    pub const NAMES: &'static [&'static str] = &["table", "csv", "json", "plain"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "table" => Some(Self::Table),
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "plain" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+
+    /// Whether the output is meant to be read by a program, so nothing else may be printed
+    pub fn is_machine_readable(self) -> bool {
+        self != Self::Table
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Empty,
+    Text(String),
+    /// A number as it's printed, which is right aligned in tables and unquoted in JSON
+    Number(String),
+}
+
+impl Cell {
+    fn text(&self) -> &str {
+        match self {
+            Cell::Empty => "",
+            Cell::Text(text) | Cell::Number(text) => text,
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        match self {
+            Cell::Empty => Json::Null,
+            Cell::Text(text) => text.as_str().into(),
+            Cell::Number(text) => text.parse::<f64>().map_or(Json::Null, Json::Number),
+        }
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::Text(text.to_string())
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::Text(text)
+    }
+}
+
+impl<T: Into<Cell>> From<Option<T>> for Cell {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Cell::Empty, Into::into)
+    }
+}
+
+macro_rules! impl_from_integer {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for Cell {
+            fn from(value: $ty) -> Self {
+                Cell::Number(value.to_string())
+            }
+        })*
+    };
+}
+
+impl_from_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+macro_rules! impl_from_float {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for Cell {
+            fn from(value: $ty) -> Self {
+                if value.is_finite() {
+                    Cell::Number(value.to_string())
+                } else {
+                    Cell::Empty
+                }
+            }
+        })*
+    };
+}
+
+impl_from_float!(f32, f64);
+
+pub struct Table {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+}
+
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl Table {
+    pub fn new(columns: &[&'static str]) -> Self {
+        Self {
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    /// Adds a row, with a cell for each column
+    pub fn push(&mut self, row: Vec<Cell>) {
+        debug_assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    /// Prints the rows in `format`. `color` only affects tables, where it makes the header bold.
+    pub fn print<W: Write>(&self, w: &mut W, format: Format, color: bool) -> io::Result<()> {
+        match format {
+            Format::Table => self.write_table(w, color),
+            Format::Csv => {
+                csv::write_record(w, &self.columns)?;
+                for row in &self.rows {
+                    let record: Vec<&str> = row.iter().map(Cell::text).collect();
+                    csv::write_record(w, &record)?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                let rows = self.rows.iter().map(|row| {
+                    Json::object(
+                        self.columns
+                            .iter()
+                            .copied()
+                            .zip(row.iter().map(Cell::to_json)),
+                    )
+                });
+                writeln!(w, "{}", Json::Array(rows.collect()))
+            }
+            Format::Plain => {
+                for row in &self.rows {
+                    let fields: Vec<String> = row
+                        .iter()
+                        .map(|cell| cell.text().replace(['\t', '\n', '\r'], " "))
+                        .collect();
+                    writeln!(w, "{}", fields.join("\t"))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn write_table<W: Write>(&self, w: &mut W, color: bool) -> io::Result<()> {
+        let mut widths: Vec<usize> = self.columns.iter().map(|c| c.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.text().chars().count());
+            }
+        }
+
+        // Numbers are right aligned, so a column is if every cell in it is a number
+        let numeric: Vec<bool> = (0..self.columns.len())
+            .map(|i| self.rows.iter().all(|row| !matches!(row[i], Cell::Text(_))))
+            .collect();
+
+        let header: Vec<Cell> = self.columns.iter().map(|&c| Cell::from(c)).collect();
+        for (i, row) in std::iter::once(&header).chain(&self.rows).enumerate() {
+            let mut line = String::new();
+            for (j, cell) in row.iter().enumerate() {
+                let text = cell.text();
+                let padding = widths[j] - text.chars().count();
+                if j != 0 {
+                    line.push_str("  ");
+                }
+                if numeric[j] {
+                    line.extend(std::iter::repeat_n(' ', padding));
+                    line.push_str(text);
+                } else {
+                    line.push_str(text);
+                    if j + 1 != row.len() {
+                        line.extend(std::iter::repeat_n(' ', padding));
+                    }
+                }
+            }
+
+            if i == 0 && color {
+                writeln!(w, "{BOLD}{}{RESET}", line.trim_end())?;
+            } else {
+                writeln!(w, "{}", line.trim_end())?;
+            }
+        }
+
+        Ok(())
+    }
+}
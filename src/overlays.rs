@@ -0,0 +1,111 @@
+//! Listing of the overlays and water overlays placed on the map's faces.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::assets::AssetStore;
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, Overlay, TexInfo, Vector};
+
+pub struct Entry {
+    pub id: i32,
+    pub water: bool,
+    /// None if the texinfo index is out of range
+    pub material: Option<String>,
+    pub faces: usize,
+    pub render_order: u16,
+    pub origin: Vector,
+    pub u: [f32; 2],
+    pub v: [f32; 2],
+    /// Whether the material exists, if checked
+    pub found: Option<bool>,
+}
+
+fn entries<const N: usize>(
+    overlays: &[Overlay<N>],
+    water: bool,
+    texinfo: &[TexInfo],
+    names: &[String],
+) -> Vec<Entry> {
+    overlays
+        .iter()
+        .map(|overlay| {
+            let material = usize::try_from(overlay.texinfo)
+                .ok()
+                .and_then(|i| texinfo.get(i))
+                .and_then(|info| names.get(usize::try_from(info.texdata).ok()?))
+                .cloned();
+
+            Entry {
+                id: overlay.id,
+                water,
+                material,
+                faces: overlay.face_count(),
+                render_order: overlay.render_order(),
+                origin: overlay.origin,
+                u: overlay.u,
+                v: overlay.v,
+                found: None,
+            }
+        })
+        .collect()
+}
+
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Vec<Entry>> {
+    let overlays: Vec<Overlay<64>> = lumps::read_array(bsp, LumpType::OVERLAYS)?;
+    let water: Vec<Overlay<256>> = lumps::read_array(bsp, LumpType::WATER_OVERLAYS)?;
+    let texinfo: Vec<TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)?;
+    let names = lumps::texture_names(bsp)?;
+
+    let mut all = entries(&overlays, false, &texinfo, &names);
+    all.extend(entries(&water, true, &texinfo, &names));
+    Some(all)
+}
+
+/// Marks whether each overlay's material can be found in the pakfile or game directories.
+pub fn check_materials(entries: &mut [Entry], store: &AssetStore) {
+    for entry in entries {
+        entry.found = entry
+            .material
+            .as_ref()
+            .map(|name| store.exists(&store.matching.material(name)));
+    }
+}
+
+pub fn print<W: Write>(entries: &[Entry], w: &mut W) -> io::Result<()> {
+    let water = entries.iter().filter(|e| e.water).count();
+    writeln!(w, "Overlays: {} ({water} water)", entries.len() - water)?;
+
+    for entry in entries {
+        let [x, y, z] = entry.origin;
+        writeln!(
+            w,
+            "{:5} {:<5} {:<40} faces = {:3} order = {} origin = ({x}, {y}, {z}) u = {}..{} v = {}..{}",
+            entry.id,
+            if entry.water { "water" } else { "" },
+            entry.material.as_deref().unwrap_or("<invalid texinfo>"),
+            entry.faces,
+            entry.render_order,
+            entry.u[0],
+            entry.u[1],
+            entry.v[0],
+            entry.v[1]
+        )?;
+    }
+
+    for entry in entries {
+        match (&entry.material, entry.found) {
+            (None, _) => writeln!(w, "error: overlay {} has an invalid texinfo", entry.id)?,
+            (Some(material), Some(false)) => writeln!(
+                w,
+                "warning: overlay {} uses missing material {material}",
+                entry.id
+            )?,
+            _ => {}
+        }
+        if entry.faces == 0 {
+            writeln!(w, "warning: overlay {} isn't on any faces", entry.id)?;
+        }
+    }
+
+    Ok(())
+}
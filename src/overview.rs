@@ -0,0 +1,185 @@
+//! Top-down overview images of the world, like the radar and overview map the game draws, and
+//! the overview script placing them in the world.
+
+use std::io::{self, Write};
+
+use crate::lumps::Vector;
+use crate::mesh::Mesh;
+
+/// Units of empty border kept around the map when the scale is picked automatically
+const MARGIN: f32 = 64.0;
+/// Size of the images overview scripts are written for
+pub const GAME_SIZE: u32 = 1024;
+/// Brightness of the lowest and highest floors
+const SHADE_RANGE: (f32, f32) = (0.25, 1.0);
+
+/// Where the image's corner is in the world, and how much of it each pixel covers
+struct Projection {
+    pos_x: f32,
+    pos_y: f32,
+    scale: f32,
+}
+
+impl Projection {
+    fn pixel(&self, p: Vector) -> (f32, f32) {
+        // Rows go down the image while y goes up the map
+        (
+            (p[0] - self.pos_x) / self.scale,
+            (self.pos_y - p[1]) / self.scale,
+        )
+    }
+}
+
+pub struct Overview {
+    pub size: u32,
+    /// World position of the image's top left corner
+    pub pos_x: f32,
+    pub pos_y: f32,
+    /// World units per pixel
+    pub scale: f32,
+    /// `size * size` RGB triples in rows from the top
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Triangles drawn on an overview: those facing up, under `max_z` where it's given, and not
+/// of tool materials, whose faces, like the skybox, the game doesn't draw as world
+fn triangles(mesh: &Mesh, max_z: Option<f32>) -> Vec<[Vector; 3]> {
+    let mut triangles = vec![];
+    for group in &mesh.groups {
+        if group.material.to_ascii_lowercase().starts_with("tools/") {
+            continue;
+        }
+
+        for indices in group.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| group.vertices[indices[i] as usize].position);
+            let up = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+            let top = a[2].max(b[2]).max(c[2]);
+            if up > 0.0 && max_z.is_none_or(|max_z| top <= max_z) {
+                triangles.push([a, b, c]);
+            }
+        }
+    }
+    triangles
+}
+
+/// How much a triangle faces straight up, from 0 for a wall to 1 for a flat floor
+fn flatness([a, b, c]: [Vector; 3]) -> f32 {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let normal = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let len = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+    if len == 0.0 {
+        0.0
+    } else {
+        normal[2] / len
+    }
+}
+
+/// Draws the mesh from above into a square image `size` pixels across, shading higher floors
+/// brighter and slopes darker. `scale` is in world units per pixel, picked so the whole map
+/// fits when it isn't given. Returns `None` if nothing faces up.
+pub fn render(mesh: &Mesh, size: u32, scale: Option<f32>, max_z: Option<f32>) -> Option<Overview> {
+    let triangles = triangles(mesh, max_z);
+    let points = || triangles.iter().flatten();
+    let min = |axis: usize| points().map(|p| p[axis]).fold(f32::INFINITY, f32::min);
+    let max = |axis: usize| points().map(|p| p[axis]).fold(f32::NEG_INFINITY, f32::max);
+    if triangles.is_empty() {
+        return None;
+    }
+
+    let (x0, x1, y0, y1) = (min(0), max(0), min(1), max(1));
+    // Rounded like the script stores them, so the image lines up with what the game reads
+    let scale = scale.unwrap_or_else(|| {
+        let scale = ((x1 - x0).max(y1 - y0) + MARGIN * 2.0) / size as f32;
+        (scale * 100.0).ceil() / 100.0
+    });
+    let half = size as f32 * scale / 2.0;
+    let projection = Projection {
+        pos_x: ((x0 + x1) / 2.0 - half).round(),
+        pos_y: ((y0 + y1) / 2.0 + half).round(),
+        scale,
+    };
+
+    // Keeps the highest surface under each pixel, with how flat it is
+    let mut heights = vec![f32::NEG_INFINITY; (size * size) as usize];
+    let mut flat = vec![0.0f32; (size * size) as usize];
+    for &triangle in &triangles {
+        let [a, b, c] = triangle.map(|p| projection.pixel(p));
+        let area = (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0);
+        if area == 0.0 {
+            continue;
+        }
+
+        let flatness = flatness(triangle);
+        let clamp = |v: f32| (v.max(0.0) as u32).min(size - 1);
+        let (left, right) = (clamp(a.0.min(b.0).min(c.0)), clamp(a.0.max(b.0).max(c.0)));
+        let (top, bottom) = (clamp(a.1.min(b.1).min(c.1)), clamp(a.1.max(b.1).max(c.1)));
+        for y in top..=bottom {
+            for x in left..=right {
+                // Sample at the pixel's center, with barycentric weights of the corners
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                let edge = |s: (f32, f32), e: (f32, f32)| {
+                    ((e.0 - s.0) * (p.1 - s.1) - (e.1 - s.1) * (p.0 - s.0)) / area
+                };
+                let weights = [edge(b, c), edge(c, a), edge(a, b)];
+                if weights.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+
+                let z = weights[0] * triangle[0][2]
+                    + weights[1] * triangle[1][2]
+                    + weights[2] * triangle[2][2];
+                let i = (y * size + x) as usize;
+                if z > heights[i] {
+                    heights[i] = z;
+                    flat[i] = flatness;
+                }
+            }
+        }
+    }
+
+    let (z0, z1) = (min(2), max(2));
+    let pixels = heights
+        .iter()
+        .zip(&flat)
+        .map(|(&z, &flatness)| {
+            if z == f32::NEG_INFINITY {
+                return [0; 3];
+            }
+            let height = if z1 > z0 { (z - z0) / (z1 - z0) } else { 1.0 };
+            let shade = SHADE_RANGE.0 + (SHADE_RANGE.1 - SHADE_RANGE.0) * height;
+            let value = (shade * (0.5 + 0.5 * flatness) * 255.0) as u8;
+            [value; 3]
+        })
+        .collect();
+
+    Some(Overview {
+        size,
+        pos_x: projection.pos_x,
+        pos_y: projection.pos_y,
+        scale,
+        pixels,
+    })
+}
+
+impl Overview {
+    /// Writes the overview script the game reads from `resource/overviews/<map>.txt`, placing
+    /// the image material `overviews/<map>` in the world. The game takes the scale to be of a
+    /// 1024 pixel image, whatever the size of the one it's given.
+    pub fn write_script<W: Write>(&self, w: &mut W, map_name: &str) -> io::Result<()> {
+        let scale = self.scale * self.size as f32 / GAME_SIZE as f32;
+        writeln!(w, "\"{map_name}\"")?;
+        writeln!(w, "{{")?;
+        writeln!(w, "\t\"material\"\t\"overviews/{map_name}\"")?;
+        writeln!(w, "\t\"pos_x\"\t\t\"{}\"", self.pos_x)?;
+        writeln!(w, "\t\"pos_y\"\t\t\"{}\"", self.pos_y)?;
+        writeln!(w, "\t\"scale\"\t\t\"{scale}\"")?;
+        writeln!(w, "\t\"rotate\"\t\"0\"")?;
+        writeln!(w, "\t\"zoom\"\t\t\"1\"")?;
+        writeln!(w, "}}")
+    }
+}
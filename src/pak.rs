@@ -0,0 +1,641 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read, Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+use zip::{
+    result::{ZipError, ZipResult},
+    write::FileOptions,
+    CompressionMethod, ZipArchive, ZipWriter,
+};
+
+use crate::assets::normalize;
+use crate::backup;
+use crate::bsp::{compress_lzma, BspFile, LumpType};
+use crate::xzp::Xzp;
+
+#[non_exhaustive]
+pub struct PakEntry {
+    pub name: String,
+    pub crc32: u32,
+}
+
+/// Largest packed file read into memory
+pub const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+/// Largest total size of a pakfile's entries. Zip bombs reach sizes far beyond their own by
+/// pointing many entries at the same compressed data.
+pub const MAX_TOTAL_SIZE: u64 = 1024 * 1024 * 1024;
+
+fn open<T: AsRef<[u8]>>(data: T) -> ZipResult<ZipArchive<Cursor<T>>> {
+    let mut zip = ZipArchive::new(Cursor::new(data))?;
+
+    let mut total: u64 = 0;
+    for i in 0..zip.len() {
+        let size = zip.by_index_raw(i)?.size();
+        if size > MAX_FILE_SIZE {
+            return Err(ZipError::InvalidArchive("packed file too large to read"));
+        }
+        total += size;
+    }
+    if total > MAX_TOTAL_SIZE {
+        return Err(ZipError::InvalidArchive("packed files too large to read"));
+    }
+
+    Ok(zip)
+}
+
+/// Lists the pakfile's central directory without decompressing anything.
+pub fn entries(data: &[u8]) -> ZipResult<Vec<PakEntry>> {
+    zip_entries(&mut open(data)?)
+}
+
+fn zip_entries<R: Read + Seek>(zip: &mut ZipArchive<R>) -> ZipResult<Vec<PakEntry>> {
+    (0..zip.len())
+        .map(|i| {
+            let file = zip.by_index_raw(i)?;
+            Ok(PakEntry {
+                name: file.name().to_string(),
+                crc32: file.crc32(),
+            })
+        })
+        .collect()
+}
+
+/// The files packed into a map, from the zip in PAKFILE or, on console maps, the XZP archive in
+/// XZIP_PAKFILE.
+#[non_exhaustive]
+pub enum PakFile {
+    Zip(ZipArchive<Cursor<Vec<u8>>>),
+    Xzp(Xzp),
+}
+
+impl PakFile {
+    /// Returns `None` if the map has neither lump.
+    pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> ZipResult<Option<Self>> {
+        if let Some(data) = bsp.get_lump(LumpType::PAKFILE) {
+            return Ok(Some(Self::Zip(open(data.into_owned())?)));
+        }
+
+        match bsp.get_lump(LumpType::XZIP_PAKFILE) {
+            Some(data) => match Xzp::parse(data.into_owned()) {
+                Some(xzp) => Ok(Some(Self::Xzp(xzp))),
+                None => Err(ZipError::InvalidArchive("invalid XZP archive")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn entries(&mut self) -> ZipResult<Vec<PakEntry>> {
+        match self {
+            Self::Zip(zip) => zip_entries(zip),
+            // XZP only stores the CRC of the name, so hash the contents like zip does
+            Self::Xzp(xzp) => xzp
+                .entries
+                .iter()
+                .map(|entry| {
+                    let contents = xzp
+                        .contents(entry)
+                        .ok_or(ZipError::InvalidArchive("XZP entry outside the archive"))?;
+                    Ok(PakEntry {
+                        name: entry.name.clone(),
+                        crc32: crc32fast::hash(contents),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Names and contents of every packed file.
+    pub fn files(&mut self) -> ZipResult<Vec<(String, Vec<u8>)>> {
+        match self {
+            Self::Zip(zip) => (0..zip.len())
+                .map(|i| {
+                    let contents = read_contents(zip, i)?;
+                    Ok((zip.by_index_raw(i)?.name().to_string(), contents))
+                })
+                .collect(),
+            Self::Xzp(xzp) => {
+                // Entries can overlap just like those of a zip bomb
+                let total: u64 = xzp.entries.iter().map(|e| e.length as u64).sum();
+                if total > MAX_TOTAL_SIZE {
+                    return Err(ZipError::InvalidArchive("packed files too large to read"));
+                }
+                xzp.entries
+                    .iter()
+                    .map(|entry| {
+                        let contents = xzp
+                            .contents(entry)
+                            .ok_or(ZipError::InvalidArchive("XZP entry outside the archive"))?;
+                        Ok((entry.name.clone(), contents.to_vec()))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Writes every packed file under `dir`, skipping names that would end up outside it.
+    /// Returns the names that were skipped.
+    pub fn extract(&mut self, dir: &Path) -> ZipResult<Vec<String>> {
+        let mut skipped = vec![];
+        for (name, contents) in self.files()? {
+            let relative = Path::new(&name);
+            if !relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)))
+            {
+                skipped.push(name);
+                continue;
+            }
+
+            let path = dir.join(relative);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, contents)?;
+        }
+
+        Ok(skipped)
+    }
+}
+
+/// What [`update`] did to each entry of the pakfile
+#[derive(Default)]
+pub struct UpdateSummary {
+    pub added: Vec<String>,
+    pub replaced: Vec<String>,
+    pub deleted: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+/// Adds the files under `dir` to `files`, named by their path below it after `prefix`
+fn content_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, (String, PathBuf)>,
+) -> io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        if path.is_dir() {
+            content_files(&path, &format!("{name}/"), files)?;
+            continue;
+        }
+
+        files.insert(normalize(&name), (name, path));
+    }
+
+    Ok(())
+}
+
+pub fn update(
+    pakfile: Option<&[u8]>,
+    dir: &Path,
+    sync: bool,
+) -> ZipResult<(Vec<u8>, UpdateSummary)> {
+    let mut files = BTreeMap::new();
+    content_files(dir, "", &mut files)?;
+
+    let mut summary = UpdateSummary::default();
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut out = ZipWriter::new(Cursor::new(vec![]));
+
+    if let Some(data) = pakfile {
+        let mut zip = open(data)?;
+        for i in 0..zip.len() {
+            let file = zip.by_index(i)?;
+            let name = file.name().to_string();
+
+            let Some((_, path)) = files.get(&normalize(&name)) else {
+                if sync && !name.starts_with(backup::PREFIX) {
+                    summary.deleted.push(name);
+                } else {
+                    out.raw_copy_file(file)?;
+                }
+                continue;
+            };
+
+            let contents = std::fs::read(path)?;
+            if sync
+                && file.size() == contents.len() as u64
+                && file.crc32() == crc32fast::hash(&contents)
+            {
+                out.raw_copy_file(file)?;
+                summary.unchanged.push(name.clone());
+            } else {
+                out.start_file(name.as_str(), options)?;
+                out.write_all(&contents)?;
+                summary.replaced.push(name.clone());
+            }
+
+            files.remove(&normalize(&name));
+        }
+    }
+
+    for (name, path) in files.into_values() {
+        out.start_file(name.as_str(), options)?;
+        out.write_all(&std::fs::read(path)?)?;
+        summary.added.push(name);
+    }
+
+    Ok((out.finish()?.into_inner(), summary))
+}
+
+/// Reads the contents of every entry whose name starts with `prefix`.
+pub fn read_prefixed(data: &[u8], prefix: &str) -> ZipResult<Vec<(String, Vec<u8>)>> {
+    let mut zip = open(data)?;
+
+    let mut files = vec![];
+    for i in 0..zip.len() {
+        let name = zip.by_index_raw(i)?.name().to_string();
+        if name.starts_with(prefix) {
+            files.push((name, read_contents(&mut zip, i)?));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Copies the pakfile without entries for which `keep` returns false, then adds `files` stored
+/// uncompressed.
+pub fn rewrite(
+    pakfile: Option<&[u8]>,
+    keep: impl Fn(&str) -> bool,
+    files: Vec<(String, Vec<u8>)>,
+) -> ZipResult<Vec<u8>> {
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut out = ZipWriter::new(Cursor::new(vec![]));
+
+    if let Some(data) = pakfile {
+        let mut zip = open(data)?;
+        for i in 0..zip.len() {
+            let file = zip.by_index(i)?;
+            if keep(file.name()) {
+                out.raw_copy_file(file)?;
+            }
+        }
+    }
+
+    for (name, contents) in files {
+        out.start_file(name, options)?;
+        out.write_all(&contents)?;
+    }
+
+    Ok(out.finish()?.into_inner())
+}
+
+/// Zip compression method of LZMA entries, the only compression the engine reads in pakfiles
+const METHOD_LZMA: u16 = 14;
+const METHOD_DEFLATE: u16 = 8;
+/// Version of the LZMA SDK recorded in LZMA entries, as written by bspzip
+const LZMA_SDK_VERSION: [u8; 2] = [9, 20];
+
+struct RepackedFile {
+    name: String,
+    method: u16,
+    crc32: u32,
+    size: u32,
+    /// MS-DOS time and date
+    modified: (u16, u16),
+    data: Vec<u8>,
+}
+
+/// Reads an entry's contents, including LZMA entries the zip crate can't decompress. Entries
+/// decompressing to more than the size they give are refused.
+pub fn read_contents<R: Read + Seek>(zip: &mut ZipArchive<R>, i: usize) -> ZipResult<Vec<u8>> {
+    let mut file = zip.by_index_raw(i)?;
+    let size = file.size();
+    if size > MAX_FILE_SIZE {
+        return Err(ZipError::InvalidArchive("packed file too large to read"));
+    }
+
+    #[allow(deprecated)]
+    let lzma = file.compression() == CompressionMethod::Unsupported(METHOD_LZMA);
+    if !lzma {
+        drop(file);
+        let mut contents = vec![];
+        zip.by_index(i)?.take(size + 1).read_to_end(&mut contents)?;
+        if contents.len() as u64 > size {
+            return Err(ZipError::InvalidArchive("packed file larger than its size"));
+        }
+        return Ok(contents);
+    }
+
+    let mut raw = vec![];
+    file.read_to_end(&mut raw)?;
+
+    // The SDK version and size of the properties precede the properties and the stream
+    let stream = raw
+        .get(4..)
+        .ok_or(ZipError::InvalidArchive("truncated LZMA entry"))?;
+    let mut contents = vec![];
+    lzma_rs::lzma_decompress_with_options(
+        &mut Cursor::new(stream),
+        &mut contents,
+        &lzma_rs::decompress::Options {
+            unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(size)),
+            allow_incomplete: false,
+            memlimit: None,
+        },
+    )
+    .map_err(|_| ZipError::InvalidArchive("invalid LZMA entry"))?;
+
+    Ok(contents)
+}
+
+/// Writes the local header of a file, or its central directory header if `local_offset`, the
+/// offset of the local header, is given.
+fn write_header<W: Write>(
+    w: &mut W,
+    file: &RepackedFile,
+    local_offset: Option<u32>,
+) -> io::Result<()> {
+    let version: u16 = match file.method {
+        METHOD_LZMA => 63,
+        METHOD_DEFLATE => 20,
+        _ => 10,
+    };
+    let (time, date) = file.modified;
+
+    match local_offset {
+        None => w.write_u32::<LittleEndian>(0x04034b50)?,
+        Some(_) => {
+            w.write_u32::<LittleEndian>(0x02014b50)?;
+            // Version made by
+            w.write_u16::<LittleEndian>(version)?;
+        }
+    }
+    w.write_u16::<LittleEndian>(version)?;
+    // Flags
+    w.write_u16::<LittleEndian>(0)?;
+    w.write_u16::<LittleEndian>(file.method)?;
+    w.write_u16::<LittleEndian>(time)?;
+    w.write_u16::<LittleEndian>(date)?;
+    w.write_u32::<LittleEndian>(file.crc32)?;
+    w.write_u32::<LittleEndian>(file.data.len() as u32)?;
+    w.write_u32::<LittleEndian>(file.size)?;
+    w.write_u16::<LittleEndian>(file.name.len() as u16)?;
+    // Extra field length
+    w.write_u16::<LittleEndian>(0)?;
+    if let Some(offset) = local_offset {
+        // Comment length, disk number, internal and external attributes
+        w.write_all(&[0; 10])?;
+        w.write_u32::<LittleEndian>(offset)?;
+    }
+
+    w.write_all(file.name.as_bytes())
+}
+
+fn write_zip(files: &[RepackedFile]) -> io::Result<Vec<u8>> {
+    let mut out = vec![];
+    let mut directory = vec![];
+    for file in files {
+        write_header(&mut directory, file, Some(out.len() as u32))?;
+        write_header(&mut out, file, None)?;
+        out.write_all(&file.data)?;
+    }
+
+    let directory_offset = out.len() as u32;
+    out.write_all(&directory)?;
+    out.write_u32::<LittleEndian>(0x06054b50)?;
+    // Disk numbers
+    out.write_u32::<LittleEndian>(0)?;
+    out.write_u16::<LittleEndian>(files.len() as u16)?;
+    out.write_u16::<LittleEndian>(files.len() as u16)?;
+    out.write_u32::<LittleEndian>(directory.len() as u32)?;
+    out.write_u32::<LittleEndian>(directory_offset)?;
+    // Comment length
+    out.write_u16::<LittleEndian>(0)?;
+
+    Ok(out)
+}
+
+/// Reads an entry for rewriting, stored, LZMA compressing it if `compress` and that makes it
+/// smaller.
+fn repack_file<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    i: usize,
+    compress: bool,
+) -> ZipResult<RepackedFile> {
+    let contents = read_contents(zip, i)?;
+    let file = zip.by_index_raw(i)?;
+    let modified = file.last_modified();
+
+    let mut repacked = RepackedFile {
+        name: file.name().to_string(),
+        method: 0,
+        crc32: crc32fast::hash(&contents),
+        size: contents.len() as u32,
+        modified: (modified.timepart(), modified.datepart()),
+        data: contents,
+    };
+    if compress {
+        compress_file(&mut repacked, Recompression::Lzma)?;
+    }
+    Ok(repacked)
+}
+
+/// Compresses a stored entry with `method` if that makes it smaller
+fn compress_file(file: &mut RepackedFile, method: Recompression) -> io::Result<()> {
+    let (id, compressed) = match method {
+        Recompression::Lzma => {
+            // Zip LZMA entries keep the properties from the Valve header, without the sizes
+            let lzma = compress_lzma(&file.data)?;
+            let mut entry = LZMA_SDK_VERSION.to_vec();
+            entry.extend_from_slice(&5u16.to_le_bytes());
+            entry.extend_from_slice(&lzma[12..]);
+            (METHOD_LZMA, entry)
+        }
+        Recompression::Deflate => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(vec![], flate2::Compression::best());
+            encoder.write_all(&file.data)?;
+            (METHOD_DEFLATE, encoder.finish()?)
+        }
+    };
+
+    if compressed.len() < file.data.len() {
+        (file.method, file.data) = (id, compressed);
+    }
+    Ok(())
+}
+
+/// Reads an entry for rewriting, keeping its data as it is if it's compressed with a method
+/// [`write_zip`] can record.
+fn copy_file<R: Read + Seek>(zip: &mut ZipArchive<R>, i: usize) -> ZipResult<RepackedFile> {
+    let mut raw = zip.by_index_raw(i)?;
+    let Some(method) = method_id(raw.compression()).filter(|&id| id != 0) else {
+        drop(raw);
+        return repack_file(zip, i, false);
+    };
+
+    let modified = raw.last_modified();
+    let mut data = vec![];
+    raw.read_to_end(&mut data)?;
+    Ok(RepackedFile {
+        name: raw.name().to_string(),
+        method,
+        crc32: raw.crc32(),
+        size: raw.size() as u32,
+        modified: (modified.timepart(), modified.datepart()),
+        data,
+    })
+}
+
+/// Rewrites the pakfile with each entry LZMA compressed where that makes it smaller, like
+/// `bspzip -repack`, or with every entry stored for engine branches that can't read LZMA.
+pub fn repack(data: &[u8], compress: bool) -> ZipResult<Vec<u8>> {
+    let mut zip = open(data)?;
+    let files = (0..zip.len())
+        .map(|i| repack_file(&mut zip, i, compress))
+        .collect::<ZipResult<Vec<_>>>()?;
+
+    Ok(write_zip(&files)?)
+}
+
+/// MS-DOS time and date of midnight on 1980-01-01, the earliest a zip can record
+const EPOCH: (u16, u16) = (0, 1 << 5 | 1);
+
+/// Rewrites the pakfile so its bytes only depend on the files in it: entries sorted by name,
+/// every timestamp set to [`EPOCH`], directory entries dropped and every entry stored, or LZMA
+/// compressed where that makes it smaller if `compress`.
+pub fn normalize_archive(data: &[u8], compress: bool) -> ZipResult<Vec<u8>> {
+    let mut zip = open(data)?;
+    let mut files = vec![];
+    for i in 0..zip.len() {
+        if zip.by_index_raw(i)?.is_dir() {
+            continue;
+        }
+
+        let mut file = repack_file(&mut zip, i, compress)?;
+        file.modified = EPOCH;
+        files.push(file);
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(write_zip(&files)?)
+}
+
+/// How [`optimize`] compresses stored entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recompression {
+    Lzma,
+    /// Smaller archives for tools reading them, but the engine can't read deflate entries
+    Deflate,
+}
+
+/// What [`optimize`] did to the pakfile
+#[derive(Default)]
+pub struct OptimizeSummary {
+    /// Entries removed for being identical to an earlier entry with the same name
+    pub duplicates: Vec<String>,
+    /// Names packed more than once with different contents, which are all kept
+    pub conflicts: Vec<String>,
+    /// Groups of entries with different names but the same contents
+    pub shared_contents: Vec<Vec<String>>,
+    /// Stored entries that were compressed
+    pub compressed: usize,
+}
+
+#[allow(deprecated)]
+fn method_id(method: CompressionMethod) -> Option<u16> {
+    match method {
+        CompressionMethod::Stored => Some(0),
+        CompressionMethod::Deflated => Some(METHOD_DEFLATE),
+        CompressionMethod::Unsupported(METHOD_LZMA) => Some(METHOD_LZMA),
+        _ => None,
+    }
+}
+
+/// Removes redundant copies of entries and compresses the stored entries where that makes
+/// them smaller. Entries that are already compressed are copied as they are.
+pub fn optimize(data: &[u8], method: Recompression) -> ZipResult<(Vec<u8>, OptimizeSummary)> {
+    let mut zip = open(data)?;
+    let mut summary = OptimizeSummary::default();
+    let mut files: Vec<RepackedFile> = vec![];
+    let mut by_contents: BTreeMap<(u32, u32), Vec<String>> = BTreeMap::new();
+
+    for i in 0..zip.len() {
+        let raw = zip.by_index_raw(i)?;
+        let name = raw.name().to_string();
+        let (crc32, size) = (raw.crc32(), raw.size() as u32);
+
+        if let Some(earlier) = files
+            .iter()
+            .find(|f| normalize(&f.name) == normalize(&name))
+        {
+            if earlier.crc32 == crc32 && earlier.size == size {
+                summary.duplicates.push(name);
+                continue;
+            }
+            summary.conflicts.push(name.clone());
+        }
+        if !raw.is_dir() && size != 0 {
+            by_contents
+                .entry((crc32, size))
+                .or_default()
+                .push(name.clone());
+        }
+
+        drop(raw);
+        let mut file = copy_file(&mut zip, i)?;
+        if file.method == 0 {
+            compress_file(&mut file, method)?;
+            if file.method != 0 {
+                summary.compressed += 1;
+            }
+        }
+        files.push(file);
+    }
+
+    summary.shared_contents = by_contents
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .collect();
+
+    Ok((write_zip(&files)?, summary))
+}
+
+/// Rewrites the pakfile without the metadata zip tools record: timestamps are set to [`EPOCH`]
+/// and extra fields and comments, which can hold file owners or paths, are dropped. Entries
+/// keep their order and compression.
+pub fn redact_archive(data: &[u8]) -> ZipResult<Vec<u8>> {
+    let mut zip = open(data)?;
+    let files = (0..zip.len())
+        .map(|i| {
+            let mut file = copy_file(&mut zip, i)?;
+            file.modified = EPOCH;
+            Ok(file)
+        })
+        .collect::<ZipResult<Vec<_>>>()?;
+
+    Ok(write_zip(&files)?)
+}
+
+pub struct RenamedFile {
+    pub from: String,
+    pub to: String,
+}
+
+/// Rewrites the pakfile with the entries `rename` gives a new name renamed. Entries keep their
+/// order, compression and timestamps.
+pub fn rename_archive(
+    data: &[u8],
+    rename: impl Fn(&str) -> Option<String>,
+) -> ZipResult<(Vec<u8>, Vec<RenamedFile>)> {
+    let mut zip = open(data)?;
+    let mut renamed = vec![];
+    let files = (0..zip.len())
+        .map(|i| {
+            let mut file = copy_file(&mut zip, i)?;
+            if let Some(to) = rename(&file.name) {
+                let from = std::mem::replace(&mut file.name, to.clone());
+                renamed.push(RenamedFile { from, to });
+            }
+            Ok(file)
+        })
+        .collect::<ZipResult<Vec<_>>>()?;
+
+    Ok((write_zip(&files)?, renamed))
+}
@@ -0,0 +1,222 @@
+//! Summary of the VPhysics collision data stored for each brush model.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::error::BspError;
+
+/// Header of each solid written by newer versions of vphysics
+const VPHYSICS_ID: &[u8; 4] = b"VPHY";
+/// Model index of the entry that ends the lump
+const END_MODEL: i32 = -1;
+
+pub struct Model {
+    pub index: i32,
+    pub solids: usize,
+    /// Solids without a `VPHY` header, written by older compilers
+    pub legacy_solids: usize,
+    /// Bytes of collision data, excluding the keyvalues
+    pub data_size: usize,
+    pub keydata: String,
+}
+
+impl Model {
+    /// Number of solids using each surfaceprop, from the keyvalue blocks describing them
+    pub fn surfaceprops(&self) -> BTreeMap<String, usize> {
+        let mut surfaceprops = BTreeMap::new();
+        let mut key: Option<String> = None;
+
+        for token in tokenize(&self.keydata) {
+            match token.as_str() {
+                "{" | "}" => key = None,
+                _ => match key.take() {
+                    Some(key) if key.eq_ignore_ascii_case("surfaceprop") => {
+                        *surfaceprops.entry(token).or_default() += 1
+                    }
+                    Some(_) => {}
+                    None => key = Some(token),
+                },
+            }
+        }
+
+        surfaceprops
+    }
+}
+
+/// Splits keyvalue text into quoted or bare tokens and braces
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => tokens.push(c.to_string()),
+            '"' => tokens.push(chars.by_ref().take_while(|&c| c != '"').collect()),
+            c if c.is_whitespace() || c == '\0' => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && !"{}\"\0".contains(c))
+                {
+                    token.push(c);
+                }
+                tokens.push(token);
+            }
+        }
+    }
+
+    tokens
+}
+
+pub struct Report {
+    pub lump_size: usize,
+    pub models: Vec<Model>,
+    /// Size of the displacement collision lump
+    pub displacement_size: usize,
+    /// Set if the lump ends before its terminating entry
+    pub truncated: bool,
+}
+
+/// A size or count, which can't be negative
+fn size(value: i32) -> io::Result<usize> {
+    usize::try_from(value).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "negative size"))
+}
+
+/// Reads the next model's entry, or `None` for the entry ending the lump. Fails if the lump
+/// ends within the entry or it has a negative size.
+fn read_model(reader: &mut Cursor<&[u8]>) -> io::Result<Option<Model>> {
+    let index = reader.read_i32::<LittleEndian>()?;
+    let data_size = reader.read_i32::<LittleEndian>()?;
+    let keydata_size = reader.read_i32::<LittleEndian>()?;
+    let solid_count = reader.read_i32::<LittleEndian>()?;
+    if index == END_MODEL {
+        return Ok(None);
+    }
+
+    let mut legacy_solids = 0;
+    for _ in 0..solid_count {
+        let mut solid = vec![0; size(reader.read_i32::<LittleEndian>()?)?];
+        reader.read_exact(&mut solid)?;
+        if !solid.starts_with(VPHYSICS_ID) {
+            legacy_solids += 1;
+        }
+    }
+
+    let mut keydata = vec![0; size(keydata_size)?];
+    reader.read_exact(&mut keydata)?;
+
+    Ok(Some(Model {
+        index,
+        solids: size(solid_count)?,
+        legacy_solids,
+        data_size: size(data_size)?,
+        keydata: String::from_utf8_lossy(&keydata)
+            .trim_end_matches('\0')
+            .to_string(),
+    }))
+}
+
+/// Summarizes the collision data of every model. A map without any has no PHYSICS_COLLIDE lump,
+/// or an empty one.
+pub fn summarize<R: Read + Seek>(bsp: &mut BspFile<R>) -> Result<Report, BspError> {
+    let data = bsp.try_get_lump(LumpType::PHYSICS_COLLIDE)?;
+    if data.is_empty() {
+        return Err(BspError::MissingLump(LumpType::PHYSICS_COLLIDE));
+    }
+    let displacement_size = bsp
+        .get_lump(LumpType::PHYSICS_DISPLACEMENT)
+        .map_or(0, |lump| lump.len());
+
+    let mut reader = Cursor::new(&data[..]);
+    let mut models = vec![];
+    let truncated = loop {
+        if reader.position() as usize >= data.len() {
+            break true;
+        }
+
+        match read_model(&mut reader) {
+            Ok(Some(model)) => models.push(model),
+            Ok(None) => break false,
+            Err(_) => break true,
+        }
+    };
+
+    Ok(Report {
+        lump_size: data.len(),
+        models,
+        displacement_size,
+        truncated,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W, keyvalues: bool) -> io::Result<()> {
+        let solids: usize = self.models.iter().map(|m| m.solids).sum();
+        let data: usize = self.models.iter().map(|m| m.data_size).sum();
+        let keydata: usize = self.models.iter().map(|m| m.keydata.len()).sum();
+
+        writeln!(w, "Physics collision: {} bytes", self.lump_size)?;
+        writeln!(w, "  Models: {}", self.models.len())?;
+        writeln!(w, "  Solids: {solids}")?;
+        writeln!(w, "  Collision data: {data} bytes")?;
+        writeln!(w, "  Keyvalues: {keydata} bytes")?;
+        writeln!(
+            w,
+            "Displacement collision: {} bytes",
+            self.displacement_size
+        )?;
+
+        writeln!(w, "Models:")?;
+        writeln!(
+            w,
+            "  {:>6} {:>7} {:>12} {:>10}",
+            "model", "solids", "collision", "keyvalues"
+        )?;
+        for model in &self.models {
+            writeln!(
+                w,
+                "  {:>6} {:>7} {:>12} {:>10}",
+                model.index,
+                model.solids,
+                model.data_size,
+                model.keydata.len()
+            )?;
+        }
+
+        let mut surfaceprops: BTreeMap<String, usize> = BTreeMap::new();
+        for model in &self.models {
+            for (name, count) in model.surfaceprops() {
+                *surfaceprops.entry(name).or_default() += count;
+            }
+        }
+        if !surfaceprops.is_empty() {
+            writeln!(w, "Surface properties:")?;
+            for (name, count) in &surfaceprops {
+                writeln!(w, "  {count:8} {name}")?;
+            }
+        }
+
+        if keyvalues {
+            for model in &self.models {
+                writeln!(w, "Model {} keyvalues:", model.index)?;
+                for line in model.keydata.lines() {
+                    writeln!(w, "  {line}")?;
+                }
+            }
+        }
+
+        let legacy: usize = self.models.iter().map(|m| m.legacy_solids).sum();
+        if legacy != 0 {
+            writeln!(
+                w,
+                "warning: {legacy} solids have no VPHY header and were written by an old compiler"
+            )?;
+        }
+        if self.truncated {
+            writeln!(w, "error: the lump ends before its terminating entry")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+//! CSV round-tripping of static props and point entities, so placements can be bulk edited in a
+//! spreadsheet and written back into the map.
+
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::{self, Write},
+};
+
+use crate::csv;
+use crate::entities::Entity;
+use crate::staticprops::StaticProps;
+
+const PROP_COLUMNS: [&str; 10] = [
+    "index", "model", "origin_x", "origin_y", "origin_z", "pitch", "yaw", "roll", "skin", "solid",
+];
+
+#[derive(Debug)]
+pub struct ImportError {
+    /// 1-based line in the CSV file
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+fn error(line: usize, message: impl Into<String>) -> ImportError {
+    ImportError {
+        line,
+        message: message.into(),
+    }
+}
+
+fn field<T: std::str::FromStr>(
+    record: &[String],
+    column: usize,
+    line: usize,
+) -> Result<T, ImportError> {
+    let value = record
+        .get(column)
+        .ok_or_else(|| error(line, format!("missing column {}", PROP_COLUMNS[column])))?;
+
+    value
+        .trim()
+        .parse()
+        .map_err(|_| error(line, format!("invalid {} {value:?}", PROP_COLUMNS[column])))
+}
+
+/// Refuses text the entity lump can't hold: it has no escapes, so quotes and line breaks would
+/// end the key or value early
+fn check_text(text: &str, line: usize) -> Result<(), ImportError> {
+    match text.chars().find(|c| matches!(c, '"' | '\n' | '\r')) {
+        Some(c) => Err(error(
+            line,
+            format!("{text:?} has a {c:?}, which the entity lump can't hold"),
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn export_props<W: Write>(props: &StaticProps, w: &mut W) -> io::Result<()> {
+    csv::write_record(w, &PROP_COLUMNS)?;
+
+    for (i, prop) in props.props.iter().enumerate() {
+        let [x, y, z] = prop.origin();
+        let [pitch, yaw, roll] = prop.angles();
+
+        csv::write_record(
+            w,
+            &[
+                i.to_string(),
+                props.model_name(prop).unwrap_or_default().to_string(),
+                x.to_string(),
+                y.to_string(),
+                z.to_string(),
+                pitch.to_string(),
+                yaw.to_string(),
+                roll.to_string(),
+                prop.skin().to_string(),
+                prop.solid().to_string(),
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Applies an edited prop CSV. Rows are matched to props by index; props without a row are left
+/// untouched. The lighting origin is moved along with the prop. Models have to be in the map's
+/// dictionary already unless `add_models` is set, so a typo doesn't become a missing model.
+pub fn import_props(
+    props: &mut StaticProps,
+    records: &[Vec<String>],
+    add_models: bool,
+) -> Result<(), ImportError> {
+    let Some((header, rows)) = records.split_first() else {
+        return Ok(());
+    };
+
+    if !header.iter().map(|f| f.trim()).eq(PROP_COLUMNS) {
+        return Err(error(
+            1,
+            format!("the columns must be {}", PROP_COLUMNS.join(",")),
+        ));
+    }
+
+    for (line, record) in rows.iter().enumerate() {
+        let line = line + 2;
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+
+        let index: usize = field(record, 0, line)?;
+        let model = record
+            .get(1)
+            .ok_or_else(|| error(line, "missing column model"))?
+            .trim();
+        let origin = [
+            field(record, 2, line)?,
+            field(record, 3, line)?,
+            field(record, 4, line)?,
+        ];
+        let angles = [
+            field(record, 5, line)?,
+            field(record, 6, line)?,
+            field(record, 7, line)?,
+        ];
+        let skin = field(record, 8, line)?;
+        let solid = field(record, 9, line)?;
+
+        if model.is_empty() {
+            return Err(error(line, "empty model"));
+        }
+        let model = match props.find_model(model) {
+            Some(index) => index,
+            None if add_models => props.model_index(model),
+            None => {
+                let message = format!("{model} isn't one of the map's static prop models");
+                return Err(error(line, message + ", use --add-models to add it"));
+            }
+        };
+        let prop = props
+            .props
+            .get_mut(index)
+            .ok_or_else(|| error(line, format!("no static prop with index {index}")))?;
+
+        let old_origin = prop.origin();
+        let lighting_origin = prop.lighting_origin();
+        prop.set_lighting_origin(std::array::from_fn(|i| {
+            lighting_origin[i] + origin[i] - old_origin[i]
+        }));
+
+        prop.set_origin(origin);
+        prop.set_angles(angles);
+        prop.set_model(model);
+        prop.set_skin(skin);
+        prop.set_solid(solid);
+    }
+
+    Ok(())
+}
+
+fn is_point_entity(entity: &Entity) -> bool {
+    entity.get("origin").is_some() && !entity.get("model").is_some_and(|m| m.starts_with('*'))
+}
+
+/// Keys that can be represented as a single column. Keys that repeat within an entity (usually
+/// outputs) are left out, and are preserved as-is on import.
+fn entity_columns(entities: &[Entity]) -> Vec<String> {
+    let mut keys = BTreeSet::new();
+    let mut repeated = BTreeSet::new();
+
+    for entity in entities.iter().filter(|e| is_point_entity(e)) {
+        let mut seen = BTreeSet::new();
+        for (key, _) in &entity.properties {
+            let key = key.to_ascii_lowercase();
+            if !seen.insert(key.clone()) {
+                repeated.insert(key.clone());
+            }
+            keys.insert(key);
+        }
+    }
+
+    // Keep the most useful columns first
+    let mut columns: Vec<String> = ["classname", "targetname", "origin", "angles"]
+        .into_iter()
+        .map(String::from)
+        .filter(|k| keys.contains(k) && !repeated.contains(k))
+        .collect();
+    for key in keys.difference(&repeated) {
+        if !columns.contains(key) {
+            columns.push(key.clone());
+        }
+    }
+
+    columns
+}
+
+pub fn export_entities<W: Write>(entities: &[Entity], w: &mut W) -> io::Result<()> {
+    let columns = entity_columns(entities);
+
+    let mut header = vec!["index".to_string()];
+    header.extend(columns.iter().cloned());
+    csv::write_record(w, &header)?;
+
+    for (i, entity) in entities.iter().enumerate() {
+        if !is_point_entity(entity) {
+            continue;
+        }
+
+        let mut record = vec![i.to_string()];
+        record.extend(
+            columns
+                .iter()
+                .map(|key| entity.get(key).unwrap_or_default().to_string()),
+        );
+        csv::write_record(w, &record)?;
+    }
+
+    Ok(())
+}
+
+/// Applies an edited entity CSV. Rows are matched to entities by their index in the entity
+/// lump. Non-empty cells set the key, empty cells remove it.
+pub fn import_entities(
+    entities: &mut [Entity],
+    records: &[Vec<String>],
+) -> Result<(), ImportError> {
+    let Some((header, rows)) = records.split_first() else {
+        return Ok(());
+    };
+
+    if header.first().map(String::as_str) != Some("index") {
+        return Err(error(1, "first column must be index"));
+    }
+    for key in header {
+        check_text(key, 1)?;
+    }
+
+    for (line, record) in rows.iter().enumerate() {
+        let line = line + 2;
+        if record.iter().all(|f| f.is_empty()) {
+            continue;
+        }
+
+        let index: usize = record[0]
+            .trim()
+            .parse()
+            .map_err(|_| error(line, format!("invalid index {:?}", record[0])))?;
+        let entity = entities
+            .get_mut(index)
+            .ok_or_else(|| error(line, format!("no entity with index {index}")))?;
+
+        for value in record {
+            check_text(value, line)?;
+        }
+        for (key, value) in header.iter().zip(record).skip(1) {
+            if value.is_empty() {
+                entity.remove(key);
+            } else {
+                entity.set(key, value.clone());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::staticprops::StaticProp;
+
+    fn records(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|field| field.to_string()).collect())
+            .collect()
+    }
+
+    fn props() -> StaticProps {
+        StaticProps {
+            models: vec!["models/props/tree.mdl".to_string()],
+            leaves: vec![],
+            props: vec![StaticProp { raw: vec![0; 56] }],
+        }
+    }
+
+    const PROP_HEADER: &[&str] = &PROP_COLUMNS;
+
+    #[test]
+    fn props_are_moved_and_given_known_models() {
+        let mut props = props();
+        let row: &[&str] = &[
+            "0",
+            "MODELS/props/tree.mdl",
+            "1",
+            "2",
+            "3",
+            "0",
+            "90",
+            "0",
+            "1",
+            "6",
+        ];
+        import_props(&mut props, &records(&[PROP_HEADER, row]), false).unwrap();
+
+        assert_eq!(props.props[0].origin(), [1.0, 2.0, 3.0]);
+        assert_eq!(props.props[0].lighting_origin(), [1.0, 2.0, 3.0]);
+        assert_eq!(props.models.len(), 1);
+    }
+
+    #[test]
+    fn unknown_and_empty_models_are_refused() {
+        let row = |model| ["0", model, "0", "0", "0", "0", "0", "0", "0", "0"];
+
+        let mut props = props();
+        let csv = records(&[PROP_HEADER, &row("models/props/tre.mdl")]);
+        assert_eq!(import_props(&mut props, &csv, false).unwrap_err().line, 2);
+        import_props(&mut props, &csv, true).unwrap();
+        assert_eq!(props.models.len(), 2);
+
+        let csv = records(&[PROP_HEADER, &row("")]);
+        assert_eq!(import_props(&mut props, &csv, true).unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn prop_header_is_checked() {
+        let csv = records(&[&["index", "model"], &["0", "models/props/tree.mdl"]]);
+        assert_eq!(import_props(&mut props(), &csv, false).unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn entity_values_are_set_and_removed() {
+        let mut entities = vec![Entity::default()];
+        entities[0].set("targetname", "old".to_string());
+
+        let csv = records(&[&["index", "classname", "targetname"], &["0", "light", ""]]);
+        import_entities(&mut entities, &csv).unwrap();
+
+        assert_eq!(entities[0].get("classname"), Some("light"));
+        assert_eq!(entities[0].get("targetname"), None);
+    }
+
+    #[test]
+    fn quotes_and_line_breaks_are_refused() {
+        for text in ["say \"hi\"", "two\nlines", "cr\r"] {
+            let mut entities = vec![Entity::default()];
+            let csv = records(&[&["index", "message"], &["0", text]]);
+            let error = import_entities(&mut entities, &csv).unwrap_err();
+            assert_eq!(error.line, 2);
+
+            let csv = records(&[&["index", text], &["0", "x"]]);
+            let error = import_entities(&mut entities, &csv).unwrap_err();
+            assert_eq!(error.line, 1);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! How parsers treat maps that don't quite follow the format: unknown lump versions, lumps that
+//! are cut short and other inconsistencies compilers and hand edits leave behind.
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum Policy {
+    /// Problems are errors, and whatever was being read fails
+    #[default]
+    Strict,
+    /// Problems are warnings, and parsers make the best of what's there
+    Lenient,
+}
+
+/// State shared by every parser reading a map, carried by its [`BspFile`](crate::bsp::BspFile)
+#[derive(Debug, Clone, Copy, Default)]
+#[non_exhaustive]
+pub struct ParseContext {
+    pub policy: Policy,
+}
+
+impl ParseContext {
+    pub fn new(policy: Policy) -> Self {
+        Self { policy }
+    }
+
+    /// Reports a problem with the map on stderr. Returns whether the parser should carry on,
+    /// which it only does under the lenient policy.
+    pub fn tolerate(&self, problem: impl Display) -> bool {
+        match self.policy {
+            Policy::Strict => {
+                eprintln!("error: {problem} (use --lenient to read it anyway)");
+                false
+            }
+            Policy::Lenient => {
+                eprintln!("warning: {problem}");
+                true
+            }
+        }
+    }
+}
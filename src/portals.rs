@@ -0,0 +1,217 @@
+//! Sanity checks of areaportal entities against the compiled portal data and the doors and
+//! brushes they are linked to, and the areas those portals connect.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::entities::{name_matches, Entity};
+use crate::lumps::{Area, AreaPortal};
+use crate::validate::Report;
+
+fn describe(index: usize, entity: &Entity) -> String {
+    let classname = entity.get("classname").unwrap_or_default();
+    match entity.get("targetname") {
+        Some(name) => format!("{classname} \"{name}\" (entity {index})"),
+        None => format!("{classname} (entity {index})"),
+    }
+}
+
+fn find_target<'a>(entities: &'a [Entity], target: &str) -> Option<&'a Entity> {
+    entities.iter().find(|e| {
+        e.get("targetname")
+            .is_some_and(|name| name_matches(target, name))
+    })
+}
+
+/// Whether any entity output sends `input` (or Toggle) to the entity named `name`
+fn receives_input(entities: &[Entity], name: &str, input: &str) -> bool {
+    entities.iter().flat_map(Entity::outputs).any(|output| {
+        name_matches(output.target, name)
+            && (output.input.eq_ignore_ascii_case(input)
+                || output.input.eq_ignore_ascii_case("Toggle"))
+    })
+}
+
+pub fn check_links(entities: &[Entity], portals: &[AreaPortal]) -> Report {
+    let mut report = Report::default();
+    let portal_keys: BTreeSet<u16> = portals.iter().map(|p| p.portal_key).collect();
+    let mut linked = BTreeSet::new();
+
+    for (i, entity) in entities.iter().enumerate() {
+        let classname = entity.get("classname").unwrap_or_default();
+        let is_window = classname.eq_ignore_ascii_case("func_areaportalwindow");
+        if !is_window && !classname.eq_ignore_ascii_case("func_areaportal") {
+            continue;
+        }
+
+        let what = describe(i, entity);
+        match entity.get("portalnumber").map(str::parse::<u16>) {
+            None => report.warning(format!(
+                "{what} has no portalnumber, it isn't sealing two areas"
+            )),
+            Some(Err(_)) => report.error(format!("{what} has an invalid portalnumber")),
+            Some(Ok(key)) if !portal_keys.contains(&key) => report.error(format!(
+                "{what} references portal {key}, which doesn't exist in AREA_PORTALS"
+            )),
+            Some(Ok(key)) => {
+                linked.insert(key);
+            }
+        }
+
+        let target = entity.get("target").filter(|t| !t.is_empty());
+        if is_window {
+            match target {
+                Some(target) if find_target(entities, target).is_none() => report.error(format!(
+                    "{what} targets \"{target}\" to fade, which doesn't exist"
+                )),
+                _ => {}
+            }
+            continue;
+        }
+
+        match target {
+            Some(target) => {
+                if find_target(entities, target).is_none() {
+                    report.error(format!(
+                        "{what} is linked to door \"{target}\", which doesn't exist"
+                    ));
+                }
+            }
+            None => {
+                let name = entity.get("targetname");
+                let start_open = entity.get("StartOpen").unwrap_or("1") != "0";
+                let input = if start_open { "Close" } else { "Open" };
+                let controlled = name.is_some_and(|name| receives_input(entities, name, input));
+
+                if start_open && !controlled {
+                    report.warning(format!(
+                        "{what} starts open and nothing ever closes it, so it never blocks visibility"
+                    ));
+                } else if !start_open && !controlled {
+                    report.warning(format!("{what} starts closed and nothing ever opens it"));
+                }
+            }
+        }
+    }
+
+    for key in portal_keys.difference(&linked) {
+        report.warning(format!(
+            "portal {key} in AREA_PORTALS has no matching areaportal entity"
+        ));
+    }
+
+    report
+}
+
+/// The areas of a map and the areaportal entities controlling the portals between them
+pub struct AreaReport {
+    /// Portals leading out of each area
+    pub areas: Vec<Vec<AreaPortal>>,
+    /// Descriptions of the areaportal entities using each portal key
+    pub entities: BTreeMap<u16, Vec<String>>,
+    pub findings: Report,
+}
+
+pub fn areas(entities: &[Entity], areas: &[Area], portals: &[AreaPortal]) -> AreaReport {
+    let mut findings = Report::default();
+
+    let areas: Vec<Vec<AreaPortal>> = areas
+        .iter()
+        .enumerate()
+        .map(|(i, area)| {
+            let range = usize::try_from(area.first_area_portal)
+                .ok()
+                .and_then(|first| {
+                    let count = usize::try_from(area.num_area_portals).ok()?;
+                    portals.get(first..first + count)
+                });
+            range.map(<[_]>::to_vec).unwrap_or_else(|| {
+                findings.error(format!("area {i} has portals outside AREA_PORTALS"));
+                vec![]
+            })
+        })
+        .collect();
+
+    let mut users: BTreeMap<u16, Vec<String>> = BTreeMap::new();
+    for (i, entity) in entities.iter().enumerate() {
+        let classname = entity.get("classname").unwrap_or_default();
+        let is_portal = ["func_areaportal", "func_areaportalwindow"]
+            .iter()
+            .any(|c| classname.eq_ignore_ascii_case(c));
+        let key = entity.get("portalnumber").and_then(|n| n.parse().ok());
+        if let (true, Some(key)) = (is_portal, key) {
+            users.entry(key).or_default().push(describe(i, entity));
+        }
+    }
+
+    for (from, portals) in areas.iter().enumerate() {
+        for portal in portals {
+            let to = portal.other_area as usize;
+            let key = portal.portal_key;
+            let Some(back) = areas.get(to) else {
+                findings.error(format!(
+                    "portal {key} leads from area {from} to area {to}, which doesn't exist"
+                ));
+                continue;
+            };
+
+            if !back
+                .iter()
+                .any(|p| p.portal_key == key && p.other_area as usize == from)
+            {
+                findings.error(format!(
+                    "portal {key} leads from area {from} to area {to}, but not back, so it can only be closed from one side"
+                ));
+            }
+        }
+    }
+
+    let keys: BTreeSet<u16> = portals.iter().map(|p| p.portal_key).collect();
+    for key in &keys {
+        match users.get(key).map(Vec::as_slice) {
+            None | Some([]) => findings.warning(format!(
+                "portal {key} has no areaportal entity, so it's always open"
+            )),
+            Some([_]) => {}
+            Some(users) => findings.error(format!(
+                "portal {key} is used by {} entities, which fight over opening and closing it: {}",
+                users.len(),
+                users.join(", ")
+            )),
+        }
+    }
+    for (key, users) in &users {
+        if !keys.contains(key) {
+            for user in users {
+                findings.error(format!(
+                    "{user} references portal {key}, which doesn't exist in AREA_PORTALS, so opening and closing it does nothing"
+                ));
+            }
+        }
+    }
+
+    AreaReport {
+        areas,
+        entities: users,
+        findings,
+    }
+}
+
+impl AreaReport {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Areas: {}", self.areas.len())?;
+        for (i, portals) in self.areas.iter().enumerate() {
+            writeln!(w, "  Area {i}: {} portals", portals.len())?;
+            for portal in portals {
+                let key = portal.portal_key;
+                let users = match self.entities.get(&key) {
+                    Some(users) => users.join(", "),
+                    None => "no entity".to_string(),
+                };
+                writeln!(w, "    portal {key} to area {}: {users}", portal.other_area)?;
+            }
+        }
+
+        self.findings.print(w)
+    }
+}
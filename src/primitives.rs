@@ -0,0 +1,166 @@
+//! Statistics of the PRIMITIVES lumps, the extra triangles vbsp emits for faces whose edges
+//! t-junction fixing split, and for the "waterindices" surfaces drawn from them, along with
+//! how close they come to the compile limits.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{
+    self, Face, Primitive, TexInfo, Vector, FACE_NO_SHADOWS, PRIM_TRILIST, PRIM_TRISTRIP, SURF_WARP,
+};
+
+/// vbsp's MAX_MAP_PRIMITIVES, MAX_MAP_PRIMVERTS and MAX_MAP_PRIMINDICES
+pub const MAX_PRIMITIVES: usize = 32768;
+pub const MAX_PRIMVERTS: usize = 65536;
+pub const MAX_PRIMINDICES: usize = 65536;
+/// Share of a limit used before it's worth warning about
+const NEAR_LIMIT: f64 = 0.9;
+
+const PRIMITIVE_SIZE: usize = 10;
+const VERTEX_SIZE: usize = 12;
+const INDEX_SIZE: usize = 2;
+
+pub struct Report {
+    pub primitives: Vec<Primitive>,
+    pub vertices: usize,
+    pub indices: usize,
+    /// Faces drawn with primitives
+    pub faces: usize,
+    /// Of `faces`, those of water, which vbsp builds its "waterindices" primitives for
+    pub water_faces: usize,
+    /// Faces whose primitives are outside the PRIMITIVES lump
+    pub invalid_faces: Vec<usize>,
+    /// Primitives whose indices or vertices are outside their lumps
+    pub invalid_primitives: Vec<usize>,
+}
+
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    let primitives: Vec<Primitive> = lumps::read_array(bsp, LumpType::PRIMITIVES)?;
+    let vertices: Vec<Vector> = lumps::read_array(bsp, LumpType::PRIMITIVE_VERTICES)?;
+    let indices: Vec<u16> = lumps::read_array(bsp, LumpType::PRIMITIVE_INDICES)?;
+    let faces: Vec<Face> = lumps::read_array(bsp, LumpType::FACES)?;
+    let texinfo: Vec<TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)?;
+
+    let mut report = Report {
+        primitives: vec![],
+        vertices: vertices.len(),
+        indices: indices.len(),
+        faces: 0,
+        water_faces: 0,
+        invalid_faces: vec![],
+        invalid_primitives: vec![],
+    };
+
+    for (i, face) in faces.iter().enumerate() {
+        let count = (face.num_prims & !FACE_NO_SHADOWS) as usize;
+        if count == 0 {
+            continue;
+        }
+
+        report.faces += 1;
+        let water = usize::try_from(face.texinfo)
+            .ok()
+            .and_then(|t| texinfo.get(t))
+            .is_some_and(|t| t.flags & SURF_WARP != 0);
+        if water {
+            report.water_faces += 1;
+        }
+        if face.first_prim_id as usize + count > primitives.len() {
+            report.invalid_faces.push(i);
+        }
+    }
+
+    for (i, primitive) in primitives.iter().enumerate() {
+        let index_end = primitive.first_index as usize + primitive.index_count as usize;
+        let vert_end = primitive.first_vert as usize + primitive.vert_count as usize;
+        if index_end > indices.len() || vert_end > vertices.len() {
+            report.invalid_primitives.push(i);
+        }
+    }
+
+    report.primitives = primitives;
+    Some(report)
+}
+
+impl Report {
+    fn count(&self, kind: u8) -> usize {
+        self.primitives.iter().filter(|p| p.kind == kind).count()
+    }
+
+    /// Bytes the primitives add to the map
+    pub fn bytes(&self) -> usize {
+        self.primitives.len() * PRIMITIVE_SIZE
+            + self.vertices * VERTEX_SIZE
+            + self.indices * INDEX_SIZE
+    }
+
+    fn limits(&self) -> [(&'static str, usize, usize); 3] {
+        [
+            ("MAX_MAP_PRIMITIVES", self.primitives.len(), MAX_PRIMITIVES),
+            ("MAX_MAP_PRIMVERTS", self.vertices, MAX_PRIMVERTS),
+            ("MAX_MAP_PRIMINDICES", self.indices, MAX_PRIMINDICES),
+        ]
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.invalid_faces.is_empty()
+            || !self.invalid_primitives.is_empty()
+            || self.limits().iter().any(|&(_, used, max)| used > max)
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let lists = self.count(PRIM_TRILIST);
+        let strips = self.count(PRIM_TRISTRIP);
+        let other = self.primitives.len() - lists - strips;
+        write!(
+            w,
+            "Primitives: {} ({lists} triangle lists, {strips} triangle strips",
+            self.primitives.len()
+        )?;
+        if other > 0 {
+            write!(w, ", {other} of unknown type")?;
+        }
+        writeln!(w, ")")?;
+        writeln!(w, "  Vertices: {}", self.vertices)?;
+        writeln!(w, "  Indices: {}", self.indices)?;
+        writeln!(w, "  Size: {} bytes", self.bytes())?;
+        writeln!(
+            w,
+            "Faces with primitives: {} ({} water)",
+            self.faces, self.water_faces
+        )?;
+
+        for (limit, used, max) in self.limits() {
+            writeln!(
+                w,
+                "{limit:<20} {used:>6} / {max:<6} {:5.1}%",
+                used as f64 * 100.0 / max as f64
+            )?;
+        }
+
+        for i in &self.invalid_faces {
+            writeln!(
+                w,
+                "error: face {i} refers to primitives outside the PRIMITIVES lump"
+            )?;
+        }
+        for i in &self.invalid_primitives {
+            writeln!(
+                w,
+                "error: primitive {i} refers to indices or vertices outside their lumps"
+            )?;
+        }
+        for (limit, used, max) in self.limits() {
+            if used > max {
+                writeln!(w, "error: {limit} exceeded ({used} > {max})")?;
+            } else if used as f64 >= max as f64 * NEAR_LIMIT {
+                writeln!(
+                    w,
+                    "warning: {limit} is nearly reached ({used} of {max}), more t-junctions or water will fail to compile"
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,60 @@
+//! Removing what a map gives away about the machine and people that made it, for mappers
+//! sharing a release: absolute paths from the compile machine and notes left in entities.
+//! [`pak::redact_archive`](crate::pak::redact_archive) does the same for the pakfile's zip
+//! metadata.
+
+use crate::entities::Entity;
+
+/// Entity keys editors and compile tools keep notes for the mapper in
+const COMMENT_KEYS: &[&str] = &["comments", "comment", "_comment", "_note"];
+
+/// Directories user accounts live in, named in paths on Windows, macOS and Linux
+const HOME_DIRS: &[&str] = &["/users/", "/home/", "/documents and settings/"];
+
+pub struct Redaction {
+    pub entity: usize,
+    pub classname: String,
+    pub key: String,
+    pub reason: &'static str,
+}
+
+/// Whether `value` has an absolute path in it, such as `C:\Users\...`, a UNC path or a home
+/// directory
+pub fn has_absolute_path(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let drive = bytes.windows(3).enumerate().any(|(i, w)| {
+        let starts_word = i == 0 || !bytes[i - 1].is_ascii_alphanumeric();
+        starts_word && w[0].is_ascii_alphabetic() && w[1] == b':' && matches!(w[2], b'\\' | b'/')
+    });
+
+    let value = value.replace('\\', "/").to_ascii_lowercase();
+    drive || value.starts_with("//") || HOME_DIRS.iter().any(|dir| value.contains(dir))
+}
+
+/// Removes editor comments and keys whose values have absolute paths in them, returning what
+/// was removed.
+pub fn redact_entities(entities: &mut [Entity]) -> Vec<Redaction> {
+    let mut redactions = vec![];
+    for (i, entity) in entities.iter_mut().enumerate() {
+        let classname = entity.get("classname").unwrap_or_default().to_string();
+        entity.properties.retain(|(key, value)| {
+            let reason = if COMMENT_KEYS.iter().any(|k| key.eq_ignore_ascii_case(k)) {
+                "comment"
+            } else if has_absolute_path(value) {
+                "absolute path"
+            } else {
+                return true;
+            };
+
+            redactions.push(Redaction {
+                entity: i,
+                classname: classname.clone(),
+                key: key.clone(),
+                reason,
+            });
+            false
+        });
+    }
+
+    redactions
+}
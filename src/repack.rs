@@ -0,0 +1,80 @@
+//! LZMA compressing every lump of a map like `bspzip -repack`, or decompressing them all for
+//! engine branches that can't read compressed lumps.
+
+use std::io::{self, Read, Seek};
+
+use crate::bsp::{compress_lzma, BspFile, LumpInfo, LumpType, HEADER_LUMPS};
+use crate::writer::LumpWriter;
+use crate::{gamelump, pak};
+
+/// Whether the game lump has entries compressed with [`gamelump::COMPRESSED`]
+fn has_compressed_game_lumps(lump: &[u8]) -> bool {
+    gamelump::parse_directory(lump).is_ok_and(|entries| {
+        entries
+            .iter()
+            .any(|entry| !entry.is_terminator() && entry.flags & gamelump::COMPRESSED != 0)
+    })
+}
+
+/// Compresses or decompresses every lump in `writer`, returning the lumps that changed. Lumps
+/// are only compressed where that makes them smaller. The game lump and pakfile aren't
+/// compressed as a whole: each game lump and pakfile entry is instead.
+pub fn repack<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    writer: &mut LumpWriter,
+    compress: bool,
+) -> io::Result<Vec<LumpType>> {
+    let mut changed = vec![];
+
+    for i in 0..HEADER_LUMPS {
+        let lump = LumpType::try_from(i as u32).unwrap();
+        let info = *bsp.lump_info(lump);
+        let Some(raw) = bsp.get_raw_lump(lump) else {
+            continue;
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid {lump:?}"));
+
+        match lump {
+            LumpType::GAME_LUMP => {
+                let lumps = gamelump::read(bsp)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let data = match compress {
+                    true if !lumps.is_empty() => gamelump::serialize_compressed(&lumps)?,
+                    _ => gamelump::serialize(&lumps)?,
+                };
+                if compress != has_compressed_game_lumps(&raw) || info.uncompressed_size != 0 {
+                    writer.replace_lump(lump, data);
+                    changed.push(lump);
+                }
+            }
+            LumpType::PAKFILE => {
+                let data = bsp.get_lump(lump).ok_or_else(invalid)?;
+                let repacked = pak::repack(&data, compress)?;
+                if repacked[..] != raw[..] {
+                    writer.replace_lump(lump, repacked);
+                    changed.push(lump);
+                }
+            }
+            // Read as it is by console builds, which can't be repacked here
+            LumpType::XZIP_PAKFILE => {}
+            _ if compress && info.uncompressed_size == 0 => {
+                let compressed = compress_lzma(&raw)?;
+                if compressed.len() < raw.len() {
+                    let info = LumpInfo {
+                        uncompressed_size: raw.len() as u32,
+                        ..info
+                    };
+                    writer.replace_raw_lump(lump, info, compressed);
+                    changed.push(lump);
+                }
+            }
+            _ if !compress && info.uncompressed_size != 0 => {
+                writer.replace_lump(lump, bsp.get_lump(lump).ok_or_else(invalid)?.into_owned());
+                changed.push(lump);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(changed)
+}
@@ -0,0 +1,110 @@
+//! The custom content a server has to offer with a map: a `.res` file listing what clients
+//! download along with it, and entries for the sv_pure whitelist letting clients load that
+//! content from disk.
+
+use std::collections::{BTreeSet, HashSet};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::assets::{normalize, AssetStore, PathMatching};
+use crate::deps::{AssetKind, Graph};
+
+/// Files a model is split into besides the `.mdl`
+const MODEL_FILES: &[&str] = &[".vvd", ".dx90.vtx", ".dx80.vtx", ".sw.vtx", ".vtx", ".phy"];
+
+/// Content that ships with the game, from directories of extracted VPKs or lists of their
+/// paths, one per line, as `vpk l` prints them
+pub struct Stock {
+    dirs: AssetStore,
+    listed: HashSet<String>,
+}
+
+impl Stock {
+    pub fn load(sources: Vec<PathBuf>) -> io::Result<Self> {
+        let (mut dirs, mut listed) = (vec![], HashSet::new());
+        for source in sources {
+            if source.is_dir() {
+                dirs.push(source);
+                continue;
+            }
+            let text = std::fs::read(&source)?;
+            listed.extend(
+                String::from_utf8_lossy(&text)
+                    .lines()
+                    .map(normalize)
+                    .filter(|line| !line.is_empty()),
+            );
+        }
+
+        Ok(Self {
+            dirs: AssetStore::new(None, dirs, PathMatching::Normalized),
+            listed,
+        })
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        self.listed.contains(&normalize(path)) || self.dirs.exists(path)
+    }
+}
+
+pub struct ResList {
+    /// Custom files, found in the pakfile or a game directory
+    pub files: BTreeSet<String>,
+    /// Assets found nowhere, which are left out as they're usually stock content that wasn't
+    /// given
+    pub missing: BTreeSet<String>,
+}
+
+/// Collects the map's packed files and the assets it depends on that aren't stock
+pub fn collect(graph: &Graph, store: &AssetStore, stock: &Stock, packed: &[String]) -> ResList {
+    let mut files: BTreeSet<String> = packed.iter().map(|name| normalize(name)).collect();
+    let mut missing = BTreeSet::new();
+
+    for (path, node) in &graph.nodes {
+        if node.kind == AssetKind::Map || stock.contains(path) {
+            continue;
+        }
+        if !node.found {
+            missing.insert(path.clone());
+            continue;
+        }
+        files.insert(path.clone());
+
+        // Clients need every part of a model, of which only the .mdl is referenced
+        if node.kind == AssetKind::Model {
+            let Some(base) = path.strip_suffix(".mdl") else {
+                continue;
+            };
+            for extension in MODEL_FILES {
+                let part = format!("{base}{extension}");
+                if store.exists(&part) && !stock.contains(&part) {
+                    files.insert(part);
+                }
+            }
+        }
+    }
+
+    ResList { files, missing }
+}
+
+impl ResList {
+    /// Writes the `maps/<map>.res` file clients read to know what to download
+    pub fn write_res<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "\"Resources\"")?;
+        writeln!(w, "{{")?;
+        for path in &self.files {
+            writeln!(w, "\t\"{path}\"\t\"file\"")?;
+        }
+        writeln!(w, "}}")
+    }
+
+    /// Writes a `pure_server_whitelist.txt` block allowing the files to be loaded from disk
+    pub fn write_whitelist<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "whitelist")?;
+        writeln!(w, "{{")?;
+        for path in &self.files {
+            writeln!(w, "\t{path}\t\tany")?;
+        }
+        writeln!(w, "}}")
+    }
+}
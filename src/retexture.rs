@@ -0,0 +1,124 @@
+//! Renaming the materials a map's faces use, by rewriting the texture name strings every
+//! TEXTURE_DATA entry points at, and optionally the packed files of those materials.
+
+use std::collections::HashMap;
+
+/// A material renamed. A `from` ending in `/` renames every material in that directory.
+pub struct Mapping {
+    pub from: String,
+    pub to: String,
+}
+
+impl Mapping {
+    /// Parses `old/material=new/material`
+    pub fn parse(arg: &str) -> Option<Self> {
+        let (from, to) = arg.split_once('=')?;
+        let (from, to) = (normalize(from), normalize(to));
+        if from.is_empty() || to.is_empty() || from.ends_with('/') != to.ends_with('/') {
+            return None;
+        }
+        Some(Self { from, to })
+    }
+
+    /// The new name of `name`, if this renames it
+    fn apply(&self, name: &str) -> Option<String> {
+        let lower = normalize(name);
+        if self.from.ends_with('/') {
+            let rest = lower.strip_prefix(&self.from)?;
+            Some(format!("{}{rest}", self.to))
+        } else {
+            (lower == self.from).then(|| self.to.clone())
+        }
+    }
+}
+
+/// Material names are matched the way the engine looks them up, ignoring case and the
+/// direction of slashes
+fn normalize(name: &str) -> String {
+    name.trim()
+        .trim_start_matches("materials/")
+        .replace('\\', "/")
+        .to_ascii_lowercase()
+}
+
+fn rename(mappings: &[Mapping], name: &str) -> Option<String> {
+    mappings.iter().find_map(|m| m.apply(name))
+}
+
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+}
+
+/// The rewritten TEXTURE_DATA_STRING_TABLE and TEXTURE_DATA_STRING_DATA lumps
+pub struct Strings {
+    pub table: Vec<i32>,
+    pub data: Vec<u8>,
+    pub renamed: Vec<Renamed>,
+}
+
+fn read_string(data: &[u8], offset: i32) -> Option<&[u8]> {
+    let string = data.get(usize::try_from(offset).ok()?..)?;
+    let len = string.iter().position(|&c| c == 0).unwrap_or(string.len());
+    Some(&string[..len])
+}
+
+/// Renames the strings of the string table, writing them out again in table order. Entries
+/// sharing a string keep sharing it, and offsets outside the data are left as they were.
+pub fn rewrite_strings(table: &[i32], data: &[u8], mappings: &[Mapping]) -> Strings {
+    let mut out = Strings {
+        table: Vec::with_capacity(table.len()),
+        data: vec![],
+        renamed: vec![],
+    };
+    let mut written: HashMap<i32, i32> = HashMap::new();
+
+    for &offset in table {
+        if let Some(&new_offset) = written.get(&offset) {
+            out.table.push(new_offset);
+            continue;
+        }
+        let Some(string) = read_string(data, offset) else {
+            out.table.push(offset);
+            continue;
+        };
+
+        let name = String::from_utf8_lossy(string);
+        let new_offset = out.data.len() as i32;
+        match rename(mappings, &name) {
+            Some(to) => {
+                out.data.extend_from_slice(to.as_bytes());
+                out.renamed.push(Renamed {
+                    from: name.into_owned(),
+                    to,
+                });
+            }
+            None => out.data.extend_from_slice(string),
+        }
+        out.data.push(0);
+
+        written.insert(offset, new_offset);
+        out.table.push(new_offset);
+    }
+
+    out
+}
+
+/// The new path of a packed `materials/*.vmt` or `*.vtf` file of a renamed material
+pub fn rename_packed(mappings: &[Mapping], path: &str) -> Option<String> {
+    let normalized = path.replace('\\', "/");
+    let (dir, rest) = normalized.split_at(normalized.find('/')?);
+    if !dir.eq_ignore_ascii_case("materials") {
+        return None;
+    }
+    let (stem, extension) = rest[1..].rsplit_once('.')?;
+    if !["vmt", "vtf"]
+        .iter()
+        .any(|e| extension.eq_ignore_ascii_case(e))
+    {
+        return None;
+    }
+
+    let to = rename(mappings, stem)?;
+    Some(format!("materials/{to}.{extension}"))
+}
@@ -0,0 +1,188 @@
+//! Which parts of the map can see the sky, from the flags vbsp sets on leaves that see a sky
+//! face.
+
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::entities::Entity;
+use crate::lumps::{
+    self, Face, Leaf, TexInfo, LEAF_FLAGS_SKY, LEAF_FLAGS_SKY2D, SURF_SKY, SURF_SKY2D,
+};
+
+pub struct SkyLeaf {
+    pub index: usize,
+    pub leaf: Leaf,
+}
+
+impl SkyLeaf {
+    pub fn sees_3d(&self) -> bool {
+        self.leaf.flags() & LEAF_FLAGS_SKY != 0
+    }
+
+    pub fn sees_2d(&self) -> bool {
+        self.leaf.flags() & LEAF_FLAGS_SKY2D != 0
+    }
+
+    fn volume(&self) -> f64 {
+        leaf_volume(&self.leaf)
+    }
+}
+
+pub struct Report {
+    /// Leaves in a visibility cluster, which excludes solid leaves and the outside
+    pub open_leaves: usize,
+    pub open_volume: f64,
+    /// Open leaves with either sky flag
+    pub sky_leaves: Vec<SkyLeaf>,
+    pub clusters: usize,
+    pub sky_clusters: BTreeSet<i16>,
+    pub sky_faces: usize,
+    pub has_sky_camera: bool,
+}
+
+fn leaf_volume(leaf: &Leaf) -> f64 {
+    (0..3)
+        .map(|i| (leaf.maxs[i] as f64 - leaf.mins[i] as f64).max(0.0))
+        .product()
+}
+
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>, entities: &[Entity]) -> Option<Report> {
+    let leaves = lumps::read_leaves(bsp)?;
+    let faces: Vec<Face> = lumps::read_array(bsp, LumpType::FACES)?;
+    let texinfo: Vec<TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)?;
+
+    let open: Vec<(usize, &Leaf)> = leaves
+        .iter()
+        .enumerate()
+        .filter(|(_, leaf)| leaf.cluster >= 0)
+        .collect();
+    let sky_leaves: Vec<SkyLeaf> = open
+        .iter()
+        .filter(|(_, leaf)| leaf.flags() & (LEAF_FLAGS_SKY | LEAF_FLAGS_SKY2D) != 0)
+        .map(|&(index, &leaf)| SkyLeaf { index, leaf })
+        .collect();
+
+    let sky_faces = faces
+        .iter()
+        .filter_map(|face| texinfo.get(usize::try_from(face.texinfo).ok()?))
+        .filter(|info| info.flags & (SURF_SKY | SURF_SKY2D) != 0)
+        .count();
+
+    Some(Report {
+        open_leaves: open.len(),
+        open_volume: open.iter().map(|(_, leaf)| leaf_volume(leaf)).sum(),
+        clusters: open
+            .iter()
+            .map(|(_, leaf)| leaf.cluster)
+            .collect::<BTreeSet<_>>()
+            .len(),
+        sky_clusters: sky_leaves.iter().map(|l| l.leaf.cluster).collect(),
+        sky_leaves,
+        sky_faces,
+        has_sky_camera: entities
+            .iter()
+            .any(|e| e.get("classname") == Some("sky_camera")),
+    })
+}
+
+/// Formats sorted numbers as ranges, like `0-3, 7, 9-10`
+fn ranges(numbers: impl IntoIterator<Item = i64>) -> String {
+    let mut ranges: Vec<(i64, i64)> = vec![];
+    for n in numbers {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == n => *end = n,
+            _ => ranges.push((n, n)),
+        }
+    }
+
+    ranges
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{start}-{end}"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn percent(part: f64, total: f64) -> f64 {
+    if total == 0.0 {
+        0.0
+    } else {
+        part / total * 100.0
+    }
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W, list_leaves: bool) -> io::Result<()> {
+        let sky_volume: f64 = self.sky_leaves.iter().map(SkyLeaf::volume).sum();
+        let sees_3d = self.sky_leaves.iter().filter(|l| l.sees_3d()).count();
+        let sees_2d = self.sky_leaves.iter().filter(|l| l.sees_2d()).count();
+
+        writeln!(
+            w,
+            "Leaves open to sky: {} of {} ({:.1}%)",
+            self.sky_leaves.len(),
+            self.open_leaves,
+            percent(self.sky_leaves.len() as f64, self.open_leaves as f64)
+        )?;
+        writeln!(w, "  Seeing the 3D skybox: {sees_3d}")?;
+        writeln!(w, "  Seeing the 2D skybox: {sees_2d}")?;
+        writeln!(
+            w,
+            "Volume open to sky: {:.1}%",
+            percent(sky_volume, self.open_volume)
+        )?;
+        writeln!(
+            w,
+            "Clusters open to sky: {} of {} ({:.1}%)",
+            self.sky_clusters.len(),
+            self.clusters,
+            percent(self.sky_clusters.len() as f64, self.clusters as f64)
+        )?;
+        if !self.sky_clusters.is_empty() {
+            writeln!(
+                w,
+                "  {}",
+                ranges(self.sky_clusters.iter().map(|&c| c as i64))
+            )?;
+        }
+        writeln!(w, "Sky faces: {}", self.sky_faces)?;
+
+        if list_leaves {
+            writeln!(w, "Sky leaves:")?;
+            for sky in &self.sky_leaves {
+                let skies: Vec<&str> = [("3D", sky.sees_3d()), ("2D", sky.sees_2d())]
+                    .into_iter()
+                    .filter_map(|(name, sees)| sees.then_some(name))
+                    .collect();
+                writeln!(
+                    w,
+                    "  {:6} cluster {:5} area {:3} {:?} to {:?} sees {}",
+                    sky.index,
+                    sky.leaf.cluster,
+                    sky.leaf.area(),
+                    sky.leaf.mins,
+                    sky.leaf.maxs,
+                    skies.join(" and ")
+                )?;
+            }
+        }
+
+        if self.sky_faces != 0 && self.sky_leaves.is_empty() {
+            writeln!(
+                w,
+                "warning: the map has sky faces but no leaf is flagged as seeing them, so the sky won't draw and sky lighting may be missing"
+            )?;
+        }
+        if self.has_sky_camera && sees_3d == 0 && !self.sky_leaves.is_empty() {
+            writeln!(
+                w,
+                "warning: the map has a sky_camera but no leaf can see the 3D skybox"
+            )?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! How much vbsp split the map's faces, comparing FACES against the ORIGINAL_FACES they were
+//! cut from. A face count that exploded usually comes down to a few materials on brushes
+//! that cross many BSP splits.
+
+use std::collections::HashMap;
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, Face, World};
+
+/// Materials listed, from the one split the most
+const WORST_MATERIALS: usize = 10;
+
+pub struct Material {
+    pub name: String,
+    pub original_faces: usize,
+    pub faces: usize,
+}
+
+impl Material {
+    /// Faces splitting added
+    pub fn added(&self) -> usize {
+        self.faces.saturating_sub(self.original_faces)
+    }
+}
+
+pub struct Report {
+    pub faces: usize,
+    pub original_faces: usize,
+    /// Faces not cut from an original face, which only displacements and faces from tools
+    /// other than vbsp should be
+    pub unlinked: usize,
+    /// Faces naming an original face past the end of the lump
+    pub invalid: Vec<usize>,
+    /// The original face split into the most faces, with how many
+    pub most_split: Option<(usize, usize)>,
+    /// Materials sorted by the faces splitting added
+    pub materials: Vec<Material>,
+}
+
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    let world = World::read(bsp)?;
+    let originals: Vec<Face> = lumps::read_array(bsp, LumpType::ORIGINAL_FACES)?;
+
+    let mut pieces = vec![0usize; originals.len()];
+    let mut unlinked = 0;
+    let mut invalid = vec![];
+    for (i, face) in world.faces.iter().enumerate() {
+        match usize::try_from(face.orig_face) {
+            Ok(original) if original < originals.len() => pieces[original] += 1,
+            Ok(_) => invalid.push(i),
+            Err(_) => unlinked += 1,
+        }
+    }
+
+    let mut materials: HashMap<&str, Material> = HashMap::new();
+    for (original, &count) in originals.iter().zip(&pieces) {
+        let name = world.texinfo_name(original.texinfo).unwrap_or("<unknown>");
+        let material = materials.entry(name).or_insert_with(|| Material {
+            name: name.to_string(),
+            original_faces: 0,
+            faces: 0,
+        });
+        material.original_faces += 1;
+        material.faces += count;
+    }
+
+    let mut materials: Vec<Material> = materials.into_values().collect();
+    materials.sort_by(|a, b| b.added().cmp(&a.added()).then(a.name.cmp(&b.name)));
+
+    let most_split = pieces
+        .iter()
+        .copied()
+        .enumerate()
+        .filter(|&(_, count)| count > 1)
+        .max_by_key(|&(i, count)| (count, std::cmp::Reverse(i)));
+
+    Some(Report {
+        faces: world.faces.len(),
+        original_faces: originals.len(),
+        unlinked,
+        invalid,
+        most_split,
+        materials,
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Original faces: {}", self.original_faces)?;
+        writeln!(w, "Faces: {}", self.faces)?;
+        if self.original_faces > 0 {
+            let linked = self.faces - self.unlinked - self.invalid.len();
+            writeln!(
+                w,
+                "Split ratio: {:.2} faces per original face",
+                linked as f64 / self.original_faces as f64
+            )?;
+        }
+        if self.unlinked > 0 {
+            writeln!(w, "Faces without an original: {}", self.unlinked)?;
+        }
+        if let Some((original, count)) = self.most_split {
+            writeln!(
+                w,
+                "Most split: original face {original}, into {count} faces"
+            )?;
+        }
+
+        let worst: Vec<&Material> = self
+            .materials
+            .iter()
+            .filter(|m| m.added() > 0)
+            .take(WORST_MATERIALS)
+            .collect();
+        if !worst.is_empty() {
+            writeln!(w, "Most split materials:")?;
+            writeln!(
+                w,
+                "  {:>8} {:>8} {:>8}  material",
+                "original", "faces", "added"
+            )?;
+            for material in worst {
+                writeln!(
+                    w,
+                    "  {:>8} {:>8} {:>8}  {}",
+                    material.original_faces,
+                    material.faces,
+                    material.added(),
+                    material.name
+                )?;
+            }
+        }
+
+        for i in &self.invalid {
+            writeln!(
+                w,
+                "error: face {i} refers to an original face outside the ORIGINAL_FACES lump"
+            )?;
+        }
+
+        Ok(())
+    }
+}
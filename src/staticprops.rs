@@ -0,0 +1,303 @@
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Cursor, Read};
+
+use crate::error::BspError;
+
+const MODEL_NAME_LENGTH: usize = 128;
+
+/// The static prop game lump. Prop structs differ between versions, but every version from 4
+/// onwards starts with the same fields, so props are kept as raw bytes and only the common
+/// prefix is interpreted.
+pub struct StaticProps {
+    pub models: Vec<String>,
+    pub leaves: Vec<u16>,
+    pub props: Vec<StaticProp>,
+}
+
+pub struct StaticProp {
+    pub raw: Vec<u8>,
+}
+
+impl StaticProp {
+    fn f32_at(&self, ofs: usize) -> f32 {
+        f32::from_le_bytes(self.raw[ofs..ofs + 4].try_into().unwrap())
+    }
+
+    fn set_f32_at(&mut self, ofs: usize, value: f32) {
+        self.raw[ofs..ofs + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn vector_at(&self, ofs: usize) -> [f32; 3] {
+        [self.f32_at(ofs), self.f32_at(ofs + 4), self.f32_at(ofs + 8)]
+    }
+
+    fn set_vector_at(&mut self, ofs: usize, value: [f32; 3]) {
+        for (i, v) in value.into_iter().enumerate() {
+            self.set_f32_at(ofs + i * 4, v);
+        }
+    }
+
+    pub fn origin(&self) -> [f32; 3] {
+        self.vector_at(0)
+    }
+
+    pub fn set_origin(&mut self, origin: [f32; 3]) {
+        self.set_vector_at(0, origin)
+    }
+
+    pub fn angles(&self) -> [f32; 3] {
+        self.vector_at(12)
+    }
+
+    pub fn set_angles(&mut self, angles: [f32; 3]) {
+        self.set_vector_at(12, angles)
+    }
+
+    pub fn model(&self) -> u16 {
+        u16::from_le_bytes([self.raw[24], self.raw[25]])
+    }
+
+    pub fn set_model(&mut self, model: u16) {
+        self.raw[24..26].copy_from_slice(&model.to_le_bytes());
+    }
+
+    pub fn solid(&self) -> u8 {
+        self.raw[30]
+    }
+
+    pub fn set_solid(&mut self, solid: u8) {
+        self.raw[30] = solid;
+    }
+
+    /// The prop's range of the leaf list
+    pub fn leaves(&self) -> (u16, u16) {
+        (
+            u16::from_le_bytes([self.raw[26], self.raw[27]]),
+            u16::from_le_bytes([self.raw[28], self.raw[29]]),
+        )
+    }
+
+    pub fn set_leaves(&mut self, first: u16, count: u16) {
+        self.raw[26..28].copy_from_slice(&first.to_le_bytes());
+        self.raw[28..30].copy_from_slice(&count.to_le_bytes());
+    }
+
+    pub fn skin(&self) -> i32 {
+        i32::from_le_bytes(self.raw[32..36].try_into().unwrap())
+    }
+
+    pub fn set_skin(&mut self, skin: i32) {
+        self.raw[32..36].copy_from_slice(&skin.to_le_bytes());
+    }
+
+    pub fn lighting_origin(&self) -> [f32; 3] {
+        self.vector_at(44)
+    }
+
+    pub fn set_lighting_origin(&mut self, origin: [f32; 3]) {
+        self.set_vector_at(44, origin)
+    }
+}
+
+/// The dictionary path of a model given as `props/tree.mdl` or `models/props/tree.mdl`, with
+/// forward slashes
+pub fn model_path(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    if name
+        .get(..7)
+        .is_some_and(|dir| dir.eq_ignore_ascii_case("models/"))
+    {
+        name
+    } else {
+        format!("models/{name}")
+    }
+}
+
+/// Smallest prop struct, used by version 4
+const MIN_PROP_SIZE: usize = 56;
+
+impl StaticProps {
+    pub fn parse(data: &[u8]) -> Result<Self, BspError> {
+        let malformed = |problem| BspError::malformed("static prop game lump", problem);
+        let mut reader = Cursor::new(data);
+        let read_count = |reader: &mut Cursor<&[u8]>, what| {
+            reader
+                .read_i32::<LittleEndian>()
+                .map_err(|_| malformed(format!("it ends before the {what} count")))
+        };
+
+        let model_count = read_count(&mut reader, "model")?;
+        let mut models = vec![];
+        for _ in 0..model_count {
+            let mut name = [0u8; MODEL_NAME_LENGTH];
+            reader
+                .read_exact(&mut name)
+                .map_err(|_| malformed(format!("it ends before its {model_count} models")))?;
+
+            let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+            models.push(String::from_utf8_lossy(&name[..len]).into_owned());
+        }
+
+        let leaf_count = read_count(&mut reader, "leaf")?;
+        let mut leaves = vec![];
+        for _ in 0..leaf_count {
+            let leaf = reader
+                .read_u16::<LittleEndian>()
+                .map_err(|_| malformed(format!("it ends before its {leaf_count} leaves")))?;
+            leaves.push(leaf);
+        }
+
+        let prop_count = read_count(&mut reader, "prop")? as usize;
+        let remaining = &data[reader.position() as usize..];
+
+        let mut props = vec![];
+        // Derive the struct size from the lump instead of the version, since some games ship
+        // modified structs under the same version number
+        if let Some(prop_size) = remaining.len().checked_div(prop_count) {
+            if prop_size < MIN_PROP_SIZE {
+                return Err(malformed(format!(
+                    "{} bytes are too few for {prop_count} props",
+                    remaining.len()
+                )));
+            }
+
+            props = remaining
+                .chunks_exact(prop_size)
+                .take(prop_count)
+                .map(|raw| StaticProp { raw: raw.to_vec() })
+                .collect();
+        }
+
+        Ok(Self {
+            models,
+            leaves,
+            props,
+        })
+    }
+
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+
+        out.write_i32::<LittleEndian>(self.models.len() as i32)?;
+        for model in &self.models {
+            let mut name = [0u8; MODEL_NAME_LENGTH];
+            let len = model.len().min(MODEL_NAME_LENGTH - 1);
+            name[..len].copy_from_slice(&model.as_bytes()[..len]);
+            out.extend_from_slice(&name);
+        }
+
+        out.write_i32::<LittleEndian>(self.leaves.len() as i32)?;
+        for leaf in &self.leaves {
+            out.write_u16::<LittleEndian>(*leaf)?;
+        }
+
+        out.write_i32::<LittleEndian>(self.props.len() as i32)?;
+        for prop in &self.props {
+            out.extend_from_slice(&prop.raw);
+        }
+
+        Ok(out)
+    }
+
+    pub fn model_name(&self, prop: &StaticProp) -> Option<&str> {
+        self.models.get(prop.model() as usize).map(String::as_str)
+    }
+
+    /// Removes the props `remove` returns true for, along with their leaves, returning how many
+    /// were removed.
+    pub fn remove_props(&mut self, remove: impl Fn(&Self, &StaticProp) -> bool) -> usize {
+        let keep: Vec<bool> = self.props.iter().map(|prop| !remove(self, prop)).collect();
+        let before = self.props.len();
+
+        // Each prop's leaves are copied to the new list in prop order, which is the order vbsp
+        // writes them in
+        let mut leaves = vec![];
+        let mut props = vec![];
+        for (mut prop, keep) in std::mem::take(&mut self.props).into_iter().zip(keep) {
+            if !keep {
+                continue;
+            }
+            let (first, count) = prop.leaves();
+            let range = self
+                .leaves
+                .get(first as usize..first as usize + count as usize)
+                .unwrap_or_default();
+            prop.set_leaves(leaves.len() as u16, range.len() as u16);
+            leaves.extend_from_slice(range);
+            props.push(prop);
+        }
+
+        self.leaves = leaves;
+        self.props = props;
+        self.prune_models();
+        before - self.props.len()
+    }
+
+    /// Makes the props using the model `from` use `to` instead, returning how many did.
+    pub fn replace_model(&mut self, from: &str, to: &str) -> usize {
+        let from = model_path(from);
+        let Some(old) = self
+            .models
+            .iter()
+            .position(|m| model_path(m).eq_ignore_ascii_case(&from))
+        else {
+            return 0;
+        };
+        let new = self.model_index(&model_path(to));
+
+        let mut replaced = 0;
+        for prop in &mut self.props {
+            if prop.model() as usize == old {
+                prop.set_model(new);
+                replaced += 1;
+            }
+        }
+
+        self.prune_models();
+        replaced
+    }
+
+    /// Removes models no prop uses from the dictionary.
+    pub fn prune_models(&mut self) {
+        let mut used = vec![false; self.models.len()];
+        for prop in &self.props {
+            if let Some(used) = used.get_mut(prop.model() as usize) {
+                *used = true;
+            }
+        }
+
+        let mut index = vec![0u16; self.models.len()];
+        let mut models = vec![];
+        for (i, model) in std::mem::take(&mut self.models).into_iter().enumerate() {
+            if used[i] {
+                index[i] = models.len() as u16;
+                models.push(model);
+            }
+        }
+        for prop in &mut self.props {
+            if let Some(&model) = index.get(prop.model() as usize) {
+                prop.set_model(model);
+            }
+        }
+        self.models = models;
+    }
+
+    /// Returns the dictionary index of a model, if it's in the dictionary
+    pub fn find_model(&self, name: &str) -> Option<u16> {
+        self.models
+            .iter()
+            .position(|m| m.eq_ignore_ascii_case(name))
+            .map(|i| i as u16)
+    }
+
+    /// Returns the dictionary index of a model, adding it to the dictionary if needed.
+    pub fn model_index(&mut self, name: &str) -> u16 {
+        match self.find_model(name) {
+            Some(i) => i,
+            None => {
+                self.models.push(name.to_string());
+                (self.models.len() - 1) as u16
+            }
+        }
+    }
+}
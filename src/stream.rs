@@ -0,0 +1,29 @@
+//! Maps that don't come from a file that can be seeked in: piped from stdin, such as from
+//! `curl`, or compressed for fast download servers. Parsing jumps around the map, so these are
+//! read into memory first, and any other `Read` can be parsed the same way:
+//!
+//! ```no_run
+//! let data = bspinfo::stream::buffer(std::io::stdin().lock())?;
+//! let mut reader = std::io::Cursor::new(data);
+//! let bsp = bspinfo::BspFile::new(&mut reader)?;
+//! # Ok::<(), bspinfo::BspError>(())
+//! ```
+
+use std::io::{self, Read};
+
+use bzip2::read::MultiBzDecoder;
+
+use crate::magic::{self, FileKind};
+
+/// Reads the whole of a stream, decompressing it if it's bzip2 compressed
+pub fn buffer<R: Read>(mut reader: R) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader.read_to_end(&mut data)?;
+
+    if magic::identify(&data) == Some(FileKind::Bzip2) {
+        let mut decompressed = vec![];
+        MultiBzDecoder::new(&data[..]).read_to_end(&mut decompressed)?;
+        return Ok(decompressed);
+    }
+    Ok(data)
+}
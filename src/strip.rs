@@ -0,0 +1,56 @@
+//! Removing lumps a map can be played without, to shrink the copies dedicated servers run.
+
+use crate::bsp::LumpType;
+use crate::writer::LumpWriter;
+
+/// Lumps only clients use: lighting, which dedicated servers never load, and the pakfile,
+/// which clients read from their own copy of the map. All of them are part of the map CRC
+/// ([`checksum::map_crc`](crate::checksum::map_crc)), so clients with the unstripped map can't
+/// join a server running the stripped one.
+const SERVER: &[LumpType] = &[
+    LumpType::LIGHTING,
+    LumpType::LIGHTING_HDR,
+    LumpType::LEAF_AMBIENT_INDEX,
+    LumpType::LEAF_AMBIENT_INDEX_HDR,
+    LumpType::LEAF_AMBIENT_LIGHTING,
+    LumpType::LEAF_AMBIENT_LIGHTING_HDR,
+    LumpType::DISPLACEMENT_LIGHTMAP_ALPHAS,
+    LumpType::DISPLACEMENT_LIGHTMAP_SAMPLE_POSITIONS,
+    LumpType::PAKFILE,
+];
+
+/// LDR lighting, for maps compiled with HDR that only need to run with it
+const LDR: &[LumpType] = &[
+    LumpType::LIGHTING,
+    LumpType::LEAF_AMBIENT_INDEX,
+    LumpType::LEAF_AMBIENT_LIGHTING,
+    LumpType::WORLD_LIGHTS,
+];
+
+/// Lumps old compilers wrote that the engine no longer loads: the Havok compressed terrain
+/// collision displacements used before PHYSICS_DISPLACEMENT replaced it
+pub const LEGACY: &[LumpType] = &[LumpType::PHYSICS_COLLIDE_SURFACE];
+
+pub fn preset(name: &str) -> Option<&'static [LumpType]> {
+    match name {
+        "server" => Some(SERVER),
+        "ldr" => Some(LDR),
+        _ => None,
+    }
+}
+
+/// Removes `lumps` from the writer, returning the ones the map had along with their size.
+pub fn strip(writer: &mut LumpWriter, lumps: &[LumpType]) -> Vec<(LumpType, usize)> {
+    let mut removed = vec![];
+    for &lump in lumps {
+        let size = writer.lump_len(lump);
+        if size == 0 || removed.iter().any(|&(l, _)| l == lump) {
+            continue;
+        }
+
+        writer.remove_lump(lump);
+        removed.push((lump, size));
+    }
+
+    removed
+}
@@ -0,0 +1,68 @@
+//! Finds the menu photos and loading screens maps conventionally pack, for export as images.
+
+use crate::assets::{normalize, AssetStore};
+use crate::vmt::Vmt;
+use crate::vtf::{self, Image};
+
+/// Normalized path prefixes of materials and textures games show for a map
+const PREFIXES: &[&str] = &[
+    "materials/vgui/maps/menu_photos_",
+    "materials/vgui/maps/menu_thumb_",
+    "materials/vgui/loadingscreen",
+    "materials/vgui/maps/loadingscreen",
+    "materials/console/background",
+];
+
+pub struct Thumbnail {
+    /// Pakfile entry that matched a known prefix
+    pub source: String,
+    /// Texture the image was decoded from
+    pub texture: String,
+    /// None if the texture is missing or in a format that can't be decoded
+    pub image: Option<Image>,
+}
+
+/// The texture a matched entry shows: a material's base texture, or the texture itself.
+fn texture_of(store: &mut AssetStore, path: &str) -> Option<String> {
+    if path.ends_with(".vtf") {
+        return Some(path.to_string());
+    }
+
+    let text = store.read(path)?;
+    let vmt = Vmt::parse(&String::from_utf8_lossy(&text))?;
+    vmt.textures(store.matching).into_iter().next()
+}
+
+/// Decodes the textures of every packed entry matching a known prefix. A texture shared by
+/// several entries, such as a material and its own texture, is only decoded once.
+pub fn extract(store: &mut AssetStore, names: &[String]) -> Vec<Thumbnail> {
+    let mut thumbnails: Vec<Thumbnail> = vec![];
+
+    for name in names {
+        let path = normalize(name);
+        let matches = PREFIXES.iter().any(|prefix| path.starts_with(prefix))
+            && (path.ends_with(".vmt") || path.ends_with(".vtf"));
+        if !matches {
+            continue;
+        }
+
+        let Some(texture) = texture_of(store, name) else {
+            continue;
+        };
+        if thumbnails
+            .iter()
+            .any(|t| normalize(&t.texture) == normalize(&texture))
+        {
+            continue;
+        }
+
+        let image = store.read(&texture).and_then(|data| vtf::decode(&data));
+        thumbnails.push(Thumbnail {
+            source: name.clone(),
+            texture,
+            image,
+        });
+    }
+
+    thumbnails
+}
@@ -0,0 +1,330 @@
+use crate::entities::Entity;
+use crate::lumps::{Leaf, Model, Node, Plane};
+use crate::staticprops::{StaticProp, StaticProps};
+use crate::tree;
+
+/// A rotation around the world Z axis followed by a translation.
+pub struct Transform {
+    pub translation: [f64; 3],
+    pub yaw: f64,
+}
+
+/// The world's BSP tree, which static props list the leaves of
+pub struct Tree<'a> {
+    pub nodes: &'a [Node],
+    pub planes: &'a [Plane],
+    pub models: &'a [Model],
+    pub leaves: &'a [Leaf],
+}
+
+/// Rounds away floating point noise introduced by the rotation
+fn clean(v: f64) -> f64 {
+    (v * 1000.0).round() / 1000.0 + 0.0
+}
+
+fn parse_vector(s: &str) -> Option<[f64; 3]> {
+    let mut parts = s.split_ascii_whitespace().map(|p| p.parse::<f64>().ok());
+    let v = [parts.next()??, parts.next()??, parts.next()??];
+
+    Some(v)
+}
+
+fn format_vector(v: [f64; 3]) -> String {
+    format!("{} {} {}", clean(v[0]), clean(v[1]), clean(v[2]))
+}
+
+impl Transform {
+    pub fn point(&self, p: [f64; 3]) -> [f64; 3] {
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+
+        [
+            p[0] * cos - p[1] * sin + self.translation[0],
+            p[0] * sin + p[1] * cos + self.translation[1],
+            p[2] + self.translation[2],
+        ]
+    }
+
+    pub fn angles(&self, a: [f64; 3]) -> [f64; 3] {
+        [a[0], a[1] + self.yaw, a[2]]
+    }
+
+    pub fn apply_to_entity(&self, entity: &mut Entity) {
+        if let Some(origin) = entity.get("origin").and_then(parse_vector) {
+            entity.set("origin", format_vector(self.point(origin)));
+        }
+
+        if self.yaw == 0.0 {
+            return;
+        }
+
+        if let Some(angles) = entity.get("angles").and_then(parse_vector) {
+            entity.set("angles", format_vector(self.angles(angles)));
+        }
+
+        // -1 and -2 are special values meaning straight up and straight down
+        if let Some(angle) = entity.get("angle").and_then(|a| a.parse::<f64>().ok()) {
+            if angle >= 0.0 {
+                entity.set("angle", clean(angle + self.yaw).to_string());
+            }
+        }
+    }
+
+    /// Transforms the prop's origin, angles and lighting origin. The prop's leaf list is left
+    /// untouched, which [`apply_to_static_props`](Self::apply_to_static_props) updates as well.
+    pub fn apply_to_static_prop(&self, prop: &mut StaticProp) {
+        let to_f64 = |v: [f32; 3]| v.map(|c| c as f64);
+        let to_f32 = |v: [f64; 3]| v.map(|c| clean(c) as f32);
+
+        prop.set_origin(to_f32(self.point(to_f64(prop.origin()))));
+        prop.set_angles(to_f32(self.angles(to_f64(prop.angles()))));
+        prop.set_lighting_origin(to_f32(self.point(to_f64(prop.lighting_origin()))));
+    }
+
+    /// Transforms every prop, and gives each the leaves of `tree` around where it ends up, as
+    /// the engine only draws props in the leaves they list. Models aren't read, so a prop is
+    /// taken to fill the box around the leaves it was in, which holds all of it: the new lists
+    /// can have leaves the prop doesn't reach, but never miss one it does. None if the tree is
+    /// missing or malformed.
+    pub fn apply_to_static_props(&self, props: &mut StaticProps, tree: &Tree) -> Option<()> {
+        let mut leaves = vec![];
+
+        for prop in &mut props.props {
+            let (first, count) = prop.leaves();
+            let range = first as usize..first as usize + count as usize;
+            let old = props.leaves.get(range).unwrap_or_default();
+            let (mut mins, mut maxs) = (prop.origin().map(f64::from), prop.origin().map(f64::from));
+            for leaf in old.iter().filter_map(|&i| tree.leaves.get(i as usize)) {
+                for i in 0..3 {
+                    mins[i] = mins[i].min(leaf.mins[i].into());
+                    maxs[i] = maxs[i].max(leaf.maxs[i].into());
+                }
+            }
+
+            self.apply_to_static_prop(prop);
+
+            // The box around the moved corners of the old box
+            let (mut new_mins, mut new_maxs) = ([f64::MAX; 3], [f64::MIN; 3]);
+            for corner in 0..8 {
+                let pick = |i: usize| {
+                    if corner & (1 << i) == 0 {
+                        mins[i]
+                    } else {
+                        maxs[i]
+                    }
+                };
+                let moved = self.point([pick(0), pick(1), pick(2)]);
+                for i in 0..3 {
+                    new_mins[i] = new_mins[i].min(moved[i]);
+                    new_maxs[i] = new_maxs[i].max(moved[i]);
+                }
+            }
+
+            let found = tree::leaves_in_box(
+                tree.nodes,
+                tree.planes,
+                tree.models,
+                new_mins.map(|c| c as f32),
+                new_maxs.map(|c| c as f32),
+            )?;
+            let first = u16::try_from(leaves.len()).ok()?;
+            let count = u16::try_from(found.len()).ok()?;
+            for leaf in found {
+                leaves.push(u16::try_from(leaf).ok()?);
+            }
+            prop.set_leaves(first, count);
+        }
+
+        props.leaves = leaves;
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(properties: &[(&str, &str)]) -> Entity {
+        Entity {
+            properties: properties
+                .iter()
+                .map(|&(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Two leaves split by the plane x = 0, leaf 0 in front spanning 0 to 64 and leaf 1 behind
+    /// spanning -64 to 0
+    fn split_world() -> (Vec<Node>, Vec<Plane>, Vec<Model>, Vec<Leaf>) {
+        let node = Node {
+            planenum: 0,
+            children: [-1, -2],
+            mins: [-64; 3],
+            maxs: [64; 3],
+            firstface: 0,
+            numfaces: 0,
+            area: 0,
+        };
+        let plane = Plane {
+            normal: [1.0, 0.0, 0.0],
+            dist: 0.0,
+            ty: 0,
+        };
+        let model = Model {
+            mins: [-64.0; 3],
+            maxs: [64.0; 3],
+            origin: [0.0; 3],
+            headnode: 0,
+            firstface: 0,
+            numfaces: 0,
+        };
+        let leaf = |mins, maxs| Leaf {
+            contents: 0,
+            cluster: 0,
+            area_flags: 0,
+            mins,
+            maxs,
+            first_leaf_face: 0,
+            num_leaf_faces: 0,
+            first_leaf_brush: 0,
+            num_leaf_brushes: 0,
+            leaf_water_data_id: -1,
+        };
+        let leaves = vec![
+            leaf([0, -64, -64], [64, 64, 64]),
+            leaf([-64, -64, -64], [0, 64, 64]),
+        ];
+        (vec![node], vec![plane], vec![model], leaves)
+    }
+
+    fn rotation(yaw: f64) -> Transform {
+        Transform {
+            translation: [0.0; 3],
+            yaw,
+        }
+    }
+
+    #[test]
+    fn translation_moves_points() {
+        let transform = Transform {
+            translation: [10.0, -20.0, 5.5],
+            yaw: 0.0,
+        };
+        assert_eq!(transform.point([1.0, 2.0, 3.0]), [11.0, -18.0, 8.5]);
+    }
+
+    #[test]
+    fn yaw_rotates_counterclockwise_around_z() {
+        let point = rotation(90.0).point([1.0, 2.0, 3.0]);
+        assert_eq!(point.map(clean), [-2.0, 1.0, 3.0]);
+
+        let point = rotation(-90.0).point([1.0, 2.0, 3.0]);
+        assert_eq!(point.map(clean), [2.0, -1.0, 3.0]);
+    }
+
+    #[test]
+    fn rotation_comes_before_translation() {
+        let transform = Transform {
+            translation: [100.0, 0.0, 0.0],
+            yaw: 180.0,
+        };
+        assert_eq!(
+            transform.point([10.0, 5.0, 0.0]).map(clean),
+            [90.0, -5.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn yaw_only_changes_the_angles_yaw() {
+        assert_eq!(
+            rotation(45.0).angles([10.0, 30.0, -5.0]),
+            [10.0, 75.0, -5.0]
+        );
+    }
+
+    #[test]
+    fn entities_are_moved_and_turned() {
+        let transform = Transform {
+            translation: [0.0, 0.0, 64.0],
+            yaw: 90.0,
+        };
+        let mut e = entity(&[("origin", "16 0 0"), ("angles", "0 90 0"), ("angle", "270")]);
+        transform.apply_to_entity(&mut e);
+
+        assert_eq!(e.get("origin"), Some("0 16 64"));
+        assert_eq!(e.get("angles"), Some("0 180 0"));
+        assert_eq!(e.get("angle"), Some("360"));
+    }
+
+    #[test]
+    fn up_and_down_angles_are_kept() {
+        for special in ["-1", "-2"] {
+            let mut e = entity(&[("angle", special)]);
+            rotation(90.0).apply_to_entity(&mut e);
+            assert_eq!(e.get("angle"), Some(special));
+        }
+    }
+
+    #[test]
+    fn translation_leaves_angles_alone() {
+        let transform = Transform {
+            translation: [1.0, 1.0, 1.0],
+            yaw: 0.0,
+        };
+        let mut e = entity(&[("origin", "0 0 0"), ("angles", "0.1234567 0 0")]);
+        transform.apply_to_entity(&mut e);
+
+        assert_eq!(e.get("origin"), Some("1 1 1"));
+        assert_eq!(e.get("angles"), Some("0.1234567 0 0"));
+    }
+
+    #[test]
+    fn static_props_are_moved_and_turned() {
+        let mut prop = StaticProp { raw: vec![0; 56] };
+        prop.set_origin([16.0, 0.0, 0.0]);
+        prop.set_angles([0.0, 10.0, 0.0]);
+        prop.set_lighting_origin([16.0, 0.0, 8.0]);
+
+        let transform = Transform {
+            translation: [0.0, 0.0, 64.0],
+            yaw: 90.0,
+        };
+        transform.apply_to_static_prop(&mut prop);
+
+        assert_eq!(prop.origin(), [0.0, 16.0, 64.0]);
+        assert_eq!(prop.angles(), [0.0, 100.0, 0.0]);
+        assert_eq!(prop.lighting_origin(), [0.0, 16.0, 72.0]);
+    }
+
+    #[test]
+    fn moved_static_props_list_the_leaves_they_end_up_in() {
+        let (nodes, planes, models, leaves) = split_world();
+        let tree = Tree {
+            nodes: &nodes,
+            planes: &planes,
+            models: &models,
+            leaves: &leaves,
+        };
+        let moved = |x| {
+            let mut prop = StaticProp { raw: vec![0; 56] };
+            prop.set_origin([32.0, 0.0, 0.0]);
+            prop.set_leaves(0, 1);
+            let mut props = StaticProps {
+                models: vec![],
+                leaves: vec![0],
+                props: vec![prop],
+            };
+            let transform = Transform {
+                translation: [x, 0.0, 0.0],
+                yaw: 0.0,
+            };
+            transform.apply_to_static_props(&mut props, &tree).unwrap();
+
+            let (first, count) = props.props[0].leaves();
+            props.leaves[first as usize..(first + count) as usize].to_vec()
+        };
+
+        assert_eq!(moved(0.0), [0]);
+        assert_eq!(moved(-32.0), [0, 1]);
+        assert_eq!(moved(-100.0), [1]);
+    }
+}
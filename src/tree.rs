@@ -0,0 +1,215 @@
+//! Shape of the world's BSP tree: depth, leaves per cluster and leaf contents.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, Leaf, Model, Node, Plane, Vector, CONTENTS_NAMES};
+
+pub struct Stats {
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Nodes and leaves reachable from the world model's head node
+    pub world_nodes: usize,
+    pub world_leaves: usize,
+    /// Deepest leaf, counting the head node as depth 1
+    pub max_depth: usize,
+    pub average_depth: f64,
+    /// Number of leaves in each cluster. Leaves in cluster -1 are outside the world or solid.
+    pub cluster_leaves: BTreeMap<i16, usize>,
+    /// Leaves with each contents flag, in the order of `CONTENTS_NAMES`
+    pub contents: Vec<(&'static str, usize)>,
+    pub empty: usize,
+    /// Child indices that point past the end of the nodes or leaves
+    pub bad_children: usize,
+}
+
+pub fn analyze<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Stats> {
+    let nodes: Vec<Node> = lumps::read_array(bsp, LumpType::NODES)?;
+    let leaves: Vec<Leaf> = lumps::read_leaves(bsp)?;
+    let models: Vec<Model> = lumps::read_array(bsp, LumpType::MODELS)?;
+
+    let (mut world_nodes, mut depths, mut bad_children) = (0, vec![], 0);
+    if let (Some(world), false) = (models.first(), nodes.is_empty() && leaves.is_empty()) {
+        // Depth first walk from the head node, as (child, depth) pairs
+        let mut stack = vec![(world.headnode, 1)];
+        while let Some((child, depth)) = stack.pop() {
+            if child < 0 {
+                if ((-1 - child) as usize) < leaves.len() {
+                    depths.push(depth);
+                } else {
+                    bad_children += 1;
+                }
+                continue;
+            }
+
+            let Some(node) = nodes.get(child as usize) else {
+                bad_children += 1;
+                continue;
+            };
+            // A malformed tree can loop, but no real tree visits more nodes than it has
+            world_nodes += 1;
+            if world_nodes > nodes.len() {
+                break;
+            }
+            stack.extend(node.children.map(|c| (c, depth + 1)));
+        }
+    }
+
+    let mut cluster_leaves = BTreeMap::new();
+    for leaf in &leaves {
+        *cluster_leaves.entry(leaf.cluster).or_default() += 1;
+    }
+
+    let contents = CONTENTS_NAMES
+        .iter()
+        .map(|&(flag, name)| {
+            let count = leaves.iter().filter(|l| l.contents & flag != 0).count();
+            (name, count)
+        })
+        .collect();
+
+    Some(Stats {
+        nodes: nodes.len(),
+        leaves: leaves.len(),
+        world_nodes,
+        world_leaves: depths.len(),
+        max_depth: depths.iter().copied().max().unwrap_or(0),
+        average_depth: depths.iter().sum::<usize>() as f64 / depths.len().max(1) as f64,
+        cluster_leaves,
+        contents,
+        empty: leaves.iter().filter(|l| l.contents == 0).count(),
+        bad_children,
+    })
+}
+
+impl Stats {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Nodes: {}", self.nodes)?;
+        writeln!(w, "Leaves: {}", self.leaves)?;
+        writeln!(
+            w,
+            "World tree: {} nodes, {} leaves, max depth {}, average leaf depth {:.1}",
+            self.world_nodes, self.world_leaves, self.max_depth, self.average_depth
+        )?;
+
+        let clusters: Vec<usize> = self
+            .cluster_leaves
+            .iter()
+            .filter(|&(&cluster, _)| cluster >= 0)
+            .map(|(_, &count)| count)
+            .collect();
+        if let (Some(min), Some(max)) = (clusters.iter().min(), clusters.iter().max()) {
+            writeln!(
+                w,
+                "Clusters: {}, leaves per cluster: min {min}, average {:.1}, max {max}",
+                clusters.len(),
+                clusters.iter().sum::<usize>() as f64 / clusters.len() as f64,
+            )?;
+        }
+        if let Some(outside) = self.cluster_leaves.get(&-1) {
+            writeln!(w, "Leaves without a cluster: {outside}")?;
+        }
+
+        if self.leaves != 0 {
+            writeln!(w, "Leaf contents:")?;
+            if self.empty != 0 {
+                writeln!(w, "  {:<22} {:8}", "empty", self.empty)?;
+            }
+            for (name, count) in self.contents.iter().filter(|(_, count)| *count != 0) {
+                writeln!(w, "  {name:<22} {count:8}")?;
+            }
+        }
+
+        if self.bad_children != 0 {
+            writeln!(
+                w,
+                "error: {} node children point past the end of their lump",
+                self.bad_children
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Index of the world leaf containing `point`, found by walking the tree from the world's head
+/// node. None if the tree is missing or malformed.
+pub fn locate(nodes: &[Node], planes: &[Plane], models: &[Model], point: Vector) -> Option<usize> {
+    let mut child = models.first()?.headnode;
+
+    // Every step goes one level down, so a valid walk takes at most one step per node
+    for _ in 0..=nodes.len() {
+        if child < 0 {
+            return Some((-1 - child) as usize);
+        }
+
+        let node = nodes.get(child as usize)?;
+        let plane = planes.get(usize::try_from(node.planenum).ok()?)?;
+        let dist: f32 = (0..3).map(|i| plane.normal[i] * point[i]).sum::<f32>() - plane.dist;
+        child = node.children[if dist >= 0.0 { 0 } else { 1 }];
+    }
+
+    None
+}
+
+/// Indices of the world leaves the box from `mins` to `maxs` touches, in order. None if the tree
+/// is missing or malformed.
+pub fn leaves_in_box(
+    nodes: &[Node],
+    planes: &[Plane],
+    models: &[Model],
+    mins: Vector,
+    maxs: Vector,
+) -> Option<Vec<usize>> {
+    let mut leaves = vec![];
+    let mut stack = vec![models.first()?.headnode];
+    let mut visited = 0;
+
+    while let Some(child) = stack.pop() {
+        if child < 0 {
+            leaves.push((-1 - child) as usize);
+            continue;
+        }
+
+        // A valid tree reaches every node once at most, more means its children loop
+        visited += 1;
+        if visited > nodes.len() {
+            return None;
+        }
+
+        let node = nodes.get(child as usize)?;
+        let plane = planes.get(usize::try_from(node.planenum).ok()?)?;
+        // Distances of the box's corners nearest to and furthest from the plane's front side
+        let (mut near, mut far) = (-plane.dist, -plane.dist);
+        for i in 0..3 {
+            let (a, b) = (plane.normal[i] * mins[i], plane.normal[i] * maxs[i]);
+            near += a.min(b);
+            far += a.max(b);
+        }
+        if far >= 0.0 {
+            stack.push(node.children[0]);
+        }
+        if near < 0.0 {
+            stack.push(node.children[1]);
+        }
+    }
+
+    leaves.sort_unstable();
+    leaves.dedup();
+    Some(leaves)
+}
+
+/// Comma separated names of the contents flags set in `contents`
+pub fn contents_names(contents: i32) -> String {
+    if contents == 0 {
+        return "empty".to_string();
+    }
+
+    let names: Vec<&str> = CONTENTS_NAMES
+        .iter()
+        .filter(|&&(flag, _)| contents & flag != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    names.join(", ")
+}
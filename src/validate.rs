@@ -0,0 +1,388 @@
+//! Structural checks of a map's lump directory and contents.
+
+use std::{
+    fmt,
+    io::{self, Read, Seek, Write},
+};
+
+use crate::bsp::{self, BspFile, BspFormat, LumpType, LZMA_HEADER_SIZE};
+use crate::entities::{self, Entity};
+use crate::gamelump;
+use crate::lumps::{self, LeafAmbientIndex, Model};
+use crate::pak;
+use crate::strip;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn warning(&mut self, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message: message.into(),
+        });
+    }
+
+    pub fn error(&mut self, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message: message.into(),
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for finding in &self.findings {
+            writeln!(w, "{}: {}", finding.severity, finding.message)?;
+        }
+
+        let errors = self
+            .findings
+            .iter()
+            .filter(|f| f.severity == Severity::Error)
+            .count();
+        writeln!(
+            w,
+            "{} errors, {} warnings",
+            errors,
+            self.findings.len() - errors
+        )
+    }
+}
+
+fn header_size(format: BspFormat) -> u64 {
+    match format {
+        BspFormat::Valve => 4 + 4 + bsp::HEADER_LUMPS as u64 * 16 + 4,
+        BspFormat::Respawn => 16 + bsp::RESPAWN_HEADER_LUMPS as u64 * 16,
+    }
+}
+
+fn check_directory<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    let format = bsp.format();
+    let file_size = match bsp.file_size() {
+        Ok(size) => size,
+        Err(e) => {
+            report.error(format!("couldn't determine file size: {e}"));
+            return;
+        }
+    };
+
+    let mut ranges = vec![];
+    for i in 0..bsp.lump_count() {
+        let lump = *bsp.lump_info_by_index(i);
+        let name = format.lump_name(i);
+
+        if lump.filelen == 0 || bsp.external_lump_path(i).is_some() {
+            continue;
+        }
+
+        let start = lump.fileofs as u64;
+        let end = start + lump.filelen as u64;
+        if lump.fileofs == 0 {
+            report.error(format!(
+                "{name} has an offset of 0 but a length of {}",
+                lump.filelen
+            ));
+            continue;
+        }
+        if start < header_size(format) {
+            report.error(format!("{name} overlaps the header"));
+        }
+        if end > file_size {
+            report.error(format!(
+                "{name} extends past the end of the file ({end} > {file_size})"
+            ));
+        }
+
+        ranges.push((start, end, name));
+    }
+
+    ranges.sort();
+    for pair in ranges.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        if b.0 < a.1 {
+            report.error(format!("{} overlaps {}", a.2, b.2));
+        }
+    }
+}
+
+fn check_compression<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    if bsp.format() != BspFormat::Valve {
+        return;
+    }
+
+    for i in 0..bsp.lump_count() {
+        let lump = *bsp.lump_info_by_index(i);
+        if lump.uncompressed_size == 0 || lump.filelen == 0 {
+            continue;
+        }
+
+        let name = bsp.format().lump_name(i);
+        let Some(raw) = bsp.get_raw_lump_by_index(i) else {
+            continue;
+        };
+
+        match bsp::lzma_header(&raw) {
+            None => report.error(format!(
+                "{name} is marked compressed but has no LZMA header"
+            )),
+            Some((actual_size, lzma_size)) => {
+                if actual_size != lump.uncompressed_size {
+                    report.error(format!(
+                        "{name} LZMA header size {actual_size} doesn't match the directory's {}",
+                        lump.uncompressed_size
+                    ));
+                }
+                if lzma_size as usize + LZMA_HEADER_SIZE > raw.len() {
+                    report.error(format!("{name} compressed data is truncated"));
+                } else if let Err(e) = bsp::decompress_lzma(&raw) {
+                    report.error(format!("{name} fails to decompress: {e}"));
+                }
+            }
+        }
+    }
+
+    if bsp.lump_info(LumpType::PAKFILE).uncompressed_size != 0 {
+        report.warning("PAKFILE is LZMA compressed, which the engine can't read");
+    }
+}
+
+fn check_legacy_lumps<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    for &lump in strip::LEGACY {
+        let size = bsp.lump_info(lump).filelen;
+        if size != 0 {
+            report.warning(format!(
+                "{lump:?} is a deprecated lump the engine doesn't load, bspinfo optimize can remove its {size} bytes"
+            ));
+        }
+    }
+}
+
+fn check_pakfile<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    let Some(data) = bsp.get_lump(LumpType::PAKFILE) else {
+        return;
+    };
+
+    if let Err(e) = pak::entries(&data) {
+        report.error(format!("PAKFILE is not a readable zip archive: {e}"));
+    }
+}
+
+fn check_game_lumps<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    let lump_ofs = bsp.lump_info(LumpType::GAME_LUMP).fileofs;
+    let Some(data) = bsp.get_lump(LumpType::GAME_LUMP) else {
+        return;
+    };
+
+    let Ok(directory) = gamelump::parse_directory(&data) else {
+        report.error("GAME_LUMP directory is truncated");
+        return;
+    };
+
+    for entry in directory.iter().filter(|e| !e.is_terminator()) {
+        let name = entry.name();
+        let range = entry
+            .fileofs
+            .checked_sub(lump_ofs)
+            .map(|start| start as usize..start as usize + entry.filelen as usize);
+
+        let Some(contents) = range.and_then(|range| data.get(range)) else {
+            report.error(format!("game lump {name} lies outside of GAME_LUMP"));
+            continue;
+        };
+
+        if entry.flags & gamelump::COMPRESSED == 0 {
+            continue;
+        }
+        if let Err(e) = bsp::decompress_lzma(contents) {
+            report.error(format!("game lump {name} fails to decompress: {e}"));
+        }
+    }
+}
+
+/// Size of a leaf ambient lighting sample: a compressed light cube and a position in the leaf
+const LEAF_AMBIENT_SAMPLE_SIZE: usize = 28;
+
+/// Checks that every leaf has an ambient index entry, and that its samples exist. Props in
+/// leaves with bad entries are lit black.
+fn check_leaf_ambient<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    // Version 0 leaves store their ambient lighting themselves
+    if bsp.lump_info(LumpType::LEAVES).version == 0 {
+        return;
+    }
+    let leaves = bsp.lump_info(LumpType::LEAVES).filelen as usize / 32;
+
+    let passes = [
+        (
+            LumpType::LIGHTING,
+            LumpType::LEAF_AMBIENT_INDEX,
+            LumpType::LEAF_AMBIENT_LIGHTING,
+        ),
+        (
+            LumpType::LIGHTING_HDR,
+            LumpType::LEAF_AMBIENT_INDEX_HDR,
+            LumpType::LEAF_AMBIENT_LIGHTING_HDR,
+        ),
+    ];
+
+    for (lighting, index_lump, samples_lump) in passes {
+        let Some(indices) = lumps::read_array::<LeafAmbientIndex, _>(bsp, index_lump) else {
+            report.error(format!(
+                "{index_lump:?} is not a whole number of 4 byte entries"
+            ));
+            continue;
+        };
+        let samples_len = bsp.get_lump(samples_lump).map_or(0, |data| data.len());
+
+        if indices.is_empty() {
+            if bsp.lump_info(lighting).filelen != 0 {
+                report.warning(format!(
+                    "map has {lighting:?} but no {index_lump:?}, so props will be unlit"
+                ));
+            }
+            continue;
+        }
+
+        if indices.len() != leaves {
+            report.error(format!(
+                "{index_lump:?} has {} entries but there are {leaves} leaves",
+                indices.len()
+            ));
+        }
+        if !samples_len.is_multiple_of(LEAF_AMBIENT_SAMPLE_SIZE) {
+            report.error(format!(
+                "{samples_lump:?} is not a whole number of {LEAF_AMBIENT_SAMPLE_SIZE} byte samples"
+            ));
+        }
+
+        let samples = samples_len / LEAF_AMBIENT_SAMPLE_SIZE;
+        let bad: Vec<usize> = indices
+            .iter()
+            .enumerate()
+            .filter(|(_, index)| {
+                index.first_ambient_sample as usize + index.ambient_sample_count as usize > samples
+            })
+            .map(|(leaf, _)| leaf)
+            .collect();
+        if let Some(first) = bad.first() {
+            report.error(format!(
+                "{} leaves reference samples past the end of {samples_lump:?} ({samples} samples), starting with leaf {first}",
+                bad.len()
+            ));
+        }
+    }
+}
+
+fn check_entities<R: Read + Seek>(bsp: &mut BspFile<R>, report: &mut Report) {
+    if bsp.lump_info(LumpType::ENTITIES).filelen == 0 {
+        report.error("map has no entity lump");
+        return;
+    }
+
+    // Unreadable lumps have already been reported by the directory and compression checks
+    let Some(data) = bsp.get_lump(LumpType::ENTITIES) else {
+        return;
+    };
+
+    let entities = match entities::parse(&data) {
+        Ok(entities) => entities,
+        Err(e) => {
+            report.error(format!("entity lump failed to parse: {e}"));
+            return;
+        }
+    };
+
+    let revision = bsp.map_revision();
+    match entities::map_version(&entities) {
+        Some(version) if version != revision => report.warning(format!(
+            "worldspawn mapversion {version} doesn't match map revision {revision}, so the entity lump was probably edited after compiling"
+        )),
+        _ => {}
+    }
+
+    check_brush_models(bsp, &entities, report);
+}
+
+/// Checks that `model` keys of the form `*N` name a brush model in the map, which the engine
+/// errors on otherwise, and that every brush model but the world is used by some entity.
+fn check_brush_models<R: Read + Seek>(
+    bsp: &mut BspFile<R>,
+    entities: &[Entity],
+    report: &mut Report,
+) {
+    let Some(models) = lumps::read_array::<Model, _>(bsp, LumpType::MODELS) else {
+        return;
+    };
+
+    let mut used = vec![false; models.len()];
+    for entity in entities {
+        let Some(index) = entity
+            .get("model")
+            .and_then(|model| model.strip_prefix('*'))
+        else {
+            continue;
+        };
+
+        match index.parse::<usize>() {
+            Ok(i) if i < models.len() => used[i] = true,
+            _ => report.error(format!(
+                "{} refers to brush model *{index}, but the map has {} brush models",
+                entity.get("classname").unwrap_or("an entity"),
+                models.len()
+            )),
+        }
+    }
+
+    let unused: Vec<usize> = (1..models.len()).filter(|&i| !used[i]).collect();
+    if !unused.is_empty() {
+        let faces: i64 = unused.iter().map(|&i| models[i].numfaces as i64).sum();
+        let names: Vec<String> = unused.iter().map(|i| format!("*{i}")).collect();
+        report.warning(format!(
+            "{} brush models aren't used by any entity, wasting {faces} faces: {}",
+            unused.len(),
+            names.join(", ")
+        ));
+    }
+}
+
+pub fn validate<R: Read + Seek>(bsp: &mut BspFile<R>) -> Report {
+    let mut report = Report::default();
+
+    check_directory(bsp, &mut report);
+    check_compression(bsp, &mut report);
+    if bsp.format() == BspFormat::Valve {
+        check_pakfile(bsp, &mut report);
+        check_game_lumps(bsp, &mut report);
+        check_leaf_ambient(bsp, &mut report);
+        check_legacy_lumps(bsp, &mut report);
+    }
+    check_entities(bsp, &mut report);
+
+    report
+}
@@ -0,0 +1,255 @@
+//! Statistics of the VISIBILITY lump's potentially visible and audible sets, and top-down
+//! images of what a cluster can see.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::{self, Cursor, Write};
+
+use crate::lumps::Leaf;
+
+const HIDDEN_COLOR: [u8; 3] = [64, 64, 64];
+const VISIBLE_COLOR: [u8; 3] = [64, 192, 64];
+const SOURCE_COLOR: [u8; 3] = [255, 64, 64];
+
+pub struct Visibility {
+    /// Run-length compressed PVS and PAS rows for each cluster
+    pub pvs: Vec<Vec<u8>>,
+    pub pas: Vec<Vec<u8>>,
+    pub compressed_size: usize,
+}
+
+/// Decompresses one row of the vis data, where a zero byte is followed by a count of zero bytes.
+fn decompress_row(data: &[u8], ofs: usize, row_size: usize) -> Option<Vec<u8>> {
+    let mut row = Vec::with_capacity(row_size);
+    let mut input = data.get(ofs..)?.iter();
+
+    while row.len() < row_size {
+        match *input.next()? {
+            0 => {
+                let count = *input.next()? as usize;
+                row.resize(row.len() + count, 0);
+            }
+            byte => row.push(byte),
+        }
+    }
+
+    row.truncate(row_size);
+    Some(row)
+}
+
+impl Visibility {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut reader = Cursor::new(data);
+        let num_clusters = usize::try_from(reader.read_i32::<LittleEndian>().ok()?).ok()?;
+        // Each cluster has two offsets after the count
+        if num_clusters > data.len() / 8 {
+            return None;
+        }
+        let row_size = num_clusters.div_ceil(8);
+
+        let mut pvs = Vec::with_capacity(num_clusters);
+        let mut pas = Vec::with_capacity(num_clusters);
+        for _ in 0..num_clusters {
+            let pvs_ofs = reader.read_u32::<LittleEndian>().ok()? as usize;
+            let pas_ofs = reader.read_u32::<LittleEndian>().ok()? as usize;
+
+            pvs.push(decompress_row(data, pvs_ofs, row_size)?);
+            pas.push(decompress_row(data, pas_ofs, row_size)?);
+        }
+
+        Some(Self {
+            pvs,
+            pas,
+            compressed_size: data.len(),
+        })
+    }
+
+    pub fn num_clusters(&self) -> usize {
+        self.pvs.len()
+    }
+
+    /// Size of the PVS and PAS of every cluster when decompressed, as the engine holds them
+    pub fn decompressed_size(&self) -> usize {
+        4 + self.num_clusters() * (8 + 2 * self.num_clusters().div_ceil(8))
+    }
+
+    /// Whether cluster `to` is in the PVS of cluster `from`
+    pub fn can_see(&self, from: usize, to: usize) -> bool {
+        self.pvs
+            .get(from)
+            .and_then(|row| row.get(to / 8))
+            .is_some_and(|byte| byte & (1 << (to % 8)) != 0)
+    }
+
+    /// Draws the map from above with each leaf's bounds, `size` pixels along its longer side.
+    /// Leaves in `cluster` are red, leaves it can see green and the rest grey, with the highest
+    /// of these colors winning where leaves overlap. Returns the width, height and pixels.
+    pub fn render_pvs(
+        &self,
+        leaves: &[Leaf],
+        cluster: usize,
+        size: u32,
+    ) -> (u32, u32, Vec<[u8; 3]>) {
+        let open: Vec<&Leaf> = leaves.iter().filter(|leaf| leaf.cluster >= 0).collect();
+        let min = |axis: usize| open.iter().map(|l| l.mins[axis]).min().unwrap_or(0) as f64;
+        let max = |axis: usize| open.iter().map(|l| l.maxs[axis]).max().unwrap_or(0) as f64;
+        let (x0, y0) = (min(0), min(1));
+        let extent = (max(0) - x0).max(max(1) - y0).max(1.0);
+
+        let scale = size as f64 / extent;
+        let width = (((max(0) - x0) * scale).ceil() as u32).clamp(1, size);
+        let height = (((max(1) - y0) * scale).ceil() as u32).clamp(1, size);
+
+        // 0 is the background, then hidden, visible and the source cluster
+        let mut priorities = vec![0u8; (width * height) as usize];
+        for leaf in open {
+            let leaf_cluster = leaf.cluster as usize;
+            let priority = if leaf_cluster == cluster {
+                3
+            } else if self.can_see(cluster, leaf_cluster) {
+                2
+            } else {
+                1
+            };
+
+            let column = |x: i16| (((x as f64 - x0) * scale) as u32).min(width - 1);
+            // Rows go down the image while y goes up the map
+            let row = |y: i16| (((y as f64 - y0) * scale) as u32).min(height - 1);
+            for y in row(leaf.mins[1])..=row(leaf.maxs[1]) {
+                let start = ((height - 1 - y) * width) as usize;
+                for x in column(leaf.mins[0])..=column(leaf.maxs[0]) {
+                    let pixel = &mut priorities[start + x as usize];
+                    *pixel = (*pixel).max(priority);
+                }
+            }
+        }
+
+        let colors = [[0; 3], HIDDEN_COLOR, VISIBLE_COLOR, SOURCE_COLOR];
+        let pixels = priorities.iter().map(|&p| colors[p as usize]).collect();
+        (width, height, pixels)
+    }
+
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let clusters = self.num_clusters();
+        writeln!(w, "Clusters: {clusters}")?;
+        if clusters == 0 {
+            return Ok(());
+        }
+
+        for (name, rows) in [("PVS", &self.pvs), ("PAS", &self.pas)] {
+            let visible: Vec<u32> = rows
+                .iter()
+                .map(|row| row.iter().map(|b| b.count_ones()).sum())
+                .collect();
+            let total: u64 = visible.iter().map(|&v| v as u64).sum();
+            let Some((worst, &max)) = visible
+                .iter()
+                .enumerate()
+                .max_by_key(|&(i, v)| (v, std::cmp::Reverse(i)))
+            else {
+                continue;
+            };
+
+            writeln!(
+                w,
+                "{name}: {:.1} visible clusters on average ({:.1}%), worst is cluster {worst} with {max}",
+                total as f64 / clusters as f64,
+                total as f64 * 100.0 / (clusters * clusters) as f64
+            )?;
+        }
+
+        writeln!(
+            w,
+            "Vis data: {} bytes compressed, {} bytes decompressed",
+            self.compressed_size,
+            self.decompressed_size()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two clusters, where 0 only sees itself and 1 sees both, and both hear each other
+    fn two_clusters() -> Vec<u8> {
+        let mut data = vec![];
+        for value in [2u32, 20, 21, 22, 21] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[0b01, 0b11, 0b11]);
+        data
+    }
+
+    fn leaf(cluster: i16, mins: [i16; 2], maxs: [i16; 2]) -> Leaf {
+        Leaf {
+            contents: 0,
+            cluster,
+            area_flags: 0,
+            mins: [mins[0], mins[1], 0],
+            maxs: [maxs[0], maxs[1], 0],
+            first_leaf_face: 0,
+            num_leaf_faces: 0,
+            first_leaf_brush: 0,
+            num_leaf_brushes: 0,
+            leaf_water_data_id: -1,
+        }
+    }
+
+    #[test]
+    fn zero_runs_are_cut_at_the_row_size() {
+        assert_eq!(decompress_row(&[7, 0, 200], 0, 3), Some(vec![7, 0, 0]));
+        assert_eq!(
+            decompress_row(&[9, 0, 2, 5, 0, 9], 1, 4),
+            Some(vec![0, 0, 5, 0])
+        );
+    }
+
+    #[test]
+    fn truncated_rows_are_refused() {
+        assert_eq!(decompress_row(&[1], 0, 2), None);
+        assert_eq!(decompress_row(&[1, 0], 0, 2), None);
+        assert_eq!(decompress_row(&[1, 2], 3, 1), None);
+    }
+
+    #[test]
+    fn clusters_see_what_their_row_says() {
+        let vis = Visibility::parse(&two_clusters()).unwrap();
+        assert_eq!(vis.num_clusters(), 2);
+        assert!(vis.can_see(0, 0));
+        assert!(!vis.can_see(0, 1));
+        assert!(vis.can_see(1, 0) && vis.can_see(1, 1));
+        assert!(!vis.can_see(2, 0) && !vis.can_see(0, 9));
+        assert_eq!(vis.pas, [[0b11], [0b11]]);
+
+        let mut data = two_clusters();
+        data.truncate(22);
+        assert!(Visibility::parse(&data).is_none());
+    }
+
+    #[test]
+    fn pvs_image_has_y_going_up() {
+        let vis = Visibility::parse(&two_clusters()).unwrap();
+        let leaves = [
+            leaf(0, [0, 0], [32, 32]),
+            leaf(1, [32, 32], [64, 64]),
+            // Solid leaves aren't drawn or counted in the bounds
+            leaf(-1, [-1000, -1000], [1000, 1000]),
+        ];
+        let (width, height, pixels) = vis.render_pvs(&leaves, 0, 4);
+        assert_eq!((width, height), (4, 4));
+
+        let (b, h, s) = ([0; 3], HIDDEN_COLOR, SOURCE_COLOR);
+        #[rustfmt::skip]
+        let expected = [
+            b, b, h, h,
+            s, s, s, h,
+            s, s, s, b,
+            s, s, s, b,
+        ];
+        assert_eq!(pixels, expected);
+
+        let (_, _, pixels) = vis.render_pvs(&leaves, 1, 4);
+        assert_eq!(pixels[4], VISIBLE_COLOR);
+        assert_eq!(pixels[2], SOURCE_COLOR);
+    }
+}
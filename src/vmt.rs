@@ -0,0 +1,177 @@
+//! Just enough of a KeyValues parser to pull texture and material references out of VMTs.
+
+use crate::assets::PathMatching;
+
+pub struct Vmt {
+    /// Every key in the material, including ones in nested blocks, with lowercase keys
+    pub params: Vec<(String, String)>,
+}
+
+/// Parameters whose value is a texture, relative to `materials/`
+const TEXTURE_PARAMS: &[&str] = &[
+    "$basetexture",
+    "$basetexture2",
+    "$basetexture3",
+    "$basetexture4",
+    "$bumpmap",
+    "$bumpmap2",
+    "$normalmap",
+    "$normalmap2",
+    "$detail",
+    "$detail2",
+    "$envmap",
+    "$envmapmask",
+    "$envmapmask2",
+    "$selfillummask",
+    "$selfillumtexture",
+    "$blendmodulatetexture",
+    "$phongexponenttexture",
+    "$phongwarptexture",
+    "$lightwarptexture",
+    "$dudvmap",
+    "$refracttexture",
+    "$reflecttexture",
+    "$flowmap",
+    "$flow_noise_texture",
+    "$tintmasktexture",
+    "$ambientoccltexture",
+    "$iris",
+    "$corneatexture",
+    "$texture2",
+    "$hdrbasetexture",
+    "$hdrcompressedtexture",
+];
+
+/// Parameters whose value is another material, relative to `materials/`
+const MATERIAL_PARAMS: &[&str] = &[
+    "$bottommaterial",
+    "$underwateroverlay",
+    "$crackmaterial",
+    "$fallbackmaterial",
+];
+
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = text;
+
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("//") {
+            rest = rest.find('\n').map_or("", |i| &rest[i..]);
+            continue;
+        }
+
+        let Some(c) = rest.chars().next() else {
+            break;
+        };
+
+        let len = match c {
+            '{' | '}' => 1,
+            '"' => {
+                let body = &rest[1..];
+                match body.find('"') {
+                    Some(end) => {
+                        tokens.push(&body[..end]);
+                        rest = &body[end + 1..];
+                    }
+                    None => {
+                        tokens.push(body);
+                        rest = "";
+                    }
+                }
+                continue;
+            }
+            _ => rest
+                .find(|c: char| c.is_whitespace() || matches!(c, '{' | '}' | '"'))
+                .unwrap_or(rest.len()),
+        };
+
+        tokens.push(&rest[..len]);
+        rest = &rest[len..];
+    }
+
+    tokens
+}
+
+fn parse_block(tokens: &[&str], i: &mut usize, params: &mut Vec<(String, String)>) {
+    while let Some(&key) = tokens.get(*i) {
+        *i += 1;
+        if key == "}" {
+            return;
+        }
+
+        match tokens.get(*i) {
+            Some(&"{") => {
+                *i += 1;
+                parse_block(tokens, i, params);
+            }
+            Some(value) => {
+                params.push((key.to_ascii_lowercase(), value.to_string()));
+                *i += 1;
+            }
+            None => return,
+        }
+    }
+}
+
+impl Vmt {
+    pub fn parse(text: &str) -> Option<Self> {
+        let tokens: Vec<&str> = tokenize(text.trim_start_matches('\u{feff}'))
+            .into_iter()
+            // Platform conditionals such as [$X360] apply to the preceding key
+            .filter(|t| !(t.starts_with('[') && t.ends_with(']')))
+            .collect();
+
+        // The first token is the shader name
+        let (_, body) = tokens.split_first()?;
+        if body.first() != Some(&"{") {
+            return None;
+        }
+
+        let mut params = vec![];
+        parse_block(&body[1..], &mut 0, &mut params);
+
+        Some(Self { params })
+    }
+
+    /// Textures referenced by the material, as `materials/....vtf` paths
+    pub fn textures(&self, matching: PathMatching) -> Vec<String> {
+        let mut textures = vec![];
+        for (key, value) in &self.params {
+            if !TEXTURE_PARAMS.contains(&key.as_str()) {
+                continue;
+            }
+
+            // Render targets and the cubemap placeholder aren't files
+            let lowercase = value.to_ascii_lowercase();
+            if lowercase.starts_with("_rt_") || lowercase == "env_cubemap" || value.is_empty() {
+                continue;
+            }
+
+            let path = matching.texture(value);
+            if !textures.contains(&path) {
+                textures.push(path);
+            }
+        }
+
+        textures
+    }
+
+    /// Other materials referenced by the material, as `materials/....vmt` paths
+    pub fn materials(&self, matching: PathMatching) -> Vec<String> {
+        let mut materials = vec![];
+        for (key, value) in &self.params {
+            let path = if key == "include" || MATERIAL_PARAMS.contains(&key.as_str()) {
+                matching.material(value)
+            } else {
+                continue;
+            };
+
+            if !materials.contains(&path) {
+                materials.push(path);
+            }
+        }
+
+        materials
+    }
+}
@@ -0,0 +1,222 @@
+//! Decodes the largest mipmap of a Valve texture (.vtf) to 8-bit RGB.
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Cursor;
+
+const TEXTUREFLAGS_ENVMAP: u32 = 0x4000;
+/// Resource tag of the high resolution image in 7.3+ files
+const HIGH_RES_RESOURCE: [u8; 3] = [0x30, 0, 0];
+/// Image format meaning there is no low resolution thumbnail
+const NO_FORMAT: u32 = 0xFFFF_FFFF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Rgba8888,
+    Abgr8888,
+    Rgb888,
+    Bgr888,
+    I8,
+    Ia88,
+    Argb8888,
+    Bgra8888,
+    Dxt1,
+    Dxt3,
+    Dxt5,
+    Bgrx8888,
+}
+
+impl Format {
+    fn from_id(id: u32) -> Option<Self> {
+        use Format::*;
+
+        Some(match id {
+            0 => Rgba8888,
+            1 => Abgr8888,
+            2 => Rgb888,
+            3 => Bgr888,
+            5 => I8,
+            6 => Ia88,
+            11 => Argb8888,
+            12 => Bgra8888,
+            // DXT1 with one bit alpha decodes the same way
+            13 | 20 => Dxt1,
+            14 => Dxt3,
+            15 => Dxt5,
+            16 => Bgrx8888,
+            _ => return None,
+        })
+    }
+
+    /// Bytes taken by one mip level of the given size
+    fn image_size(self, width: usize, height: usize) -> usize {
+        let blocks = width.div_ceil(4) * height.div_ceil(4);
+        match self {
+            Format::Dxt1 => blocks * 8,
+            Format::Dxt3 | Format::Dxt5 => blocks * 16,
+            Format::I8 => width * height,
+            Format::Ia88 => width * height * 2,
+            Format::Rgb888 | Format::Bgr888 => width * height * 3,
+            _ => width * height * 4,
+        }
+    }
+}
+
+/// Bytes taken by a low resolution thumbnail, which may have a format we can't decode
+fn thumbnail_size(id: u32, width: usize, height: usize) -> Option<usize> {
+    if id == NO_FORMAT {
+        return Some(0);
+    }
+
+    Some(Format::from_id(id)?.image_size(width, height))
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+    let r = (color >> 11) & 0x1f;
+    let g = (color >> 5) & 0x3f;
+    let b = color & 0x1f;
+    [
+        (r << 3 | r >> 2) as u8,
+        (g << 2 | g >> 4) as u8,
+        (b << 3 | b >> 2) as u8,
+    ]
+}
+
+fn lerp(a: [u8; 3], b: [u8; 3], num: u16, den: u16) -> [u8; 3] {
+    [0, 1, 2].map(|i| ((a[i] as u16 * (den - num) + b[i] as u16 * num) / den) as u8)
+}
+
+/// Decodes a DXT color block to its 16 pixels, in rows
+fn dxt_colors(block: &[u8], four_color: bool) -> [[u8; 3]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (p0, p1) = (rgb565(c0), rgb565(c1));
+
+    let palette = if c0 > c1 || four_color {
+        [p0, p1, lerp(p0, p1, 1, 3), lerp(p0, p1, 2, 3)]
+    } else {
+        // The fourth color is transparent, which becomes black without alpha
+        [p0, p1, lerp(p0, p1, 1, 2), [0; 3]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+fn decode_dxt(data: &[u8], format: Format, width: usize, height: usize) -> Vec<[u8; 3]> {
+    let block_size = format.image_size(1, 1);
+    let blocks_wide = width.div_ceil(4);
+    let mut pixels = vec![[0; 3]; width * height];
+
+    for (i, block) in data.chunks_exact(block_size).enumerate() {
+        // DXT3 and DXT5 blocks start with 8 bytes of alpha
+        let colors = dxt_colors(&block[block_size - 8..], format != Format::Dxt1);
+        let (bx, by) = (i % blocks_wide * 4, i / blocks_wide * 4);
+
+        for (j, color) in colors.into_iter().enumerate() {
+            let (x, y) = (bx + j % 4, by + j / 4);
+            if x < width && y < height {
+                pixels[y * width + x] = color;
+            }
+        }
+    }
+
+    pixels
+}
+
+fn decode_uncompressed(data: &[u8], format: Format) -> Vec<[u8; 3]> {
+    use Format::*;
+
+    let (stride, order): (usize, [usize; 3]) = match format {
+        Rgba8888 => (4, [0, 1, 2]),
+        Abgr8888 => (4, [3, 2, 1]),
+        Rgb888 => (3, [0, 1, 2]),
+        Bgr888 => (3, [2, 1, 0]),
+        I8 => (1, [0, 0, 0]),
+        Ia88 => (2, [0, 0, 0]),
+        Argb8888 => (4, [1, 2, 3]),
+        Bgra8888 | Bgrx8888 => (4, [2, 1, 0]),
+        Dxt1 | Dxt3 | Dxt5 => unreachable!(),
+    };
+
+    data.chunks_exact(stride)
+        .map(|pixel| order.map(|i| pixel[i]))
+        .collect()
+}
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// RGB pixels in rows from the top. Alpha is dropped.
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Decodes the first frame and face of the texture at full resolution.
+pub fn decode(data: &[u8]) -> Option<Image> {
+    if data.get(..4)? != b"VTF\0" {
+        return None;
+    }
+
+    let mut reader = Cursor::new(data);
+    reader.set_position(8);
+    let minor = reader.read_u32::<LittleEndian>().ok()?;
+    let header_size = reader.read_u32::<LittleEndian>().ok()? as usize;
+    let width = reader.read_u16::<LittleEndian>().ok()? as usize;
+    let height = reader.read_u16::<LittleEndian>().ok()? as usize;
+    let flags = reader.read_u32::<LittleEndian>().ok()?;
+    let frames = reader.read_u16::<LittleEndian>().ok()? as usize;
+    let first_frame = reader.read_u16::<LittleEndian>().ok()?;
+
+    reader.set_position(52);
+    let format = Format::from_id(reader.read_u32::<LittleEndian>().ok()?)?;
+    let mipmaps = reader.read_u8().ok()? as usize;
+    let thumbnail_format = reader.read_u32::<LittleEndian>().ok()?;
+    let thumbnail_width = reader.read_u8().ok()? as usize;
+    let thumbnail_height = reader.read_u8().ok()? as usize;
+    let depth = if minor >= 2 {
+        reader.read_u16::<LittleEndian>().ok()?.max(1) as usize
+    } else {
+        1
+    };
+
+    // Cubemaps before 7.5 carry a spheremap as a seventh face, unless first_frame says not
+    let faces = match flags & TEXTUREFLAGS_ENVMAP != 0 {
+        false => 1,
+        true if minor < 5 && first_frame != 0xFFFF => 7,
+        true => 6,
+    };
+
+    let image_start = if minor >= 3 {
+        reader.set_position(68);
+        let resources = reader.read_u32::<LittleEndian>().ok()?;
+
+        reader.set_position(80);
+        (0..resources).find_map(|_| {
+            let mut tag = [0; 4];
+            std::io::Read::read_exact(&mut reader, &mut tag).ok()?;
+            let ofs = reader.read_u32::<LittleEndian>().ok()?;
+            (tag[..3] == HIGH_RES_RESOURCE).then_some(ofs as usize)
+        })?
+    } else {
+        header_size + thumbnail_size(thumbnail_format, thumbnail_width, thumbnail_height)?
+    };
+
+    // Mipmaps are stored smallest first, each holding every frame, face and slice
+    let copies = frames.max(1) * faces * depth;
+    let smaller: usize = (1..mipmaps.max(1))
+        .map(|mip| format.image_size((width >> mip).max(1), (height >> mip).max(1)) * copies)
+        .sum();
+
+    let start = image_start + smaller;
+    let data = data.get(start..start + format.image_size(width, height))?;
+
+    let pixels = match format {
+        Format::Dxt1 | Format::Dxt3 | Format::Dxt5 => decode_dxt(data, format, width, height),
+        _ => decode_uncompressed(data, format),
+    };
+
+    Some(Image {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
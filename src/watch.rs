@@ -0,0 +1,253 @@
+//! Waiting for a map to be rewritten, as the compile tools do after each pass, and comparing the
+//! reports of one compile with the next.
+//!
+//! Linux is told about changes by inotify. Other platforms check the file's size and modified
+//! time twice a second.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long the file has to stay the same before it's taken to be written, since the tools
+/// write maps in several steps
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+#[cfg(not(target_os = "linux"))]
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Largest number of line pairs compared to find the smallest diff. Longer reports have
+/// everything between their common start and end listed as changed.
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// What identifies a version of the file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some(Stamp {
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+    })
+}
+
+pub struct Watcher {
+    path: PathBuf,
+    last: Option<Stamp>,
+    #[cfg(target_os = "linux")]
+    inotify: Inotify,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            last: stamp(path),
+            #[cfg(target_os = "linux")]
+            inotify: Inotify::new(path)?,
+        })
+    }
+
+    /// Blocks until the file has been written again and has stopped changing
+    pub fn wait(&mut self) -> io::Result<()> {
+        loop {
+            self.wait_for_event()?;
+
+            let mut current = stamp(&self.path);
+            loop {
+                std::thread::sleep(SETTLE_TIME);
+                let next = stamp(&self.path);
+                if next == current {
+                    break;
+                }
+                current = next;
+            }
+
+            // Removed files are waited on until they're written again
+            if current.is_some() && current != self.last {
+                self.last = current;
+                return Ok(());
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn wait_for_event(&mut self) -> io::Result<()> {
+        self.inotify.wait()
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn wait_for_event(&mut self) -> io::Result<()> {
+        while stamp(&self.path) == self.last {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+}
+
+/// Watches the directory holding the map rather than the map itself, since tools replacing the
+/// file give it a new inode the old watch doesn't follow
+#[cfg(target_os = "linux")]
+struct Inotify {
+    fd: libc::c_int,
+    name: std::ffi::OsString,
+}
+
+#[cfg(target_os = "linux")]
+impl Inotify {
+    fn new(path: &Path) -> io::Result<Self> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file"))?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut dir = dir.as_os_str().as_bytes().to_vec();
+        dir.push(0);
+
+        // Safety: plain system calls, with a nul terminated path that outlives the call
+        unsafe {
+            let fd = libc::inotify_init1(libc::IN_CLOEXEC);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_CREATE;
+            if libc::inotify_add_watch(fd, dir.as_ptr().cast(), mask) < 0 {
+                let error = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(error);
+            }
+
+            Ok(Self {
+                fd,
+                name: name.to_os_string(),
+            })
+        }
+    }
+
+    /// Blocks until an event names the map
+    fn wait(&self) -> io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+
+        const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+        let mut buffer = [0u8; 4096];
+        loop {
+            // Safety: the buffer is valid for its whole length
+            let len = unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+            if len < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+
+            // Each event is its header, then a nul padded name of the length the header gives
+            let events = &buffer[..len as usize];
+            let mut offset = 0;
+            while offset + HEADER <= events.len() {
+                let name_len =
+                    u32::from_ne_bytes(events[offset + 12..offset + 16].try_into().unwrap());
+                let name = events
+                    .get(offset + HEADER..offset + HEADER + name_len as usize)
+                    .unwrap_or_default();
+                let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+                if name == self.name.as_bytes() {
+                    return Ok(());
+                }
+                offset += HEADER + name_len as usize;
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        // Safety: the descriptor is owned by this and closed once
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Change<'a> {
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The lines removed from `old` and added in `new`, in order
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    if old.len().saturating_mul(new.len()) > MAX_DIFF_CELLS {
+        let removed = old.iter().map(|line| Change::Removed(line));
+        return removed
+            .chain(new.iter().map(|line| Change::Added(line)))
+            .collect();
+    }
+
+    // Lengths of the longest common subsequences of every pair of suffixes
+    let width = new.len() + 1;
+    let mut lengths = vec![0u32; (old.len() + 1) * width];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j == new.len()
+            || (i < old.len() && lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+        {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    changes
+}
+
+pub fn print_diff<W: Write>(w: &mut W, changes: &[Change]) -> io::Result<()> {
+    if changes.is_empty() {
+        return writeln!(w, "  (no changes)");
+    }
+    for change in changes {
+        match change {
+            Change::Removed(line) => writeln!(w, "- {line}")?,
+            Change::Added(line) => writeln!(w, "+ {line}")?,
+        }
+    }
+    Ok(())
+}
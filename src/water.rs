@@ -0,0 +1,147 @@
+//! The bodies of water vbsp records in LEAF_WATER_DATA, from which the engine draws water fog,
+//! the surface seen from below and the physics of swimming.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, Leaf, LeafWaterData, World, CONTENTS_SLIME, CONTENTS_WATER};
+
+const CONTENTS_LIQUID: i32 = CONTENTS_WATER | CONTENTS_SLIME;
+
+pub struct Volume {
+    pub data: LeafWaterData,
+    pub material: Option<String>,
+    /// Leaves referring to the volume
+    pub leaves: usize,
+}
+
+pub struct Report {
+    pub volumes: Vec<Volume>,
+    /// Leaves with water or slime contents
+    pub underwater: usize,
+    /// Liquid leaves without water data, which have no fog or physics
+    pub missing_data: Vec<usize>,
+    /// Leaves referring to water data past the end of the lump
+    pub invalid: Vec<usize>,
+    /// Distances to water in LEAF_MIN_DIST_TO_WATER, if the map has them
+    pub distances: Option<Vec<u16>>,
+    pub leaf_count: usize,
+}
+
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Report> {
+    let data: Vec<LeafWaterData> = lumps::read_array(bsp, LumpType::LEAF_WATER_DATA)?;
+    let leaves: Vec<Leaf> = lumps::read_leaves(bsp)?;
+    let world = World::read(bsp)?;
+    let distances = match bsp.lump_info(LumpType::LEAF_MIN_DIST_TO_WATER).filelen {
+        0 => None,
+        _ => Some(lumps::read_array(bsp, LumpType::LEAF_MIN_DIST_TO_WATER)?),
+    };
+
+    let mut volumes: Vec<Volume> = data
+        .into_iter()
+        .map(|data| Volume {
+            data,
+            material: world.texinfo_name(data.surface_texinfo).map(str::to_string),
+            leaves: 0,
+        })
+        .collect();
+
+    let (mut underwater, mut missing_data, mut invalid) = (0, vec![], vec![]);
+    for (i, leaf) in leaves.iter().enumerate() {
+        let liquid = leaf.contents & CONTENTS_LIQUID != 0;
+        underwater += liquid as usize;
+        match usize::try_from(leaf.leaf_water_data_id) {
+            Ok(id) => match volumes.get_mut(id) {
+                Some(volume) => volume.leaves += 1,
+                None => invalid.push(i),
+            },
+            Err(_) if liquid => missing_data.push(i),
+            Err(_) => {}
+        }
+    }
+
+    Some(Report {
+        volumes,
+        underwater,
+        missing_data,
+        invalid,
+        distances,
+        leaf_count: leaves.len(),
+    })
+}
+
+impl Report {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "Water volumes: {}", self.volumes.len())?;
+        if !self.volumes.is_empty() {
+            writeln!(
+                w,
+                "  {:>5} {:>10} {:>10} {:>6}  material",
+                "index", "surface z", "bottom z", "leaves"
+            )?;
+        }
+        for (i, volume) in self.volumes.iter().enumerate() {
+            writeln!(
+                w,
+                "  {i:>5} {:>10} {:>10} {:>6}  {}",
+                volume.data.surface_z,
+                volume.data.min_z,
+                volume.leaves,
+                volume.material.as_deref().unwrap_or("<none>")
+            )?;
+        }
+        writeln!(w, "Leaves underwater: {}", self.underwater)?;
+
+        if let Some(distances) = &self.distances {
+            let near = distances.iter().filter(|&&d| d == 0).count();
+            writeln!(
+                w,
+                "Leaves with distances to water: {} ({near} touching water)",
+                distances.len()
+            )?;
+        }
+
+        for i in &self.invalid {
+            writeln!(
+                w,
+                "error: leaf {i} refers to water data outside the LEAF_WATER_DATA lump"
+            )?;
+        }
+        for (i, volume) in self.volumes.iter().enumerate() {
+            if volume.material.is_none() {
+                writeln!(
+                    w,
+                    "error: water volume {i} has no surface material, so its surface isn't drawn from below"
+                )?;
+            }
+            if volume.leaves == 0 {
+                writeln!(w, "warning: no leaf is in water volume {i}")?;
+            }
+        }
+        if !self.missing_data.is_empty() {
+            writeln!(
+                w,
+                "warning: {} water leaves have no water data, so they have no fog, surface from below or swimming, as when a water brush has no water material on top",
+                self.missing_data.len()
+            )?;
+        }
+        if let Some(distances) = self
+            .distances
+            .as_ref()
+            .filter(|d| d.len() != self.leaf_count)
+        {
+            writeln!(
+                w,
+                "warning: LEAF_MIN_DIST_TO_WATER has {} entries for {} leaves",
+                distances.len(),
+                self.leaf_count
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.invalid.is_empty() || self.volumes.iter().any(|v| v.material.is_none())
+    }
+}
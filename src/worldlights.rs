@@ -0,0 +1,166 @@
+//! Listing of the lights vrad stored in the map, which the engine uses to light models.
+
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, LumpType};
+use crate::lumps::{self, TexInfo, WorldLight};
+
+/// Names of the `emittype_t` values
+const EMIT_TYPES: &[&str] = &[
+    "surface",
+    "point",
+    "spot",
+    "skylight",
+    "quakelight",
+    "skyambient",
+];
+const EMIT_SURFACE: i32 = 0;
+const EMIT_SPOTLIGHT: i32 = 2;
+
+pub fn emit_type_name(emit_type: i32) -> &'static str {
+    usize::try_from(emit_type)
+        .ok()
+        .and_then(|i| EMIT_TYPES.get(i))
+        .copied()
+        .unwrap_or("unknown")
+}
+
+pub struct Light {
+    pub light: WorldLight,
+    /// Material of the emitting face, for surface lights
+    pub texture: Option<String>,
+}
+
+impl Light {
+    pub fn brightness(&self) -> f32 {
+        self.light
+            .intensity
+            .iter()
+            .map(|c| c * c)
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+pub struct Lights {
+    pub ldr: Vec<Light>,
+    pub hdr: Vec<Light>,
+}
+
+fn resolve(lights: Vec<WorldLight>, texinfo: &[TexInfo], names: &[String]) -> Vec<Light> {
+    lights
+        .into_iter()
+        .map(|light| {
+            let texture = (light.emit_type == EMIT_SURFACE)
+                .then(|| {
+                    let info = texinfo.get(usize::try_from(light.texinfo).ok()?)?;
+                    names.get(usize::try_from(info.texdata).ok()?).cloned()
+                })
+                .flatten();
+            Light { light, texture }
+        })
+        .collect()
+}
+
+pub fn read<R: Read + Seek>(bsp: &mut BspFile<R>) -> Option<Lights> {
+    let ldr = lumps::read_world_lights(bsp, LumpType::WORLD_LIGHTS)?;
+    let hdr = lumps::read_world_lights(bsp, LumpType::WORLD_LIGHTS_HDR)?;
+    let texinfo: Vec<TexInfo> = lumps::read_array(bsp, LumpType::TEXTURE_INFO)?;
+    let names = lumps::texture_names(bsp)?;
+
+    Some(Lights {
+        ldr: resolve(ldr, &texinfo, &names),
+        hdr: resolve(hdr, &texinfo, &names),
+    })
+}
+
+fn print_lights<W: Write>(lights: &[Light], w: &mut W) -> io::Result<()> {
+    for (i, light) in lights.iter().enumerate() {
+        let l = &light.light;
+        let [x, y, z] = l.origin;
+        let [r, g, b] = l.intensity;
+        write!(
+            w,
+            "{i:5} {:<10} origin = ({x}, {y}, {z}) intensity = ({r}, {g}, {b})",
+            emit_type_name(l.emit_type)
+        )?;
+
+        if l.style != 0 {
+            write!(w, " style = {}", l.style)?;
+        }
+        if l.emit_type == EMIT_SPOTLIGHT {
+            let [inner, outer] = [l.stopdot, l.stopdot2].map(|dot| dot.clamp(-1.0, 1.0).acos());
+            write!(
+                w,
+                " cone = {:.1}..{:.1} exponent = {}",
+                inner.to_degrees(),
+                outer.to_degrees(),
+                l.exponent
+            )?;
+        }
+        if l.emit_type == EMIT_SURFACE {
+            write!(
+                w,
+                " texture = {}",
+                light.texture.as_deref().unwrap_or("<invalid texinfo>")
+            )?;
+        }
+        if l.radius != 0.0 {
+            write!(w, " radius = {}", l.radius)?;
+        }
+        writeln!(w)?;
+    }
+
+    let mut counts = vec![];
+    for name in EMIT_TYPES {
+        let count = lights
+            .iter()
+            .filter(|l| emit_type_name(l.light.emit_type) == *name)
+            .count();
+        if count != 0 {
+            counts.push(format!("{count} {name}"));
+        }
+    }
+    if !counts.is_empty() {
+        writeln!(w, "  {}", counts.join(", "))?;
+    }
+
+    let brightest = lights
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.brightness().total_cmp(&b.1.brightness()));
+    if let Some((i, light)) = brightest {
+        writeln!(
+            w,
+            "  Brightest: light {i} ({}) with intensity {:.1}",
+            emit_type_name(light.light.emit_type),
+            light.brightness()
+        )?;
+    }
+
+    Ok(())
+}
+
+impl Lights {
+    pub fn print<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "World lights: {}", self.ldr.len())?;
+        print_lights(&self.ldr, w)?;
+        writeln!(w, "HDR world lights: {}", self.hdr.len())?;
+        print_lights(&self.hdr, w)?;
+
+        for (hdr, lights) in [(false, &self.ldr), (true, &self.hdr)] {
+            for (i, light) in lights.iter().enumerate() {
+                if emit_type_name(light.light.emit_type) == "unknown" {
+                    writeln!(
+                        w,
+                        "error: {}light {i} has unknown type {}",
+                        if hdr { "HDR " } else { "" },
+                        light.light.emit_type
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+//! Rewriting VBSP files with replaced lumps.
+//!
+//! [`LumpWriter`] starts from an existing map and only changes what it's told to. The files it
+//! writes keep these invariants:
+//!
+//! - The header's version and map revision are the original map's.
+//! - Lumps are written in the order they appeared in the original file, except those moved by
+//!   [`LumpWriter::append_lump`], which follow all the others in the order they were appended.
+//! - Every non-empty lump starts on a 4 byte boundary, with zero padding before it. Empty lumps
+//!   have an offset and length of 0.
+//! - Lumps that weren't replaced are copied byte for byte, keeping any LZMA compression. Lumps
+//!   replaced with [`LumpWriter::replace_lump`] are written uncompressed.
+//! - The game lump directory's absolute offsets are adjusted to wherever the game lump ends up.
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::{self, Read, Seek, Write};
+
+use crate::bsp::{BspFile, BspFormat, LumpInfo, LumpType, HEADER_LUMPS};
+use crate::gamelump;
+
+const IDENT: u32 = u32::from_le_bytes(*b"VBSP");
+const HEADER_SIZE: usize = 4 + 4 + HEADER_LUMPS * 16 + 4;
+
+#[derive(Default, Clone)]
+struct Lump {
+    /// Lump contents as they will be written to the file
+    data: Vec<u8>,
+    version: u32,
+    uncompressed_size: u32,
+    /// Offset the contents were read from, used to relocate the game lump directory
+    origin_ofs: u32,
+}
+
+/// Rebuilds a BSP file, keeping lumps in the order they appeared in the original file.
+pub struct LumpWriter {
+    version: u32,
+    map_revision: u32,
+    lumps: Vec<Lump>,
+    order: Vec<usize>,
+}
+
+impl LumpWriter {
+    /// Starts from a copy of every lump in `bsp`. Only VBSP files are supported, and a lump
+    /// that can't be read under the map's policy, such as one past the end of the file, is an
+    /// error rather than left out of the copy.
+    pub fn from_bsp<R: Read + Seek>(bsp: &mut BspFile<R>) -> io::Result<Self> {
+        if bsp.format() != BspFormat::Valve {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only VBSP files can be rewritten",
+            ));
+        }
+
+        let mut lumps = vec![Lump::default(); HEADER_LUMPS];
+        let mut order: Vec<usize> = (0..HEADER_LUMPS).collect();
+
+        for (i, lump) in lumps.iter_mut().enumerate() {
+            let ty = LumpType::try_from(i as u32).unwrap();
+            let info = *bsp.lump_info(ty);
+
+            let data = if bsp.has_lump(i) {
+                bsp.get_raw_lump(ty)
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("the {ty:?} lump couldn't be read"),
+                        )
+                    })?
+                    .into_owned()
+            } else {
+                vec![]
+            };
+
+            *lump = Lump {
+                data,
+                version: info.version,
+                uncompressed_size: info.uncompressed_size,
+                origin_ofs: info.fileofs,
+            };
+        }
+
+        order.sort_by_key(|&i| lumps[i].origin_ofs);
+
+        Ok(Self {
+            version: bsp.version(),
+            map_revision: bsp.map_revision(),
+            lumps,
+            order,
+        })
+    }
+
+    /// Replaces a lump with uncompressed data, leaving it at its current place in the order.
+    /// Replacement game lumps must use offsets relative to the start of the lump, as produced by
+    /// [`gamelump::serialize`].
+    pub fn replace_lump(&mut self, lump: LumpType, data: Vec<u8>) {
+        let lump = &mut self.lumps[lump as usize];
+
+        lump.data = data;
+        lump.uncompressed_size = 0;
+        lump.origin_ofs = 0;
+    }
+
+    /// Replaces a lump with raw contents as read from a file, keeping its compression. `info`
+    /// is the lump's original directory entry.
+    pub fn replace_raw_lump(&mut self, lump: LumpType, info: LumpInfo, data: Vec<u8>) {
+        self.lumps[lump as usize] = Lump {
+            data,
+            version: info.version,
+            uncompressed_size: info.uncompressed_size,
+            origin_ofs: info.fileofs,
+        };
+    }
+
+    /// Replaces a lump like [`replace_lump`](Self::replace_lump) and moves it after all the
+    /// others, so changing its size leaves every other lump at its original offset. Used for
+    /// the pakfile, which vbsp also writes last.
+    pub fn append_lump(&mut self, lump: LumpType, data: Vec<u8>) {
+        self.replace_lump(lump, data);
+        self.order.retain(|&i| i != lump as usize);
+        self.order.push(lump as usize);
+    }
+
+    /// Drops a lump entirely, leaving a directory entry of all zeroes.
+    pub fn remove_lump(&mut self, lump: LumpType) {
+        self.lumps[lump as usize] = Lump::default();
+    }
+
+    /// Offsets each lump will be written at, or 0 for empty lumps, and the total file size.
+    pub fn layout(&self) -> ([u32; HEADER_LUMPS], usize) {
+        let mut offsets = [0u32; HEADER_LUMPS];
+        let mut pos = HEADER_SIZE;
+        for &i in &self.order {
+            if self.lumps[i].data.is_empty() {
+                continue;
+            }
+
+            pos = pos.next_multiple_of(4);
+            offsets[i] = pos as u32;
+            pos += self.lumps[i].data.len();
+        }
+
+        (offsets, pos)
+    }
+
+    /// Length the lump will have in the written file
+    pub fn lump_len(&self, lump: LumpType) -> usize {
+        self.lumps[lump as usize].data.len()
+    }
+
+    /// Writes the whole file. Fails if a game lump that has to be relocated is malformed.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let (offsets, _) = self.layout();
+
+        w.write_u32::<LittleEndian>(IDENT)?;
+        w.write_u32::<LittleEndian>(self.version)?;
+        for (lump, ofs) in self.lumps.iter().zip(offsets) {
+            w.write_u32::<LittleEndian>(ofs)?;
+            w.write_u32::<LittleEndian>(lump.data.len() as u32)?;
+            w.write_u32::<LittleEndian>(lump.version)?;
+            w.write_u32::<LittleEndian>(lump.uncompressed_size)?;
+        }
+        w.write_u32::<LittleEndian>(self.map_revision)?;
+
+        let mut pos = HEADER_SIZE;
+        for &i in &self.order {
+            let lump = &self.lumps[i];
+            if lump.data.is_empty() {
+                continue;
+            }
+
+            let padding = offsets[i] as usize - pos;
+            w.write_all(&vec![0; padding])?;
+
+            if i == LumpType::GAME_LUMP as usize && lump.uncompressed_size == 0 {
+                let mut data = lump.data.clone();
+                gamelump::relocate(&mut data, offsets[i] as i64 - lump.origin_ofs as i64)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                w.write_all(&data)?;
+            } else {
+                w.write_all(&lump.data)?;
+            }
+
+            pos = offsets[i] as usize + lump.data.len();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::io::Cursor;
+
+    /// A VBSP header with every lump empty
+    fn empty_map() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(b"VBSP");
+        data.extend_from_slice(&20u32.to_le_bytes());
+        data.resize(HEADER_SIZE - 4, 0);
+        data.extend_from_slice(&7u32.to_le_bytes());
+        data
+    }
+
+    fn rewrite(data: &[u8], edit: impl FnOnce(&mut LumpWriter)) -> Vec<u8> {
+        let mut reader = Cursor::new(data.to_vec());
+        let mut bsp = BspFile::new(&mut reader).unwrap();
+        let mut writer = LumpWriter::from_bsp(&mut bsp).unwrap();
+        edit(&mut writer);
+
+        let mut out = vec![];
+        writer.write_to(&mut out).unwrap();
+        out
+    }
+
+    fn infos(data: &[u8]) -> Vec<LumpInfo> {
+        let mut reader = Cursor::new(data.to_vec());
+        let bsp = BspFile::new(&mut reader).unwrap();
+        (0..HEADER_LUMPS)
+            .map(|i| *bsp.lump_info_by_index(i))
+            .collect()
+    }
+
+    fn lump(data: &[u8], lump: LumpType) -> Option<Vec<u8>> {
+        let mut reader = Cursor::new(data);
+        let lump = BspFile::from_slice(&mut reader).unwrap().get_lump(lump);
+        lump.map(Cow::into_owned)
+    }
+
+    /// A map with an entity, plane and game lump, in that order
+    fn sample_map() -> Vec<u8> {
+        let game_lump = gamelump::serialize(&[gamelump::GameLump {
+            id: gamelump::STATIC_PROPS,
+            flags: 0,
+            version: 10,
+            data: b"props".to_vec(),
+        }])
+        .unwrap();
+
+        rewrite(&empty_map(), |writer| {
+            writer.replace_lump(LumpType::ENTITIES, b"{}\0".to_vec());
+            writer.replace_lump(LumpType::PLANES, vec![1; 20]);
+            writer.replace_lump(LumpType::GAME_LUMP, game_lump);
+        })
+    }
+
+    #[test]
+    fn unchanged_map_is_identical() {
+        let map = sample_map();
+        assert_eq!(rewrite(&map, |_| {}), map);
+    }
+
+    #[test]
+    fn unreadable_lumps_are_an_error() {
+        let mut map = sample_map();
+        map.pop();
+
+        let mut reader = Cursor::new(map);
+        let mut bsp = BspFile::new(&mut reader).unwrap();
+        let error = LumpWriter::from_bsp(&mut bsp).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn header_is_preserved() {
+        let out = rewrite(&sample_map(), |_| {});
+        let mut reader = Cursor::new(out);
+        let bsp = BspFile::new(&mut reader).unwrap();
+
+        assert_eq!(bsp.version(), 20);
+        assert_eq!(bsp.map_revision(), 7);
+    }
+
+    #[test]
+    fn lumps_are_aligned() {
+        let out = rewrite(&sample_map(), |writer| {
+            writer.replace_lump(LumpType::ENTITIES, b"{ \"a\" \"b\" }\0".to_vec());
+        });
+
+        for info in infos(&out) {
+            if info.filelen == 0 {
+                assert_eq!(info.fileofs, 0);
+            } else {
+                assert_eq!(info.fileofs % 4, 0);
+            }
+        }
+        // Padding only goes between lumps, never after the last one
+        let game = infos(&out)[LumpType::GAME_LUMP as usize];
+        assert_eq!(out.len(), (game.fileofs + game.filelen) as usize);
+    }
+
+    #[test]
+    fn replace_lump_keeps_order_and_moves_later_lumps() {
+        let map = sample_map();
+        let out = rewrite(&map, |writer| {
+            writer.replace_lump(LumpType::ENTITIES, vec![b'x'; 100]);
+        });
+
+        let (before, after) = (infos(&map), infos(&out));
+        let [entities, planes, game] =
+            [LumpType::ENTITIES, LumpType::PLANES, LumpType::GAME_LUMP].map(|l| l as usize);
+
+        assert_eq!(after[entities].fileofs, before[entities].fileofs);
+        assert!(after[planes].fileofs > before[planes].fileofs);
+        assert!(after[game].fileofs > after[planes].fileofs);
+        assert_eq!(lump(&out, LumpType::ENTITIES).unwrap(), vec![b'x'; 100]);
+        assert_eq!(lump(&out, LumpType::PLANES).unwrap(), vec![1; 20]);
+    }
+
+    #[test]
+    fn append_lump_leaves_other_offsets_alone() {
+        let map = sample_map();
+        let out = rewrite(&map, |writer| {
+            writer.append_lump(LumpType::PAKFILE, vec![b'y'; 1000]);
+        });
+
+        let (before, after) = (infos(&map), infos(&out));
+        for lump in [LumpType::ENTITIES, LumpType::PLANES, LumpType::GAME_LUMP] {
+            assert_eq!(after[lump as usize].fileofs, before[lump as usize].fileofs);
+        }
+
+        let pakfile = after[LumpType::PAKFILE as usize];
+        assert!(pakfile.fileofs > before[LumpType::GAME_LUMP as usize].fileofs);
+        assert_eq!(pakfile.fileofs as usize + 1000, out.len());
+    }
+
+    #[test]
+    fn append_lump_moves_an_existing_lump_last() {
+        let out = rewrite(&sample_map(), |writer| {
+            writer.append_lump(LumpType::ENTITIES, b"{}\0".to_vec());
+        });
+
+        let after = infos(&out);
+        let entities = after[LumpType::ENTITIES as usize];
+        assert!(entities.fileofs > after[LumpType::GAME_LUMP as usize].fileofs);
+        assert_eq!(entities.fileofs as usize + 3, out.len());
+        assert_eq!(lump(&out, LumpType::ENTITIES).unwrap(), b"{}\0");
+    }
+
+    #[test]
+    fn game_lump_offsets_follow_the_lump() {
+        let out = rewrite(&sample_map(), |writer| {
+            writer.replace_lump(LumpType::ENTITIES, vec![b'z'; 333]);
+        });
+
+        let mut reader = Cursor::new(out);
+        let mut bsp = BspFile::new(&mut reader).unwrap();
+        let lumps = gamelump::read(&mut bsp).unwrap();
+
+        assert_eq!(lumps.len(), 1);
+        assert_eq!(lumps[0].id, gamelump::STATIC_PROPS);
+        assert_eq!(lumps[0].data, b"props");
+    }
+
+    #[test]
+    fn emptied_lumps_take_no_space() {
+        let map = sample_map();
+        let out = rewrite(&map, |writer| writer.replace_lump(LumpType::PLANES, vec![]));
+
+        let info = infos(&out)[LumpType::PLANES as usize];
+        assert_eq!((info.fileofs, info.filelen), (0, 0));
+        assert_eq!(out.len(), map.len() - 20);
+    }
+
+    #[test]
+    fn removed_lumps_have_an_empty_entry() {
+        let map = rewrite(&sample_map(), |writer| {
+            let info = LumpInfo {
+                fileofs: 0,
+                filelen: 0,
+                version: 1,
+                uncompressed_size: 0,
+            };
+            writer.replace_raw_lump(LumpType::PLANES, info, vec![2; 8]);
+        });
+        let out = rewrite(&map, |writer| writer.remove_lump(LumpType::PLANES));
+
+        let info = infos(&out)[LumpType::PLANES as usize];
+        assert_eq!(
+            (
+                info.fileofs,
+                info.filelen,
+                info.version,
+                info.uncompressed_size
+            ),
+            (0, 0, 0, 0)
+        );
+        assert_eq!(out.len(), map.len() - 8);
+        assert_eq!(
+            lump(&out, LumpType::ENTITIES),
+            lump(&map, LumpType::ENTITIES)
+        );
+    }
+
+    #[test]
+    fn replaced_lumps_are_uncompressed() {
+        let map = rewrite(&sample_map(), |writer| {
+            let info = LumpInfo {
+                fileofs: 0,
+                filelen: 0,
+                version: 1,
+                uncompressed_size: 1234,
+            };
+            writer.replace_raw_lump(LumpType::PLANES, info, vec![2; 8]);
+        });
+        assert_eq!(
+            infos(&map)[LumpType::PLANES as usize].uncompressed_size,
+            1234
+        );
+        assert_eq!(infos(&map)[LumpType::PLANES as usize].version, 1);
+
+        let out = rewrite(&map, |writer| {
+            writer.replace_lump(LumpType::PLANES, vec![3; 8])
+        });
+        assert_eq!(infos(&out)[LumpType::PLANES as usize].uncompressed_size, 0);
+    }
+}
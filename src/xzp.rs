@@ -0,0 +1,90 @@
+//! Reader for the XZP archives console builds pack into XZIP_PAKFILE instead of a zip. Entries
+//! are stored uncompressed and found by the CRC of their name, with the names themselves kept
+//! in an optional table.
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::collections::HashMap;
+
+const MAGIC: &[u8; 4] = b"xZip";
+/// The magic as written by little endian builds
+const MAGIC_LE: &[u8; 4] = b"piZx";
+const VERSION: u32 = 6;
+const HEADER_SIZE: usize = 36;
+const ENTRY_SIZE: usize = 12;
+
+pub struct Entry {
+    /// Name of the file, or its name CRC in hex if the archive has no name table
+    pub name: String,
+    pub name_crc: u32,
+    pub offset: u32,
+    pub length: u32,
+}
+
+pub struct Xzp {
+    data: Vec<u8>,
+    pub entries: Vec<Entry>,
+}
+
+/// Reads the `N` u32s at `ofs`, in the byte order of the archive
+fn read_u32s<const N: usize>(data: &[u8], ofs: usize, big_endian: bool) -> Option<[u32; N]> {
+    let bytes = data.get(ofs..ofs + N * 4)?;
+    Some(std::array::from_fn(|i| {
+        let word = &bytes[i * 4..i * 4 + 4];
+        match big_endian {
+            true => BigEndian::read_u32(word),
+            false => LittleEndian::read_u32(word),
+        }
+    }))
+}
+
+impl Xzp {
+    pub fn is_xzp(data: &[u8]) -> bool {
+        data.starts_with(MAGIC) || data.starts_with(MAGIC_LE)
+    }
+
+    pub fn parse(data: Vec<u8>) -> Option<Self> {
+        let big_endian = data.starts_with(MAGIC);
+        if !Self::is_xzp(&data) {
+            return None;
+        }
+
+        let [_, version, _, entry_count, _, _, name_count, names_ofs, _] =
+            read_u32s::<9>(&data, 0, big_endian)?;
+        if version != VERSION {
+            return None;
+        }
+
+        let mut names = HashMap::new();
+        for i in 0..name_count as usize {
+            let [crc, name_ofs, _] =
+                read_u32s::<3>(&data, names_ofs as usize + i * ENTRY_SIZE, big_endian)?;
+            let name = data.get(name_ofs as usize..)?;
+            let end = name.iter().position(|&c| c == 0)?;
+            names.insert(crc, String::from_utf8_lossy(&name[..end]).into_owned());
+        }
+
+        // The preload directory after this one only duplicates some of its entries
+        let entries = (0..entry_count as usize)
+            .map(|i| {
+                let [name_crc, length, offset] =
+                    read_u32s::<3>(&data, HEADER_SIZE + i * ENTRY_SIZE, big_endian)?;
+                Some(Entry {
+                    name: names
+                        .get(&name_crc)
+                        .cloned()
+                        .unwrap_or_else(|| format!("{name_crc:08x}")),
+                    name_crc,
+                    offset,
+                    length,
+                })
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self { data, entries })
+    }
+
+    pub fn contents(&self, entry: &Entry) -> Option<&[u8]> {
+        let start = entry.offset as usize;
+        self.data.get(start..start + entry.length as usize)
+    }
+}
@@ -0,0 +1,278 @@
+//! Reader for the block style subset of YAML people write configuration files in: nested
+//! mappings and sequences, plain or quoted scalars, flow sequences of scalars and comments.
+//! Anchors, multi-line scalars and flow mappings aren't supported. Documents are read into
+//! [`Json`] values, like JSON configuration files.
+
+use crate::json::{Json, ParseError};
+
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// Cuts a comment off the end of a line, leaving `#` inside quotes alone
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if previous.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+fn lines(text: &str) -> Vec<Line<'_>> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = strip_comment(line).trim_end();
+            let text = line.trim_start();
+            (!text.is_empty() && text != "---").then(|| Line {
+                number: i + 1,
+                indent: line.len() - text.len(),
+                text,
+            })
+        })
+        .collect()
+}
+
+fn error(line: usize, message: impl Into<String>) -> ParseError {
+    ParseError {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Splits `key: value` at its colon, which needs to be followed by whitespace or end the line
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['"', '\'']) {
+        let quote = text.chars().next()?;
+        let end = text[1..].find(quote)? + 2;
+        let rest = text[end..].trim_start().strip_prefix(':')?;
+        return (rest.is_empty() || rest.starts_with(' ')).then(|| (&text[..end], rest.trim()));
+    }
+
+    let colon = text
+        .match_indices(':')
+        .map(|(i, _)| i)
+        .find(|&i| text[i + 1..].is_empty() || text[i + 1..].starts_with(' '))?;
+    Some((text[..colon].trim_end(), text[colon + 1..].trim()))
+}
+
+fn scalar(text: &str, line: usize) -> Result<Json, ParseError> {
+    if text.starts_with('"') {
+        return Json::parse(text).map_err(|e| error(line, e.message));
+    }
+    if let Some(quoted) = text.strip_prefix('\'') {
+        let Some(quoted) = quoted.strip_suffix('\'') else {
+            return Err(error(line, "unterminated string"));
+        };
+        return Ok(Json::String(quoted.replace("''", "'")));
+    }
+    if let Some(items) = text.strip_prefix('[') {
+        let Some(items) = items.strip_suffix(']') else {
+            return Err(error(line, "unterminated sequence"));
+        };
+        return items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| scalar(item, line))
+            .collect::<Result<_, _>>()
+            .map(Json::Array);
+    }
+    if text.starts_with('{') {
+        return Err(error(line, "flow mappings aren't supported"));
+    }
+
+    Ok(match text {
+        "true" | "True" | "TRUE" => Json::Bool(true),
+        "false" | "False" | "FALSE" => Json::Bool(false),
+        "null" | "Null" | "NULL" | "~" => Json::Null,
+        _ => match text.parse::<f64>() {
+            Ok(number) if !text.starts_with(['+', '.']) => Json::Number(number),
+            _ => Json::String(text.to_string()),
+        },
+    })
+}
+
+struct Parser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn is_item(text: &str) -> bool {
+        text == "-" || text.starts_with("- ")
+    }
+
+    /// Parses the node starting at the current line, which is indented by `indent`
+    fn node(&mut self, indent: usize) -> Result<Json, ParseError> {
+        let line = &self.lines[self.pos];
+        if Self::is_item(line.text) {
+            self.sequence(indent)
+        } else if split_key(line.text).is_some() {
+            self.mapping(indent)
+        } else {
+            self.pos += 1;
+            scalar(line.text, line.number)
+        }
+    }
+
+    /// Parses the value after a `key:` or `-` with nothing else on its line, which is the
+    /// node on the next line if it's indented further
+    fn nested(&mut self, indent: usize, allow_sequence: bool) -> Result<Json, ParseError> {
+        match self.lines.get(self.pos) {
+            Some(next) if next.indent > indent => self.node(next.indent),
+            // Sequences are often written at the same indentation as their key
+            Some(next) if allow_sequence && next.indent == indent && Self::is_item(next.text) => {
+                self.sequence(indent)
+            }
+            _ => Ok(Json::Null),
+        }
+    }
+
+    fn sequence(&mut self, indent: usize) -> Result<Json, ParseError> {
+        let mut items = vec![];
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent != indent || !Self::is_item(line.text) {
+                break;
+            }
+
+            let rest = line.text[1..].trim_start();
+            if rest.is_empty() {
+                self.pos += 1;
+                items.push(self.nested(indent, false)?);
+            } else {
+                // Whatever follows the dash is a node of its own, indented past the dash
+                let item_indent = indent + (line.text.len() - rest.len());
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: item_indent,
+                    text: rest,
+                };
+                items.push(self.node(item_indent)?);
+            }
+        }
+        Ok(Json::Array(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Result<Json, ParseError> {
+        let mut fields: Vec<(String, Json)> = vec![];
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.indent < indent {
+                break;
+            }
+            if line.indent > indent {
+                return Err(error(line.number, "unexpected indentation"));
+            }
+            if Self::is_item(line.text) {
+                return Err(error(line.number, "expected a key"));
+            }
+            let Some((key, value)) = split_key(line.text) else {
+                return Err(error(line.number, "expected a key"));
+            };
+
+            let key = match scalar(key, line.number)? {
+                Json::String(key) => key,
+                _ => key.to_string(),
+            };
+            if fields.iter().any(|(k, _)| *k == key) {
+                return Err(error(line.number, format!("duplicate key {key}")));
+            }
+
+            let number = line.number;
+            self.pos += 1;
+            let value = match value {
+                "" => self.nested(indent, true)?,
+                value => scalar(value, number)?,
+            };
+            fields.push((key, value));
+        }
+        Ok(Json::Object(fields))
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json, ParseError> {
+    let mut parser = Parser {
+        lines: lines(text),
+        pos: 0,
+    };
+    let Some(first) = parser.lines.first() else {
+        return Ok(Json::Null);
+    };
+
+    let value = parser.node(first.indent)?;
+    match parser.lines.get(parser.pos) {
+        Some(line) => Err(error(line.number, "unexpected indentation")),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_json(text: &str) -> String {
+        parse(text).unwrap().to_string()
+    }
+
+    #[test]
+    fn nested_mappings_and_sequences_are_parsed() {
+        let text = "
+# a comment
+jobs:
+  - name: info
+    args: [--format, json]
+  -
+    name: strip
+    options:
+      dry_run: true
+      keep: ~
+maps:
+- a.bsp
+- b.bsp   # another comment
+";
+        assert_eq!(
+            to_json(text),
+            r#"{"jobs":[{"name":"info","args":["--format","json"]},{"name":"strip","options":{"dry_run":true,"keep":null}}],"maps":["a.bsp","b.bsp"]}"#
+        );
+    }
+
+    #[test]
+    fn quoted_scalars_are_unescaped() {
+        let text = r#"
+"quoted key": "a \"b\" # not a comment\n"
+single: 'it''s #1'
+plain: 1.5
+version: "20"
+dotted: .5
+"#;
+        assert_eq!(
+            to_json(text),
+            r#"{"quoted key":"a \"b\" # not a comment\n","single":"it's #1","plain":1.5,"version":"20","dotted":".5"}"#
+        );
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for (text, line) in [
+            ("a: 1\n  b: 2", 2),
+            ("a: 1\na: 2", 2),
+            ("a: 'unterminated", 1),
+            ("a: \"unterminated", 1),
+            ("a: [1, 2", 1),
+            ("a: {b: 1}", 1),
+            ("a:\n  - 1\n  b: 2", 3),
+        ] {
+            let error = parse(text).err();
+            assert_eq!(error.map(|e| e.line), Some(line), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Guards the API re-exported at the crate root, which other tools depend on. A test failing
+//! here means a release needs a new major version.
+
+use std::borrow::Cow;
+use std::io::{Cursor, Write};
+
+use bspinfo::writer::LumpWriter;
+use bspinfo::{
+    entities, BspError, BspFile, BspFormat, Entity, Error, LumpInfo, LumpType, PakFile, Policy,
+};
+
+/// A VBSP header with every lump empty
+fn empty_map() -> Vec<u8> {
+    let mut data = vec![];
+    data.extend_from_slice(b"VBSP");
+    data.extend_from_slice(&20u32.to_le_bytes());
+    data.resize(8 + 64 * 16, 0);
+    data.extend_from_slice(&7u32.to_le_bytes());
+    data
+}
+
+fn map_with(lumps: &[(LumpType, &[u8])]) -> Vec<u8> {
+    let mut reader = Cursor::new(empty_map());
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    let mut writer = LumpWriter::from_bsp(&mut bsp).unwrap();
+    for (lump, data) in lumps {
+        writer.replace_lump(*lump, data.to_vec());
+    }
+
+    let mut out = vec![];
+    writer.write_to(&mut out).unwrap();
+    out
+}
+
+fn pakfile(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, data) in files {
+        zip.start_file(*name, options).unwrap();
+        zip.write_all(data).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn lump_indices_are_stable() {
+    // Spot checks of the values maps store, spread over the whole table
+    let lumps = [
+        (LumpType::ENTITIES, 0),
+        (LumpType::PLANES, 1),
+        (LumpType::FACES, 7),
+        (LumpType::LEAVES, 10),
+        (LumpType::GAME_LUMP, 35),
+        (LumpType::PAKFILE, 40),
+        (LumpType::PHYSICS_LEVEL, 62),
+        (LumpType::UNUSED_63, 63),
+    ];
+    for (lump, index) in lumps {
+        assert_eq!(u32::from(lump), index);
+        assert_eq!(LumpType::try_from(index).unwrap(), lump);
+    }
+
+    for index in 0..64u32 {
+        let lump = LumpType::try_from(index).unwrap();
+        assert_eq!(LumpType::from_name(&format!("{lump:?}")), Some(lump));
+    }
+    assert!(LumpType::try_from(64u32).is_err());
+}
+
+#[test]
+fn default_policy_is_strict() {
+    assert_eq!(Policy::default(), Policy::Strict);
+}
+
+#[test]
+fn header_and_lumps_are_read() {
+    let map = map_with(&[(LumpType::PLANES, &[1; 20])]);
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+
+    assert_eq!(bsp.format(), BspFormat::Valve);
+    assert_eq!(bsp.version(), 20);
+    assert_eq!(bsp.map_revision(), 7);
+    assert_eq!(bsp.lump_count(), 64);
+
+    let info: LumpInfo = *bsp.lump_info(LumpType::PLANES);
+    assert_eq!(info.filelen, 20);
+    assert_eq!(info.uncompressed_size, 0);
+    assert_eq!(
+        bsp.get_lump(LumpType::PLANES).as_deref(),
+        Some(&[1; 20][..])
+    );
+    assert_eq!(bsp.get_lump(LumpType::FACES), None);
+}
+
+#[test]
+fn maps_in_memory_lend_uncompressed_lumps() {
+    let mut reader = Cursor::new(map_with(&[(LumpType::PLANES, &[1; 20])]));
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    let mut writer = LumpWriter::from_bsp(&mut bsp).unwrap();
+    let info = LumpInfo {
+        uncompressed_size: 40,
+        ..*bsp.lump_info(LumpType::VERTICES)
+    };
+    let compressed = bspinfo::bsp::compress_lzma(&[2; 40]).unwrap();
+    writer.replace_raw_lump(LumpType::VERTICES, info, compressed);
+    let mut map = vec![];
+    writer.write_to(&mut map).unwrap();
+
+    let mut reader = Cursor::new(&map[..]);
+    let mut bsp = BspFile::from_slice(&mut reader).unwrap();
+    let planes = bsp.get_lump(LumpType::PLANES).unwrap();
+    assert!(matches!(planes, Cow::Borrowed(&[1, ..])));
+    let vertices = bsp.get_lump(LumpType::VERTICES).unwrap();
+    assert!(matches!(&vertices, Cow::Owned(data) if *data == [2; 40]));
+}
+
+#[test]
+fn files_that_arent_maps_are_header_errors() {
+    let mut reader = Cursor::new(b"PK\x03\x04 not a map".to_vec());
+    let error = Error::from(BspFile::new(&mut reader).err().unwrap());
+    assert!(matches!(error, Error::Header(_)));
+}
+
+#[test]
+fn entities_are_read() {
+    let map = map_with(&[(
+        LumpType::ENTITIES,
+        b"{\n\"classname\" \"worldspawn\"\n}\n{\n\"classname\" \"info_target\"\n}\n\0",
+    )]);
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+
+    let entities: Vec<Entity> = entities::try_read(&mut bsp).unwrap();
+    assert_eq!(entities.len(), 2);
+    assert_eq!(entities[1].get("CLASSNAME"), Some("info_target"));
+}
+
+#[test]
+fn entity_errors_follow_the_policy() {
+    let map = map_with(&[(
+        LumpType::ENTITIES,
+        b"{ \"classname\" \"light\" }\n{ \"a\" \0",
+    )]);
+
+    let mut reader = Cursor::new(map.clone());
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert!(matches!(
+        entities::try_read(&mut bsp),
+        Err(Error::Entities(_))
+    ));
+
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader)
+        .unwrap()
+        .with_policy(Policy::Lenient);
+    assert_eq!(entities::try_read(&mut bsp).unwrap().len(), 1);
+}
+
+#[test]
+fn missing_entity_lump_is_an_error() {
+    let mut reader = Cursor::new(empty_map());
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert!(matches!(
+        entities::try_read(&mut bsp),
+        Err(Error::MissingLump(LumpType::ENTITIES))
+    ));
+}
+
+#[test]
+fn lumps_past_the_end_follow_the_policy() {
+    let mut map = map_with(&[(LumpType::PLANES, &[1; 20])]);
+    map.truncate(map.len() - 5);
+
+    let mut reader = Cursor::new(map.clone());
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert!(matches!(
+        bsp.try_get_lump(LumpType::PLANES),
+        Err(BspError::LumpOutOfBounds { .. })
+    ));
+
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader)
+        .unwrap()
+        .with_policy(Policy::Lenient);
+    assert_eq!(bsp.try_get_lump(LumpType::PLANES).unwrap().len(), 15);
+}
+
+#[test]
+fn entity_edits() {
+    let mut entity = Entity::default();
+    entity.set("classname", "light".to_string());
+    entity.set("Classname", "light_spot".to_string());
+    assert_eq!(entity.get("classname"), Some("light_spot"));
+    assert_eq!(entity.properties.len(), 1);
+
+    entity.remove("CLASSNAME");
+    assert_eq!(entity.get("classname"), None);
+}
+
+#[test]
+fn pakfile_is_read() {
+    let pak = pakfile(&[("materials/a.vmt", b"LightmappedGeneric {}")]);
+    let map = map_with(&[(LumpType::PAKFILE, &pak)]);
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+
+    let mut pak = PakFile::read(&mut bsp).unwrap().unwrap();
+    let entries = pak.entries().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].name, "materials/a.vmt");
+    assert_eq!(
+        pak.files().unwrap(),
+        vec![(
+            "materials/a.vmt".to_string(),
+            b"LightmappedGeneric {}".to_vec()
+        )]
+    );
+
+    let mut reader = Cursor::new(empty_map());
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert!(PakFile::read(&mut bsp).unwrap().is_none());
+}
+
+type Reader = Cursor<Vec<u8>>;
+type Memory<'c> = Cursor<&'c [u8]>;
+type Lump<'a> = Option<Cow<'a, [u8]>>;
+type TryLump<'a> = Result<Cow<'a, [u8]>, BspError>;
+
+/// Only compiles while the signatures tools call stay the same. Lifetimes of `BspFile` methods
+/// are bound by its impl, so they're named here rather than left to the fn pointer types.
+fn check_signatures<'a: 'b, 'b, 'c: 'a>() {
+    let _: fn(&'a mut Reader) -> binrw::BinResult<BspFile<'a, Reader>> = BspFile::new;
+    let _: fn(&'a mut Memory<'c>) -> binrw::BinResult<BspFile<'a, Memory<'c>>> =
+        BspFile::from_slice;
+    let _: fn(BspFile<'a, Reader>, Policy) -> BspFile<'a, Reader> = BspFile::with_policy;
+    let _: fn(&'b mut BspFile<'a, Reader>, LumpType) -> Lump<'a> = BspFile::get_lump;
+    let _: fn(&'b mut BspFile<'a, Reader>, LumpType) -> TryLump<'a> = BspFile::try_get_lump;
+    let _: fn(&'b mut BspFile<'a, Reader>) -> Result<Vec<Entity>, Error> = entities::try_read;
+    let _: fn(&'b mut BspFile<'a, Reader>) -> zip::result::ZipResult<Option<PakFile>> =
+        PakFile::read;
+    let _: fn(&[u8]) -> Result<Vec<Entity>, entities::ParseError> = entities::parse;
+    let _: fn(&[Entity]) -> Vec<u8> = entities::serialize;
+}
+
+#[test]
+fn signatures() {
+    check_signatures();
+
+    fn is_error<E: std::error::Error + Send + Sync + 'static>() {}
+    is_error::<BspError>();
+    is_error::<entities::ParseError>();
+}
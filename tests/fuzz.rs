@@ -0,0 +1,310 @@
+//! Runs the fuzz target over mutations of a small map with every kind of lump, so parsers
+//! meeting hostile maps keep failing cleanly between runs of a real fuzzer.
+//!
+//! Each run is deterministic. `BSPINFO_FUZZ_ITERATIONS` sets how many mutated maps are tried,
+//! and `BSPINFO_FUZZ_CORPUS` names a directory of further `.bsp` files to mutate. Inputs that
+//! panic are saved in `target/tmp` to be run again.
+
+use std::io::{Cursor, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use bspinfo::bsp::compress_lzma;
+use bspinfo::gamelump::{self, GameLump};
+use bspinfo::writer::LumpWriter;
+use bspinfo::{BspFile, LumpInfo, LumpType, PakFile, Policy};
+
+const DEFAULT_ITERATIONS: usize = 300;
+/// Size of a VBSP header, which mutations favor since it decides what every parser reads
+const HEADER_SIZE: usize = 8 + 64 * 16 + 4;
+
+/// xorshift64*, for mutations that are the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n.max(1) as u64) as usize
+    }
+
+    /// Bytes of mostly small values, so indices often land inside other lumps
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| match self.below(4) {
+                0 => self.next() as u8,
+                _ => self.below(4) as u8,
+            })
+            .collect()
+    }
+}
+
+/// A writer for a VBSP map with every lump empty
+fn empty_map() -> LumpWriter {
+    let mut header = b"VBSP".to_vec();
+    header.extend_from_slice(&20u32.to_le_bytes());
+    header.resize(HEADER_SIZE, 0);
+    let mut reader = Cursor::new(header);
+    LumpWriter::from_bsp(&mut BspFile::new(&mut reader).unwrap()).unwrap()
+}
+
+fn pakfile() -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+    let stored =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("materials/fuzz/a.vmt", stored).unwrap();
+    zip.write_all(b"\"LightmappedGeneric\" { \"$basetexture\" \"fuzz/a\" }")
+        .unwrap();
+    zip.start_file("maps/fuzz.nav", zip::write::FileOptions::default())
+        .unwrap();
+    zip.write_all(&[7; 4096]).unwrap();
+    zip.finish().unwrap().into_inner()
+}
+
+/// Static props with one model, one leaf and props of random contents, compressed as the
+/// newer games store them
+fn game_lump(rng: &mut Rng) -> Vec<u8> {
+    let mut props = vec![];
+    props.extend_from_slice(&1i32.to_le_bytes());
+    let mut model = b"models/fuzz.mdl".to_vec();
+    model.resize(128, 0);
+    props.extend_from_slice(&model);
+    props.extend_from_slice(&1i32.to_le_bytes());
+    props.extend_from_slice(&0u16.to_le_bytes());
+    let count = 1 + rng.below(3);
+    props.extend_from_slice(&(count as i32).to_le_bytes());
+    props.extend_from_slice(&rng.bytes(count * 76));
+
+    let lumps = [GameLump {
+        id: gamelump::STATIC_PROPS,
+        flags: 0,
+        version: 10,
+        data: props,
+    }];
+    gamelump::serialize_compressed(&lumps).unwrap()
+}
+
+/// A map with random records in each lump of the world, real entities and a real pakfile
+fn seed(rng: &mut Rng) -> Vec<u8> {
+    let mut writer = empty_map();
+
+    let records = [
+        (LumpType::PLANES, 20),
+        (LumpType::TEXTURE_DATA, 32),
+        (LumpType::VERTICES, 12),
+        (LumpType::VISIBILITY, 8),
+        (LumpType::NODES, 32),
+        (LumpType::TEXTURE_INFO, 72),
+        (LumpType::FACES, 56),
+        (LumpType::FACE_IDS, 2),
+        (LumpType::FACE_MACRO_TEXTURE_INFO, 2),
+        (LumpType::LEAVES, 32),
+        (LumpType::EDGES, 4),
+        (LumpType::SURFEDGES, 4),
+        (LumpType::MODELS, 48),
+        (LumpType::WORLD_LIGHTS, 88),
+        (LumpType::LEAF_FACES, 2),
+        (LumpType::LEAF_BRUSHES, 2),
+        (LumpType::BRUSHES, 12),
+        (LumpType::BRUSH_SIDES, 8),
+        (LumpType::AREAS, 8),
+        (LumpType::AREA_PORTALS, 12),
+        (LumpType::DISPLACEMENT_INFO, 176),
+        (LumpType::ORIGINAL_FACES, 56),
+        (LumpType::PHYSICS_COLLIDE, 16),
+        (LumpType::LEAF_WATER_DATA, 12),
+        (LumpType::PRIMITIVES, 10),
+        (LumpType::PRIMITIVE_INDICES, 2),
+        (LumpType::CLIP_PORTAL_VERTICES, 12),
+        (LumpType::CUBEMAPS, 16),
+        (LumpType::TEXTURE_DATA_STRING_TABLE, 4),
+        (LumpType::OVERLAYS, 352),
+        (LumpType::LEAF_MIN_DIST_TO_WATER, 2),
+        (LumpType::DISPLACEMENT_VERTICES, 20),
+        (LumpType::LIGHTING, 4),
+        (LumpType::LEAF_AMBIENT_INDEX, 4),
+        (LumpType::LEAF_AMBIENT_LIGHTING, 28),
+        (LumpType::OCCLUSION, 4),
+    ];
+    for (lump, size) in records {
+        let count = 1 + rng.below(6);
+        writer.replace_lump(lump, rng.bytes(count * size));
+    }
+
+    writer.replace_lump(
+        LumpType::ENTITIES,
+        b"{\n\"classname\" \"worldspawn\"\n\"skyname\" \"sky_fuzz\"\n}\n\
+          {\n\"classname\" \"func_areaportal\"\n\"target\" \"door\"\n\"portalnumber\" \"1\"\n}\n\
+          {\n\"classname\" \"infodecal\"\n\"texture\" \"decals/fuzz\"\n}\n\0"
+            .to_vec(),
+    );
+    writer.replace_lump(
+        LumpType::TEXTURE_DATA_STRING_DATA,
+        b"TOOLS/TOOLSNODRAW\0fuzz/a\0".to_vec(),
+    );
+
+    // Compressed lumps and their LZMA headers
+    let leaves = rng.bytes(64);
+    let info = LumpInfo {
+        fileofs: 0,
+        filelen: 0,
+        version: 1,
+        uncompressed_size: leaves.len() as u32,
+    };
+    writer.replace_raw_lump(LumpType::LEAVES, info, compress_lzma(&leaves).unwrap());
+
+    writer.replace_lump(LumpType::GAME_LUMP, game_lump(rng));
+    writer.append_lump(LumpType::PAKFILE, pakfile());
+
+    let mut out = vec![];
+    writer.write_to(&mut out).unwrap();
+    out
+}
+
+fn mutate(rng: &mut Rng, data: &mut Vec<u8>) {
+    const INTERESTING: [u32; 8] = [0, 1, 0x7f, 0xff, 0xffff, 0x7fff_ffff, 0x8000_0000, u32::MAX];
+
+    for _ in 0..1 + rng.below(8) {
+        // Half of the mutations go to the header and the lump directory
+        let end = match rng.below(2) {
+            0 => data.len().min(HEADER_SIZE),
+            _ => data.len(),
+        };
+        let at = rng.below(end);
+        match rng.below(6) {
+            0 => data[at] ^= 1 << rng.below(8),
+            1 => data[at] = rng.next() as u8,
+            2 | 3 => {
+                let value = match rng.below(3) {
+                    0 => INTERESTING[rng.below(INTERESTING.len())],
+                    1 => rng.below(data.len() + 64) as u32,
+                    _ => rng.next() as u32,
+                };
+                let at = at.min(data.len().saturating_sub(4));
+                let end = (at + 4).min(data.len());
+                data[at..end].copy_from_slice(&value.to_le_bytes()[..end - at]);
+            }
+            4 => data.truncate(HEADER_SIZE.min(data.len()) + rng.below(data.len())),
+            _ => {
+                let len = rng.below(64).min(data.len() - at);
+                let chunk = data[at..at + len].to_vec();
+                let to = rng.below(data.len());
+                data.splice(to..to, chunk);
+            }
+        }
+    }
+}
+
+fn run(input: &[u8], name: &str) {
+    if panic::catch_unwind(AssertUnwindSafe(|| bspinfo::fuzz::run(input))).is_err() {
+        let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("fuzz-{name}.bsp"));
+        std::fs::write(&path, input).unwrap();
+        panic!("{name} panicked, saved as {}", path.display());
+    }
+}
+
+#[test]
+fn mutated_maps_dont_panic() {
+    let iterations = std::env::var("BSPINFO_FUZZ_ITERATIONS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS);
+
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let mut corpus = vec![("seed".to_string(), seed(&mut rng))];
+    if let Some(dir) = std::env::var_os("BSPINFO_FUZZ_CORPUS") {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "bsp") {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                corpus.push((name, std::fs::read(&path).unwrap()));
+            }
+        }
+    }
+
+    for (name, map) in &corpus {
+        run(map, name);
+        for i in 0..iterations {
+            let mut input = map.clone();
+            mutate(&mut rng, &mut input);
+            run(&input, &format!("{name}-{i}"));
+        }
+    }
+}
+
+fn map_with_pakfile(pakfile: Vec<u8>) -> Vec<u8> {
+    let mut writer = empty_map();
+    writer.append_lump(LumpType::PAKFILE, pakfile);
+
+    let mut out = vec![];
+    writer.write_to(&mut out).unwrap();
+    out
+}
+
+/// Sets the uncompressed size every central directory header of a zip gives
+fn set_zip_sizes(zip: &mut [u8], size: u32) {
+    for at in 0..zip.len() - 28 {
+        if zip[at..at + 4] == [0x50, 0x4b, 0x01, 0x02] {
+            zip[at + 24..at + 28].copy_from_slice(&size.to_le_bytes());
+        }
+    }
+}
+
+#[test]
+fn zip_bombs_are_refused() {
+    let mut huge = pakfile();
+    set_zip_sizes(&mut huge, u32::MAX);
+    let map = map_with_pakfile(huge);
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert!(PakFile::read(&mut bsp).is_err());
+
+    // Entries decompressing to more than they claim are refused once they pass their size
+    let mut lying = pakfile();
+    set_zip_sizes(&mut lying, 16);
+    let map = map_with_pakfile(lying);
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    let mut pak = PakFile::read(&mut bsp).unwrap().unwrap();
+    assert!(pak.files().is_err());
+}
+
+#[test]
+fn lzma_bombs_are_refused() {
+    let mut lump = compress_lzma(&[0; 64]).unwrap();
+    lump[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+
+    let mut writer = empty_map();
+    let info = |uncompressed_size| LumpInfo {
+        fileofs: 0,
+        filelen: 0,
+        version: 0,
+        uncompressed_size,
+    };
+    writer.replace_raw_lump(LumpType::PLANES, info(u32::MAX), lump);
+    // A header agreeing with the data, but not with the lump directory
+    writer.replace_raw_lump(
+        LumpType::VERTICES,
+        info(12),
+        compress_lzma(&[0; 64]).unwrap(),
+    );
+    let mut map = vec![];
+    writer.write_to(&mut map).unwrap();
+
+    let mut reader = Cursor::new(map);
+    let mut bsp = BspFile::new(&mut reader).unwrap();
+    assert_eq!(bsp.get_lump(LumpType::PLANES), None);
+    assert_eq!(bsp.get_lump(LumpType::VERTICES), None);
+
+    let mut bsp = bsp.with_policy(Policy::Lenient);
+    assert_eq!(bsp.get_lump(LumpType::PLANES), None);
+    assert_eq!(
+        bsp.get_lump(LumpType::VERTICES).as_deref(),
+        Some(&[0; 64][..])
+    );
+}
@@ -0,0 +1,298 @@
+//! Runs every read-only command against real maps of different games and BSP versions.
+//!
+//! The maps are downloaded, so this only runs with `BSPINFO_SAMPLE_MAPS=1` set. They are listed
+//! in `sample_maps.csv` and cached in `BSPINFO_SAMPLE_CACHE`, by default `target/tmp/sample-maps`,
+//! where any other `.bsp` files are tested as well. The test fails unless the maps cover every
+//! variant in [`VARIANTS`].
+
+use bspinfo::{BspFile, BspFormat};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const MANIFEST: &str = include_str!("sample_maps.csv");
+
+/// Commands that don't write a map, with the arguments after the map path. `{out}` is replaced
+/// with a scratch directory.
+const COMMANDS: &[&[&str]] = &[
+    &["info"],
+    &["lumps"],
+    &["lumps", "--format", "json"],
+    &["files"],
+    &["files", "--format", "csv"],
+    &["textures", "--format", "plain"],
+    &["entities"],
+    &["entities", "--stats"],
+    &["faceids"],
+    &["staticprops"],
+    &["locate", "0", "0", "0"],
+    &["validate"],
+    &["clipgaps"],
+    &["portallinks"],
+    &["areaportals"],
+    &["leaks"],
+    &["vis"],
+    &["vis", "--cluster", "0", "--out", "{out}/pvs.png"],
+    &["occluders"],
+    &["primitives"],
+    &["splits"],
+    &["water"],
+    &["sky", "--leaves"],
+    &["lighting"],
+    &["displacements"],
+    &["cubemaps"],
+    &["overlays"],
+    &["decals"],
+    &["brushes"],
+    &["tree"],
+    &["io", "--dot"],
+    &["index"],
+    &["physics", "--keyvalues"],
+    &["worldlights"],
+    &["limits"],
+    &["compileinfo"],
+    &["crc"],
+    &["hash", "--per-lump"],
+    &["hexdump", "--lump", "0", "--length", "256"],
+    &["deps", "--format", "json"],
+    &["reslist", "--out-dir", "{out}"],
+    &["lightmaps", "--out", "{out}/lightmaps"],
+    &["thumbnails", "{out}/thumbnails"],
+    &["extract", "{out}/extract"],
+    &["export-mesh", "{out}/mesh.obj"],
+    &["overview", "--out", "{out}/overview.png", "--displacements"],
+    &["export-csv", "entities", "{out}/entities.csv"],
+    &[
+        "offset-entities",
+        "{out}/offset.bsp",
+        "--translate",
+        "1",
+        "2",
+        "3",
+        "--dry-run",
+    ],
+    &[
+        "strip",
+        "--out",
+        "{out}/stripped.bsp",
+        "--preset",
+        "server",
+        "--dry-run",
+    ],
+    &["repack", "--out", "{out}/repacked.bsp", "--dry-run"],
+    &["bz2", "--repack", "{out}/fastdl.bsp"],
+    &["optimize", "--out", "{out}/optimized.bsp", "--dry-run"],
+    &["normalize", "--out", "{out}/normalized.bsp", "--dry-run"],
+    &[
+        "pak-optimize",
+        "--out",
+        "{out}/pak-optimized.bsp",
+        "--dry-run",
+    ],
+    &[
+        "retexture",
+        "--map",
+        "tools/=tools/",
+        "--pak",
+        "--out",
+        "{out}/retextured.bsp",
+        "--dry-run",
+    ],
+];
+
+/// Commands that exit with 1 when they find problems in the map
+const CHECKS: &[&str] = &[
+    "validate",
+    "limits",
+    "areaportals",
+    "leaks",
+    "primitives",
+    "water",
+];
+
+/// Commands that exit with 5 when the map doesn't have the lump they read, as many maps don't
+const OPTIONAL_LUMPS: &[&str] = &["faceids"];
+
+/// Variants of the format the sample maps have to include, and whether a map is one
+const VARIANTS: &[(&str, Is)] = &[
+    ("a VBSP version 20 map, as from TF2 or CS:S", |h| {
+        h.format == BspFormat::Valve && h.version == 20
+    }),
+    ("a VBSP version 21 map", |h| {
+        h.format == BspFormat::Valve && h.version == 21
+    }),
+    ("a map with LZMA compressed lumps", |h| h.compressed),
+];
+
+type Is = fn(&Header) -> bool;
+
+struct Header {
+    format: BspFormat,
+    version: u32,
+    compressed: bool,
+}
+
+fn read_header(path: &Path) -> Result<Header, String> {
+    let data = fs::read(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut reader = Cursor::new(data);
+    let bsp = BspFile::new(&mut reader).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    Ok(Header {
+        format: bsp.format(),
+        version: bsp.version(),
+        compressed: (0..bsp.lump_count())
+            .any(|i| bsp.has_lump(i) && bsp.lump_info_by_index(i).uncompressed_size != 0),
+    })
+}
+
+fn cache_dir() -> PathBuf {
+    match std::env::var_os("BSPINFO_SAMPLE_CACHE") {
+        Some(dir) => dir.into(),
+        None => Path::new(env!("CARGO_TARGET_TMPDIR")).join("sample-maps"),
+    }
+}
+
+fn download(url: &str, sha256: &str, path: &Path) -> Result<(), String> {
+    let partial = path.with_extension("part");
+    let status = Command::new("curl")
+        .args(["-fsSL", "-o"])
+        .arg(&partial)
+        .arg(url)
+        .status()
+        .map_err(|e| format!("couldn't run curl: {e}"))?;
+    if !status.success() {
+        return Err(format!("downloading {url} failed ({status})"));
+    }
+
+    let mut data = fs::read(&partial).map_err(|e| e.to_string())?;
+    fs::remove_file(&partial).map_err(|e| e.to_string())?;
+
+    if url.ends_with(".bz2") {
+        let mut decompressed = vec![];
+        bzip2::read::BzDecoder::new(&data[..])
+            .read_to_end(&mut decompressed)
+            .map_err(|e| format!("decompressing {url} failed: {e}"))?;
+        data = decompressed;
+    }
+
+    let digest: String = Sha256::digest(&data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if !digest.eq_ignore_ascii_case(sha256) {
+        return Err(format!("{url} has sha256 {digest}, expected {sha256}"));
+    }
+
+    fs::write(path, data).map_err(|e| e.to_string())
+}
+
+/// Downloads the maps in the manifest that aren't cached yet, then lists every cached map. An
+/// empty manifest is an error, so the test can't pass on whatever happens to be in the cache.
+fn sample_maps(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let records: Vec<&str> = MANIFEST
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.trim().is_empty())
+        .skip(1)
+        .collect();
+    if records.is_empty() {
+        return Err("sample_maps.csv doesn't list any maps".to_string());
+    }
+
+    for record in records {
+        let fields: Vec<&str> = record.split(',').map(str::trim).collect();
+        let [name, url, sha256] = fields[..] else {
+            return Err(format!("bad manifest line: {record}"));
+        };
+        if sha256.is_empty() {
+            return Err(format!("{name} has no sha256 in the manifest"));
+        }
+
+        let path = dir.join(name);
+        if !path.exists() {
+            download(url, sha256, &path)?;
+        }
+    }
+
+    let mut maps: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bsp"))
+        .collect();
+    maps.sort();
+
+    Ok(maps)
+}
+
+fn run(map: &Path, command: &[&str], out: &Path) -> Result<(), String> {
+    let args: Vec<String> = command[1..]
+        .iter()
+        .map(|arg| arg.replace("{out}", &out.to_string_lossy()))
+        .collect();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bspinfo"))
+        .arg(command[0])
+        .arg(map)
+        .args(&args)
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    let allowed_failure = match output.status.code() {
+        Some(1) => CHECKS.contains(&command[0]),
+        Some(5) => OPTIONAL_LUMPS.contains(&command[0]),
+        _ => false,
+    };
+    if output.status.success() || allowed_failure {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{} {}: {}\n{}",
+        command[0],
+        map.display(),
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    ))
+}
+
+#[test]
+fn read_only_commands() {
+    if std::env::var_os("BSPINFO_SAMPLE_MAPS").is_none() {
+        eprintln!("skipping sample map tests, set BSPINFO_SAMPLE_MAPS=1 to run them");
+        return;
+    }
+
+    let dir = cache_dir();
+    let maps = sample_maps(&dir).unwrap();
+    let headers: Vec<Header> = maps.iter().map(|map| read_header(map).unwrap()).collect();
+    let missing: Vec<&str> = VARIANTS
+        .iter()
+        .filter(|(_, is)| !headers.iter().any(is))
+        .map(|&(variant, _)| variant)
+        .collect();
+    assert!(
+        missing.is_empty(),
+        "the sample maps in {} don't include {}",
+        dir.display(),
+        missing.join(", ")
+    );
+
+    let mut failures = vec![];
+    for map in &maps {
+        let out = dir.join("out").join(map.file_stem().unwrap());
+        fs::create_dir_all(&out).unwrap();
+
+        for command in COMMANDS {
+            if let Err(failure) = run(map, command, &out) {
+                failures.push(failure);
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}